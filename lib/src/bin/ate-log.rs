@@ -0,0 +1,297 @@
+#![allow(unused_imports)]
+use log::{info, warn, debug, error};
+use std::collections::BTreeMap;
+use clap::Clap;
+use serde_json::json;
+use ate::prelude::*;
+use ate::redo::LogFileReader;
+use ate::event::EventHeader;
+use ate::loader::LoadData;
+use ate::sink::EventSink;
+use ate::signature::SignaturePlugin;
+use ate::tree::TreeAuthorityPlugin;
+use ate::transform::EventDataTransformer;
+use ate::error::SerializationError;
+
+#[derive(Clap)]
+#[clap(version = "0.7", author = "John S. <johnathan.sharratt@gmail.com>")]
+struct Opts {
+    /// Sets the level of log verbosity, can be used multiple times
+    #[clap(short, long, parse(from_occurrences))]
+    verbose: i32,
+    /// Logs debug info to the console
+    #[clap(short, long)]
+    debug: bool,
+    /// Path to the redo log without the archive index (e.g. /tmp/ate/mychain.log)
+    #[clap(index = 1)]
+    path: String,
+    /// Index of the archive within the redo log that will be processed
+    #[clap(short, long, default_value = "0")]
+    index: u32,
+    #[clap(subcommand)]
+    subcmd: SubCommand,
+}
+
+#[derive(Clap)]
+enum SubCommand {
+    /// Dumps all the events in the archive as JSON
+    #[clap()]
+    Dump,
+    /// Verifies the hashes and signatures of all the events in the archive
+    #[clap()]
+    Verify,
+    /// Displays statistics about the events in the archive
+    #[clap()]
+    Stats,
+    /// Truncates the archive at a particular offset (e.g. where it is corrupt)
+    #[clap()]
+    TruncateAt(OptsTruncate),
+    /// Dumps all the events in the archive as JSON with their data decrypted
+    #[clap()]
+    Decrypt(OptsDecrypt),
+}
+
+/// Truncates the archive at a particular offset
+#[derive(Clap)]
+struct OptsTruncate {
+    /// Offset of the first event to be removed (decimal or hex prefixed with '0x')
+    #[clap(index = 1)]
+    offset: String,
+}
+
+/// Decrypts the data of events using a key or a token
+#[derive(Clap)]
+struct OptsDecrypt {
+    /// Read key (encoded as hex) that will be used to decrypt the data
+    #[clap(short, long)]
+    key: Option<String>,
+    /// Token that holds the read keys used to decrypt the data
+    #[clap(short, long)]
+    token: Option<String>,
+    /// Token file to read that holds the read keys used to decrypt the data
+    #[clap(long)]
+    token_path: Option<String>,
+}
+
+#[tokio::main]
+async fn main() -> Result<(), AteError>
+{
+    let opts: Opts = Opts::parse();
+
+    // Prepare the logging
+    let mut log_level = match opts.verbose {
+        0 => "error",
+        1 => "warn",
+        2 => "info",
+        _ => "debug",
+    };
+    if opts.debug { log_level = "debug"; }
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(log_level)).init();
+
+    // Determine what we need to do
+    match opts.subcmd {
+        SubCommand::Dump => {
+            main_dump(&opts.path, opts.index).await?;
+        },
+        SubCommand::Verify => {
+            main_verify(&opts.path, opts.index).await?;
+        },
+        SubCommand::Stats => {
+            main_stats(&opts.path, opts.index).await?;
+        },
+        SubCommand::TruncateAt(opts_truncate) => {
+            let offset = parse_offset(&opts_truncate.offset)?;
+            LogFileReader::truncate(&opts.path, opts.index, offset).await?;
+            println!("Truncated {}.{} at 0x{:x}", opts.path, opts.index, offset);
+        },
+        SubCommand::Decrypt(opts_decrypt) => {
+            let session = main_session(opts_decrypt).await?;
+            main_decrypt(&opts.path, opts.index, session).await?;
+        },
+    }
+
+    // We are done
+    Ok(())
+}
+
+fn parse_offset(offset: &str) -> Result<u64, AteError>
+{
+    let ret = match offset.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => u64::from_str(offset),
+    };
+    ret.map_err(|err| AteError::IO(tokio::io::Error::new(tokio::io::ErrorKind::InvalidInput, format!("Invalid offset ({}) - {}", offset, err))))
+}
+
+async fn main_session(opts: OptsDecrypt) -> Result<AteSession, AteError>
+{
+    // The session is either a token (base64 encoded) or a plain read key
+    let token = match opts.token_path {
+        Some(path) => Some(tokio::fs::read_to_string(path).await?),
+        None => opts.token,
+    };
+    let mut session = match token {
+        Some(token) => {
+            let bytes = base64::decode(token.trim())
+                .map_err(|err| SerializationError::IO(tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, err.to_string())))?;
+            SerializationFormat::MessagePack.deserialize(&bytes[..])?
+        },
+        None => AteSession::default(),
+    };
+    if let Some(key) = opts.key {
+        let bytes = hex::decode(key.trim())
+            .map_err(|err| SerializationError::IO(tokio::io::Error::new(tokio::io::ErrorKind::InvalidData, err.to_string())))?;
+        session.add_user_read_key(&EncryptKey::from_bytes(&bytes[..])?);
+    }
+    Ok(session)
+}
+
+/// Reads every event in the archive and stops at the first one that is corrupt
+async fn read_events(path: &str, index: u32, mut callback: impl FnMut(u64, LoadData)) -> Result<(), AteError>
+{
+    let cfg = ConfAte::default();
+    let mut reader = LogFileReader::open(path, index, cfg.event_limits()).await?;
+    loop {
+        match reader.next().await {
+            Ok(Some((offset, evt))) => callback(offset, evt),
+            Ok(None) => break,
+            Err(err) => {
+                eprintln!("The redo log ({}) is corrupt at offset 0x{:x} - {}", reader.path(), reader.offset(), err);
+                eprintln!("Use 'truncate-at 0x{:x}' to remove the corrupt tail of the log", reader.offset());
+                return Err(AteError::SerializationError(err));
+            }
+        }
+    }
+    Ok(())
+}
+
+fn event_to_json(offset: u64, evt: &LoadData) -> serde_json::Value
+{
+    json!({
+        "offset": format!("0x{:x}", offset),
+        "event_hash": evt.header.event_hash.to_string(),
+        "meta_hash": evt.header.meta_hash.to_string(),
+        "data_hash": evt.header.data_hash.map(|h| h.to_string()),
+        "data_size": evt.header.data_size,
+        "meta": evt.data.meta.core.iter().map(|m| m.to_string()).collect::<Vec<_>>(),
+    })
+}
+
+fn data_to_json(format: SerializationFormat, data: &[u8]) -> serde_json::Value
+{
+    // Data stored as JSON is displayed inline otherwise its just encoded
+    if let SerializationFormat::Json = format {
+        if let Ok(ret) = serde_json::from_slice::<serde_json::Value>(data) {
+            return ret;
+        }
+    }
+    serde_json::Value::String(base64::encode(data))
+}
+
+async fn main_dump(path: &str, index: u32) -> Result<(), AteError>
+{
+    read_events(path, index, |offset, evt| {
+        println!("{}", event_to_json(offset, &evt));
+    }).await
+}
+
+async fn main_decrypt(path: &str, index: u32, session: AteSession) -> Result<(), AteError>
+{
+    // The tree plugin is fed all the events so that it can work out which key
+    // is needed to decrypt each of the data blobs
+    let mut plugin = TreeAuthorityPlugin::new();
+    read_events(path, index, |offset, evt| {
+        let mut ret = event_to_json(offset, &evt);
+        let header = EventHeader {
+            raw: evt.header.clone(),
+            meta: evt.data.meta.clone(),
+        };
+        if let Err(err) = plugin.feed(&header, None) {
+            ret["error"] = json!(err.to_string());
+        }
+        if let Some(data) = evt.data.data_bytes {
+            match plugin.data_as_overlay(&header.meta, data, &session) {
+                Ok(data) => { ret["data"] = data_to_json(evt.data.format.data, &data[..]); },
+                Err(err) => { ret["error"] = json!(err.to_string()); }
+            }
+        }
+        println!("{}", ret);
+    }).await
+}
+
+async fn main_verify(path: &str, index: u32) -> Result<(), AteError>
+{
+    let mut plugin = SignaturePlugin::new();
+    let mut needs_signature = Vec::new();
+    let mut cnt = 0usize;
+    let mut errors = 0usize;
+
+    // The event hashes are recomputed (and checksums validated) as the events are read
+    // and then fed through the signature plugin which checks the signatures
+    read_events(path, index, |offset, evt| {
+        cnt = cnt + 1;
+
+        let header = EventHeader {
+            raw: evt.header.clone(),
+            meta: evt.data.meta.clone(),
+        };
+        if let Err(err) = plugin.feed(&header, None) {
+            println!("0x{:x}: invalid signature on event {} - {}", offset, evt.header.event_hash, err);
+            errors = errors + 1;
+        }
+        if header.meta.get_sign_with().is_some() {
+            needs_signature.push((offset, evt.header.event_hash));
+        }
+    }).await?;
+
+    // Every event that was meant to be signed must have been covered by a valid signature
+    for (offset, hash) in needs_signature {
+        if plugin.get_verified_signatures(&hash).is_none() {
+            println!("0x{:x}: event {} is missing a signature", offset, hash);
+            errors = errors + 1;
+        }
+    }
+
+    println!("Verified {} events with {} errors", cnt, errors);
+    if errors > 0 {
+        std::process::exit(1);
+    }
+    Ok(())
+}
+
+#[derive(Default)]
+struct TypeStats
+{
+    events: usize,
+    tombstones: usize,
+    meta_bytes: u64,
+    data_bytes: u64,
+}
+
+async fn main_stats(path: &str, index: u32) -> Result<(), AteError>
+{
+    let mut stats = BTreeMap::<String, TypeStats>::new();
+    let mut total = TypeStats::default();
+
+    read_events(path, index, |_offset, evt| {
+        let type_name = match evt.data.meta.get_type_name() {
+            Some(t) => t.type_name.clone(),
+            None => "(none)".to_string(),
+        };
+        let tombstone = evt.data.meta.get_tombstone().is_some();
+        for s in vec![stats.entry(type_name).or_default(), &mut total] {
+            s.events = s.events + 1;
+            if tombstone {
+                s.tombstones = s.tombstones + 1;
+            }
+            s.meta_bytes = s.meta_bytes + evt.header.meta_bytes.len() as u64;
+            s.data_bytes = s.data_bytes + evt.header.data_size as u64;
+        }
+    }).await?;
+
+    println!("{:<50} {:>10} {:>10} {:>14} {:>14}", "type", "events", "tombstones", "meta-bytes", "data-bytes");
+    for (type_name, s) in stats.iter().chain(std::iter::once((&"(total)".to_string(), &total))) {
+        println!("{:<50} {:>10} {:>10} {:>14} {:>14}", type_name, s.events, s.tombstones, s.meta_bytes, s.data_bytes);
+    }
+    Ok(())
+}
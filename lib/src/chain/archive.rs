@@ -0,0 +1,261 @@
+#[allow(unused_imports)]
+use log::{info, error, debug};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncRead, AsyncWrite, AsyncReadExt, AsyncWriteExt};
+use bytes::Bytes;
+
+use crate::crypto::*;
+use crate::error::*;
+use crate::event::*;
+use crate::index::EventLeaf;
+use crate::spec::*;
+use crate::trust::*;
+
+use super::*;
+
+static ARCHIVE_MAGIC: &'static [u8; 8] = b"ATEARCHV";
+
+/// Version of the chain archive format, archives written with a different
+/// version will be rejected when they are imported or verified
+pub(crate) static ARCHIVE_VERSION: u32 = 1;
+
+/// Number of events that are loaded from the chain at a time while exporting
+static ARCHIVE_BATCH_SIZE: usize = 1000;
+
+/// Self-describing header that is written at the start of every chain archive
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct ChainArchiveHeader
+{
+    pub version: u32,
+    pub key: ChainKey,
+    pub chain_header: ChainHeader,
+    pub root_keys: Vec<PublicSignKey>,
+    pub integrity: IntegrityMode,
+    pub events: u64,
+}
+
+/// Single event as it is stored within a chain archive
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArchiveEvent
+{
+    format: MessageFormat,
+    meta: Vec<u8>,
+    data: Option<Vec<u8>>,
+    event_hash: AteHash,
+}
+
+/// Trailer that closes the archive so that truncated archives are detected
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct ArchiveTrailer
+{
+    events: u64,
+    summary: Option<AteHash>,
+}
+
+fn summary_hash(summary: Option<AteHash>, event_hash: &AteHash) -> AteHash {
+    match summary {
+        Some(a) => DoubleHash::from_hashes(&a, event_hash).hash(),
+        None => event_hash.clone(),
+    }
+}
+
+async fn write_frame(writer: &mut (impl AsyncWrite + Unpin), tag: u8, bytes: &[u8]) -> Result<(), ArchiveError> {
+    writer.write_u8(tag).await?;
+    writer.write_u32(bytes.len() as u32).await?;
+    writer.write_all(bytes).await?;
+    Ok(())
+}
+
+/// Reads the events out of a chain archive while checking every event hash
+/// against its meta and data bytes
+pub struct ChainArchiveReader<R>
+where R: AsyncRead + Unpin
+{
+    reader: R,
+    header: ChainArchiveHeader,
+    limits: EventLimits,
+    events: u64,
+    summary: Option<AteHash>,
+    finished: bool,
+}
+
+impl<R> ChainArchiveReader<R>
+where R: AsyncRead + Unpin
+{
+    pub async fn open(mut reader: R, limits: EventLimits) -> Result<ChainArchiveReader<R>, ArchiveError>
+    {
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic[..]).await?;
+        if &magic != ARCHIVE_MAGIC {
+            return Err(ArchiveError::InvalidMagic);
+        }
+
+        let version = reader.read_u32().await?;
+        if version != ARCHIVE_VERSION {
+            return Err(ArchiveError::UnsupportedVersion(version));
+        }
+
+        let header_len = reader.read_u32().await? as usize;
+        limits.check_meta(header_len)?;
+        let mut header = vec![0u8; header_len];
+        reader.read_exact(&mut header[..]).await?;
+        let header: ChainArchiveHeader = SerializationFormat::Json.deserialize(&header[..])?;
+
+        Ok(
+            ChainArchiveReader {
+                reader,
+                header,
+                limits,
+                events: 0,
+                summary: None,
+                finished: false,
+            }
+        )
+    }
+
+    pub fn header(&self) -> &ChainArchiveHeader {
+        &self.header
+    }
+
+    /// Reads the next event from the archive, once all the events are read the
+    /// trailer is checked to make sure that the archive is complete
+    pub async fn next(&mut self) -> Result<Option<EventData>, ArchiveError>
+    {
+        if self.finished {
+            return Ok(None);
+        }
+
+        let tag = self.reader.read_u8().await?;
+        let len = self.reader.read_u32().await? as usize;
+        if len > self.limits.max_meta_size.saturating_add(self.limits.max_data_size) {
+            return Err(ArchiveError::SerializationError(SerializationError::DataTooLarge { size: len as u64, limit: self.limits.max_data_size as u64 }));
+        }
+        let mut frame = vec![0u8; len];
+        self.reader.read_exact(&mut frame[..]).await?;
+
+        // A zero tag marks the trailer at the end of the archive
+        if tag == 0 {
+            self.finished = true;
+            let trailer: ArchiveTrailer = bincode::deserialize(&frame[..])?;
+            if trailer.events != self.events || trailer.events != self.header.events {
+                return Err(ArchiveError::CountMismatch { expected: trailer.events, actual: self.events });
+            }
+            if let (Some(expected), Some(actual)) = (trailer.summary, self.summary) {
+                if expected != actual {
+                    return Err(ArchiveError::HashMismatch { expected, actual });
+                }
+            }
+            return Ok(None);
+        }
+
+        // Recompute the event hash from the bytes that were actually stored
+        let evt: ArchiveEvent = bincode::deserialize(&frame[..])?;
+        self.limits.check_meta(evt.meta.len())?;
+        if let Some(data) = evt.data.as_ref() {
+            self.limits.check_data(data.len())?;
+        }
+        let data_hash = evt.data.as_ref().map(|d| AteHash::from_bytes(&d[..]));
+        let event_hash = event_sig_hash(&AteHash::from_bytes(&evt.meta[..]), &data_hash);
+        if event_hash != evt.event_hash {
+            return Err(ArchiveError::HashMismatch { expected: evt.event_hash, actual: event_hash });
+        }
+        self.events = self.events + 1;
+        self.summary = Some(summary_hash(self.summary, &event_hash));
+
+        Ok(
+            Some(
+                EventData {
+                    meta: evt.format.meta.deserialize(&evt.meta[..])?,
+                    data_bytes: evt.data.map(|d| Bytes::from(d)),
+                    format: evt.format,
+                }
+            )
+        )
+    }
+
+    /// Reads every event in the archive to check it is complete and not corrupt
+    /// without needing to import it into a chain
+    pub async fn verify(mut self) -> Result<ChainArchiveHeader, ArchiveError>
+    {
+        while let Some(_) = self.next().await? { }
+        Ok(self.header)
+    }
+}
+
+impl<'a> Chain
+{
+    /// Streams every event in the chain (including its root keys) into a
+    /// portable archive that can later be imported with `ChainBuilder::import`
+    pub async fn export(&'a self, writer: &mut (impl AsyncWrite + Unpin)) -> Result<u64, ArchiveError>
+    {
+        let multi = self.multi().await;
+
+        // Take a snapshot of all the events that will be exported
+        let (chain_header, hashes) = {
+            let guard = multi.inside_async.read().await;
+            let chain_header = guard.chain.redo.read_chain_header()?;
+            let hashes = guard.range(..)
+                .map(|(_, h)| h.event_hash)
+                .collect::<Vec<_>>();
+            (chain_header, hashes)
+        };
+        let (integrity, root_keys) = {
+            let guard = multi.inside_sync.read();
+            let root_keys = guard
+                .plugins
+                .iter()
+                .flat_map(|p| p.root_keys())
+                .collect::<Vec<_>>();
+            (guard.integrity, root_keys)
+        };
+
+        // Write the header
+        let header = ChainArchiveHeader {
+            version: ARCHIVE_VERSION,
+            key: self.key.clone(),
+            chain_header,
+            root_keys,
+            integrity,
+            events: hashes.len() as u64,
+        };
+        let header = SerializationFormat::Json.serialize(&header)?;
+        writer.write_all(&ARCHIVE_MAGIC[..]).await?;
+        writer.write_u32(ARCHIVE_VERSION).await?;
+        writer.write_u32(header.len() as u32).await?;
+        writer.write_all(&header[..]).await?;
+
+        // Stream all the events in batches so that writes to the chain are not blocked
+        let mut summary = None;
+        for batch in hashes.chunks(ARCHIVE_BATCH_SIZE) {
+            let leafs = batch.iter()
+                .map(|h| EventLeaf {
+                    record: h.clone(),
+                    created: 0,
+                    updated: 0,
+                })
+                .collect::<Vec<_>>();
+
+            for evt in multi.load_many(leafs).await? {
+                let evt = ArchiveEvent {
+                    format: evt.header.format,
+                    meta: evt.header.meta_bytes.to_vec(),
+                    data: evt.data.data_bytes.map(|d| d.to_vec()),
+                    event_hash: evt.header.event_hash,
+                };
+                summary = Some(summary_hash(summary, &evt.event_hash));
+                write_frame(writer, 1, &bincode::serialize(&evt)?[..]).await?;
+            }
+        }
+
+        // Close the archive with the trailer
+        let trailer = ArchiveTrailer {
+            events: hashes.len() as u64,
+            summary,
+        };
+        write_frame(writer, 0, &bincode::serialize(&trailer)?[..]).await?;
+        writer.flush().await?;
+
+        debug!("exported {} events from {}", hashes.len(), self.key);
+        Ok(hashes.len() as u64)
+    }
+}
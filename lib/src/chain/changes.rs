@@ -0,0 +1,499 @@
+#[allow(unused_imports)]
+use log::{info, error, debug};
+use serde::{Serialize, Deserialize};
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use futures::Stream;
+use futures::FutureExt;
+use futures::future::BoxFuture;
+use tokio::sync::RwLock;
+use tokio::sync::broadcast;
+use tokio::sync::watch;
+use tokio::select;
+
+use crate::crypto::AteHash;
+use crate::error::*;
+use crate::event::*;
+use crate::header::PrimaryKey;
+use crate::meta::*;
+use crate::time::ChainTimestamp;
+use crate::trust::ChainTimeline;
+
+use super::*;
+
+/// Maximum number of events that are read from the history while holding the lock
+const CHANGES_BATCH_SIZE: usize = 100;
+
+/// Describes what happened to a data object
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind
+{
+    Insert,
+    Update,
+    Tombstone,
+}
+
+impl std::fmt::Display
+for ChangeKind
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeKind::Insert => write!(f, "insert"),
+            ChangeKind::Update => write!(f, "update"),
+            ChangeKind::Tombstone => write!(f, "tombstone"),
+        }
+    }
+}
+
+/// Position in the change stream of a chain, consumers can store the cursor
+/// and later use it to resume the stream exactly where they left off
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq)]
+pub struct ChangeCursor
+{
+    /// Timestamp of the last event that was consumed
+    pub timestamp: ChainTimestamp,
+    /// Hash of the last event that was consumed (None means the stream starts
+    /// from the timestamp instead)
+    pub event: Option<AteHash>,
+}
+
+impl From<ChainTimestamp>
+for ChangeCursor
+{
+    fn from(timestamp: ChainTimestamp) -> ChangeCursor {
+        ChangeCursor {
+            timestamp,
+            event: None,
+        }
+    }
+}
+
+/// A single change made to a data object in the chain
+#[derive(Debug, Clone)]
+pub struct ChainChange
+{
+    pub timestamp: ChainTimestamp,
+    pub key: PrimaryKey,
+    pub type_name: MetaType,
+    pub kind: ChangeKind,
+    /// Cursor that resumes the stream straight after this change
+    pub cursor: ChangeCursor,
+}
+
+/// Events that were already in the chain when the stream was positioned by a
+/// timestamp (rather than an event), those older than the timestamp are skipped
+#[derive(Debug, Clone)]
+struct ChangeSince
+{
+    until: u64,
+    cursor: ChangeCursor,
+}
+
+impl ChangeSince
+{
+    fn is_consumed(&self, position: u64, timestamp: &ChainTimestamp, hash: &AteHash) -> bool {
+        position <= self.until && (
+            *timestamp < self.cursor.timestamp ||
+            (*timestamp == self.cursor.timestamp && self.cursor.event.as_ref() == Some(hash))
+        )
+    }
+}
+
+struct ChangeReader
+{
+    inside_async: Arc<RwLock<ChainProtectedAsync>>,
+    cursor: ChangeCursor,
+    read_cursor: ChangeCursor,
+    /// Position in the timeline of the next event to be read
+    position: u64,
+    generation: u64,
+    since: Option<ChangeSince>,
+    type_name: Option<String>,
+    pending: VecDeque<ChainChange>,
+    changed: watch::Receiver<()>,
+    exit: broadcast::Receiver<()>,
+}
+
+/// Stream of all the changes made to the data objects in a chain which first
+/// replays the history and then follows the new events as they are written
+///
+/// Changes are returned in the order the events were added to the chain, when
+/// the events that a cursor refers to have been compacted away the stream falls
+/// back to the timestamp of the cursor instead.
+pub struct ChangeStream
+{
+    reader: Option<ChangeReader>,
+    cursor: ChangeCursor,
+    next: Option<BoxFuture<'static, (ChangeReader, Result<ChainChange, BusError>)>>,
+}
+
+impl ChangeStream
+{
+    /// Only returns changes to data objects of this type
+    pub fn with_type<D>(self) -> ChangeStream {
+        self.with_type_name(std::any::type_name::<D>())
+    }
+
+    /// Only returns changes to data objects with this type name (the type is only
+    /// known for objects whose events record it, see `ChainBuilder::track_types`)
+    pub fn with_type_name(mut self, type_name: &str) -> ChangeStream {
+        if let Some(reader) = self.reader.as_mut() {
+            reader.type_name = Some(type_name.to_string());
+        }
+        self
+    }
+
+    /// Returns the position of the stream which can be stored to resume it later
+    pub fn cursor(&self) -> &ChangeCursor {
+        &self.cursor
+    }
+
+    /// Waits for the next change in the chain
+    pub async fn recv(&mut self) -> Result<ChainChange, BusError>
+    {
+        // A receive that was started by the stream keeps hold of the reader until it
+        // finishes, it stays in place so that dropping this future does not lose it
+        if let Some(next) = self.next.as_mut() {
+            let (reader, ret) = next.await;
+            self.next = None;
+            self.cursor = reader.cursor.clone();
+            self.reader = Some(reader);
+            return ret;
+        }
+        let reader = self.reader.as_mut().ok_or(BusError::ChannelClosed)?;
+        let ret = reader.recv().await;
+        self.cursor = reader.cursor.clone();
+        ret
+    }
+
+    /// Returns the next change in the chain or None if the stream has caught up
+    pub async fn try_recv(&mut self) -> Result<Option<ChainChange>, BusError>
+    {
+        if let Some(next) = self.next.as_mut() {
+            let (reader, ret) = match next.now_or_never() {
+                Some(a) => a,
+                None => { return Ok(None); }
+            };
+            self.next = None;
+            self.cursor = reader.cursor.clone();
+            self.reader = Some(reader);
+            return ret.map(|a| Some(a));
+        }
+        let reader = self.reader.as_mut().ok_or(BusError::ChannelClosed)?;
+        let ret = reader.try_recv().await;
+        self.cursor = reader.cursor.clone();
+        ret
+    }
+}
+
+impl Stream
+for ChangeStream
+{
+    type Item = Result<ChainChange, BusError>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>>
+    {
+        let this = self.get_mut();
+        if this.next.is_none() {
+            let mut reader = match this.reader.take() {
+                Some(a) => a,
+                None => { return Poll::Ready(None); }
+            };
+            this.next = Some(Box::pin(async move {
+                let ret = reader.recv().await;
+                (reader, ret)
+            }));
+        }
+
+        let (reader, ret) = futures::ready!(this.next.as_mut().unwrap().as_mut().poll(cx));
+        this.next = None;
+        this.cursor = reader.cursor.clone();
+        this.reader = Some(reader);
+        Poll::Ready(match ret {
+            Ok(a) => Some(Ok(a)),
+            Err(BusError::ChannelClosed) => None,
+            Err(err) => Some(Err(err)),
+        })
+    }
+}
+
+impl ChangeReader
+{
+    async fn recv(&mut self) -> Result<ChainChange, BusError>
+    {
+        loop {
+            if let Some(ret) = self.try_recv().await? {
+                return Ok(ret);
+            }
+            select! {
+                ret = self.changed.changed() => {
+                    if ret.is_err() {
+                        return Err(BusError::ChannelClosed);
+                    }
+                },
+                _ = self.exit.recv() => {
+                    return Err(BusError::ChannelClosed);
+                }
+            }
+        }
+    }
+
+    async fn try_recv(&mut self) -> Result<Option<ChainChange>, BusError>
+    {
+        loop {
+            if let Some(ret) = self.pending.pop_front() {
+                self.cursor = match self.pending.is_empty() {
+                    true => self.read_cursor.clone(),
+                    false => ret.cursor.clone(),
+                };
+                return Ok(Some(ret));
+            }
+            if self.fill().await? == false {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Finds where the cursor is in the timeline of the chain
+    fn seek(&mut self, timeline: &ChainTimeline)
+    {
+        self.generation = timeline.generation;
+        self.since = None;
+        self.position = match self.read_cursor.event.as_ref().and_then(|a| timeline.arrival_of.get(a)) {
+            Some(a) => *a + 1,
+            None => {
+                if let Some(until) = timeline.arrivals.keys().next_back() {
+                    self.since = Some(ChangeSince {
+                        until: *until,
+                        cursor: self.read_cursor.clone(),
+                    });
+                }
+                timeline.arrivals.keys().next().map_or(0, |a| *a)
+            }
+        };
+    }
+
+    async fn fill(&mut self) -> Result<bool, BusError>
+    {
+        let inside_async = Arc::clone(&self.inside_async);
+        let guard = inside_async.read().await;
+        let timeline = &guard.chain.timeline;
+
+        // The positions of the events change whenever the timeline is rebuilt
+        if timeline.generation != self.generation {
+            self.seek(timeline);
+        }
+
+        // The events are read in batches thus the read cursor runs ahead of the
+        // cursor of the changes that were returned to the caller
+        let mut cnt = 0usize;
+        for (position, (timestamp, raw)) in timeline.arrivals.range(self.position..) {
+            self.position = *position + 1;
+            cnt = cnt + 1;
+
+            let consumed = match &self.since {
+                Some(a) => a.is_consumed(*position, timestamp, &raw.event_hash),
+                None => false,
+            };
+            if consumed == false {
+                self.read_cursor = ChangeCursor {
+                    timestamp: timestamp.clone(),
+                    event: Some(raw.event_hash.clone()),
+                };
+
+                let header = raw.as_header()?;
+                if let Some((key, type_name, kind)) = ChangeReader::process(timeline, &header) {
+                    let relevant = match &self.type_name {
+                        Some(a) => *a == type_name,
+                        None => true,
+                    };
+                    if relevant {
+                        self.pending.push_back(ChainChange {
+                            timestamp: timestamp.clone(),
+                            key,
+                            type_name: MetaType { type_name },
+                            kind,
+                            cursor: self.read_cursor.clone(),
+                        });
+                    }
+                }
+            }
+
+            if cnt >= CHANGES_BATCH_SIZE {
+                break;
+            }
+        }
+        if self.since.as_ref().map_or(false, |a| self.position > a.until) {
+            self.since = None;
+        }
+
+        // If nothing relevant was found then the stream still moves forward
+        if self.pending.is_empty() {
+            self.cursor = self.read_cursor.clone();
+        }
+        Ok(cnt > 0)
+    }
+
+    /// Uses the index of the chain to tell inserts apart from updates
+    fn process(timeline: &ChainTimeline, header: &EventHeader) -> Option<(PrimaryKey, String, ChangeKind)>
+    {
+        let (key, kind) = match header.meta.get_tombstone() {
+            Some(key) => (key, ChangeKind::Tombstone),
+            None if header.raw.data_hash.is_some() => (header.meta.get_data_key()?, ChangeKind::Update),
+            None => { return None; }
+        };
+
+        // (versions that are no longer known were written before the chain was compacted)
+        let (kind, type_name) = match timeline.pointers.lookup_change(&key, &header.raw.event_hash) {
+            Some((false, type_name)) if kind == ChangeKind::Update => (ChangeKind::Insert, type_name),
+            Some((_, type_name)) => (kind, type_name),
+            None => (kind, None),
+        };
+        let type_name = match (type_name, header.meta.get_type_name()) {
+            (Some(a), _) => a.to_string(),
+            (None, Some(a)) => a.type_name.clone(),
+            (None, None) => String::default(),
+        };
+        Some((key, type_name, kind))
+    }
+}
+
+impl<'a> Chain
+{
+    /// Returns a stream of all the changes made to data objects in this chain starting
+    /// from a particular point in time
+    pub async fn changes(&'a self, from: ChainTimestamp) -> Result<ChangeStream, BusError> {
+        self.changes_from(ChangeCursor::from(from)).await
+    }
+
+    /// Resumes a stream of changes from a cursor that was stored earlier
+    pub async fn changes_from(&'a self, cursor: ChangeCursor) -> Result<ChangeStream, BusError>
+    {
+        let guard = self.inside_async.read().await;
+        let mut reader = ChangeReader {
+            inside_async: Arc::clone(&self.inside_async),
+            cursor: cursor.clone(),
+            read_cursor: cursor.clone(),
+            position: 0,
+            generation: 0,
+            since: None,
+            type_name: None,
+            pending: VecDeque::new(),
+            changed: guard.changed_rx.clone(),
+            exit: self.exit.subscribe(),
+        };
+        reader.seek(&guard.chain.timeline);
+
+        Ok(ChangeStream {
+            reader: Some(reader),
+            cursor,
+            next: None,
+        })
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_chain_changes() -> Result<(), AteError>
+{
+    use crate::prelude::*;
+    use crate::dio::test::{TestUserDao, TestEnumDao};
+    use crate::spec::MessageFormat;
+
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_chain_changes", |builder| builder
+        .track_types(true)
+    ).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let key = {
+        let mut dio = chain.dio(&session).await;
+        let dao = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        dio.store(TestEnumDao::Blah1)?;
+        dio.commit().await?;
+        dao.key().clone()
+    };
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut dao = dio.load::<TestUserDao>(&key).await?;
+        dao.team = "blue".to_string();
+        dao.commit(&mut dio)?;
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.delete::<TestUserDao>(&key).await?;
+        dio.commit().await?;
+    }
+
+    // The history is replayed first
+    let type_name = std::any::type_name::<TestUserDao>();
+    let mut changes = chain.changes(ChainTimestamp::from(0u64)).await?.with_type::<TestUserDao>();
+    let mut replayed = Vec::new();
+    while let Some(change) = changes.try_recv().await? {
+        assert_eq!(change.key, key);
+        assert_eq!(change.type_name.type_name, type_name);
+        replayed.push(change);
+    }
+    assert_eq!(replayed.iter().map(|a| a.kind).collect::<Vec<_>>(), vec![ChangeKind::Insert, ChangeKind::Update, ChangeKind::Tombstone]);
+    assert_eq!(changes.cursor(), &replayed[2].cursor);
+
+    // Resuming from a stored cursor continues exactly where it left off
+    let cursor = SerializationFormat::Json.serialize(&replayed[0].cursor)?;
+    let cursor: ChangeCursor = SerializationFormat::Json.deserialize(&cursor[..])?;
+    let mut resumed = chain.changes_from(cursor).await?.with_type::<TestUserDao>();
+    assert_eq!(resumed.try_recv().await?.map(|a| a.kind), Some(ChangeKind::Update));
+    assert_eq!(resumed.try_recv().await?.map(|a| a.kind), Some(ChangeKind::Tombstone));
+    assert!(resumed.try_recv().await?.is_none());
+
+    // Receives that are abandoned half way (e.g. they lost a select!) leave the stream usable
+    use futures::StreamExt;
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(10), changes.next()).await.is_err());
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(10), changes.recv()).await.is_err());
+    assert!(changes.try_recv().await?.is_none());
+
+    // Then it follows new events as they are written
+    let writer = {
+        let chain = Arc::clone(&chain);
+        let session = session.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+            let mut dio = chain.dio(&session).await;
+            dio.store(TestEnumDao::Blah4).unwrap();
+            let dao = dio.store(TestUserDao { email: "bob@here.com".to_string(), team: "red".to_string() }).unwrap();
+            dio.commit().await.unwrap();
+            dao.key().clone()
+        })
+    };
+    // (the stream can also be consumed as a futures::Stream)
+    let change = tokio::time::timeout(std::time::Duration::from_secs(5), changes.next()).await.expect("the change should have arrived").unwrap()?;
+    assert_eq!(change.kind, ChangeKind::Insert);
+    assert_eq!(change.key, writer.await.unwrap());
+
+    // Events that arrive late with an older timestamp are still seen by the stream
+    {
+        let late = PrimaryKey::generate();
+        let format = MessageFormat { meta: SerializationFormat::Json, data: SerializationFormat::Json };
+        let mut meta = Metadata::for_data(late.clone());
+        meta.core.push(CoreMetadata::Timestamp(ChainTimestamp::from(1u64)));
+        meta.core.push(CoreMetadata::Type(MetaType { type_name: type_name.to_string() }));
+        let data = format.data.serialize(&TestUserDao { email: "carol@here.com".to_string(), team: "red".to_string() })?;
+        chain.pipe.feed(crate::transaction::Transaction {
+            scope: TransactionScope::Local,
+            transmit: false,
+            events: vec![EventData { meta, data_bytes: Some(bytes::Bytes::from(data)), format }],
+            conversation: None,
+            unconfirmed: false,
+        }).await?;
+
+        let change = changes.try_recv().await?.expect("the late change should have arrived");
+        assert_eq!(change.kind, ChangeKind::Insert);
+        assert_eq!(change.key, late);
+    }
+
+    Ok(())
+}
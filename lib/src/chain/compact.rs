@@ -4,7 +4,9 @@ use std::sync::{Arc};
 use tokio::sync::RwLock;
 use parking_lot::RwLock as StdRwLock;
 use btreemultimap::BTreeMultiMap;
+use std::collections::BTreeMap;
 use multimap::MultiMap;
+use fxhash::FxHashMap;
 
 use crate::trust::*;
 use crate::spec::*;
@@ -64,6 +66,9 @@ impl<'a> Chain
             history: BTreeMultiMap::new(),
             pointers: BinaryTreeIndexer::default(),
             compactors: Vec::new(),
+            arrivals: BTreeMap::new(),
+            arrival_of: FxHashMap::default(),
+            generation: 0,
         };
 
         // create the flip
@@ -165,9 +170,13 @@ impl<'a> Chain
                     transformers: Vec::new(),
                     listeners: MultiMap::new(),
                     services: Vec::new(),
+                    secondary_indexes: FxHashMap::default(),
+                    unique_constraints: Vec::new(),
+                    track_types: false,
                     repository: None,
                     default_session: AteSession::default(),
                     integrity: guard_sync.integrity,
+                    idempotency_window: guard_sync.idempotency_window,
                 }
             };
             sync.plugins.iter_mut().for_each(|a| a.reset());
@@ -200,7 +209,10 @@ impl<'a> Chain
             // step6 - build a list of the events that are actually relevant to a compacted log
             for header in headers.iter().filter(|a| a.1).map(|a| &a.0) {
                 flip.event_summary.push(header.raw.clone());
-                let _lookup = flip.copy_event(&guard_async.chain.redo, header.raw.event_hash).await?;
+                // (events that are still staged stay out of the main log until they are confirmed)
+                if guard_async.chain.redo.is_staged(&header.raw.event_hash) == false {
+                    let _lookup = flip.copy_event(&guard_async.chain.redo, header.raw.event_hash).await?;
+                }
                 new_timeline.add_history(&header);
             }
 
@@ -227,8 +239,12 @@ impl<'a> Chain
                 .map(|e| e.as_header())
                 .collect::<Result<Vec<_>,_>>()?;
 
-            // Flip all the indexes
+            // Flip all the indexes (delayed children that were already delivered
+            // are not delivered a second time)
             let chain = &mut single.inside_async.chain;
+            new_timeline.release_delayed(chain.timeline.pointers.watermark());
+            new_timeline.pointers.prune(cut_off);
+            new_timeline.generation = chain.timeline.generation + 1;
             chain.timeline = new_timeline;
 
             debug!("compact: rebuilding indexes");
@@ -13,7 +13,8 @@ use parking_lot::RwLock as StdRwLock;
 use crate::single::*;
 use crate::multi::*;
 use crate::pipe::*;
-use crate::meta::*;
+use crate::event::EventData;
+use crate::crypto::AteHash;
 use crate::spec::*;
 use crate::repository::ChainRepository;
 use crate::redo::RedoLog;
@@ -89,6 +90,14 @@ impl<'a> Chain
         )
     }
 
+    /// Writes a checkpoint next to the redo log so that the next time this chain
+    /// is opened only the events written after the checkpoint are replayed
+    /// (this does nothing unless `checkpoint_interval` is set in the configuration)
+    pub async fn checkpoint(&'a self) -> Result<(), tokio::io::Error> {
+        let mut lock = self.inside_async.write().await;
+        lock.checkpoint(&self.inside_sync).await
+    }
+
     pub async fn sync(&'a self) -> Result<(), CommitError>
     {
         // Create the transaction
@@ -97,6 +106,7 @@ impl<'a> Chain
             transmit: true,
             events: Vec::new(),
             conversation: None,
+            unconfirmed: false,
         };
 
         // Feed the transaction into the chain
@@ -107,10 +117,28 @@ impl<'a> Chain
         Ok(())
     }
 
-    pub(crate) async fn get_pending_uploads(&self) -> Vec<MetaDelayedUpload>
+    /// Returns all the events that were written locally but have not yet been
+    /// confirmed by the server (these are kept in the staging log)
+    pub(crate) async fn staged_events(&self) -> Vec<EventData>
     {
         let guard = self.inside_async.read().await;
-        guard.chain.timeline.pointers.get_pending_uploads()
+        guard.chain.redo.staged()
+    }
+
+    /// Moves events that the server has confirmed out of the staging log and
+    /// into the main redo log
+    pub(crate) async fn confirm_staged(&self, hashes: &[AteHash]) -> Result<usize, SerializationError>
+    {
+        let mut guard = self.inside_async.write().await;
+        guard.chain.redo.confirm(hashes).await
+    }
+
+    /// Removes events that the server rejected from the staging log, they stay in the
+    /// chain until it is next opened but are never uploaded again
+    pub(crate) async fn discard_staged(&self, hashes: &[AteHash]) -> Result<usize, SerializationError>
+    {
+        let mut guard = self.inside_async.write().await;
+        guard.chain.redo.discard(hashes).await
     }
 
     pub fn repository(&self) -> Option<Arc<dyn ChainRepository>>
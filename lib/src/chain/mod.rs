@@ -6,12 +6,16 @@ mod protected_async;
 mod protected_sync;
 mod workers;
 mod compact;
+mod archive;
+mod changes;
 #[cfg(feature = "rotate")]
 mod rotate;
 
 pub use self::core::*;
 pub use new::*;
 pub use compact::*;
+pub use archive::*;
+pub use changes::*;
 pub(crate) use listener::*;
 pub(crate) use protected_async::*;
 pub(crate) use protected_sync::*;
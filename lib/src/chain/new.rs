@@ -1,9 +1,11 @@
 #[allow(unused_imports)]
-use log::{info, error, debug};
+use log::{info, error, warn, debug};
 
 use multimap::MultiMap;
 use btreemultimap::BTreeMultiMap;
+use std::collections::BTreeMap;
 use tokio::sync::broadcast;
+use tokio::sync::watch;
 
 use crate::error::*;
 use crate::conf::*;
@@ -13,7 +15,7 @@ use crate::compact::*;
 
 use std::sync::{Arc};
 use parking_lot::Mutex as StdMutex;
-use fxhash::{FxHashSet};
+use fxhash::{FxHashSet, FxHashMap};
 use tokio::sync::RwLock;
 use parking_lot::RwLock as StdRwLock;
 use tokio::sync::mpsc;
@@ -24,6 +26,7 @@ use crate::time::TimeKeeper;
 use crate::trust::*;
 use crate::pipe::*;
 use crate::loader::*;
+use crate::unique::*;
 
 use crate::trust::ChainKey;
 
@@ -105,7 +108,10 @@ impl<'a> Chain
         }
         
         // Join the redo log thread earlier after the events were successfully streamed in
-        let redo_log = redo_log.await.unwrap()?;
+        let mut redo_log = redo_log.await.unwrap()?;
+
+        // If the redo log was opened from a checkpoint then the events it covers do not need to be processed
+        let checkpoint = redo_log.take_checkpoint();
 
         // Construnct the chain-of-trust on top of the redo-log
         let chain = ChainOfTrust {
@@ -115,6 +121,9 @@ impl<'a> Chain
                 history: BTreeMultiMap::new(),
                 pointers: BinaryTreeIndexer::default(),
                 compactors: builder.compactors,
+                arrivals: BTreeMap::new(),
+                arrival_of: FxHashMap::default(),
+                generation: 0,
             },
         };
 
@@ -129,11 +138,28 @@ impl<'a> Chain
             transformers: builder.transformers,
             listeners: MultiMap::new(),
             services: Vec::new(),
+            secondary_indexes: FxHashMap::default(),
+            unique_constraints: builder.unique_constraints.clone(),
+            track_types: builder.track_types,
             repository: None,
             default_session: builder.session,
             integrity: builder.integrity,
+            idempotency_window: builder.cfg.idempotency_window,
         };
 
+        // Each secondary index gets its own state that is kept up to date by an indexer
+        for index in builder.secondary_indexes.iter() {
+            let index = Arc::new(index.fresh());
+            inside_sync.indexers.push(Box::new(SecondaryIndexer::new(&index)));
+            inside_sync.secondary_indexes.insert(index.name.clone(), index);
+        }
+
+        // Unique constraints are enforced by a plugin while the writers use the
+        // declarations to attach the values they claim to their events
+        if builder.unique_constraints.len() > 0 {
+            inside_sync.plugins.push(Box::new(UniqueConstraintPlugin::new(builder.unique_constraints.clone(), builder.cfg.sync_tolerance)));
+        }
+
         // Add a tree authority plug if one is in the builder
         if let Some(tree) = builder.tree {
             inside_sync.plugins.push(Box::new(tree));
@@ -151,12 +177,20 @@ impl<'a> Chain
 
         // The asynchronous critical section protects the chain-of-trust itself and
         // will have longer waits on it when there are writes occuring
+        let (changed_tx, changed_rx) = watch::channel(());
         let mut inside_async = ChainProtectedAsync {
             chain,
             default_format: builder.cfg.log_format,
             disable_new_roots: false,
             sync_tolerance: builder.cfg.sync_tolerance,
+            #[cfg(feature = "local_fs")]
+            checkpoint_interval: builder.cfg.checkpoint_interval,
+            #[cfg(not(feature = "local_fs"))]
+            checkpoint_interval: None,
+            checkpoint_pending: 0,
             exit: exit_tx.clone(),
+            changed: changed_tx,
+            changed_rx,
         };
 
         // Check all the process events
@@ -168,14 +202,33 @@ impl<'a> Chain
             }
         }
         
-        // Process all the events in the chain-of-trust
+        // Restore the events covered by the checkpoint (only the events after it are validated)
         let conversation = Arc::new(ConversationSession::new(true));
+        let uploads = match checkpoint {
+            Some(mut checkpoint) => {
+                let mut restored = Vec::with_capacity(checkpoint.headers.len());
+                for header in checkpoint.headers.drain(..) {
+                    restored.push(header.as_header()?);
+                }
+                let uploads = ChainProtectedAsync::pending_uploads(restored.iter().chain(headers.iter()));
+                inside_async.restore(&mut inside_sync.write(), restored, checkpoint, &conversation);
+                uploads
+            },
+            None => ChainProtectedAsync::pending_uploads(headers.iter()),
+        };
+
+        // Process all the events in the chain-of-trust
         if let Err(err) = inside_async.process(inside_sync.write(), headers, Some(&conversation)) {
             if allow_process_errors == false {
                 return Err(err);
             }
         }
         
+        // Events that older versions never uploaded are moved into the staging log
+        if let Err(err) = inside_async.migrate_uploads(&inside_sync, uploads).await {
+            warn!("failed to migrate the delayed uploads - {}", err);
+        }
+        
         // Create the compaction state (which later we will pass to the compaction thread)
         let (compact_tx, compact_rx) = CompactState::new(compact_mode, inside_async.chain.redo.size() as u64);
 
@@ -192,7 +245,7 @@ impl<'a> Chain
         let worker_exit = exit_tx.subscribe();
         let worker_inside_async = Arc::clone(&inside_async);
         let worker_inside_sync = Arc::clone(&inside_sync);
-        tokio::task::spawn(Chain::worker_receiver(worker_inside_async, worker_inside_sync, receiver, compact_tx, worker_exit));
+        tokio::task::spawn(Chain::worker_receiver(worker_inside_async, worker_inside_sync, receiver, compact_tx, builder.cfg.durability, worker_exit));
 
         // The inbox pipe intercepts requests to and processes them
         let mut pipe: Arc<Box<dyn EventPipe>> = Arc::new(Box::new(InboxPipe {
@@ -207,6 +260,10 @@ impl<'a> Chain
         let tolerance = builder.configured_for.ntp_tolerance();
         let time = Arc::new(TimeKeeper::new(&builder.cfg, tolerance).await?);
 
+        // Any delayed children that became due while the chain was closed are delivered
+        // straight away, the rest stay in the timer wheel until their time comes
+        inside_async.write().await.chain.release_delayed(time.current_timestamp()?);
+
         // Create the chain that will be returned to the caller
         let chain = Chain {
             key: key.clone(),
@@ -238,6 +295,15 @@ impl<'a> Chain
             debug!("compact-mode-off: {}", builder.cfg.compact_mode);
         }
 
+        // Start the worker that delivers the delayed children when they become due
+        {
+            let worker_exit = exit_tx.subscribe();
+            let worker_inside_async = Arc::clone(&chain.inside_async);
+            let worker_inside_sync = Arc::clone(&chain.inside_sync);
+            let time = Arc::clone(&chain.time);
+            tokio::task::spawn(Chain::worker_delivery(worker_inside_async, worker_inside_sync, time, worker_exit));
+        }
+
         // Create the chain
         Ok(
             chain
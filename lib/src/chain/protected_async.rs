@@ -1,20 +1,24 @@
 #[allow(unused_imports)]
-use log::{info, error, debug};
+use log::{info, error, warn, debug};
 
 use crate::redo::LogWritable;
+use crate::redo::ChainCheckpoint;
 use crate::error::*;
 use crate::event::*;
 use crate::transaction::*;
+use crate::crypto::AteHash;
+use crate::meta::*;
 
 use std::sync::{Arc};
+use fxhash::FxHashMap;
 use parking_lot::RwLock as StdRwLock;
 use parking_lot::RwLockWriteGuard as StdRwLockWriteGuard;
 use std::ops::*;
 use std::time::Duration;
 use tokio::sync::broadcast;
+use tokio::sync::watch;
 
 use crate::trust::*;
-use crate::meta::*;
 use crate::spec::*;
 use crate::time::*;
 
@@ -26,7 +30,11 @@ pub(crate) struct ChainProtectedAsync
     pub(crate) default_format: MessageFormat,
     pub(crate) disable_new_roots: bool,
     pub(crate) sync_tolerance: Duration,
+    pub(crate) checkpoint_interval: Option<usize>,
+    pub(crate) checkpoint_pending: usize,
     pub(crate) exit: broadcast::Sender<()>,
+    pub(crate) changed: watch::Sender<()>,
+    pub(crate) changed_rx: watch::Receiver<()>,
 }
 
 impl ChainProtectedAsync
@@ -61,25 +69,169 @@ impl ChainProtectedAsync
         }
     }
 
-    pub(crate) async fn feed_meta_data(&mut self, sync: &Arc<StdRwLock<ChainProtectedSync>>, meta: Metadata)
-        -> Result<Vec<EventHeader>, CommitError>
+    /// Restores the state of the chain from a checkpoint that was taken earlier, plugins
+    /// that support snapshots skip the replay entirely. The events that the checkpoint
+    /// covers are not validated again as the redo log already checked that the part of
+    /// the log it covers has not changed since it was taken (only the tail is validated)
+    pub(super) fn restore(&mut self, sync: &mut ChainProtectedSync, headers: Vec<EventHeader>, checkpoint: ChainCheckpoint, conversation: &Arc<ConversationSession>)
+    {
+        let mut replay_plugins = Vec::new();
+        for (n, plugin) in sync.plugins.iter_mut().enumerate() {
+            let restored = match checkpoint.plugins.get(n) {
+                Some((name, Some(snapshot))) if name.as_str() == plugin.validator_name() => plugin.restore(&snapshot[..]).is_ok(),
+                _ => false,
+            };
+            if restored == false {
+                debug!("checkpoint-replay: plugin({})", plugin.validator_name());
+                plugin.reset();
+                replay_plugins.push(n);
+            }
+        }
+
+        for header in headers.iter() {
+            for n in replay_plugins.iter() {
+                let _ = sync.plugins[*n].feed(header, Some(conversation));
+            }
+        }
+
+        // Indexers have no name so their snapshots are only used if they line up exactly
+        let indexers_match = checkpoint.indexers.len() == sync.indexers.len();
+        let mut replay_indexers = Vec::new();
+        for (n, indexer) in sync.indexers.iter_mut().enumerate() {
+            let restored = match checkpoint.indexers.get(n) {
+                Some(Some(snapshot)) if indexers_match => indexer.restore(&snapshot[..]).is_ok(),
+                _ => false,
+            };
+            if restored == false {
+                indexer.reset();
+                replay_indexers.push(n);
+            }
+        }
+
+        for header in headers.iter() {
+            for n in replay_indexers.iter() {
+                let _ = sync.indexers[*n].feed(header, Some(conversation));
+            }
+            self.chain.add_history(header);
+        }
+    }
+
+    /// Returns the delayed upload ranges that older versions recorded but never completed
+    pub(super) fn pending_uploads<'b>(headers: impl Iterator<Item=&'b EventHeader>) -> Vec<MetaDelayedUpload>
+    {
+        let mut uploads = FxHashMap::default();
+        for header in headers {
+            for core in header.meta.core.iter() {
+                if let CoreMetadata::DelayedUpload(upload) = core {
+                    if upload.complete || uploads.contains_key(&upload.from) == false {
+                        uploads.insert(upload.from, upload.clone());
+                    }
+                }
+            }
+        }
+        uploads.into_iter()
+            .map(|(_, upload)| upload)
+            .filter(|upload| upload.complete == false)
+            .collect()
+    }
+
+    /// Older versions wrote unconfirmed events straight into the redo log and marked them
+    /// with delayed upload markers, the events in any range that was never completed are
+    /// moved into the staging log so that they are uploaded like every other unconfirmed
+    /// event (the range is then marked as complete so this only happens once)
+    pub(super) async fn migrate_uploads(&mut self, sync: &Arc<StdRwLock<ChainProtectedSync>>, uploads: Vec<MetaDelayedUpload>) -> Result<(), CommitError>
     {
-        let data = EventData {
-            meta,
-            data_bytes: None,
-            format: MessageFormat {
-                meta: SerializationFormat::Json,
-                data: SerializationFormat::Json,
-            },
+        if uploads.len() <= 0 {
+            return Ok(());
+        }
+
+        for upload in uploads.iter() {
+            let hashes = self.chain.timeline.history
+                .range(upload.from..=upload.to)
+                .map(|(_, h)| h.event_hash)
+                .collect::<Vec<_>>();
+            info!("migrating {} events from delayed upload [{}..{}] to the staging log", hashes.len(), upload.from, upload.to);
+            for hash in hashes {
+                match self.chain.redo.load(hash).await {
+                    Ok(data) => self.chain.redo.migrate(&data.data).await?,
+                    Err(err) => warn!("failed to migrate delayed upload event {} - {}", hash, err),
+                };
+            }
+        }
+        self.chain.redo.sync().await?;
+
+        let markers = uploads.into_iter()
+            .map(|upload| EventData {
+                meta: Metadata {
+                    core: vec![CoreMetadata::DelayedUpload(MetaDelayedUpload {
+                        complete: true,
+                        from: upload.from,
+                        to: upload.to,
+                    })]
+                },
+                data_bytes: None,
+                format: MessageFormat {
+                    meta: SerializationFormat::Json,
+                    data: SerializationFormat::Json,
+                },
+            })
+            .collect::<Vec<_>>();
+        self.feed_async_internal(sync, &markers, None, false, false).await?;
+        self.chain.redo.flush().await?;
+        Ok(())
+    }
+
+    /// Writes a checkpoint of the chain next to its redo log
+    pub(crate) async fn checkpoint(&mut self, sync: &Arc<StdRwLock<ChainProtectedSync>>) -> Result<(), tokio::io::Error>
+    {
+        let state = {
+            let sync = sync.read();
+            ChainCheckpoint {
+                events: 0,
+                plugins: sync.plugins.iter().map(|p| (p.validator_name().to_string(), p.snapshot())).collect(),
+                indexers: sync.indexers.iter().map(|i| i.snapshot()).collect(),
+                headers: Vec::new(),
+            }
         };
-        let evts = vec![data];
+        self.chain.redo.checkpoint(state).await?;
+        self.checkpoint_pending = 0;
+        Ok(())
+    }
 
-        self.feed_async_internal(sync, &evts, None).await
+    /// Returns true if enough events have been written since the last checkpoint
+    pub(crate) fn needs_checkpoint(&self) -> bool
+    {
+        match self.checkpoint_interval {
+            Some(a) => self.checkpoint_pending >= a,
+            None => false,
+        }
     }
 
-    pub(super) async fn feed_async_internal(&mut self, sync: &Arc<StdRwLock<ChainProtectedSync>>, evts: &Vec<EventData>, conversation: Option<&Arc<ConversationSession>>)
+    pub(super) async fn feed_async_internal(&mut self, sync: &Arc<StdRwLock<ChainProtectedSync>>, evts: &Vec<EventData>, conversation: Option<&Arc<ConversationSession>>, unconfirmed: bool, fresh: bool)
         -> Result<Vec<EventHeader>, CommitError>
     {
+        // Writers that use optimistic concurrency state which version of a data object
+        // they modified, if it has since changed then the whole transaction is rejected.
+        // This is only checked for fresh commits as events that are replayed from the
+        // server (or imported) were already checked when they were first committed
+        // and the versions they refer to may since have been compacted away (new data
+        // objects expect that no version exists yet)
+        if fresh {
+            for evt in evts.iter() {
+                let key = evt.meta.get_data_key().or_else(|| evt.meta.get_tombstone());
+                if let (Some(key), Some(expected)) = (key, evt.meta.get_precondition()) {
+                    let actual = self.chain.lookup_primary(&key).map(|a| a.record);
+                    if actual.unwrap_or(AteHash::NONE) != *expected {
+                        return Err(CommitError::Conflict {
+                            key,
+                            expected: expected.clone(),
+                            actual,
+                        });
+                    }
+                }
+            }
+        }
+
         let mut errors = Vec::new();
         let mut validated_evts = Vec::new();
         {
@@ -114,10 +266,19 @@ impl ChainProtectedAsync
 
         let mut ret = Vec::new();
         for (evt, header) in validated_evts.into_iter() {
-            let _lookup = self.chain.redo.write(evt).await?;
+            match unconfirmed {
+                true => self.chain.redo.stage(evt).await?,
+                false => {
+                    let _lookup = self.chain.redo.write(evt).await?;
+                    self.checkpoint_pending = self.checkpoint_pending + 1;
+                }
+            };
             self.chain.add_history(&header);
             ret.push(header);
         }
+        if ret.len() > 0 {
+            let _ = self.changed.send(());
+        }
 
         if errors.len() > 0 {
             return Err(CommitError::ValidationError(errors));
@@ -132,6 +293,7 @@ impl ChainProtectedAsync
         self.chain.timeline.history.range(range)
     }
 
+    #[allow(dead_code)]
     pub fn range_keys<'a, R>(&'a self, range: R) -> impl DoubleEndedIterator<Item = ChainTimestamp> + 'a
     where R: RangeBounds<ChainTimestamp>
     {
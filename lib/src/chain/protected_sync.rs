@@ -2,6 +2,7 @@
 use log::{info, error, debug};
 
 use multimap::MultiMap;
+use fxhash::FxHashMap;
 
 use crate::plugin::*;
 use crate::error::*;
@@ -11,6 +12,7 @@ use crate::validator::*;
 use crate::transaction::*;
 
 use std::sync::{Arc, Weak};
+use std::time::Duration;
 use parking_lot::RwLockReadGuard as StdRwLockReadGuard;
 
 use crate::trust::*;
@@ -20,6 +22,7 @@ use crate::meta::*;
 use crate::service::*;
 use crate::session::AteSession;
 use crate::repository::ChainRepository;
+use crate::unique::UniqueConstraint;
 
 use super::*;
 
@@ -35,17 +38,30 @@ pub(crate) struct ChainProtectedSync
     pub(crate) validators: Vec<Box<dyn EventValidator>>,
     pub(crate) listeners: MultiMap<MetaCollection, ChainListener>,
     pub(crate) services: Vec<Arc<dyn Service>>,
+    pub(crate) secondary_indexes: FxHashMap<String, Arc<SecondaryIndex>>,
+    pub(crate) unique_constraints: Vec<UniqueConstraint>,
+    pub(crate) track_types: bool,
     pub(crate) repository: Option<Weak<dyn ChainRepository>>,
+    pub(crate) idempotency_window: Duration,
 }
 
 impl ChainProtectedSync
 {
+    /// Determines if the events of a particular type need to record it in their metadata
+    pub(crate) fn needs_type_name(&self, type_name: &str) -> bool
+    {
+        self.track_types ||
+        self.secondary_indexes.values().any(|a| a.type_name == type_name) ||
+        self.unique_constraints.iter().any(|a| a.type_name == type_name)
+    }
+
     #[allow(dead_code)]
     pub(super) fn validate_event(&self, header: &EventHeader, conversation: Option<&Arc<ConversationSession>>) -> Result<ValidationResult, ValidationError>
     {
         let mut deny_reason = String::default();
         let mut is_deny = false;
         let mut is_allow = false;
+        let mut specific = None;
 
         for validator in self.validators.iter() {
             match validator.validate(header, conversation) {
@@ -73,6 +89,10 @@ impl ChainProtectedSync
                     deny_reason.push_str("no signatures");
                     is_deny = true
                 },
+                Err(err @ ValidationError::UniqueViolation { .. }) => {
+                    specific = Some(err);
+                    is_deny = true
+                },
             }
         }
         for plugin in self.plugins.iter() {
@@ -101,10 +121,18 @@ impl ChainProtectedSync
                     deny_reason.push_str("no signatures");
                     is_deny = true
                 },
+                Err(err @ ValidationError::UniqueViolation { .. }) => {
+                    specific = Some(err);
+                    is_deny = true
+                },
             }
         }
 
         if is_deny == true {
+            // Errors that the writer can act upon are returned as they are
+            if let Some(err) = specific {
+                return Err(err);
+            }
             return Err(ValidationError::Denied(deny_reason))
         }
         if is_allow == false {
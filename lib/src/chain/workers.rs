@@ -7,6 +7,7 @@ use crate::compact::*;
 use crate::error::*;
 use crate::pipe::*;
 use crate::time::*;
+use crate::redo::DurabilityPolicy;
 
 use parking_lot::RwLock as StdRwLock;
 use std::sync::Arc;
@@ -14,6 +15,11 @@ use tokio::sync::RwLock;
 use tokio::sync::mpsc;
 use tokio::sync::broadcast;
 use tokio::select;
+use tokio::time::Instant;
+use std::time::Duration;
+
+/// How often the delivery worker checks for delayed children that have become due
+const DELIVERY_TICK: Duration = Duration::from_millis(100);
 
 use super::*;
 
@@ -35,12 +41,52 @@ for ChainExitNotifier {
     }
 }
 
+/// Transactions that have been written to the redo log but are waiting for
+/// a shared fsync before they are confirmed to the caller
+#[derive(Default)]
+struct GroupCommit
+{
+    pending: Vec<Option<mpsc::Sender<Result<(), CommitError>>>>,
+    deadline: Option<Instant>,
+}
+
+impl GroupCommit
+{
+    fn push(&mut self, notify: Option<mpsc::Sender<Result<(), CommitError>>>, max_delay: std::time::Duration) {
+        if self.deadline.is_none() {
+            self.deadline = Some(Instant::now() + max_delay);
+        }
+        self.pending.push(notify);
+    }
+
+    fn is_pending(&self) -> bool {
+        self.pending.len() > 0
+    }
+
+    async fn commit(&mut self, inside_async: &Arc<RwLock<ChainProtectedAsync>>)
+    {
+        // One fsync makes all the transactions in the batch durable
+        let result = inside_async.write().await.chain.sync().await;
+        self.deadline = None;
+        for notify in self.pending.drain(..) {
+            if let Some(notify) = notify {
+                let result = match &result {
+                    Ok(_) => Ok(()),
+                    Err(err) => Err(CommitError::IO(tokio::io::Error::new(err.kind(), err.to_string()))),
+                };
+                let _ = notify.send(result).await;
+            }
+        }
+    }
+}
+
 impl<'a> Chain
 {
-    pub(super) async fn worker_receiver(inside_async: Arc<RwLock<ChainProtectedAsync>>, inside_sync: Arc<StdRwLock<ChainProtectedSync>>, mut receiver: mpsc::Receiver<ChainWork>, compact_tx: CompactNotifications, mut exit: broadcast::Receiver<()>)
+    pub(super) async fn worker_receiver(inside_async: Arc<RwLock<ChainProtectedAsync>>, inside_sync: Arc<StdRwLock<ChainProtectedSync>>, mut receiver: mpsc::Receiver<ChainWork>, compact_tx: CompactNotifications, durability: DurabilityPolicy, mut exit: broadcast::Receiver<()>)
     {
         // When the worker thread exits it should trigger the broadcast
         let _exit = ChainExitNotifier { exit: inside_async.write().await.exit.clone() };
+        let mut group = GroupCommit::default();
 
         // Wait for the next transaction to be processed
         loop
@@ -48,6 +94,10 @@ impl<'a> Chain
             // Wait for the exit command or for some data to be received
             let work: ChainWork = select! {
                 _ = exit.recv() => { break; },
+                _ = tokio::time::sleep_until(group.deadline.unwrap_or_else(Instant::now)), if group.is_pending() => {
+                    group.commit(&inside_async).await;
+                    continue;
+                },
                 work = receiver.recv() => {
                     match work {
                         Some(a) => a,
@@ -58,17 +108,20 @@ impl<'a> Chain
 
             // Extract the variables
             let trans = work.trans;
-            let work_notify = work.notify;
+            let mut work_notify = work.notify;
 
             // Check all the sniffers
             let notifies = crate::service::callback_events_prepare(&inside_sync.read(), &trans.events);
 
+            // Commits are fresh if they were written here or a client is committing them to this server
+            let fresh = trans.transmit || trans.conversation.as_ref().map_or(false, |c| c.other_end_is_server == false);
+
             // We lock the chain of trust while we update the local chain
             let mut lock = inside_async.write().await;
 
             // Push the events into the chain of trust and release the lock on it before
             // we transmit the result so that there is less lock thrashing
-            let result = match lock.feed_async_internal(&inside_sync, &trans.events, trans.conversation.as_ref()).await {
+            let result = match lock.feed_async_internal(&inside_sync, &trans.events, trans.conversation.as_ref(), trans.unconfirmed, fresh).await {
                 Ok(_) => {
                     let log_size = lock.chain.redo.size() as u64;
                     let _ = compact_tx.log_size.send(log_size);
@@ -77,18 +130,55 @@ impl<'a> Chain
                 Err(err) => Err(err),
             };
 
-            // If the scope requires it then we flush
-            let late_flush = match trans.scope {
-                TransactionScope::Full => {
-                    lock.chain.flush().await.unwrap();
+            // Children that are not yet due are announced by the delivery worker instead
+            let delivered = match trans.events.iter().any(|a| a.meta.get_deliver_at().is_some()) {
+                true => Some(trans.events.iter()
+                    .filter(|a| match a.meta.get_data_key() {
+                        Some(key) => lock.chain.is_delayed(&key) == false,
+                        None => true,
+                    })
+                    .map(|a| a.clone())
+                    .collect::<Vec<_>>()),
+                false => None,
+            };
+
+            // If the scope requires it then the bytes are made durable according to the
+            // durability policy before the caller is notified
+            let mut group_full = false;
+            let late_flush = match (trans.scope, &result) {
+                (TransactionScope::None, _) | (_, Err(_)) => true,
+                (_, Ok(_)) => {
+                    let ret = match durability {
+                        DurabilityPolicy::Always => lock.chain.sync().await,
+                        DurabilityPolicy::OsBuffered => lock.chain.flush().await,
+                        DurabilityPolicy::GroupCommit { max_delay, max_batch } => {
+                            group.push(work_notify.take(), max_delay);
+                            group_full = group.pending.len() >= max_batch;
+                            lock.chain.flush().await
+                        }
+                    };
+                    if let Err(err) = ret {
+                        error!("flush-failed: {}", err);
+                    }
                     false
-                },
-                _ => true
+                }
             };
 
+            // If enough events have been written then take a checkpoint
+            if lock.needs_checkpoint() {
+                if let Err(err) = lock.checkpoint(&inside_sync).await {
+                    error!("checkpoint-failed: {}", err);
+                }
+            }
+
             // Drop the lock
             drop(lock);
 
+            // If the batch is full then the shared fsync happens straight away
+            if group_full {
+                group.commit(&inside_async).await;
+            }
+
             // We send the result of a feed operation back to the caller, if the send
             // operation fails its most likely because the caller has moved on and is
             // not concerned by the result hence we do nothing with these errors
@@ -97,7 +187,7 @@ impl<'a> Chain
             }
             {
                 let lock = inside_sync.read();
-                ChainProtectedSync::notify(&lock, &trans.events);
+                ChainProtectedSync::notify(&lock, delivered.as_ref().unwrap_or(&trans.events));
             }
 
             // Notify all the sniffers
@@ -118,6 +208,58 @@ impl<'a> Chain
             // Yield so the other async events get time
             tokio::task::yield_now().await;
         }
+
+        // Anything still waiting on a group commit must be made durable before we exit
+        if group.is_pending() {
+            group.commit(&inside_async).await;
+        }
+    }
+
+    pub(super) async fn worker_delivery(inside_async: Arc<RwLock<ChainProtectedAsync>>, inside_sync: Arc<StdRwLock<ChainProtectedSync>>, time: Arc<TimeKeeper>, mut exit: broadcast::Receiver<()>)
+    {
+        loop {
+            select! {
+                _ = exit.recv() => { break; },
+                _ = tokio::time::sleep(DELIVERY_TICK) => { }
+            }
+
+            // Only take the write lock when there is something waiting to be delivered
+            if inside_async.read().await.chain.has_delayed() == false {
+                continue;
+            }
+            let now = match time.current_timestamp() {
+                Ok(a) => a,
+                Err(err) => {
+                    debug!("delivery-err - {}", err);
+                    continue;
+                }
+            };
+
+            // Move the children that are due into their collections
+            let leafs = {
+                let mut lock = inside_async.write().await;
+                lock.chain.release_delayed(now)
+                    .into_iter()
+                    .filter_map(|a| lock.chain.lookup_primary(&a))
+                    .collect::<Vec<_>>()
+            };
+            if leafs.len() <= 0 {
+                continue;
+            }
+
+            // Then load their events (without blocking writers) so that anyone listening
+            // on a BUS is notified
+            let evts = match inside_async.read().await.chain.load_many(leafs).await {
+                Ok(a) => a.into_iter().map(|a| a.data).collect::<Vec<_>>(),
+                Err(err) => {
+                    error!("delivery-failed: {}", err);
+                    continue;
+                }
+            };
+
+            let lock = inside_sync.read();
+            ChainProtectedSync::notify(&lock, &evts);
+        }
     }
 
     pub(super) async fn worker_compactor(inside_async: Arc<RwLock<ChainProtectedAsync>>, inside_sync: Arc<StdRwLock<ChainProtectedSync>>, pipe: Arc<Box<dyn EventPipe>>, time: Arc<TimeKeeper>, mut compact_state: CompactState, mut exit: broadcast::Receiver<()>) -> Result<(), CompactError>
@@ -49,6 +49,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Default + Clone + 'static,
             conf.buffer_size,
             Arc::clone(&state),
             conf.wire_encryption,
+            conf.max_frame_size,
             conf.connect_timeout
         ).await?;
         wire_format = upstream.wire_format;
@@ -85,6 +86,7 @@ pub(super) async fn mesh_connect_to<M, C>
     buffer_size: usize,
     state: Arc<StdMutex<NodeState>>,
     wire_encryption: Option<KeySize>,
+    max_frame_size: usize,
     timeout: Duration,
 )
 -> Result<Upstream, CommsError>
@@ -112,6 +114,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
         on_connect,
         state,
         wire_encryption,
+        max_frame_size,
     );
     let worker_connect = tokio::time::timeout(timeout, worker_connect).await??;
     let wire_format = worker_connect.wire_format;
@@ -143,6 +146,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
     stream: TcpStream,
     wire_encryption: Option<KeySize>,
     wire_format: SerializationFormat,
+    max_frame_size: usize,
 }
 
 async fn mesh_connect_prepare<M, C>
@@ -157,6 +161,7 @@ async fn mesh_connect_prepare<M, C>
     on_connect: Option<M>,
     state: Arc<StdMutex<NodeState>>,
     wire_encryption: Option<KeySize>,
+    max_frame_size: usize,
 )
 -> Result<MeshConnectContext<M, C>, CommsError>
 where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
@@ -205,6 +210,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
             stream,
             wire_encryption,
             wire_format,
+            max_frame_size,
         });
     }
 }
@@ -224,6 +230,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
     let inbox = connect.inbox;
     let sender = connect.sender;
     let on_connect = connect.on_connect;
+    let max_frame_size = connect.max_frame_size;
     let state = connect.state;
     let mut stream = connect.stream;
     let wire_encryption = connect.wire_encryption;
@@ -266,7 +273,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
     let worker_terminate_tx = terminate_tx.clone();
     let worker_terminate_rx = terminate_tx.subscribe();
     let join1 = tokio::spawn(async move {
-        match process_inbox::<M, C>(rx, reply_tx1, worker_inbox, sender, worker_context, wire_format, ek2, worker_terminate_rx).await {
+        match process_inbox::<M, C>(rx, reply_tx1, worker_inbox, sender, worker_context, wire_format, ek2, max_frame_size, worker_terminate_rx).await {
             Ok(_) => { },
             Err(CommsError::IO(err)) if match err.kind() {
                 std::io::ErrorKind::UnexpectedEof => true,
@@ -58,6 +58,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone
     pub buffer_size: usize,
     pub wire_format: SerializationFormat,
     pub wire_encryption: Option<KeySize>,
+    pub max_frame_size: usize,
 }
 
 impl<M> NodeConfig<M>
@@ -72,6 +73,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone
             buffer_size: 1,
             wire_format,
             wire_encryption: None,
+            max_frame_size: usize::MAX,
         }
     }
 
@@ -100,6 +102,11 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone
         self
     }
 
+    pub(crate) fn max_frame_size(mut self, max_frame_size: usize) -> Self {
+        self.max_frame_size = max_frame_size;
+        self
+    }
+
     pub(crate) fn timeout(mut self, timeout: Duration) -> Self {
         self.connect_timeout = timeout;
         self
@@ -25,6 +25,15 @@ pub(super) fn setup_tcp_stream(stream: &TcpStream) -> io::Result<()> {
     Ok(())
 }
 
+/// Frames are bounded before any memory is allocated for them so that a corrupt
+/// or malicious length prefix can not exhaust the memory
+fn check_frame_size(size: usize, max_frame_size: usize) -> Result<(), SerializationError> {
+    if size > max_frame_size {
+        return Err(SerializationError::FrameTooLarge { size: size as u64, limit: max_frame_size as u64 });
+    }
+    Ok(())
+}
+
 #[allow(unused_variables)]
 pub(super) async fn process_inbox<M, C>(
     mut rx: tcp::OwnedReadHalf,
@@ -34,6 +43,7 @@ pub(super) async fn process_inbox<M, C>(
     context: Arc<C>,
     wire_format: SerializationFormat,
     wire_encryption: Option<EncryptKey>,
+    max_frame_size: usize,
     terminate: tokio::sync::broadcast::Receiver<bool>
 ) -> Result<(), CommsError>
 where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default,
@@ -52,6 +62,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default,
 
                 // Read the cipher text
                 let cipher_len = rx.read_u32().await? as usize;
+                check_frame_size(cipher_len, max_frame_size)?;
                 let mut cipher_bytes = vec![0 as u8; cipher_len];
                 let n = rx.read_exact(&mut cipher_bytes[0..cipher_len]).await?;
                 if n == 0 { break; }
@@ -62,6 +73,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default,
             None => {
                 // Read the next message
                 let buf_len = rx.read_u32().await? as usize;
+                check_frame_size(buf_len, max_frame_size)?;
                 let mut buf = vec![0 as u8; buf_len];
                 let n = rx.read_exact(&mut buf[0..buf_len]).await?;
                 if n == 0 { break; }
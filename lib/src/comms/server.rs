@@ -45,6 +45,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Default + Clone + 'static,
             Arc::clone(&state),
             conf.wire_format,
             conf.wire_encryption,
+            conf.max_frame_size,
         ).await;
     }
 
@@ -71,6 +72,7 @@ pub(super) async fn listen_on<M, C>(addr: SocketAddr,
                            state: Arc<StdMutex<NodeState>>,
                            wire_format: SerializationFormat,
                            wire_encryption: Option<KeySize>,
+                           max_frame_size: usize,
                         )
 where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
       C: Send + Sync + BroadcastContext + Default + 'static,
@@ -142,7 +144,7 @@ where M: Send + Sync + Serialize + DeserializeOwned + Clone + Default + 'static,
             let worker_terminate_tx = terminate_tx.clone();
             let worker_terminate_rx = terminate_tx.subscribe();
             tokio::spawn(async move {
-                match process_inbox::<M, C>(rx, reply_tx1, worker_inbox, sender, worker_context, wire_format, ek1, worker_terminate_rx).await {
+                match process_inbox::<M, C>(rx, reply_tx1, worker_inbox, sender, worker_context, wire_format, ek1, max_frame_size, worker_terminate_rx).await {
                     Ok(_) => { },
                     Err(CommsError::IO(err)) if err.kind() == std::io::ErrorKind::UnexpectedEof => { },
                     Err(err) => {
@@ -0,0 +1,86 @@
+use fxhash::{FxHashMap, FxHashSet};
+
+use crate::header::*;
+use crate::event::*;
+
+use super::*;
+
+/// Maximum depth of a tree that will be walked looking for a deleted ancestor
+const MAX_TREE_DEPTH: usize = 1000;
+
+/// Sweeps up the data objects that have become orphans because one of their
+/// ancestors (found by following the `MetaParent` of each object) was deleted
+/// and hence they can no longer be reached from the tree.
+///
+/// Chains that use cascading deletes will not normally create orphans however
+/// this compactor cleans up after objects that were deleted without them.
+#[derive(Default, Clone)]
+pub struct OrphanCompactor
+{
+    /// Objects whose newest event has already been fed
+    seen: FxHashSet<PrimaryKey>,
+    parents: FxHashMap<PrimaryKey, Option<PrimaryKey>>,
+    tombstoned: FxHashSet<PrimaryKey>,
+}
+
+impl OrphanCompactor
+{
+    fn is_orphan(&self, key: &PrimaryKey) -> bool
+    {
+        let mut key = key.clone();
+        for _ in 0..MAX_TREE_DEPTH {
+            key = match self.parents.get(&key) {
+                Some(Some(a)) => a.clone(),
+                _ => { return false; }
+            };
+            if self.tombstoned.contains(&key) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+impl EventCompactor
+for OrphanCompactor
+{
+    fn clone_compactor(&self) -> Option<Box<dyn EventCompactor>> {
+        Some(Box::new(Self::default()))
+    }
+
+    fn relevance(&self, header: &EventHeader) -> EventRelevance
+    {
+        let key = match header.meta.get_data_key() {
+            Some(key) => key,
+            None => { return EventRelevance::Abstain; }
+        };
+
+        match self.is_orphan(&key) {
+            true => EventRelevance::ForceDrop,
+            false => EventRelevance::Abstain,
+        }
+    }
+
+    fn feed(&mut self, header: &EventHeader, _keep: bool) {
+        // Events are fed from the newest to the oldest (and may be fed more than once)
+        // thus only the first event that is seen for an object decides whether it was
+        // deleted and where it currently lives, as objects can be created again after
+        // they were deleted
+        if let Some(key) = header.meta.get_tombstone() {
+            if self.seen.insert(key.clone()) {
+                self.tombstoned.insert(key);
+            }
+        } else if let Some(key) = header.meta.get_data_key() {
+            if self.seen.insert(key.clone()) {
+                let parent = header.meta.get_parent()
+                    .map(|a| a.vec.parent_id.clone())
+                    .filter(|a| *a != key);
+                self.parents.insert(key, parent);
+            }
+        }
+    }
+
+    fn name(&self) -> &str {
+        "orphan-compactor"
+    }
+}
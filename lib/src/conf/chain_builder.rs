@@ -3,9 +3,14 @@ use log::{info, error, debug};
 use async_trait::async_trait;
 use std::sync::Arc;
 use url::Url;
+use serde::{Serialize, de::DeserializeOwned};
+
+use tokio::io::AsyncRead;
 
 use crate::anti_replay::AntiReplayPlugin;
+use crate::unique::UniqueConstraint;
 use crate::chain::Chain;
+use crate::chain::ChainArchiveReader;
 use crate::time::TimestampEnforcer;
 use crate::tree::TreeAuthorityPlugin;
 use crate::validator::*;
@@ -18,8 +23,10 @@ use crate::trust::ChainKey;
 use crate::trust::IntegrityMode;
 use crate::crypto::PublicSignKey;
 use crate::crypto::KeySize;
+use crate::spec::SerializationFormat;
 use crate::error::*;
 use crate::pipe::*;
+use crate::transaction::*;
 use crate::session::AteSession;
 use crate::repository::ChainRepository;
 
@@ -37,11 +44,14 @@ pub struct ChainBuilder
     pub(crate) linters: Vec<Box<dyn EventMetadataLinter>>,
     pub(crate) transformers: Vec<Box<dyn EventDataTransformer>>,
     pub(crate) indexers: Vec<Box<dyn EventIndexer>>,
+    pub(crate) secondary_indexes: Vec<SecondaryIndex>,
+    pub(crate) unique_constraints: Vec<UniqueConstraint>,
     pub(crate) plugins: Vec<Box<dyn EventPlugin>>,
     pub(crate) pipes: Option<Arc<Box<dyn EventPipe>>>,
     pub(crate) tree: Option<TreeAuthorityPlugin>,
     pub(crate) truncate: bool,
     pub(crate) temporal: bool,
+    pub(crate) track_types: bool,
     pub(crate) integrity: IntegrityMode,
     pub(crate) session: AteSession,
 }
@@ -58,12 +68,15 @@ for ChainBuilder
             linters: self.linters.iter().map(|a| a.clone_linter()).collect::<Vec<_>>(),
             transformers: self.transformers.iter().map(|a| a.clone_transformer()).collect::<Vec<_>>(),
             indexers: self.indexers.iter().map(|a| a.clone_indexer()).collect::<Vec<_>>(),
+            secondary_indexes: self.secondary_indexes.iter().map(|a| a.fresh()).collect::<Vec<_>>(),
+            unique_constraints: self.unique_constraints.clone(),
             plugins: self.plugins.iter().map(|a| a.clone_plugin()).collect::<Vec<_>>(),
             pipes: self.pipes.clone(),
             tree: self.tree.clone(),
             session: self.session.clone(),
             truncate: self.truncate,
             temporal: self.temporal,
+            track_types: self.track_types,
             integrity: self.integrity,
         }
     }
@@ -78,6 +91,8 @@ impl ChainBuilder
             configured_for: cfg.configured_for.clone(),
             validators: Vec::new(),
             indexers: Vec::new(),
+            secondary_indexes: Vec::new(),
+            unique_constraints: Vec::new(),
             compactors: Vec::new(),
             linters: Vec::new(),
             transformers: Vec::new(),
@@ -87,6 +102,7 @@ impl ChainBuilder
             session: AteSession::new(&cfg),
             truncate: false,
             temporal: false,
+            track_types: false,
             integrity: IntegrityMode::Distributed,
         }
         .with_defaults()
@@ -97,6 +113,8 @@ impl ChainBuilder
     pub async fn with_defaults(mut self) -> Self {
         self.validators.clear();
         self.indexers.clear();
+        self.secondary_indexes.clear();
+        self.unique_constraints.clear();
         self.linters.clear();
         self.transformers.clear();
         self.plugins.clear();
@@ -147,6 +165,8 @@ impl ChainBuilder
     pub fn without_defaults(mut self) -> Self {
         self.validators.clear();
         self.indexers.clear();
+        self.secondary_indexes.clear();
+        self.unique_constraints.clear();
         self.compactors.clear();
         self.linters.clear();
         self.transformers.clear();
@@ -185,6 +205,26 @@ impl ChainBuilder
         self
     }
 
+    /// Adds a secondary index over the objects of a particular type so that they
+    /// can be found by a value extracted from them (see `Dio::query`)
+    pub fn add_index<D, K, F>(mut self, name: &str, mode: IndexMode, extractor: F) -> Self
+    where D: DeserializeOwned + 'static,
+          K: Serialize,
+          F: Fn(&D) -> K + Send + Sync + 'static,
+    {
+        self.secondary_indexes.push(SecondaryIndex::new(name, mode, extractor));
+        self
+    }
+
+    /// Adds a constraint that rejects any object of a particular type whose values
+    /// in these fields (JSON pointers such as "/email") are already used by another
+    #[allow(dead_code)]
+    pub fn add_unique_constraint<D>(mut self, name: &str, fields: &[&str]) -> Self
+    {
+        self.unique_constraints.push(UniqueConstraint::new::<D>(name, fields));
+        self
+    }
+
 
     #[allow(dead_code)]
     pub fn add_plugin(mut self, plugin: Box<dyn EventPlugin>) -> Self {
@@ -228,6 +268,15 @@ impl ChainBuilder
         self
     }
 
+    /// Records the type of every data object in its metadata so that they can be
+    /// listed by type (see `Dio::keys_by_type`), otherwise the type is only recorded
+    /// for objects that have a secondary index or unique constraint
+    #[allow(dead_code)]
+    pub fn track_types(mut self, val: bool) -> Self {
+        self.track_types = val;
+        self
+    }
+
     #[allow(dead_code)]
     pub fn integrity(mut self, mode: IntegrityMode) -> Self {
         self.integrity = mode;
@@ -269,6 +318,68 @@ impl ChainBuilder
     {
         self.open_by_key(key).await
     }
+
+    /// Imports an archive that was created with `Chain::export` into a new chain with
+    /// the same key, every event is fed through the validators of this builder so that
+    /// the chain-of-trust is checked again before it is accepted
+    pub async fn import(self: &Arc<Self>, reader: impl AsyncRead + Unpin) -> Result<Arc<Chain>, ArchiveError>
+    {
+        let mut archive = ChainArchiveReader::open(reader, self.cfg.event_limits()).await?;
+        let key = archive.header().key.clone();
+
+        // Importing into a chain that already has events would merge two histories
+        let chain = self.open_by_key(&key).await?;
+        if chain.count().await > 0 {
+            return Err(ArchiveError::ChainNotEmpty(key.to_string()));
+        }
+
+        // If this builder has no root keys of its own then the ones in the archive are used
+        {
+            let mut lock = chain.inside_sync.write();
+            if lock.plugins.iter().all(|p| p.root_keys().is_empty()) {
+                let root_keys = archive.header().root_keys.clone();
+                for plugin in lock.plugins.iter_mut() {
+                    plugin.set_root_keys(&root_keys);
+                }
+            }
+        }
+
+        // The chain header (and hence the cut-off) of the exported chain is written into
+        // the new log before any events so that it survives the import
+        {
+            let header_bytes = SerializationFormat::Json.serialize(&archive.header().chain_header)?;
+            let mut single = chain.single().await;
+            let flip = single.inside_async.chain.redo.begin_flip(header_bytes).await?;
+            single.inside_async.chain.redo.finish_flip(flip, |_, _| { }).await?;
+        }
+
+        // Feed the events into the chain in batches
+        let mut evts = Vec::new();
+        loop {
+            let evt = archive.next().await?;
+            let finished = evt.is_none();
+            if let Some(evt) = evt {
+                evts.push(evt);
+            }
+
+            if evts.len() >= 1000 || (finished && evts.len() > 0) {
+                chain.pipe.feed(Transaction {
+                    scope: TransactionScope::Local,
+                    transmit: false,
+                    events: evts.drain(..).collect(),
+                    conversation: None,
+                    unconfirmed: false,
+                }).await?;
+            }
+            if finished {
+                break;
+            }
+        }
+        chain.flush().await?;
+
+        debug!("imported {} events into {}", archive.header().events, key);
+        Ok(chain)
+    }
 }
 
 #[async_trait]
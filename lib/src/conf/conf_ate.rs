@@ -6,6 +6,9 @@ use crate::crypto::KeySize;
 use crate::spec::*;
 use crate::mesh::RecoveryMode;
 use crate::compact::CompactMode;
+use crate::redo::DurabilityPolicy;
+#[cfg(feature = "local_fs")]
+use crate::redo::ColdTier;
 
 use super::*;
 
@@ -26,6 +29,10 @@ pub struct ConfAte
     /// Compacts the redo log on bootstrapping of the program.
     pub compact_bootstrap: bool,
 
+    /// Determines when writes to the redo log are durable before transactions that
+    /// need local durability are confirmed to the caller (default=OsBuffered)
+    pub durability: DurabilityPolicy,
+
     /// Directory path that the redo logs will be stored.
     #[cfg(feature = "local_fs")]
     pub log_path: Option<String>,
@@ -54,6 +61,13 @@ pub struct ConfAte
     /// (default=30 seconds)
     pub sync_tolerance: Duration,
 
+    /// Length of time that services will remember the responses to commands that
+    /// were invoked with an idempotency key, duplicates of these commands that arrive
+    /// within this window are answered with the remembered response rather than
+    /// being processed again.
+    /// (default=10 minutes)
+    pub idempotency_window: Duration,
+
     /// Flag that indicates if encryption will be used for the underlying
     /// connections over the wire. When using a ATE's in built encryption
     /// and quantum resistant signatures it is not mandatory to use
@@ -76,6 +90,32 @@ pub struct ConfAte
     #[cfg(feature = "local_fs")]
     pub load_cache_ttl: u64,
 
+    /// Number of events that can be appended to a chain before a fresh checkpoint
+    /// is written next to its redo log. Checkpoints allow a chain to be opened
+    /// by only replaying the events written after the checkpoint was taken, while
+    /// this is set the headers of the events are also journaled next to the log.
+    /// (default=None which disables checkpoints)
+    #[cfg(feature = "local_fs")]
+    pub checkpoint_interval: Option<usize>,
+
+    /// When set the archives that are created by rotating the redo log are moved
+    /// into compressed cold storage once they are older than a number of rotations,
+    /// reads of these archives are then fetched transparently from the cold tier.
+    /// (default=None which keeps all the archives on the local disk)
+    #[cfg(feature = "local_fs")]
+    pub cold_tier: Option<ColdTier>,
+
+    /// Maximum size of the metadata of a single event, larger events will be
+    /// rejected when they are read from the redo log or received from the network
+    pub max_event_meta_size: usize,
+    /// Maximum size of the data of a single event, larger events will be
+    /// rejected when they are read from the redo log or received from the network
+    pub max_event_data_size: usize,
+    /// Maximum size of a single frame received on a mesh connection (a frame can
+    /// carry a batch of events), frames that claim to be larger are rejected before
+    /// any memory is allocated for them
+    pub max_frame_size: usize,
+
     /// Serialization format of the log files
     pub log_format: MessageFormat,
     /// Serialization format of the data on the network pipes between nodes and clients
@@ -100,7 +140,9 @@ for ConfAte
             recovery_mode: RecoveryMode::ReadOnlyAsync,
             compact_mode: CompactMode::Never,
             compact_bootstrap: false,
+            durability: DurabilityPolicy::default(),
             sync_tolerance: Duration::from_secs(30),
+            idempotency_window: Duration::from_secs(600),
             ntp_sync: true,
             ntp_pool: "pool.ntp.org".to_string(),
             ntp_port: 123,
@@ -112,6 +154,13 @@ for ConfAte
             load_cache_size: 1000,
             #[cfg(feature = "local_fs")]
             load_cache_ttl: 30,
+            #[cfg(feature = "local_fs")]
+            checkpoint_interval: None,
+            #[cfg(feature = "local_fs")]
+            cold_tier: None,
+            max_event_meta_size: 4 * 1024 * 1024,
+            max_event_data_size: 128 * 1024 * 1024,
+            max_frame_size: 256 * 1024 * 1024,
             log_format: MessageFormat {
                 meta: SerializationFormat::Bincode,
                 data: SerializationFormat::Json,
@@ -121,4 +170,14 @@ for ConfAte
             default_port: 5000,
         }
     }
+}
+
+impl ConfAte
+{
+    pub fn event_limits(&self) -> EventLimits {
+        EventLimits {
+            max_meta_size: self.max_event_meta_size,
+            max_data_size: self.max_event_data_size,
+        }
+    }
 }
\ No newline at end of file
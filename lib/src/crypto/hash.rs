@@ -20,6 +20,9 @@ pub struct AteHash {
 }
 
 impl AteHash {
+    /// Placeholder for the version of a data object that does not exist
+    pub const NONE: AteHash = AteHash { val: [0; 16] };
+
     pub fn from_bytes(input: &[u8]) -> AteHash {
         Self::from_bytes_by_routine(input, crate::HASH_ROUTINE)
     }
@@ -0,0 +1,511 @@
+#![allow(unused_imports)]
+use log::{info, error, debug};
+use serde::*;
+use serde::de::*;
+use std::io::SeekFrom;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::collections::BTreeMap;
+use futures::future::BoxFuture;
+use futures::Future;
+use tokio::io::{AsyncRead, AsyncSeek, AsyncWrite, ReadBuf};
+
+use crate::crypto::AteHash;
+use crate::error::*;
+use crate::header::*;
+use crate::meta::*;
+use crate::multi::ChainMultiUser;
+use crate::session::AteSession;
+use crate::spec::{MessageFormat, SerializationFormat};
+use crate::time::{ChainTimestamp, TimeKeeper};
+use crate::transaction::{TransactionScope, ConversationSession};
+
+use super::dao::*;
+use super::dio::*;
+
+/// Size of each of the pages that a blob is split into
+pub const BLOB_PAGE_SIZE: usize = 131072;
+
+/// Maximum number of modified pages that a writer keeps in memory before they are flushed
+const BLOB_MAX_DIRTY_PAGES: usize = 8;
+
+/// Represents a large binary object (e.g. the contents of a file or an
+/// attachment) that belongs to a parent DAO
+///
+/// Like `DaoVec` this object does not store the data itself, instead
+/// the blob is split into pages of `BLOB_PAGE_SIZE` bytes that are each
+/// stored as a seperate event attached to the parent. The keys of the
+/// pages are derived from their position in the blob thus no index is
+/// needed to find them and any pages that were never written are holes
+/// (sparse regions) that read back as zeros.
+///
+/// The pages inherit the read and write permissions of the parent and
+/// will be deleted along with it when it is deleted with `delete_cascade`.
+///
+/// Blobs are read with a `BlobReader` and written with a `BlobWriter`
+/// which implement the tokio `AsyncRead`, `AsyncWrite` and `AsyncSeek`
+/// traits. When multiple writers change the same page at the same time
+/// then the last one to flush wins.
+#[derive(Debug, Serialize, Deserialize, Clone, Copy)]
+pub struct DaoBlob
+{
+    pub(super) blob_id: u64,
+}
+
+impl DaoBlob
+{
+    pub fn new() -> DaoBlob {
+        DaoBlob {
+            blob_id: fastrand::u64(..),
+        }
+    }
+}
+
+impl Default
+for DaoBlob
+{
+    fn default() -> DaoBlob
+    {
+        DaoBlob::new()
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct BlobPage
+{
+    buf: Vec<u8>,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone)]
+struct BlobHeader
+{
+    size: u64,
+}
+
+/// Everything needed to open a fresh `Dio` for each page that is read or written
+struct BlobInner
+{
+    multi: ChainMultiUser,
+    session: AteSession,
+    scope: TransactionScope,
+    conversation: Option<Arc<ConversationSession>>,
+    time: Arc<TimeKeeper>,
+    as_of: Option<ChainTimestamp>,
+    format: MessageFormat,
+    parent: PrimaryKey,
+    blob_id: u64,
+}
+
+impl BlobInner
+{
+    fn new<'a, D>(parent: &Dao<D>, dio: &Dio<'a>, blob: DaoBlob) -> BlobInner
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        BlobInner {
+            multi: dio.multi.clone(),
+            session: dio.session.clone(),
+            scope: dio.scope,
+            conversation: dio.conversation.clone(),
+            time: Arc::clone(&dio.time),
+            as_of: dio.as_of,
+            format: parent.ethereal.row.format,
+            parent: parent.key().clone(),
+            blob_id: blob.blob_id,
+        }
+    }
+
+    fn dio(&self) -> Dio<'_> {
+        Dio {
+            multi: self.multi.clone(),
+            state: DioState::new(),
+            session: &self.session,
+            scope: self.scope,
+            conversation: self.conversation.clone(),
+            time: Arc::clone(&self.time),
+            as_of: self.as_of,
+        }
+    }
+
+    fn key(&self, suffix: &[u8]) -> PrimaryKey {
+        let mut prefix = self.parent.as_u64().to_be_bytes().to_vec();
+        prefix.extend_from_slice(&self.blob_id.to_be_bytes());
+        PrimaryKey::from(AteHash::from_bytes_twice(&prefix[..], suffix))
+    }
+
+    fn header_key(&self) -> PrimaryKey {
+        self.key(b"header")
+    }
+
+    fn page_key(&self, index: u64) -> PrimaryKey {
+        self.key(&index.to_be_bytes())
+    }
+
+    async fn load_size(&self) -> Result<u64, LoadError>
+    {
+        let mut dio = self.dio();
+        match dio.load::<BlobHeader>(&self.header_key()).await {
+            Ok(a) => Ok(a.take().size),
+            Err(LoadError::NotFound(_)) => Ok(0),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn load_page(&self, index: u64) -> Result<Option<Vec<u8>>, LoadError>
+    {
+        let mut dio = self.dio();
+        match dio.load::<BlobPage>(&self.page_key(index)).await {
+            Ok(a) => Ok(Some(a.take().buf)),
+            Err(LoadError::NotFound(_)) => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Pages are always stored as bincode as the other formats would encode the
+    /// raw bytes as a sequence of numbers
+    fn page_format(&self) -> MessageFormat {
+        MessageFormat {
+            meta: self.format.meta,
+            data: SerializationFormat::Bincode,
+        }
+    }
+
+    async fn store(&self, pages: &[(u64, Vec<u8>)], size: Option<u64>) -> Result<(), CommitError>
+    {
+        let mut dio = self.dio();
+        for (index, buf) in pages {
+            let mut page = dio.make_ext(BlobPage { buf: buf.clone() }, Some(self.page_format()), Some(self.page_key(*index)))?;
+            page.attach_orphaned_ext(self.parent.clone(), self.blob_id);
+            page.commit(&mut dio)?;
+        }
+        if let Some(size) = size {
+            let mut header = dio.make_ext(BlobHeader { size }, Some(self.format), Some(self.header_key()))?;
+            header.attach_orphaned_ext(self.parent.clone(), self.blob_id);
+            header.commit(&mut dio)?;
+        }
+        dio.commit().await
+    }
+}
+
+fn conv_err(err: impl std::error::Error) -> std::io::Error {
+    std::io::Error::new(std::io::ErrorKind::Other, err.to_string())
+}
+
+fn seek_to(pos: u64, size: u64, seek: SeekFrom) -> std::io::Result<u64>
+{
+    let ret = match seek {
+        SeekFrom::Start(a) => Some(a),
+        SeekFrom::End(a) => (size as i64).checked_add(a).filter(|a| *a >= 0).map(|a| a as u64),
+        SeekFrom::Current(a) => (pos as i64).checked_add(a).filter(|a| *a >= 0).map(|a| a as u64),
+    };
+    ret.ok_or_else(|| std::io::Error::new(std::io::ErrorKind::InvalidInput, "invalid seek to a negative or overflowing position"))
+}
+
+type PageFuture = BoxFuture<'static, std::io::Result<(u64, Option<Vec<u8>>)>>;
+
+fn load_page(inner: &Arc<BlobInner>, index: u64) -> PageFuture {
+    let inner = Arc::clone(inner);
+    Box::pin(async move {
+        inner.load_page(index).await
+            .map(|a| (index, a))
+            .map_err(conv_err)
+    })
+}
+
+/// Reads the contents of a `DaoBlob` (the size of the blob is captured when
+/// the reader is opened)
+pub struct BlobReader
+{
+    inner: Arc<BlobInner>,
+    size: u64,
+    pos: u64,
+    page: Option<(u64, Option<Vec<u8>>)>,
+    pending: Option<PageFuture>,
+}
+
+impl BlobReader
+{
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+}
+
+impl AsyncRead
+for BlobReader
+{
+    fn poll_read(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &mut ReadBuf<'_>) -> Poll<std::io::Result<()>>
+    {
+        let this = self.get_mut();
+        loop {
+            if let Some(pending) = this.pending.as_mut() {
+                let ret = futures::ready!(pending.as_mut().poll(cx));
+                this.pending = None;
+                this.page = Some(ret?);
+                continue;
+            }
+            if this.pos >= this.size || buf.remaining() == 0 {
+                return Poll::Ready(Ok(()));
+            }
+
+            let index = this.pos / BLOB_PAGE_SIZE as u64;
+            let offset = (this.pos % BLOB_PAGE_SIZE as u64) as usize;
+            let data = match &this.page {
+                Some((i, data)) if *i == index => data,
+                _ => {
+                    this.pending = Some(load_page(&this.inner, index));
+                    continue;
+                }
+            };
+
+            // Anything past the end of the page data is a hole
+            let len = (BLOB_PAGE_SIZE - offset)
+                .min(buf.remaining())
+                .min((this.size - this.pos) as usize);
+            let data = match data {
+                Some(a) if a.len() > offset => &a[offset..a.len().min(offset + len)],
+                _ => &[],
+            };
+            buf.put_slice(data);
+            if len > data.len() {
+                buf.put_slice(&vec![0u8; len - data.len()][..]);
+            }
+
+            this.pos = this.pos + len as u64;
+            return Poll::Ready(Ok(()));
+        }
+    }
+}
+
+impl AsyncSeek
+for BlobReader
+{
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        this.pos = seek_to(this.pos, this.size, position)?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+/// Pages that were being flushed are handed back when the flush fails so they can be retried
+type FlushFuture = BoxFuture<'static, Result<(), (std::io::Error, Vec<(u64, Vec<u8>)>)>>;
+
+enum WriterPending
+{
+    Load(PageFuture),
+    Flush(FlushFuture, Option<u64>),
+}
+
+/// Writes to a `DaoBlob`, the changes are buffered in memory and written to the
+/// chain as page events whenever the buffer fills up or the writer is flushed
+/// (make sure to call `flush` or `shutdown` when finished otherwise the last
+/// changes will be lost)
+///
+/// Seeking past the end of the blob and writing there leaves a sparse region
+pub struct BlobWriter
+{
+    inner: Arc<BlobInner>,
+    size: u64,
+    /// Size of the blob as it was last written to the chain, pages after this are known to be holes
+    stored: u64,
+    pos: u64,
+    dirty: BTreeMap<u64, Vec<u8>>,
+    pending: Option<WriterPending>,
+}
+
+impl BlobWriter
+{
+    pub fn len(&self) -> u64 {
+        self.size
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.size == 0
+    }
+
+    fn start_flush(&mut self) -> WriterPending
+    {
+        let pages = std::mem::take(&mut self.dirty)
+            .into_iter()
+            .collect::<Vec<_>>();
+        let size = match self.size != self.stored {
+            true => Some(self.size),
+            false => None,
+        };
+
+        let inner = Arc::clone(&self.inner);
+        WriterPending::Flush(Box::pin(async move {
+            inner.store(&pages[..], size).await
+                .map_err(|err| (conv_err(err), pages))
+        }), size)
+    }
+
+    /// Completes any load or flush that is currently in progress
+    fn poll_pending(&mut self, cx: &mut Context<'_>) -> Poll<std::io::Result<()>>
+    {
+        let ret = match self.pending.as_mut() {
+            Some(WriterPending::Load(fut)) => {
+                let ret = futures::ready!(fut.as_mut().poll(cx));
+                ret.map(|(index, data)| {
+                    self.dirty.insert(index, data.unwrap_or_default());
+                })
+            },
+            Some(WriterPending::Flush(fut, size)) => {
+                // The state of the writer only moves forward once the pages are in the chain
+                match futures::ready!(fut.as_mut().poll(cx)) {
+                    Ok(()) => {
+                        if let Some(size) = size {
+                            self.stored = *size;
+                        }
+                        Ok(())
+                    },
+                    Err((err, pages)) => {
+                        self.dirty.extend(pages);
+                        Err(err)
+                    }
+                }
+            },
+            None => Ok(()),
+        };
+        self.pending = None;
+        Poll::Ready(ret)
+    }
+}
+
+impl AsyncWrite
+for BlobWriter
+{
+    fn poll_write(self: Pin<&mut Self>, cx: &mut Context<'_>, buf: &[u8]) -> Poll<std::io::Result<usize>>
+    {
+        let this = self.get_mut();
+        loop {
+            if this.pending.is_some() {
+                futures::ready!(this.poll_pending(cx))?;
+                continue;
+            }
+            if buf.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            let index = this.pos / BLOB_PAGE_SIZE as u64;
+            let offset = (this.pos % BLOB_PAGE_SIZE as u64) as usize;
+            if this.dirty.contains_key(&index) == false
+            {
+                if this.dirty.len() >= BLOB_MAX_DIRTY_PAGES {
+                    this.pending = Some(this.start_flush());
+                    continue;
+                }
+
+                // Only pages that already exist and are partially overwritten need to be loaded
+                let is_hole = index * BLOB_PAGE_SIZE as u64 >= this.stored;
+                let is_overwrite = offset == 0 && buf.len() >= BLOB_PAGE_SIZE;
+                if is_hole || is_overwrite {
+                    this.dirty.insert(index, Vec::new());
+                } else {
+                    this.pending = Some(WriterPending::Load(load_page(&this.inner, index)));
+                    continue;
+                }
+            }
+
+            let page = this.dirty.get_mut(&index).unwrap();
+            let len = (BLOB_PAGE_SIZE - offset).min(buf.len());
+            if page.len() < offset + len {
+                page.resize(offset + len, 0);
+            }
+            page[offset..offset + len].copy_from_slice(&buf[..len]);
+
+            this.pos = this.pos + len as u64;
+            this.size = this.size.max(this.pos);
+            return Poll::Ready(Ok(len));
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>>
+    {
+        let this = self.get_mut();
+        loop {
+            if this.pending.is_some() {
+                futures::ready!(this.poll_pending(cx))?;
+                continue;
+            }
+            if this.dirty.is_empty() && this.size == this.stored {
+                return Poll::Ready(Ok(()));
+            }
+            this.pending = Some(this.start_flush());
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.poll_flush(cx)
+    }
+}
+
+impl AsyncSeek
+for BlobWriter
+{
+    fn start_seek(self: Pin<&mut Self>, position: SeekFrom) -> std::io::Result<()> {
+        let this = self.get_mut();
+        this.pos = seek_to(this.pos, this.size, position)?;
+        Ok(())
+    }
+
+    fn poll_complete(self: Pin<&mut Self>, _cx: &mut Context<'_>) -> Poll<std::io::Result<u64>> {
+        Poll::Ready(Ok(self.pos))
+    }
+}
+
+impl Drop
+for BlobWriter
+{
+    fn drop(&mut self)
+    {
+        if self.dirty.is_empty() == false || self.size != self.stored {
+            error!("blob writer was dropped without being flushed - the last changes to the blob have been lost");
+        }
+    }
+}
+
+impl<D> Dao<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    /// Opens a blob that belongs to this data object for reading
+    pub async fn blob_reader<'a>(&self, dio: &Dio<'a>, blob: DaoBlob) -> Result<BlobReader, LoadError>
+    {
+        let inner = Arc::new(BlobInner::new(self, dio, blob));
+        let size = inner.load_size().await?;
+        Ok(
+            BlobReader {
+                inner,
+                size,
+                pos: 0,
+                page: None,
+                pending: None,
+            }
+        )
+    }
+
+    /// Opens a blob that belongs to this data object for writing, the writer starts
+    /// at the beginning of the blob (seek to the end to append to it)
+    pub async fn blob_writer<'a>(&self, dio: &Dio<'a>, blob: DaoBlob) -> Result<BlobWriter, LoadError>
+    {
+        let inner = Arc::new(BlobInner::new(self, dio, blob));
+        let size = inner.load_size().await?;
+        Ok(
+            BlobWriter {
+                inner,
+                size,
+                stored: size,
+                pos: 0,
+                dirty: BTreeMap::new(),
+                pending: None,
+            }
+        )
+    }
+}
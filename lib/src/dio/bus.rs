@@ -1,8 +1,16 @@
-use serde::{Serialize, de::DeserializeOwned};
+#[allow(unused_imports)]
+use log::{info, error, debug};
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
 use std::marker::PhantomData;
+use std::time::Duration;
+use futures::FutureExt;
+use fxhash::FxHashMap;
 use tokio::sync::mpsc;
 
 use crate::{error::*, event::*, meta::MetaCollection};
+use crate::crypto::AteHash;
+use crate::header::*;
+use crate::transaction::TransactionScope;
 use super::dao::*;
 use crate::dio::*;
 use crate::chain::*;
@@ -22,6 +30,30 @@ where D: Serialize + DeserializeOwned + Clone + Send + Sync,
         };
         Bus::new(chain, vec)
     }
+
+    /// Joins the group of consumers that process the children of a vector as a queue,
+    /// messages that fail too many times are moved into the dead letter vector
+    #[allow(dead_code)]
+    pub fn consumer_group<'a, C>(&self, chain: &'a Chain, vec: DaoVec<C>, dead_letters: DaoVec<DeadLetter<C>>) -> BusConsumerGroup<'a, C>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        // The leases are kept in their own collection so that taking them does not
+        // wake up the plain listeners of the vector
+        let leases = MetaCollection {
+            parent_id: self.key().clone(),
+            collection_id: AteHash::from_bytes_twice(&vec.vec_id.to_be_bytes(), b"leases").to_u64(),
+        };
+        BusConsumerGroup {
+            bus: self.bus(chain, vec),
+            leases: Bus::new(chain, leases),
+            consumer: fastrand::u64(..),
+            parent_id: self.key().clone(),
+            dead_letters: dead_letters.vec_id,
+            lease: DEFAULT_LEASE,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+            held: FxHashMap::default(),
+        }
+    }
 }
 
 #[allow(dead_code)]
@@ -53,7 +85,7 @@ where D: Serialize + DeserializeOwned + Clone + Send + Sync,
         }
 
         Bus {
-            id: fastrand::u64(..),
+            id,
             chain: chain,
             vec: vec,
             receiver: rx,
@@ -113,4 +145,424 @@ where D: Serialize + DeserializeOwned + Clone + Send + Sync,
             }
         }
     }
-}
\ No newline at end of file
+}
+/// Default time that a consumer may hold onto a message before it is redelivered
+const DEFAULT_LEASE: Duration = Duration::from_secs(30);
+
+/// Default number of times a message is delivered before it becomes a dead letter
+const DEFAULT_MAX_ATTEMPTS: u32 = 5;
+
+/// Message that could not be processed by a `BusConsumerGroup`
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct DeadLetter<D>
+{
+    /// Key of the original message
+    pub key: PrimaryKey,
+    pub data: D,
+    pub attempts: u32,
+    pub reason: String,
+}
+
+/// Message that was handed to one of the consumers in a `BusConsumerGroup`, once it
+/// has been processed it must be acknowledged with `ack` (or given back with `nack`)
+#[derive(Debug, Clone)]
+pub struct BusDelivery<D>
+{
+    pub key: PrimaryKey,
+    pub data: D,
+    /// Number of times the message has been delivered (including this time)
+    pub attempt: u32,
+    /// When the lease on the message expires (milliseconds since the epoch)
+    pub expires: u64,
+}
+
+/// Lease that a consumer holds on a message while it is being processed, it is
+/// stored as a seperate data object so the message itself is never rewritten
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct BusLease
+{
+    consumer: u64,
+    /// When the lease expires (milliseconds since the epoch)
+    expires: u64,
+    /// Number of times the message has been delivered
+    attempts: u32,
+    /// Reason that the last delivery of the message failed
+    reason: Option<String>,
+}
+
+enum BusClaim<D>
+{
+    Delivered(BusDelivery<D>),
+    /// Nothing is available, the time is when the next lease will expire
+    Wait(Option<u64>),
+}
+
+/// Processes the children of a `DaoVec` as a queue that is shared by a group of
+/// consumers (which may be running on different nodes).
+///
+/// Each message is handed to one consumer at a time along with a lease that is
+/// written to the chain, if the lease expires before the message is acknowledged
+/// (e.g. the consumer crashed) then it is delivered again. Messages that fail
+/// `max_attempts` times are moved into the dead letter vector with the reason.
+///
+/// Leases are claimed, released and acknowledged with optimistic concurrency so
+/// only one consumer wins each claim and stale acknowledgements are rejected,
+/// however the expiry relies on the clocks of the consumers thus messages may be
+/// delivered more than once and handlers should be idempotent.
+#[allow(dead_code)]
+pub struct BusConsumerGroup<'a, D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync
+{
+    bus: Bus<'a, D>,
+    leases: Bus<'a, BusLease>,
+    consumer: u64,
+    parent_id: PrimaryKey,
+    dead_letters: u64,
+    lease: Duration,
+    max_attempts: u32,
+    /// Expiry of the leases that are known to be held (by their key) so that those
+    /// messages are skipped without reading the chain until the lease changes
+    held: FxHashMap<PrimaryKey, u64>,
+}
+
+impl<'a, D> BusConsumerGroup<'a, D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    /// Sets how long a consumer may hold onto a message before it is redelivered
+    pub fn with_lease(mut self, lease: Duration) -> Self {
+        self.lease = lease;
+        self
+    }
+
+    /// Sets how many times a message is delivered before it becomes a dead letter
+    pub fn with_max_attempts(mut self, max_attempts: u32) -> Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Waits for the next message that is not leased by another consumer
+    pub async fn recv(&mut self, session: &AteSession) -> Result<BusDelivery<D>, BusError>
+    {
+        loop {
+            let wake = match self.claim(session).await? {
+                BusClaim::Delivered(a) => { return Ok(a); },
+                BusClaim::Wait(a) => a,
+            };
+
+            // Wait for the vector or the leases to change or for the next lease to expire
+            let messages = &mut self.bus.receiver;
+            let leases = &mut self.leases.receiver;
+            let changed = async move {
+                tokio::select! {
+                    a = messages.recv() => a.map(|_| None),
+                    a = leases.recv() => a.map(|a| Some(a)),
+                }
+            };
+            let changed = match wake {
+                Some(wake) => {
+                    let now = self.bus.chain.time.current_timestamp()?.time_since_epoch_ms;
+                    let wait = Duration::from_millis(wake.saturating_sub(now) + 1);
+                    match tokio::time::timeout(wait, changed).await {
+                        Ok(a) => a,
+                        Err(_) => { continue; }
+                    }
+                },
+                None => changed.await,
+            };
+            match changed {
+                Some(Some(evt)) => self.forget(&evt),
+                Some(None) => { },
+                None => { return Err(BusError::ChannelClosed); }
+            }
+            while let Some(Some(_)) = self.bus.receiver.recv().now_or_never() { }
+        }
+    }
+
+    /// Returns the next message that is not leased by another consumer without waiting
+    pub async fn try_recv(&mut self, session: &AteSession) -> Result<Option<BusDelivery<D>>, BusError>
+    {
+        Ok(match self.claim(session).await? {
+            BusClaim::Delivered(a) => Some(a),
+            BusClaim::Wait(_) => None,
+        })
+    }
+
+    /// Acknowledges that a message was processed which removes it from the queue
+    pub async fn ack(&self, session: &AteSession, delivery: &BusDelivery<D>) -> Result<(), BusError>
+    {
+        let mut dio = self.bus.chain.dio_ext(session, TransactionScope::Full).await;
+        dio.optimistic_concurrency();
+        self.check_lease(&mut dio, delivery).await?;
+        dio.delete::<BusLease>(&self.lease_key(&delivery.key)).await?;
+        dio.delete::<D>(&delivery.key).await?;
+
+        match dio.commit().await {
+            Err(CommitError::Conflict { .. }) => Err(BusError::LeaseLost(delivery.key.clone())),
+            ret => Ok(ret?),
+        }
+    }
+
+    /// Gives a message back so that it can be delivered again straight away, or if it
+    /// has run out of attempts then it is moved into the dead letter vector
+    pub async fn nack(&self, session: &AteSession, delivery: &BusDelivery<D>, reason: &str) -> Result<(), BusError>
+    {
+        let mut dio = self.bus.chain.dio_ext(session, TransactionScope::Full).await;
+        dio.optimistic_concurrency();
+        let mut lease = self.check_lease(&mut dio, delivery).await?;
+
+        if delivery.attempt >= self.max_attempts {
+            let dao = match dio.load::<D>(&delivery.key).await {
+                Ok(a) => a,
+                Err(LoadError::NotFound(key)) => { return Err(BusError::LeaseLost(key)); },
+                Err(err) => { return Err(err.into()); }
+            };
+            self.dead_letter(&mut dio, dao, delivery.attempt, reason.to_string()).await?;
+        } else {
+            lease.expires = dio.time.current_timestamp()?.time_since_epoch_ms;
+            lease.reason = Some(reason.to_string());
+            lease.commit(&mut dio)?;
+        }
+
+        match dio.commit().await {
+            Err(CommitError::Conflict { .. }) => Err(BusError::LeaseLost(delivery.key.clone())),
+            ret => Ok(ret?),
+        }
+    }
+
+    /// Attempts to take a lease on the first message in the queue that is available
+    async fn claim(&mut self, session: &AteSession) -> Result<BusClaim<D>, BusError>
+    {
+        let chain = self.bus.chain;
+        let keys = {
+            let dio = chain.dio_ext(session, TransactionScope::Full).await;
+            dio.lookup_secondary_raw(&self.bus.vec).await.unwrap_or_default()
+        };
+        let now = chain.time.current_timestamp()?.time_since_epoch_ms;
+
+        // Leases that changed since the last claim must be read again
+        while let Some(Some(evt)) = self.leases.receiver.recv().now_or_never() {
+            self.forget(&evt);
+        }
+        self.held.retain(|_, expires| *expires > now);
+
+        let mut wake: Option<u64> = None;
+        for key in keys
+        {
+            let lease_key = self.lease_key(&key);
+            if let Some(expires) = self.held.get(&lease_key) {
+                wake = Some(wake.map_or(*expires, |w| w.min(*expires)));
+                continue;
+            }
+
+            // Whoever commits their claim first wins the message
+            let mut claim = chain.dio_ext(session, TransactionScope::Full).await;
+            claim.optimistic_concurrency();
+
+            // Only the leases are read until a message that is available is found
+            let lease = self.load_lease(&mut claim, &key).await?;
+            let (attempts, reason) = match lease.as_ref() {
+                Some(a) if a.expires > now => {
+                    wake = Some(wake.map_or(a.expires, |w| w.min(a.expires)));
+                    self.held.insert(lease_key, a.expires);
+                    continue;
+                },
+                Some(a) => (a.attempts, a.reason.clone()),
+                None => (0, None),
+            };
+
+            // Messages whose leases keep expiring (e.g. they crash the consumer) are poison
+            if attempts >= self.max_attempts {
+                let dao = match claim.load::<D>(&key).await {
+                    Ok(a) => a,
+                    Err(LoadError::NotFound(_)) => { continue; },
+                    Err(err) => { return Err(err.into()); }
+                };
+                let reason = reason.unwrap_or_else(|| "the lease on the message expired too many times".to_string());
+                self.dead_letter(&mut claim, dao, attempts, reason).await?;
+                match claim.commit().await {
+                    Ok(_) | Err(CommitError::Conflict { .. }) => { continue; },
+                    Err(err) => { return Err(err.into()); }
+                }
+            }
+
+            let expires = now + self.lease.as_millis() as u64;
+            let next = BusLease {
+                consumer: self.consumer,
+                expires,
+                attempts: attempts + 1,
+                reason,
+            };
+            match lease {
+                Some(mut lease) => {
+                    *lease = next;
+                    lease.commit(&mut claim)?;
+                },
+                None => {
+                    let mut lease = claim.make_ext(next, None, Some(lease_key.clone()))?;
+                    lease.attach_orphaned_ext(self.parent_id.clone(), self.leases.vec.collection_id);
+                    lease.commit(&mut claim)?;
+                }
+            }
+            match claim.commit().await {
+                Ok(_) => { },
+                Err(CommitError::Conflict { .. }) => { continue; },
+                Err(err) => { return Err(err.into()); }
+            }
+            self.held.insert(lease_key.clone(), expires);
+
+            // The message is only read once the lease on it is held
+            let mut dio = chain.dio_ext(session, TransactionScope::Full).await;
+            match dio.load::<D>(&key).await {
+                Ok(dao) => {
+                    return Ok(BusClaim::Delivered(BusDelivery {
+                        key,
+                        data: dao.take(),
+                        attempt: attempts + 1,
+                        expires,
+                    }));
+                },
+                Err(LoadError::NotFound(_)) => {
+                    // (it was acknowledged just before the lease was taken)
+                    dio.delete::<BusLease>(&lease_key).await?;
+                    dio.commit().await?;
+                },
+                Err(err) => { return Err(err.into()); }
+            }
+        }
+        Ok(BusClaim::Wait(wake))
+    }
+
+    /// Drops the cached expiry of a lease that was changed (or deleted)
+    fn forget(&mut self, evt: &EventData)
+    {
+        if let Some(key) = evt.meta.get_data_key() {
+            self.held.remove(&key);
+        }
+        if let Some(key) = evt.meta.get_tombstone() {
+            self.held.remove(&key);
+        }
+    }
+
+    /// Leases are stored under a key that is derived from the message they belong to
+    fn lease_key(&self, key: &PrimaryKey) -> PrimaryKey {
+        PrimaryKey::from(AteHash::from_bytes_twice(&key.as_u64().to_be_bytes(), b"lease"))
+    }
+
+    async fn load_lease<'b>(&self, dio: &mut Dio<'b>, key: &PrimaryKey) -> Result<Option<Dao<BusLease>>, BusError>
+    {
+        match dio.load::<BusLease>(&self.lease_key(key)).await {
+            Ok(a) => Ok(Some(a)),
+            Err(LoadError::NotFound(_)) => Ok(None),
+            Err(err) => Err(err.into()),
+        }
+    }
+
+    /// Makes sure this consumer still holds an unexpired lease for the delivery, the lease
+    /// is loaded into the `Dio` so that any later change to it will conflict
+    async fn check_lease<'b>(&self, dio: &mut Dio<'b>, delivery: &BusDelivery<D>) -> Result<Dao<BusLease>, BusError>
+    {
+        let now = dio.time.current_timestamp()?.time_since_epoch_ms;
+        match self.load_lease(dio, &delivery.key).await? {
+            Some(a) if a.consumer == self.consumer && a.attempts == delivery.attempt && a.expires > now => Ok(a),
+            _ => Err(BusError::LeaseLost(delivery.key.clone())),
+        }
+    }
+
+    async fn dead_letter<'b>(&self, dio: &mut Dio<'b>, dao: Dao<D>, attempts: u32, reason: String) -> Result<(), BusError>
+    {
+        debug!("bus: dead letter {} after {} attempts - {}", dao.key(), attempts, reason);
+
+        let key = dao.key().clone();
+        let format = dao.ethereal.row.format;
+        let mut dead = dio.make_ext(DeadLetter {
+                key: key.clone(),
+                data: dao.take(),
+                attempts,
+                reason,
+            }, Some(format), None)?;
+        dead.attach_orphaned_ext(self.parent_id.clone(), self.dead_letters);
+        dead.commit(dio)?;
+        dio.delete::<BusLease>(&self.lease_key(&key)).await?;
+        dio.delete::<D>(&key).await?;
+        Ok(())
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_bus_consumer_group() -> Result<(), AteError>
+{
+    use crate::prelude::*;
+    use crate::dio::test::{TestQueueDao, TestEnumDao};
+
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_bus_consumer_group", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let mut queue = {
+        let mut dio = chain.dio(&session).await;
+        let queue = dio.store(TestQueueDao::default())?;
+        dio.commit().await?;
+        queue
+    };
+
+    // Plain listeners of the vector see the messages arrive
+    let mut bus = queue.bus(&chain, queue.jobs);
+    {
+        let mut dio = chain.dio(&session).await;
+        let jobs = queue.jobs;
+        for n in 1..=3 {
+            queue.push_store(&mut dio, jobs, TestEnumDao::Blah2(n))?;
+        }
+        dio.commit().await?;
+    }
+    for _ in 1..=3 {
+        bus.recv(&session).await?;
+    }
+
+    let lease = std::time::Duration::from_millis(100);
+    let mut group1 = queue.consumer_group(&chain, queue.jobs, queue.dead).with_lease(lease).with_max_attempts(2);
+    let mut group2 = queue.consumer_group(&chain, queue.jobs, queue.dead).with_lease(lease).with_max_attempts(2);
+
+    // Each consumer gets a different message
+    let d1 = group1.recv(&session).await?;
+    let d2 = group2.recv(&session).await?;
+    assert!(matches!(d1.data, TestEnumDao::Blah2(1)));
+    assert!(matches!(d2.data, TestEnumDao::Blah2(2)));
+    assert_eq!(d2.attempt, 1);
+
+    // ...but they are not woken up by the leases being taken
+    assert!(tokio::time::timeout(std::time::Duration::from_millis(50), bus.recv(&session)).await.is_err());
+
+    // Only the holder of the lease can acknowledge the message
+    assert!(matches!(group2.ack(&session, &d1).await, Err(BusError::LeaseLost(_))));
+    group1.ack(&session, &d1).await?;
+
+    // Messages that are given back are delivered again straight away
+    group2.nack(&session, &d2, "boom").await?;
+    let d2 = group1.recv(&session).await?;
+    assert!(matches!(d2.data, TestEnumDao::Blah2(2)));
+    assert_eq!(d2.attempt, 2);
+
+    // When the lease expires the message has run out of attempts so it becomes a dead letter
+    tokio::time::sleep(lease * 2).await;
+    let d3 = group2.recv(&session).await?;
+    assert!(matches!(d3.data, TestEnumDao::Blah2(3)));
+    assert!(matches!(group1.ack(&session, &d2).await, Err(BusError::LeaseLost(_))));
+    group2.ack(&session, &d3).await?;
+    assert!(group1.try_recv(&session).await?.is_none());
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let dead = queue.iter(&mut dio, queue.dead).await?.collect::<Vec<_>>();
+        assert_eq!(dead.len(), 1);
+        assert_eq!(dead[0].key, d2.key);
+        assert_eq!(dead[0].attempts, 2);
+        assert_eq!(dead[0].reason, "boom");
+        assert_eq!(queue.iter(&mut dio, queue.jobs).await?.count(), 0);
+    }
+
+    Ok(())
+}
@@ -19,6 +19,7 @@ use crate::crypto::AteHash;
 use crate::dio::*;
 use crate::spec::*;
 use crate::index::*;
+use crate::unique::UniqueConstraint;
 
 pub use super::vec::DaoVec;
 
@@ -28,22 +29,24 @@ where Self: Send + Sync,
       D: Serialize + DeserializeOwned + Clone + Send + Sync,
 {
     pub(super) key: PrimaryKey,
-    pub(super) type_name: &'static str,
+    pub(super) type_name: String,
     pub(super) created: u64,
     pub(super) updated: u64,
     pub(super) format: MessageFormat,
     pub(super) parent: Option<MetaParent>,
+    pub(super) order: Option<MetaOrder>,
     pub(super) data: D,
     pub(super) auth: MetaAuthorization,
     pub(super) collections: FxHashSet<MetaCollection>,
     pub(super) extra_meta: Vec<CoreMetadata>,
+    pub(super) version: Option<AteHash>,
 }
 
 impl<D> Row<D>
 where Self: Send + Sync,
       D: Serialize + DeserializeOwned + Clone + Send + Sync,
 {
-    pub(crate) fn from_event(evt: &EventData, created: u64, updated: u64) -> Result<Row<D>, SerializationError> {
+    pub(crate) fn from_event(evt: &EventData, leaf: &EventLeaf) -> Result<Row<D>, SerializationError> {
         let key = match evt.meta.get_data_key() {
             Some(key) => key,
             None => { return Result::Err(SerializationError::NoPrimarykey) }
@@ -59,18 +62,24 @@ where Self: Send + Sync,
                     None => MetaAuthorization::default(),
                 };
                 let parent = match evt.meta.get_parent() { Some(a) => Some(a.clone()), None => None };
+                let order = evt.meta.get_order().map(|a| a.clone());
                 Ok(
                     Row {
                         key,
-                        type_name: std::any::type_name::<D>(),
+                        type_name: std::any::type_name::<D>().to_string(),
                         format: evt.format,
                         parent,
+                        order,
                         data: evt.format.data.deserialize(&data)?,
                         auth,
                         collections,
-                        created,
-                        updated,
-                        extra_meta: Vec::new(),
+                        created: leaf.created,
+                        updated: leaf.updated,
+                        // (delayed children keep their delivery time when they are updated)
+                        extra_meta: evt.meta.get_deliver_at()
+                            .map(|a| vec![CoreMetadata::DeliverAt(a)])
+                            .unwrap_or_default(),
+                        version: Some(leaf.record.clone()),
                     }
                 )
             }
@@ -82,15 +91,17 @@ where Self: Send + Sync,
         Ok(
             Row {
                 key: row.key,
-                type_name: row.type_name,
+                type_name: row.type_name.clone(),
                 format: row.format,
                 parent: row.parent.clone(),
+                order: row.order.clone(),
                 data: row.format.data.deserialize(&row.data)?,
                 auth: row.auth.clone(),
                 collections: row.collections.clone(),
                 created: row.created,
                 updated: row.updated,
                 extra_meta: row.extra_meta.clone(),
+                version: row.version.clone(),
             }
         )
     }
@@ -103,9 +114,10 @@ where Self: Send + Sync,
         (
             RowData {
                 key: self.key.clone(),
-                type_name: self.type_name,
+                type_name: self.type_name.clone(),
                 format: self.format,
                 parent: self.parent.clone(),
+                order: self.order.clone(),
                 data_hash,
                 data,
                 auth: self.auth.clone(),
@@ -113,9 +125,58 @@ where Self: Send + Sync,
                 created: self.created,
                 updated: self.updated,
                 extra_meta: self.extra_meta.clone(),
+                version: self.version.clone(),
             }
         )
     }
+
+    /// Replaces the values that this row claims for the unique constraints on its type
+    pub(super) fn claim_unique(&mut self, constraints: &[UniqueConstraint]) -> Result<(), SerializationError>
+    {
+        self.extra_meta.retain(|m| match m {
+            CoreMetadata::Unique(_) => false,
+            _ => true,
+        });
+        if constraints.len() <= 0 {
+            return Ok(());
+        }
+
+        let data = serde_json::to_value(&self.data)?;
+        for constraint in constraints.iter() {
+            self.extra_meta.push(CoreMetadata::Unique(constraint.claim(&data)));
+        }
+        Ok(())
+    }
+
+    /// Replaces the values that this row has in the secondary indexes on its type
+    pub(super) fn claim_indexes(&mut self, indexes: &[Arc<SecondaryIndex>]) -> Result<(), SerializationError>
+    {
+        self.extra_meta.retain(|m| match m {
+            CoreMetadata::Indexed(_) => false,
+            _ => true,
+        });
+        if indexes.len() <= 0 {
+            return Ok(());
+        }
+
+        let data = serde_json::to_value(&self.data)?;
+        for index in indexes.iter() {
+            if let Some(indexed) = index.claim(&data) {
+                self.extra_meta.push(CoreMetadata::Indexed(indexed));
+            }
+        }
+        Ok(())
+    }
+
+    /// Position of the row within its collection (rows that were never written
+    /// and have no explicit position return None)
+    pub(super) fn order_key(&self) -> Option<MetaOrder> {
+        match &self.order {
+            Some(a) => Some(a.clone()),
+            None if self.created > 0 => Some(MetaOrder::from_timestamp(self.created)),
+            None => None,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -123,9 +184,10 @@ pub(crate) struct RowData
 where Self: Send + Sync
 {
     pub key: PrimaryKey,
-    pub type_name: &'static str,
+    pub type_name: String,
     pub format: MessageFormat,
     pub parent: Option<MetaParent>,
+    pub order: Option<MetaOrder>,
     pub data_hash: AteHash,
     pub data: Bytes,
     pub auth: MetaAuthorization,
@@ -133,6 +195,7 @@ where Self: Send + Sync
     pub created: u64,
     pub updated: u64,
     pub extra_meta: Vec<CoreMetadata>,
+    pub version: Option<AteHash>,
 }
 
 #[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -182,6 +245,9 @@ pub trait DaoObjReal
     fn when_created(&self) -> u64;
 
     fn when_updated(&self) -> u64;
+
+    /// Hash of the event this object was loaded from (None if it has never been saved)
+    fn version(&self) -> Option<&AteHash>;
 }
 
 /// Represents a data object that will be represented as one or
@@ -233,10 +299,11 @@ where Self: Send + Sync,
             },
             row: Row {
                 key,
-                type_name: std::any::type_name::<D>(),
+                type_name: std::any::type_name::<D>().to_string(),
                 created: 0,
                 updated: 0,
                 parent: None,
+                order: None,
                 data,
                 format,
                 auth: MetaAuthorization {
@@ -245,6 +312,7 @@ where Self: Send + Sync,
                 },
                 collections: FxHashSet::default(),
                 extra_meta: Vec::new(),
+                version: None,
             },
         }
     }
@@ -420,6 +488,11 @@ where Self: Send + Sync,
         self.ethereal.take()
     }
 
+    /// Name of the type that this data object is stored as
+    pub fn type_name(&self) -> &str {
+        self.ethereal.row.type_name.as_str()
+    }
+
     pub async fn try_lock<'a>(&mut self, dio: &mut Dio<'a>) -> Result<bool, LockError> {
         self.ethereal.try_lock(dio).await
     }
@@ -460,6 +533,14 @@ where Self: Send + Sync,
                 _ => {}
             }
 
+            // Attach the values this object claims for any unique constraints on its type
+            let constraints = dio.multi.unique_constraints(s.row.type_name.as_str());
+            s.row.claim_unique(&constraints)?;
+
+            // Likewise the values it has in any secondary indexes on its type
+            let indexes = dio.multi.secondary_indexes_for(s.row.type_name.as_str());
+            s.row.claim_indexes(&indexes)?;
+
             let row_data = s.row.as_row_data()?;
             let row_parent = match &s.row.parent {
                 Some(a) => Some(a),
@@ -492,6 +573,10 @@ where Self: Send + Sync,
     fn when_updated(&self) -> u64 {
         self.ethereal.row.updated
     }
+
+    fn version(&self) -> Option<&AteHash> {
+        self.ethereal.row.version.as_ref()
+    }
 }
 
 impl<D> DaoObjEthereal
@@ -533,7 +618,7 @@ pub(crate) fn delete_internal<D>(dao: &Dao<D>, state: &mut DioState)
 where D: Serialize + DeserializeOwned + Clone + Send + Sync,
 {
     let key = dao.key().clone();
-    state.add_deleted(key, dao.ethereal.row.parent.clone());
+    state.add_deleted(key, dao.ethereal.row.parent.clone(), dao.ethereal.row.type_name.as_str());
     Ok(())
 }
 
@@ -11,6 +11,7 @@ use parking_lot::Mutex;
 use std::ops::Deref;
 use tokio::sync::mpsc;
 use std::sync::mpsc as smpsc;
+use futures::future::BoxFuture;
 
 use super::dao::*;
 use crate::meta::*;
@@ -36,9 +37,12 @@ where Self: Send + Sync
     pub(super) cache_store_secondary: MultiMap<MetaCollection, PrimaryKey>,
     pub(super) cache_load: FxHashMap<PrimaryKey, (Arc<EventData>, EventLeaf)>,
     pub(super) locked: FxHashSet<PrimaryKey>,
-    pub(super) deleted: FxHashSet<PrimaryKey>,
+    pub(super) deleted: FxHashMap<PrimaryKey, String>,
+    /// Versions of the deleted data objects that were loaded before they were deleted
+    pub(super) deleted_versions: FxHashMap<PrimaryKey, AteHash>,
     pub(super) pipe_unlock: FxHashSet<PrimaryKey>,
     pub(super) auto_cancel: bool,
+    pub(super) optimistic: bool,
 }
 
 impl DioState
@@ -65,7 +69,7 @@ impl DioState
         self.locked.contains(key)
     }
 
-    pub(super) fn add_deleted(&mut self, key: PrimaryKey, parent: Option<MetaParent>)
+    pub(super) fn add_deleted(&mut self, key: PrimaryKey, parent: Option<MetaParent>, type_name: &str)
     {
         if self.lock(&key) == false {
             eprintln!("Detected concurrent write while deleting a data object ({:?}) - the delete operation will override everything else", key);
@@ -77,24 +81,28 @@ impl DioState
                 y.retain(|x| *x == key);
             }
         }
-        self.cache_load.remove(&key);
-        self.deleted.insert(key);
+        if let Some((_, leaf)) = self.cache_load.remove(&key) {
+            self.deleted_versions.insert(key.clone(), leaf.record);
+        }
+        self.deleted.insert(key, type_name.to_string());
     }
 }
 
 impl DioState
 {
     #[allow(dead_code)]
-    fn new() -> DioState {
+    pub(super) fn new() -> DioState {
         DioState {
             store: Vec::new(),
             cache_store_primary: FxHashMap::default(),
             cache_store_secondary: MultiMap::new(),
             cache_load: FxHashMap::default(),
             locked: FxHashSet::default(),
-            deleted: FxHashSet::default(),
+            deleted: FxHashMap::default(),
+            deleted_versions: FxHashMap::default(),
             pipe_unlock: FxHashSet::default(),
             auto_cancel: false,
+            optimistic: false,
         }
     }
 }
@@ -121,6 +129,7 @@ where Self: Send + Sync
     pub(super) scope: TransactionScope,
     pub(super) conversation: Option<Arc<ConversationSession>>,
     pub(super) time: Arc<TimeKeeper>,
+    pub(super) as_of: Option<ChainTimestamp>,
 }
 
 impl<'a> Dio<'a>
@@ -150,15 +159,17 @@ impl<'a> Dio<'a>
                 Some(k) => k,
                 None => PrimaryKey::generate(),
             },
-            type_name: std::any::type_name::<D>(),
+            type_name: std::any::type_name::<D>().to_string(),
             parent: None,
+            order: None,
             data: data,
             auth: MetaAuthorization::default(),
             collections: FxHashSet::default(),
             format,
             created: 0,
             updated: 0,
-            extra_meta: Vec::new()
+            extra_meta: Vec::new(),
+            version: None,
         };
 
         let mut ret = DaoEthereal::new(row);
@@ -190,18 +201,72 @@ impl<'a> Dio<'a>
                 return Ok(());
             }
             if let Some((dao, leaf)) = state.cache_load.get(key) {
-                let row = Row::from_event(dao.deref(), leaf.created, leaf.updated)?;
+                let row = Row::from_event(dao.deref(), &leaf)?;
                 let dao = Dao::new(DaoEthereal::<D>::new(row));
                 dao.delete(self)?;
                 return Ok(());
             }
-            if state.deleted.contains(&key) {
+            if state.deleted.contains_key(&key) {
                 return Result::Err(LoadError::AlreadyDeleted(key.clone()));
             }
         }
         
-        let parent = self.multi.lookup_parent(key).await;
-        self.state.add_deleted(key.clone(), parent);
+        let parent = self.lookup_parent(key).await;
+        self.state.add_deleted(key.clone(), parent, std::any::type_name::<D>());
+        Ok(())
+    }
+
+    /// Deletes a data object along with all the children that are attached to it (directly
+    /// or indirectly) through a `DaoVec`. The tombstones are written in the same transaction
+    /// and each of them must be authorized by the write rights of the child it deletes, hence
+    /// the commit will fail if any part of the tree can not be written by this session
+    pub async fn delete_cascade<D>(&mut self, key: &PrimaryKey) -> Result<(), LoadError>
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        self.delete::<D>(key).await?;
+
+        let mut todo = vec![key.clone()];
+        while let Some(parent_id) = todo.pop()
+        {
+            // Children that were attached in this transaction scope
+            let local = self.state.cache_store_secondary.iter_all()
+                .filter(|(vec, _)| vec.parent_id == parent_id)
+                .flat_map(|(_, keys)| keys.iter().map(|a| a.clone()))
+                .collect::<Vec<_>>();
+            for child in local {
+                if self.state.deleted.contains_key(&child) {
+                    continue;
+                }
+                if let Some(row) = self.state.cache_store_primary.get(&child).map(|a| Arc::clone(a)) {
+                    self.state.add_deleted(child.clone(), row.parent.clone(), row.type_name.as_str());
+                    todo.push(child);
+                }
+            }
+
+            // Children that are already in the chain
+            for child in self.multi.lookup_children(&parent_id).await {
+                if self.state.deleted.contains_key(&child) {
+                    continue;
+                }
+                if self.state.is_locked(&child) {
+                    return Err(LoadError::ObjectStillLocked(child));
+                }
+                // (the child may have been moved somewhere else in this transaction scope)
+                if let Some(row) = self.state.cache_store_primary.get(&child) {
+                    if row.parent.as_ref().map(|a| &a.vec.parent_id) != Some(&parent_id) {
+                        continue;
+                    }
+                }
+
+                if self.multi.lookup_primary(&child).await.is_none() {
+                    continue;
+                }
+                let type_name = self.multi.lookup_type_name(&child).await.unwrap_or_default();
+                let parent = self.multi.lookup_parent(&child).await;
+                self.state.add_deleted(child.clone(), parent, type_name.as_str());
+                todo.push(child);
+            }
+        }
         Ok(())
     }
 
@@ -218,15 +283,15 @@ impl<'a> Dio<'a>
                 return Ok(Dao::new(DaoEthereal::new(row)));
             }
             if let Some((dao, leaf)) = state.cache_load.get(key) {
-                let row = Row::from_event(dao.deref(), leaf.created, leaf.updated)?;
+                let row = Row::from_event(dao.deref(), &leaf)?;
                 return Ok(Dao::new(DaoEthereal::new(row)));
             }
-            if state.deleted.contains(&key) {
+            if state.deleted.contains_key(&key) {
                 return Result::Err(LoadError::AlreadyDeleted(key.clone()));
             }
         }
 
-        let entry = match self.multi.lookup_primary(key).await {
+        let entry = match self.lookup_primary(key).await {
             Some(a) => a,
             None => return Result::Err(LoadError::NotFound(key.clone()))
         };
@@ -244,12 +309,12 @@ impl<'a> Dio<'a>
             if let Some((_, _)) = state.cache_load.get(key) {
                 return true;
             }
-            if state.deleted.contains(&key) {
+            if state.deleted.contains_key(&key) {
                 return false;
             }
         }
 
-        self.multi.lookup_primary(key).await.is_some()
+        self.lookup_primary(key).await.is_some()
     }
 
     pub(crate) async fn load_from_entry<D>(&mut self, leaf: EventLeaf)
@@ -273,7 +338,7 @@ impl<'a> Dio<'a>
         let state = &mut self.state;
         match header.meta.get_data_key() {
             Some(key) => {
-                let row = Row::from_event(&data, leaf.created, leaf.updated)?;
+                let row = Row::from_event(&data, &leaf)?;
                 state.cache_load.insert(key.clone(), (Arc::new(data), leaf));
                 Ok(Dao::new(DaoEthereal::new(row)))
             },
@@ -297,7 +362,7 @@ impl<'a> Dio<'a>
         };
 
         // Build a list of keys
-        let keys = match self.multi.lookup_secondary_raw(&collection_key).await {
+        let keys = match self.lookup_secondary_raw(&collection_key).await {
             Some(a) => a,
             None => return Ok(Vec::new())
         };
@@ -312,7 +377,8 @@ impl<'a> Dio<'a>
         }
 
         // Now we search the secondary local index so any objects we have
-        // added in this transaction scope are returned
+        // added in this transaction scope are returned (unless they are not yet due)
+        let now = self.time.current_timestamp().ok();
         let state = &self.state;
         if let Some(vec) = state.cache_store_secondary.get_vec(&collection_key) {
             for a in vec {
@@ -321,7 +387,7 @@ impl<'a> Dio<'a>
                 if already.contains(a) {
                     continue;
                 }
-                if state.deleted.contains(a) {
+                if state.deleted.contains_key(a) {
                     continue;
                 }
 
@@ -331,6 +397,13 @@ impl<'a> Dio<'a>
                 }
 
                 if let Some(dao) = state.cache_store_primary.get(a) {
+                    let delayed = dao.extra_meta.iter().any(|m| match m {
+                        CoreMetadata::DeliverAt(t) => now.map(|now| *t > now).unwrap_or(true),
+                        _ => false,
+                    });
+                    if delayed {
+                        continue;
+                    }
                     let row = Row::from_row_data(dao.deref())?;
     
                     already.insert(row.key.clone());
@@ -339,6 +412,17 @@ impl<'a> Dio<'a>
             }
         }
 
+        // Sort the children by their position (positions changed in this transaction
+        // scope apply immediately and anything not yet positioned goes on the end)
+        ret.sort_by(|a, b| {
+            match (a.ethereal.row.order_key(), b.ethereal.row.order_key()) {
+                (Some(x), Some(y)) => x.cmp(&y).then_with(|| a.key().cmp(b.key())),
+                (Some(_), None) => std::cmp::Ordering::Less,
+                (None, Some(_)) => std::cmp::Ordering::Greater,
+                (None, None) => std::cmp::Ordering::Equal,
+            }
+        });
+
         Ok(ret)
     }
 
@@ -371,17 +455,17 @@ impl<'a> Dio<'a>
                     continue;
                 }
                 if let Some((dao, leaf)) = state.cache_load.get(&key) {
-                    let row = Row::from_event(dao.deref(), leaf.created, leaf.updated)?;
+                    let row = Row::from_event(dao.deref(), &leaf)?;
                     already.insert(row.key.clone());
                     ret.push(Dao::new(DaoEthereal::new(row)));
                     continue;
                 }
-                if state.deleted.contains(&key) {
+                if state.deleted.contains_key(&key) {
                     continue;
                 }
             }
 
-            to_load.push(match self.multi.lookup_primary(&key).await {
+            to_load.push(match self.lookup_primary(&key).await {
                 Some(a) => a,
                 None => { continue },
             });
@@ -409,12 +493,12 @@ impl<'a> Dio<'a>
                 continue;
             }
             if let Some((dao, leaf)) = state.cache_load.get(&key) {
-                let row = Row::from_event(dao.deref(), leaf.created, leaf.updated)?;
+                let row = Row::from_event(dao.deref(), &leaf)?;
 
                 already.insert(row.key.clone());
                 ret.push(Dao::new(DaoEthereal::new(row)));
             }
-            if state.deleted.contains(&key) {
+            if state.deleted.contains_key(&key) {
                 continue;
             }
 
@@ -435,7 +519,7 @@ impl<'a> Dio<'a>
                 None => { continue; },
             };
 
-            let row = match Row::from_event(&evt.data, evt.leaf.created, evt.leaf.updated) {
+            let row = match Row::from_event(&evt.data, &evt.leaf) {
                 Ok(a) => a,
                 Err(err) => {
                     if allow_serialization_error {
@@ -457,6 +541,44 @@ impl<'a> Dio<'a>
     pub fn session(&'a self) -> &'a AteSession {
         self.session
     }
+
+    /// Returns the point in time this `Dio` is looking at (None means the latest state)
+    pub fn as_of(&self) -> Option<ChainTimestamp> {
+        self.as_of
+    }
+
+    pub(super) async fn lookup_primary(&self, key: &PrimaryKey) -> Option<EventLeaf> {
+        match self.as_of {
+            Some(at) => self.multi.lookup_primary_at(key, at).await,
+            None => self.multi.lookup_primary(key).await,
+        }
+    }
+
+    pub(super) async fn lookup_secondary_raw(&self, key: &MetaCollection) -> Option<Vec<PrimaryKey>> {
+        match self.as_of {
+            Some(at) => self.multi.lookup_secondary_raw_at(key, at).await,
+            None => self.multi.lookup_secondary_raw(key).await,
+        }
+    }
+
+    pub(super) async fn lookup_parent(&self, key: &PrimaryKey) -> Option<MetaParent> {
+        match self.as_of {
+            Some(at) => self.multi.lookup_parent_at(key, at).await,
+            None => self.multi.lookup_parent(key).await,
+        }
+    }
+
+    /// Position of a child within its collection (positions that were changed in
+    /// this scope take precedence over the chain)
+    pub(super) async fn lookup_order(&self, key: &PrimaryKey) -> Option<MetaOrder> {
+        if let Some(order) = self.state.cache_store_primary.get(key).and_then(|a| a.order.clone()) {
+            return Some(order);
+        }
+        match self.as_of {
+            Some(at) => self.multi.lookup_order_at(key, at).await,
+            None => self.multi.lookup_order(key).await,
+        }
+    }
 }
 
 impl Chain
@@ -476,6 +598,52 @@ impl Chain
             scope,
             conversation: self.pipe.conversation().await,
             time: Arc::clone(&self.time),
+            as_of: None,
+        }
+    }
+
+    /// Opens a read-only `Dio` that sees the chain as it was at a particular point in time,
+    /// every data object resolves to its last version at or before this timestamp (history
+    /// that has already been compacted away can no longer be seen)
+    pub async fn dio_at<'a>(&'a self, session: &'a AteSession, timestamp: ChainTimestamp) -> Dio<'a> {
+        let mut ret = self.dio_ext(session, TransactionScope::None).await;
+        ret.as_of = Some(timestamp);
+        ret
+    }
+
+    /// Runs a unit of work against a `Dio` with optimistic concurrency enabled and then
+    /// commits it, if the commit conflicts with another writer then the work is repeated
+    /// on a fresh `Dio` (up to `max_attempts` times in total)
+    pub async fn retry_on_conflict<'a, T, F>(&'a self, session: &'a AteSession, max_attempts: usize, f: F) -> Result<T, AteError>
+    where F: for<'b> FnMut(&'b mut Dio<'a>) -> BoxFuture<'b, Result<T, AteError>>,
+    {
+        self.retry_on_conflict_ext(session, TransactionScope::Local, max_attempts, f).await
+    }
+
+    pub async fn retry_on_conflict_ext<'a, T, F>(&'a self, session: &'a AteSession, scope: TransactionScope, max_attempts: usize, mut f: F) -> Result<T, AteError>
+    where F: for<'b> FnMut(&'b mut Dio<'a>) -> BoxFuture<'b, Result<T, AteError>>,
+    {
+        let mut attempt = 0usize;
+        loop {
+            attempt = attempt + 1;
+
+            let mut dio = self.dio_ext(session, scope).await;
+            dio.optimistic_concurrency();
+            let ret = match f(&mut dio).await {
+                Ok(a) => dio.commit().await.map(|_| a).map_err(AteError::from),
+                Err(err) => {
+                    dio.cancel();
+                    Err(err)
+                }
+            };
+
+            match ret {
+                Err(AteError::CommitError(CommitError::Conflict { key, .. })) if attempt < max_attempts => {
+                    debug!("retry-on-conflict: key={} attempt={}", key, attempt);
+                    continue;
+                },
+                ret => { return ret; }
+            }
         }
     }
 }
@@ -496,6 +664,7 @@ impl<'a> Dio<'a>
         let state = &mut self.state;
         state.store.clear();   
         state.deleted.clear();
+        state.deleted_versions.clear();
     }
 
     pub fn auto_cancel(&mut self)
@@ -504,6 +673,16 @@ impl<'a> Dio<'a>
         state.auto_cancel = true;
     }
 
+    /// Enables optimistic concurrency for this `Dio` which means that any data objects
+    /// that were loaded before being modified (or deleted) will only be committed if no one
+    /// else has changed them in the meantime and new data objects are only created if no one
+    /// else created them first (otherwise the commit fails with `CommitError::Conflict`)
+    pub fn optimistic_concurrency(&mut self)
+    {
+        let state = &mut self.state;
+        state.optimistic = true;
+    }
+
     pub async fn commit(&mut self) -> Result<(), CommitError>
     {
         // If we have dirty records
@@ -512,6 +691,14 @@ impl<'a> Dio<'a>
             return Ok(())
         }
 
+        // Historical views of the chain can not be changed
+        if self.as_of.is_some() {
+            state.store.clear();
+            state.deleted.clear();
+            state.deleted_versions.clear();
+            return Err(CommitError::ReadOnly);
+        }
+
         debug!("commit stored={} deleted={}", state.store.len(), state.deleted.len());
         
         // Declare variables
@@ -529,6 +716,7 @@ impl<'a> Dio<'a>
             let multi_lock = self.multi.lock().await;
 
             // Convert all the events that we are storing into serialize data
+            let mut appended = 0u32;
             for row in state.store.drain(..)
             {
                 // Debug output
@@ -537,12 +725,24 @@ impl<'a> Dio<'a>
 
                 // Build a new clean metadata header
                 let mut meta = Metadata::for_data(row.key);
-                meta.core.push(CoreMetadata::Timestamp(self.time.current_timestamp()?));
+                let timestamp = self.time.current_timestamp()?;
+                meta.core.push(CoreMetadata::Timestamp(timestamp));
                 if row.auth.is_relevant() {
                     meta.core.push(CoreMetadata::Authorization(row.auth.clone()));
                 }
                 if let Some(parent) = &row.parent {
-                    meta.core.push(CoreMetadata::Parent(parent.clone()))
+                    meta.core.push(CoreMetadata::Parent(parent.clone()));
+
+                    // New children without an explicit position are appended to the end
+                    meta.core.push(CoreMetadata::Order(match &row.order {
+                        Some(a) => a.clone(),
+                        // (children written before positions existed keep the position the index gave them)
+                        None if row.created > 0 => MetaOrder::from_timestamp(row.created),
+                        None => {
+                            appended = appended + 1;
+                            MetaOrder::from_sequence(timestamp.time_since_epoch_ms, appended)
+                        }
+                    }));
                 } else {
                     if multi_lock.inside_async.disable_new_roots == true {
                         return Err(CommitError::NewRootsAreDisabled);
@@ -551,6 +751,16 @@ impl<'a> Dio<'a>
                 for extra in row.extra_meta.iter() {
                     meta.core.push(extra.clone());
                 }
+                if state.optimistic {
+                    // (new data objects expect that nothing exists yet under their key)
+                    let version = row.version.clone().unwrap_or(AteHash::NONE);
+                    meta.core.push(CoreMetadata::Precondition(version));
+                }
+                if meta.get_type_name().is_none() && multi_lock.needs_type_name(row.type_name.as_str()) {
+                    meta.core.push(CoreMetadata::Type(MetaType {
+                        type_name: row.type_name.to_string(),
+                    }));
+                }
 
                 // Compute all the extra metadata for an event
                 let extra_meta = multi_lock.metadata_lint_event(&mut meta, &self.session, &trans_meta)?;
@@ -574,6 +784,15 @@ impl<'a> Dio<'a>
                 
                 // Perform any transformation (e.g. data encryption and compression)
                 let data = multi_lock.data_as_underlay(&mut meta, row.data.clone(), &self.session, &trans_meta)?;
+
+                // The hashes of the indexed values would leak the values of encrypted rows
+                // so those rows are indexed by the clients once they decrypt them instead
+                if meta.get_iv().is_ok() {
+                    meta.core.retain(|m| match m {
+                        CoreMetadata::Indexed(_) => false,
+                        _ => true,
+                    });
+                }
                 
                 // Only once all the rows are processed will we ship it to the redo log
                 let evt = EventData {
@@ -585,7 +804,7 @@ impl<'a> Dio<'a>
             }
 
             // Build events that will represent tombstones on all these records (they will be sent after the writes)
-            for key in state.deleted.drain() {
+            for (key, type_name) in state.deleted.drain() {
                 let mut meta = Metadata::default();
                 meta.core.push(CoreMetadata::Timestamp(self.time.current_timestamp()?));
                 meta.core.push(CoreMetadata::Authorization(MetaAuthorization {
@@ -595,6 +814,16 @@ impl<'a> Dio<'a>
                 if let Some(parent) = multi_lock.inside_async.chain.lookup_parent(&key) {
                     meta.core.push(CoreMetadata::Parent(parent))
                 }
+                if multi_lock.needs_type_name(type_name.as_str()) {
+                    meta.core.push(CoreMetadata::Type(MetaType {
+                        type_name,
+                    }));
+                }
+                if state.optimistic {
+                    if let Some(version) = state.deleted_versions.remove(&key) {
+                        meta.core.push(CoreMetadata::Precondition(version));
+                    }
+                }
                 meta.add_tombstone(key);
                 
                 // Compute all the extra metadata for an event
@@ -647,6 +876,7 @@ impl<'a> Dio<'a>
                 Some(c) => Some(Arc::clone(c)),
                 None => None,
             },
+            unconfirmed: false,
         };
         debug!("commit events={}", trans.events.len());
 
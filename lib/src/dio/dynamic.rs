@@ -0,0 +1,92 @@
+#![allow(unused_imports)]
+use log::{info, error, debug};
+use fxhash::FxHashSet;
+use serde_json::Value;
+
+use crate::error::*;
+use crate::header::*;
+use crate::spec::*;
+
+use super::dao::*;
+use super::dio::Dio;
+
+/// Data object that was loaded (or stored) without knowing its type at compile
+/// time, the data is decoded into a JSON value which makes it possible to build
+/// generic tools such as viewers and migration scripts
+///
+/// Only self-describing formats (JSON and MessagePack) can be decoded this way,
+/// note that MessagePack stores structs as arrays of their fields
+pub type DynamicDao = Dao<Value>;
+
+fn check_format(format: SerializationFormat) -> Result<(), SerializationError> {
+    match format {
+        SerializationFormat::Bincode => Err(SerializationError::NotSelfDescribing(format)),
+        _ => Ok(()),
+    }
+}
+
+impl<'a> Dio<'a>
+{
+    /// Loads a data object of any type using the type name stored in its metadata
+    pub async fn load_dynamic(&mut self, key: &PrimaryKey) -> Result<DynamicDao, LoadError>
+    {
+        let mut ret = match self.load::<Value>(key).await {
+            Err(LoadError::SerializationError(SerializationError::BincodeError(_))) => {
+                return Err(LoadError::SerializationError(SerializationError::NotSelfDescribing(SerializationFormat::Bincode)));
+            },
+            ret => ret?,
+        };
+        check_format(ret.ethereal.row.format.data)?;
+
+        let state = &self.state;
+        let type_name = match (state.cache_store_primary.get(key), state.cache_load.get(key)) {
+            (Some(row), _) => Some(row.type_name.clone()),
+            (None, Some((evt, _))) => evt.meta.get_type_name().map(|a| a.type_name.clone()),
+            (None, None) => None,
+        };
+        if let Some(type_name) = type_name {
+            ret.ethereal.row.type_name = type_name;
+        }
+        Ok(ret)
+    }
+
+    /// Stores a data object with a type name that is only known at runtime, the
+    /// value must have the same layout that the real type would serialize to
+    pub fn store_dynamic(&mut self, type_name: &str, data: Value) -> Result<DynamicDao, SerializationError>
+    {
+        let format = match self.session.log_format {
+            Some(a) => a,
+            None => self.multi.default_format
+        };
+        check_format(format.data)?;
+
+        let mut ret = self.make_ext(data, Some(format), None)?;
+        ret.row.type_name = type_name.to_string();
+        ret.commit(self)
+    }
+
+    /// Returns the keys of all the data objects of a particular type
+    pub async fn keys_by_type(&mut self, type_name: &str) -> Vec<PrimaryKey>
+    {
+        let mut ret = match self.as_of {
+            Some(at) => self.multi.lookup_type_at(type_name, at).await,
+            None => self.multi.lookup_type(type_name).await,
+        };
+
+        // Include the objects that were stored or deleted in this transaction
+        let state = &self.state;
+        let mut already = ret.iter().map(|a| a.clone()).collect::<FxHashSet<_>>();
+        for row in state.store.iter() {
+            if row.type_name == type_name && already.insert(row.key.clone()) {
+                ret.push(row.key.clone());
+            }
+        }
+        ret.retain(|a| state.deleted.contains_key(a) == false);
+        ret
+    }
+
+    /// Returns the names of all the types of data objects that currently exist in the chain
+    pub async fn type_names(&self) -> Vec<String> {
+        self.multi.type_names().await
+    }
+}
@@ -0,0 +1,178 @@
+#![allow(unused_imports)]
+use log::{info, error, debug};
+use fxhash::FxHashSet;
+use serde::{Serialize, de::DeserializeOwned};
+use serde_json::{json, Value};
+
+use crate::crypto::AteHash;
+use crate::error::*;
+use crate::event::*;
+use crate::header::*;
+use crate::index::EventLeaf;
+use crate::time::ChainTimestamp;
+
+use super::dio::Dio;
+
+/// One version of a data object as it was written to the chain
+#[derive(Debug, Clone)]
+pub struct DaoVersion<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    pub key: PrimaryKey,
+    /// Hash of the event that wrote this version
+    pub version: AteHash,
+    pub when: ChainTimestamp,
+    pub author: Option<String>,
+    /// Hashes of the public keys that signed this version
+    pub signed_by: Vec<AteHash>,
+    /// Data of the object (None means that this version deleted it)
+    pub data: Option<D>,
+}
+
+impl<D> DaoVersion<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    pub fn is_deleted(&self) -> bool {
+        self.data.is_none()
+    }
+
+    /// Computes the changes between a previous version and this one as a list of
+    /// JSON patch style operations (add, remove and replace) addressed by JSON pointers
+    pub fn diff(&self, previous: Option<&DaoVersion<D>>) -> Result<Value, SerializationError>
+    {
+        let before = match previous.and_then(|a| a.data.as_ref()) {
+            Some(a) => serde_json::to_value(a)?,
+            None => Value::Null,
+        };
+        let after = match &self.data {
+            Some(a) => serde_json::to_value(a)?,
+            None => Value::Null,
+        };
+
+        let mut ops = Vec::new();
+        json_diff("", &before, &after, &mut ops);
+        Ok(Value::Array(ops))
+    }
+}
+
+/// All the versions of a data object that are still held in the chain
+#[derive(Debug, Clone)]
+pub struct DaoHistory<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    /// Versions ordered from the oldest to the newest
+    pub versions: Vec<DaoVersion<D>>,
+    /// If the chain has been compacted then versions older than this point
+    /// in time may no longer exist
+    pub cut_off: Option<ChainTimestamp>,
+}
+
+impl<D> DaoHistory<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    /// Returns true if no versions can have been lost to compaction
+    pub fn is_complete(&self) -> bool {
+        self.cut_off.is_none()
+    }
+
+    /// Computes the changes made by each version relative to the one before it
+    pub fn diffs(&self) -> Result<Vec<Value>, SerializationError>
+    {
+        let mut ret = Vec::new();
+        let mut previous = None;
+        for version in self.versions.iter() {
+            ret.push(version.diff(previous)?);
+            previous = Some(version);
+        }
+        Ok(ret)
+    }
+}
+
+impl<'a> Dio<'a>
+{
+    /// Returns every version of a data object that is still in the chain, when the chain
+    /// is compacted older versions are removed (use `CompactMode::Never` for a full audit trail)
+    pub async fn history<D>(&mut self, key: &PrimaryKey) -> Result<DaoHistory<D>, LoadError>
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        let versions = self.multi.lookup_versions(key).await;
+        let hashes = versions.iter().map(|a| a.1.clone()).collect::<FxHashSet<_>>();
+        let signatures = self.multi.lookup_signatures(&hashes).await;
+        let cut_off = self.multi.cut_off().await?;
+
+        let mut ret = Vec::new();
+        for (when, version, leaf) in versions {
+            let evt = self.multi.load(match leaf {
+                Some(a) => a,
+                None => EventLeaf {
+                    record: version.clone(),
+                    created: 0,
+                    updated: 0,
+                }
+            }).await?;
+            let header = evt.header.as_header()?;
+
+            let data = match (leaf, evt.data.data_bytes) {
+                (Some(_), Some(data)) => {
+                    let data = self.multi.data_as_overlay(&header.meta, data, &self.session)?;
+                    Some(evt.data.format.data.deserialize(&data)?)
+                },
+                _ => None,
+            };
+
+            ret.push(DaoVersion {
+                key: key.clone(),
+                when,
+                author: header.meta.get_author().map(|a| a.clone()),
+                signed_by: signatures.get_vec(&version).map(|a| a.clone()).unwrap_or_default(),
+                version,
+                data,
+            });
+        }
+
+        Ok(DaoHistory {
+            versions: ret,
+            cut_off: match cut_off > ChainTimestamp::from(0u64) {
+                true => Some(cut_off),
+                false => None,
+            }
+        })
+    }
+}
+
+fn json_diff(path: &str, before: &Value, after: &Value, ops: &mut Vec<Value>)
+{
+    match (before, after) {
+        (Value::Object(a), Value::Object(b)) => {
+            for (k, v) in a.iter() {
+                let path = format!("{}/{}", path, escape_pointer(k));
+                match b.get(k) {
+                    Some(v2) => json_diff(&path, v, v2, ops),
+                    None => ops.push(json!({ "op": "remove", "path": path, "old": v })),
+                }
+            }
+            for (k, v) in b.iter().filter(|(k, _)| a.contains_key(*k) == false) {
+                let path = format!("{}/{}", path, escape_pointer(k));
+                ops.push(json!({ "op": "add", "path": path, "value": v }));
+            }
+        },
+        (Value::Array(a), Value::Array(b)) => {
+            for (n, (v, v2)) in a.iter().zip(b.iter()).enumerate() {
+                json_diff(&format!("{}/{}", path, n), v, v2, ops);
+            }
+            // Removals are listed from the back so that the indexes stay valid
+            for n in (b.len()..a.len()).rev() {
+                ops.push(json!({ "op": "remove", "path": format!("{}/{}", path, n), "old": a[n] }));
+            }
+            for n in a.len()..b.len() {
+                ops.push(json!({ "op": "add", "path": format!("{}/{}", path, n), "value": b[n] }));
+            }
+        },
+        (a, b) if a == b => { },
+        (a, b) => ops.push(json!({ "op": "replace", "path": path, "old": a, "value": b })),
+    }
+}
+
+fn escape_pointer(key: &str) -> String {
+    key.replace("~", "~0").replace("/", "~1")
+}
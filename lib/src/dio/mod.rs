@@ -4,8 +4,12 @@ mod obj;
 mod obj_foreign;
 mod bus;
 mod foreign;
-mod test;
+pub(crate) mod test;
 mod dio;
+mod query;
+mod history;
+mod blob;
+mod dynamic;
 
 pub use crate::dio::vec::DaoVec;
 pub use crate::dio::dao::Dao;
@@ -16,4 +20,15 @@ pub use crate::dio::obj::DaoRef;
 pub use crate::dio::obj_foreign::DaoRefForeign;
 pub use crate::dio::foreign::DaoForeign;
 pub use super::dio::dio::Dio;
+pub use super::dio::query::DioQuery;
+pub use super::dio::history::DaoHistory;
+pub use super::dio::history::DaoVersion;
+pub use super::dio::dynamic::DynamicDao;
+pub use super::dio::bus::BusConsumerGroup;
+pub use super::dio::bus::BusDelivery;
+pub use super::dio::bus::DeadLetter;
+pub use super::dio::blob::DaoBlob;
+pub use super::dio::blob::BlobReader;
+pub use super::dio::blob::BlobWriter;
+pub use super::dio::blob::BLOB_PAGE_SIZE;
 pub(crate) use super::dio::dio::DioState;
\ No newline at end of file
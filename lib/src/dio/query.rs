@@ -0,0 +1,101 @@
+#[allow(unused_imports)]
+use log::{info, error, debug};
+use serde::{Serialize, de::DeserializeOwned};
+use std::marker::PhantomData;
+
+use crate::header::*;
+use crate::index::*;
+use crate::error::*;
+
+use super::dao::*;
+use super::dio::*;
+
+/// Builds a query over the objects of a particular type in a chain, queries
+/// are answered from the secondary indexes that were declared on the chain
+/// (see `ChainBuilder::add_index`) rather than scanning the objects
+pub struct DioQuery<'b, 'a, D>
+{
+    dio: &'b mut Dio<'a>,
+    _marker: PhantomData<D>,
+}
+
+impl<'b, 'a, D> DioQuery<'b, 'a, D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+{
+    /// Returns all the objects whose value in the named index matches
+    pub async fn by_index<K>(self, name: &str, value: &K) -> Result<Vec<Dao<D>>, LoadError>
+    where K: Serialize + ?Sized
+    {
+        let index = match self.dio.multi.secondary_index(name) {
+            Some(a) => a,
+            None => { return Err(LoadError::MissingIndex(name.to_string())); }
+        };
+        if index.type_name != std::any::type_name::<D>() {
+            return Err(LoadError::IndexTypeMismatch {
+                index: name.to_string(),
+                expected: index.type_name.clone(),
+                actual: std::any::type_name::<D>().to_string(),
+            });
+        }
+
+        // Rows that were written without their value in the metadata (e.g. before
+        // the index was declared) are indexed here as only the client can read them
+        for key in index.take_dirty() {
+            match self.dio.load_committed::<D>(&key).await {
+                Ok(dao) => {
+                    match serde_json::to_value(&*dao) {
+                        Ok(data) => index.update(key, &data),
+                        Err(err) => debug!("secondary-index({}): skipped {} - {}", index.name, key, err),
+                    }
+                },
+                Err(LoadError::NotFound(_)) |
+                Err(LoadError::AlreadyDeleted(_)) |
+                Err(LoadError::Tombstoned(_)) => index.remove(&key),
+                Err(err) => {
+                    debug!("secondary-index({}): skipped {} - {}", index.name, key, err);
+                    index.mark_dirty(key);
+                }
+            }
+        }
+
+        // Rows that this session is unable to read are left out of the results
+        let mut ret = Vec::new();
+        for key in index.lookup(&SecondaryIndex::encode(value)) {
+            match self.dio.load::<D>(&key).await {
+                Ok(a) => ret.push(a),
+                Err(LoadError::NotFound(_)) |
+                Err(LoadError::AlreadyDeleted(_)) |
+                Err(LoadError::Tombstoned(_)) => { },
+                Err(err) => {
+                    debug!("secondary-index({}): skipped {} - {}", index.name, key, err);
+                }
+            }
+        }
+        Ok(ret)
+    }
+}
+
+impl<'a> Dio<'a>
+{
+    /// Starts a query over all the objects of a particular type
+    pub fn query<'b, D>(&'b mut self) -> DioQuery<'b, 'a, D>
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync + 'static,
+    {
+        DioQuery {
+            dio: self,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Loads the version of an object that is in the chain ignoring any changes
+    /// that were made to it in this scope that are not yet committed
+    pub(crate) async fn load_committed<D>(&mut self, key: &PrimaryKey) -> Result<Dao<D>, LoadError>
+    where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+    {
+        let entry = match self.lookup_primary(key).await {
+            Some(a) => a,
+            None => return Result::Err(LoadError::NotFound(key.clone()))
+        };
+        self.load_from_entry(entry).await
+    }
+}
@@ -6,6 +6,15 @@ use serde::{Serialize, de::DeserializeOwned};
 use crate::dio::*;
 use crate::crypto::*;
 use crate::prelude::*;
+use crate::error::LoadError;
+use crate::error::CommitError;
+use crate::error::ValidationError;
+use crate::error::SerializationError;
+use crate::error::BusError;
+use crate::spec::MessageFormat;
+use crate::meta::{Metadata, CoreMetadata, MetaType, MetaParent, MetaCollection};
+use crate::event::EventData;
+use std::sync::Arc;
 
 #[cfg(test)]
 #[derive(Serialize, Deserialize, Debug, Clone)]
@@ -223,4 +232,909 @@ async fn test_dio() -> Result<(), AteError>
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+#[cfg(test)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct TestUserDao
+{
+    pub email: String,
+    pub team: String,
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_query() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    // The rows are encrypted so the indexes can only be built after they are decrypted
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_query", |builder| builder
+        .add_data_transformer(Box::new(crate::transform::StaticEncryptionTransformer::new(&EncryptKey::from_seed_string("test".to_string(), KeySize::Bit192))))
+        .add_index("email", IndexMode::Unique, |u: &TestUserDao| u.email.clone())
+        .add_index("team", IndexMode::NonUnique, |u: &TestUserDao| u.team.clone())
+    ).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let (key1, key2) = {
+        let mut dio = chain.dio(&session).await;
+        let dao1 = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        let dao2 = dio.store(TestUserDao { email: "bob@here.com".to_string(), team: "red".to_string() })?;
+        dio.store(TestUserDao { email: "carol@here.com".to_string(), team: "blue".to_string() })?;
+        dio.store(TestEnumDao::Blah3("alice@here.com".to_string()))?;
+        dio.commit().await?;
+        (dao1.key().clone(), dao2.key().clone())
+    };
+
+    // The values of the encrypted rows are not hashed into their metadata
+    {
+        let multi = chain.multi().await;
+        let leaf = multi.lookup_primary(&key1).await.expect("The row should exist");
+        let meta = multi.load(leaf).await?.data.meta;
+        assert!(meta.get_type_name().is_some());
+        assert!(meta.get_indexed("email").is_none());
+        assert!(meta.get_indexed("team").is_none());
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let found = dio.query::<TestUserDao>().by_index("email", "alice@here.com").await?;
+        assert_eq!(found.len(), 1);
+        assert_eq!(found[0].key(), &key1);
+        assert_eq!(dio.query::<TestUserDao>().by_index("team", "red").await?.len(), 2);
+        assert_eq!(dio.query::<TestUserDao>().by_index("team", "green").await?.len(), 0);
+
+        assert!(matches!(dio.query::<TestUserDao>().by_index("name", "alice").await, Err(LoadError::MissingIndex(_))));
+        assert!(matches!(dio.query::<TestEnumDao>().by_index("email", "alice@here.com").await, Err(LoadError::IndexTypeMismatch { .. })));
+
+        // Changes to the rows are picked up by the indexes once they are committed
+        let mut dao1 = dio.load::<TestUserDao>(&key1).await?;
+        dao1.email = "alice@there.com".to_string();
+        dao1.commit(&mut dio)?;
+        dio.load::<TestUserDao>(&key2).await?.delete(&mut dio)?;
+        dio.commit().await?;
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        assert_eq!(dio.query::<TestUserDao>().by_index("email", "alice@here.com").await?.len(), 0);
+        assert_eq!(dio.query::<TestUserDao>().by_index("email", "alice@there.com").await?.len(), 1);
+        assert_eq!(dio.query::<TestUserDao>().by_index("team", "red").await?.len(), 1);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_optimistic() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_optimistic", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let key = {
+        let mut dio = chain.dio(&session).await;
+        let dao = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        assert!(dao.version().is_none());
+        dio.commit().await?;
+        dao.key().clone()
+    };
+
+    {
+        // Two writers load the same version of the row
+        let mut dio1 = chain.dio(&session).await;
+        let mut dio2 = chain.dio(&session).await;
+        dio1.optimistic_concurrency();
+        dio2.optimistic_concurrency();
+        let mut dao1 = dio1.load::<TestUserDao>(&key).await?;
+        let mut dao2 = dio2.load::<TestUserDao>(&key).await?;
+        assert!(dao1.version().is_some());
+        assert_eq!(dao1.version(), dao2.version());
+
+        // The first one wins and the second one is told the row has changed
+        dao1.team = "blue".to_string();
+        dao1.commit(&mut dio1)?;
+        dio1.commit().await?;
+
+        dao2.team = "green".to_string();
+        dao2.commit(&mut dio2)?;
+        match dio2.commit().await {
+            Err(CommitError::Conflict { key: conflict, expected, actual }) => {
+                assert_eq!(conflict, key);
+                assert_eq!(Some(&expected), dao2.version());
+                assert!(actual.is_some());
+                assert_ne!(actual.as_ref(), dao2.version());
+            },
+            _ => panic!("the second commit should have conflicted"),
+        }
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        assert_eq!(dio.load::<TestUserDao>(&key).await?.team, "blue".to_string());
+    }
+
+    // Data objects that are created under the same key by two writers conflict as well
+    {
+        let created = PrimaryKey::generate();
+        let mut dio1 = chain.dio(&session).await;
+        let mut dio2 = chain.dio(&session).await;
+        dio1.optimistic_concurrency();
+        dio2.optimistic_concurrency();
+        dio1.store_ext(TestUserDao { email: "dave@here.com".to_string(), team: "red".to_string() }, None, Some(created.clone()))?;
+        dio2.store_ext(TestUserDao { email: "dave@here.com".to_string(), team: "blue".to_string() }, None, Some(created.clone()))?;
+        dio1.commit().await?;
+        assert!(matches!(dio2.commit().await, Err(CommitError::Conflict { actual: Some(_), .. })));
+    }
+
+    // The retry helper repeats the work when another writer gets in first
+    let mut attempts = 0usize;
+    let team = chain.retry_on_conflict(&session, 3, |dio| {
+        attempts = attempts + 1;
+        let interfere = attempts == 1;
+        let chain = &chain;
+        let session = &session;
+        Box::pin(async move {
+            let mut dao = dio.load::<TestUserDao>(&key).await?;
+            if interfere {
+                let mut other = chain.dio(session).await;
+                let mut dao = other.load::<TestUserDao>(&key).await?;
+                dao.team = "yellow".to_string();
+                dao.commit(&mut other)?;
+                other.commit().await?;
+            }
+            dao.team = format!("{}-purple", dao.team);
+            dao.commit(dio)?;
+            Ok(dao.team.clone())
+        })
+    }).await?;
+    assert_eq!(attempts, 2);
+    assert_eq!(team, "yellow-purple".to_string());
+
+    {
+        let mut dio = chain.dio(&session).await;
+        assert_eq!(dio.load::<TestUserDao>(&key).await?.team, "yellow-purple".to_string());
+    }
+
+    // Events that are replayed (e.g. from a server or an import) are not checked again
+    // as the version they refer to may have since been compacted away
+    {
+        let format = MessageFormat { meta: SerializationFormat::Json, data: SerializationFormat::Json };
+        let mut meta = Metadata::for_data(key.clone());
+        meta.core.push(CoreMetadata::Precondition(AteHash::from_bytes(b"compacted")));
+        let data = format.data.serialize(&TestUserDao { email: "alice@here.com".to_string(), team: "replayed".to_string() })?;
+        chain.pipe.feed(crate::transaction::Transaction {
+            scope: TransactionScope::Local,
+            transmit: false,
+            events: vec![EventData { meta, data_bytes: Some(bytes::Bytes::from(data)), format }],
+            conversation: None,
+            unconfirmed: false,
+        }).await?;
+
+        let mut dio = chain.dio(&session).await;
+        assert_eq!(dio.load::<TestUserDao>(&key).await?.team, "replayed".to_string());
+    }
+
+    Ok(())
+}
+
+/// Views of the past are by timestamp so the versions that a test compares must
+/// be written in different milliseconds
+#[cfg(test)]
+async fn wait_for_clock(chain: &Chain, after: ChainTimestamp) {
+    while chain.time.current_timestamp().unwrap() <= after {
+        tokio::task::yield_now().await;
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_at() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_at", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    // Version one of the object has no children
+    let key = {
+        let mut dio = chain.dio(&session).await;
+        let dao = dio.store(TestStructDao { val: 1, ..Default::default() })?;
+        dio.commit().await?;
+        dao.key().clone()
+    };
+    let t1 = ChainTimestamp::from(chain.dio(&session).await.load::<TestStructDao>(&key).await?.when_updated());
+    wait_for_clock(&chain, t1).await;
+
+    // Version two has a child
+    let child = {
+        let mut dio = chain.dio(&session).await;
+        let mut dao = dio.load::<TestStructDao>(&key).await?;
+        let child = dao.push_store(&mut dio, dao.inner, TestEnumDao::Blah1)?;
+        dao.val = 2;
+        dao.commit(&mut dio)?;
+        dio.commit().await?;
+        child.key().clone()
+    };
+    let t2 = ChainTimestamp::from(chain.dio(&session).await.load::<TestStructDao>(&key).await?.when_updated());
+    assert!(t2 > t1);
+    wait_for_clock(&chain, t2).await;
+
+    // Then everything is deleted
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.delete::<TestEnumDao>(&child).await?;
+        dio.delete::<TestStructDao>(&key).await?;
+        dio.commit().await?;
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        assert!(matches!(dio.load::<TestStructDao>(&key).await, Err(LoadError::NotFound(_))));
+    }
+
+    {
+        let mut dio = chain.dio_at(&session, ChainTimestamp::from(t1.time_since_epoch_ms - 1)).await;
+        assert!(matches!(dio.load::<TestStructDao>(&key).await, Err(LoadError::NotFound(_))));
+    }
+
+    {
+        let mut dio = chain.dio_at(&session, t1).await;
+        let dao = dio.load::<TestStructDao>(&key).await?;
+        assert_eq!(dao.val, 1);
+        assert_eq!(dao.iter(&mut dio, dao.inner).await?.count(), 0);
+    }
+
+    {
+        let mut dio = chain.dio_at(&session, t2).await;
+        let mut dao = dio.load::<TestStructDao>(&key).await?;
+        assert_eq!(dao.val, 2);
+        let children = dao.iter(&mut dio, dao.inner).await?.collect::<Vec<_>>();
+        assert_eq!(children.len(), 1);
+        assert_eq!(children[0].key(), &child);
+        assert_eq!(dio.children::<TestEnumDao>(key.clone(), dao.inner.vec_id).await?.len(), 1);
+
+        // Views of the past are read-only
+        dao.val = 3;
+        dao.commit(&mut dio)?;
+        assert!(matches!(dio.commit().await, Err(CommitError::ReadOnly)));
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_history() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let write_key = PrivateSignKey::generate(KeySize::Bit192);
+    let mut session = AteSession::new(&ConfAte::default());
+    session.user.properties.push(AteSessionProperty::WriteKey(write_key.clone()));
+    session.user.properties.push(AteSessionProperty::Identity("author@here.com".to_string()));
+
+    let mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = ChainBuilder::new(&mock_cfg)
+        .await
+        .add_root_public_key(&write_key.as_public_key())
+        .add_metadata_linter(Box::new(crate::lint::EventAuthorLinter::default()))
+        .build()
+        .open(&ChainKey::default().with_temp_name("test_dio_history".to_string()))
+        .await?;
+
+    let key = {
+        let mut dio = chain.dio(&session).await;
+        let dao = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        dio.commit().await?;
+        dao.key().clone()
+    };
+    for team in vec!["blue", "green"] {
+        let mut dio = chain.dio(&session).await;
+        let mut dao = dio.load::<TestUserDao>(&key).await?;
+        dao.team = team.to_string();
+        dao.commit(&mut dio)?;
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.delete::<TestUserDao>(&key).await?;
+        dio.commit().await?;
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let history = dio.history::<TestUserDao>(&key).await?;
+        assert!(history.is_complete());
+        assert_eq!(history.versions.len(), 4);
+        assert_eq!(history.versions.iter().filter_map(|a| a.data.as_ref()).map(|a| a.team.as_str()).collect::<Vec<_>>(), vec!["red", "blue", "green"]);
+        assert!(history.versions[3].is_deleted());
+        for version in history.versions.iter().take(3) {
+            assert_eq!(version.key, key);
+            assert_eq!(version.author.as_ref().map(|a| a.as_str()), Some("author@here.com"));
+            assert!(version.signed_by.contains(&write_key.hash()));
+        }
+        assert!(history.versions.windows(2).all(|a| a[0].when <= a[1].when));
+
+        let diffs = history.diffs()?;
+        assert_eq!(diffs[1], serde_json::json!([{ "op": "replace", "path": "/team", "old": "red", "value": "blue" }]));
+        assert_eq!(diffs[3], serde_json::json!([{ "op": "replace", "path": "", "old": { "email": "alice@here.com", "team": "green" }, "value": null }]));
+    }
+
+    // Once the chain is compacted the history can no longer be trusted to be complete
+    chain.compact().await?;
+    {
+        let mut dio = chain.dio(&session).await;
+        assert!(dio.history::<TestUserDao>(&key).await?.is_complete() == false);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dao_vec_order() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dao_vec_order", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    async fn values<'a>(dio: &mut Dio<'a>, root: &Dao<TestStructDao>) -> Result<Vec<u32>, LoadError> {
+        Ok(root.iter(dio, root.inner).await?
+            .map(|a| match *a { TestEnumDao::Blah2(n) => n, _ => 0 })
+            .collect::<Vec<_>>())
+    }
+
+    // Children pushed in the same transaction keep the order they were pushed
+    let (root_key, key1, key2, key3) = {
+        let mut dio = chain.dio(&session).await;
+        let mut root = dio.store(TestStructDao::default())?;
+        let inner = root.inner;
+        let key1 = root.push_store(&mut dio, inner, TestEnumDao::Blah2(1))?.key().clone();
+        let key2 = root.push_store(&mut dio, inner, TestEnumDao::Blah2(2))?.key().clone();
+        let key3 = root.push_store(&mut dio, inner, TestEnumDao::Blah2(3))?.key().clone();
+        dio.commit().await?;
+        (root.key().clone(), key1, key2, key3)
+    };
+
+    // Insert at the front and in the middle
+    let key0 = {
+        let mut dio = chain.dio(&session).await;
+        let mut root = dio.load::<TestStructDao>(&root_key).await?;
+        let inner = root.inner;
+        let key0 = root.push_front(&mut dio, inner, TestEnumDao::Blah2(0)).await?.key().clone();
+        root.insert_after(&mut dio, inner, &key2, TestEnumDao::Blah2(25)).await?;
+        dio.commit().await?;
+        key0
+    };
+    {
+        let mut dio = chain.dio(&session).await;
+        let root = dio.load::<TestStructDao>(&root_key).await?;
+        assert_eq!(values(&mut dio, &root).await?, vec![0, 1, 2, 25, 3]);
+    }
+
+    // Moving a child is visible straight away and after it is committed
+    {
+        let mut dio = chain.dio(&session).await;
+        let root = dio.load::<TestStructDao>(&root_key).await?;
+        let mut dao = dio.load::<TestEnumDao>(&key3).await?;
+        dao.move_before(&mut dio, &key1).await?;
+        dao.commit(&mut dio)?;
+        assert_eq!(values(&mut dio, &root).await?, vec![0, 3, 1, 2, 25]);
+        dio.commit().await?;
+    }
+
+    // Updating the data of a child leaves it where it is
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut dao = dio.load::<TestEnumDao>(&key3).await?;
+        *dao = TestEnumDao::Blah2(33);
+        dao.commit(&mut dio)?;
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        let root = dio.load::<TestStructDao>(&root_key).await?;
+        assert_eq!(values(&mut dio, &root).await?, vec![0, 33, 1, 2, 25]);
+
+        // Pages only load the children that were asked for
+        let page = root.iter_range(&mut dio, root.inner, 1, 2).await?
+            .map(|a| match *a { TestEnumDao::Blah2(n) => n, _ => 0 })
+            .collect::<Vec<_>>();
+        assert_eq!(page, vec![33, 1]);
+        assert_eq!(root.iter_range(&mut dio, root.inner, 4, 10).await?.count(), 1);
+        assert_eq!(root.iter_range(&mut dio, root.inner, 5, 10).await?.count(), 0);
+    }
+
+    // Concurrent inserts at the same spot end up next to each other in the same order for everyone
+    {
+        let mut dio1 = chain.dio(&session).await;
+        let mut dio2 = chain.dio(&session).await;
+        let mut root1 = dio1.load::<TestStructDao>(&root_key).await?;
+        let mut root2 = dio2.load::<TestStructDao>(&root_key).await?;
+        let inner = root1.inner;
+        let a = root1.insert_after(&mut dio1, inner, &key0, TestEnumDao::Blah2(7)).await?.key().clone();
+        let b = root2.insert_after(&mut dio2, inner, &key0, TestEnumDao::Blah2(8)).await?.key().clone();
+        dio1.commit().await?;
+        dio2.commit().await?;
+
+        let mut dio = chain.dio(&session).await;
+        let root = dio.load::<TestStructDao>(&root_key).await?;
+        let expected = match a < b {
+            true => vec![0, 7, 8, 33, 1, 2, 25],
+            false => vec![0, 8, 7, 33, 1, 2, 25],
+        };
+        assert_eq!(values(&mut dio, &root).await?, expected);
+    }
+
+    // Children written before positions existed stay where they are when they are edited
+    {
+        let (legacy_root, inner) = {
+            let mut dio = chain.dio(&session).await;
+            let root = dio.store(TestStructDao::default())?;
+            dio.commit().await?;
+            (root.key().clone(), root.inner)
+        };
+
+        let format = MessageFormat { meta: SerializationFormat::Json, data: SerializationFormat::Json };
+        let mut legacy = Vec::new();
+        let mut evts = Vec::new();
+        for n in 1..=3u32 {
+            let key = PrimaryKey::generate();
+            let mut meta = Metadata::for_data(key.clone());
+            meta.core.push(CoreMetadata::Timestamp(ChainTimestamp::from(1000u64 + n as u64)));
+            meta.core.push(CoreMetadata::Parent(MetaParent {
+                vec: MetaCollection { parent_id: legacy_root.clone(), collection_id: inner.vec_id },
+            }));
+            let data = format.data.serialize(&TestEnumDao::Blah2(n))?;
+            evts.push(EventData { meta, data_bytes: Some(bytes::Bytes::from(data)), format });
+            legacy.push(key);
+        }
+        chain.pipe.feed(crate::transaction::Transaction {
+            scope: TransactionScope::Local,
+            transmit: false,
+            events: evts,
+            conversation: None,
+            unconfirmed: false,
+        }).await?;
+
+        {
+            let mut dio = chain.dio(&session).await;
+            let mut dao = dio.load::<TestEnumDao>(&legacy[0]).await?;
+            *dao = TestEnumDao::Blah2(11);
+            dao.commit(&mut dio)?;
+            dio.commit().await?;
+        }
+
+        let mut dio = chain.dio(&session).await;
+        let root = dio.load::<TestStructDao>(&legacy_root).await?;
+        assert_eq!(values(&mut dio, &root).await?, vec![11, 2, 3]);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_delete_cascade() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    mock_cfg.sync_tolerance = std::time::Duration::from_millis(0);
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_delete_cascade", |builder| builder
+        .add_compactor(Box::new(crate::compact::OrphanCompactor::default()))
+    ).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let (root1, children1, root2, child2, root3, child3) = {
+        let mut dio = chain.dio(&session).await;
+
+        // A tree with children in multiple collections and a grandchild
+        let mut root1 = dio.store(TestStructDao::default())?;
+        let inner = root1.inner;
+        let child1 = root1.push_store(&mut dio, inner, TestEnumDao::Blah2(1))?;
+        let child2 = root1.push_store(&mut dio, inner, TestEnumDao::Blah2(2))?;
+        let mut grandchild = dio.make(TestEnumDao::Blah4)?;
+        grandchild.attach_orphaned_ext(child1.key().clone(), 7);
+        let grandchild = grandchild.commit(&mut dio)?;
+
+        // A tree that will be deleted without cascading
+        let mut root2 = dio.store(TestStructDao::default())?;
+        let inner = root2.inner;
+        let child2b = root2.push_store(&mut dio, inner, TestEnumDao::Blah1)?;
+
+        // A tree that will be left alone
+        let mut root3 = dio.store(TestStructDao::default())?;
+        let inner = root3.inner;
+        let child3 = root3.push_store(&mut dio, inner, TestEnumDao::Blah1)?;
+        dio.commit().await?;
+
+        (
+            root1.key().clone(),
+            vec![child1.key().clone(), child2.key().clone(), grandchild.key().clone()],
+            root2.key().clone(),
+            child2b.key().clone(),
+            root3.key().clone(),
+            child3.key().clone()
+        )
+    };
+
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.delete_cascade::<TestStructDao>(&root1).await?;
+        dio.delete::<TestStructDao>(&root2).await?;
+
+        // Children that are added in the same transaction are also deleted
+        let mut root3 = dio.load::<TestStructDao>(&root3).await?;
+        let inner = root3.inner;
+        let child = root3.push_store(&mut dio, inner, TestEnumDao::Blah5)?;
+        dio.delete_cascade::<TestStructDao>(root3.key()).await?;
+        assert!(dio.exists(child.key()).await == false);
+        dio.cancel();
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        let root1 = dio.load::<TestStructDao>(&root1).await?;
+        assert_eq!(root1.iter(&mut dio, root1.inner).await?.count(), 2);
+        assert!(dio.exists(&child2).await);
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.delete_cascade::<TestStructDao>(&root1).await?;
+        dio.delete::<TestStructDao>(&root2).await?;
+        dio.commit().await?;
+    }
+
+    // The whole tree is gone except for the child whose parent was deleted on its own
+    {
+        let mut dio = chain.dio(&session).await;
+        assert!(dio.exists(&root1).await == false);
+        for child in children1.iter() {
+            assert!(dio.exists(child).await == false);
+        }
+        assert!(dio.exists(&child2).await);
+        assert!(dio.exists(&child3).await);
+    }
+
+    // An object that is created again after it was deleted keeps its new children
+    let child1b = {
+        let mut dio = chain.dio(&session).await;
+        let mut root = dio.store_ext(TestStructDao::default(), None, Some(root1.clone()))?;
+        let inner = root.inner;
+        let child = root.push_store(&mut dio, inner, TestEnumDao::Blah2(9))?;
+        dio.commit().await?;
+        child.key().clone()
+    };
+
+    // Compacting the chain sweeps up the orphan
+    tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    chain.compact().await?;
+    {
+        let mut dio = chain.dio(&session).await;
+        assert!(dio.exists(&child2).await == false);
+        assert!(dio.exists(&root3).await);
+        assert!(dio.exists(&child3).await);
+        assert!(dio.exists(&root1).await);
+        assert!(dio.exists(&child1b).await);
+    }
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_unique_constraint() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_unique_constraint", |builder| builder
+        .add_unique_constraint::<TestUserDao>("email", &["/email"])
+    ).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let key1 = {
+        let mut dio = chain.dio(&session).await;
+        let dao1 = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        dio.store(TestUserDao { email: "bob@here.com".to_string(), team: "red".to_string() })?;
+        dio.commit().await?;
+        dao1.key().clone()
+    };
+    // (claims made in the same millisecond are ordered by their hash instead)
+    wait_for_clock(&chain, chain.time.current_timestamp().unwrap()).await;
+
+    // Another data object can not claim the same value
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "blue".to_string() })?;
+        match dio.commit().await {
+            Err(CommitError::ValidationError(errs)) => {
+                assert!(errs.iter().any(|a| match a {
+                    ValidationError::UniqueViolation { constraint, existing } => constraint == "email" && *existing == key1,
+                    _ => false,
+                }));
+            },
+            _ => panic!("the duplicate value should have been rejected"),
+        }
+    }
+
+    // The owner can update itself without releasing the value
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut dao1 = dio.load::<TestUserDao>(&key1).await?;
+        dao1.team = "blue".to_string();
+        dao1.commit(&mut dio)?;
+        dio.commit().await?;
+    }
+
+    // Later versions that do not record their type must still declare their claim
+    {
+        let format = MessageFormat { meta: SerializationFormat::Json, data: SerializationFormat::Json };
+        let meta = Metadata::for_data(key1.clone());
+        let data = format.data.serialize(&TestUserDao { email: "alice@here.com".to_string(), team: "unclaimed".to_string() })?;
+        let ret = chain.pipe.feed(crate::transaction::Transaction {
+            scope: TransactionScope::Local,
+            transmit: true,
+            events: vec![EventData { meta, data_bytes: Some(bytes::Bytes::from(data)), format }],
+            conversation: None,
+            unconfirmed: false,
+        }).await;
+        match ret {
+            Err(CommitError::ValidationError(errs)) => {
+                assert!(errs.iter().any(|a| match a {
+                    ValidationError::Denied(_) => true,
+                    _ => false,
+                }));
+            },
+            _ => panic!("the version without a claim should have been rejected"),
+        }
+    }
+
+    // Once the value is released (or deleted) it can be claimed again
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut dao1 = dio.load::<TestUserDao>(&key1).await?;
+        dao1.email = "alice@there.com".to_string();
+        dao1.commit(&mut dio)?;
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "blue".to_string() })?;
+        dio.delete::<TestUserDao>(&key1).await?;
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        dio.store(TestUserDao { email: "alice@there.com".to_string(), team: "green".to_string() })?;
+        dio.commit().await?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TestFileDao
+{
+    name: String,
+    contents: DaoBlob,
+}
+
+#[tokio::main]
+#[test]
+async fn test_dao_blob() -> Result<(), AteError>
+{
+    use tokio::io::{AsyncReadExt, AsyncWriteExt, AsyncSeekExt};
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dao_blob", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    // Write a few pages of data and then some more after a hole
+    let data = (0..(BLOB_PAGE_SIZE * 2 + 1000)).map(|a| (a % 251) as u8).collect::<Vec<_>>();
+    let hole = BLOB_PAGE_SIZE as u64 * 8;
+    let key = {
+        let mut dio = chain.dio(&session).await;
+        let file = dio.store(TestFileDao::default())?;
+        dio.commit().await?;
+
+        let mut writer = file.blob_writer(&dio, file.contents).await?;
+        writer.write_all(&data[..]).await?;
+        writer.seek(std::io::SeekFrom::Start(hole)).await?;
+        writer.write_all(b"tail").await?;
+        writer.shutdown().await?;
+        assert_eq!(writer.len(), hole + 4);
+        file.key().clone()
+    };
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let file = dio.load::<TestFileDao>(&key).await?;
+        let mut reader = file.blob_reader(&dio, file.contents).await?;
+        assert_eq!(reader.len(), hole + 4);
+
+        let mut ret = Vec::new();
+        reader.read_to_end(&mut ret).await?;
+        assert_eq!(ret.len() as u64, hole + 4);
+        assert_eq!(&ret[..data.len()], &data[..]);
+        assert!(ret[data.len()..hole as usize].iter().all(|a| *a == 0));
+        assert_eq!(&ret[hole as usize..], b"tail");
+
+        // Overwrite a few bytes that span two of the pages
+        let mut writer = file.blob_writer(&dio, file.contents).await?;
+        writer.seek(std::io::SeekFrom::Start(BLOB_PAGE_SIZE as u64 - 2)).await?;
+        writer.write_all(b"hello").await?;
+        writer.flush().await?;
+        assert_eq!(writer.len(), hole + 4);
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let file = dio.load::<TestFileDao>(&key).await?;
+        let mut reader = file.blob_reader(&dio, file.contents).await?;
+        reader.seek(std::io::SeekFrom::Start(BLOB_PAGE_SIZE as u64 - 4)).await?;
+        let mut ret = [0u8; 9];
+        reader.read_exact(&mut ret).await?;
+        assert_eq!(&ret[..2], &data[BLOB_PAGE_SIZE - 4..BLOB_PAGE_SIZE - 2]);
+        assert_eq!(&ret[2..7], b"hello");
+        assert_eq!(&ret[7..], &data[BLOB_PAGE_SIZE + 3..BLOB_PAGE_SIZE + 5]);
+
+        // The pages are children of the parent and thus are deleted along with it
+        assert_eq!(chain.multi().await.lookup_children(&key).await.len(), 5);
+        dio.delete_cascade::<TestFileDao>(&key).await?;
+        dio.commit().await?;
+    }
+
+    assert_eq!(chain.multi().await.lookup_children(&key).await.len(), 0);
+
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_dio_dynamic() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dio_dynamic", |builder| builder
+        .track_types(true)
+    ).await;
+    let session = AteSession::new(&mock_cfg);
+    let type_name = std::any::type_name::<TestUserDao>();
+    let bincode = MessageFormat { meta: SerializationFormat::Bincode, data: SerializationFormat::Bincode };
+
+    let (key1, key2, key3, key4) = {
+        let mut dio = chain.dio(&session).await;
+        let dao1 = dio.store(TestUserDao { email: "alice@here.com".to_string(), team: "red".to_string() })?;
+        let dao2 = dio.store_dynamic(type_name, serde_json::json!({ "email": "bob@here.com", "team": "blue" }))?;
+        let dao3 = dio.store_ext(TestUserDao { email: "carol@here.com".to_string(), team: "red".to_string() }, Some(bincode), None)?;
+        let dao4 = dio.store(TestEnumDao::Blah1)?;
+        assert_eq!(dio.keys_by_type(type_name).await.len(), 3);
+        dio.commit().await?;
+        (dao1.key().clone(), dao2.key().clone(), dao3.key().clone(), dao4.key().clone())
+    };
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut keys = dio.keys_by_type(type_name).await;
+        keys.sort();
+        let mut expected = vec![key1.clone(), key2.clone(), key3.clone()];
+        expected.sort();
+        assert_eq!(keys, expected);
+        assert!(dio.type_names().await.contains(&type_name.to_string()));
+        assert_eq!(dio.keys_by_type(std::any::type_name::<TestEnumDao>()).await.len(), 1);
+
+        // Objects can be read and changed without knowing their type
+        let mut dao1 = dio.load_dynamic(&key1).await?;
+        assert_eq!(dao1.type_name(), type_name);
+        assert_eq!(dao1["email"], "alice@here.com");
+        dao1["team"] = serde_json::json!("green");
+        dao1.commit(&mut dio)?;
+
+        // Bincode is not self-describing so it can not be decoded
+        assert!(matches!(dio.load_dynamic(&key3).await, Err(LoadError::SerializationError(SerializationError::NotSelfDescribing(_)))));
+        dio.delete::<TestUserDao>(&key3).await?;
+        assert_eq!(dio.keys_by_type(type_name).await.len(), 2);
+        dio.delete::<TestEnumDao>(&key4).await?;
+        dio.commit().await?;
+    }
+
+    {
+        let mut dio = chain.dio(&session).await;
+        assert_eq!(dio.load::<TestUserDao>(&key1).await?.team, "green");
+        assert_eq!(dio.load::<TestUserDao>(&key2).await?.email, "bob@here.com");
+        assert_eq!(dio.keys_by_type(type_name).await.len(), 2);
+
+        // Types that no longer have any data objects are not listed
+        assert!(dio.type_names().await.contains(&type_name.to_string()));
+        assert!(dio.type_names().await.contains(&std::any::type_name::<TestEnumDao>().to_string()) == false);
+
+        let mut session = session.clone();
+        session.log_format = Some(bincode);
+        let mut dio = chain.dio(&session).await;
+        assert!(matches!(dio.store_dynamic(type_name, serde_json::json!({})), Err(SerializationError::NotSelfDescribing(_))));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct TestQueueDao
+{
+    pub jobs: DaoVec<TestEnumDao>,
+    pub dead: DaoVec<DeadLetter<TestEnumDao>>,
+}
+
+#[tokio::main]
+#[test]
+async fn test_dao_vec_delayed() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let chain = crate::trust::create_barebone_chain(&mut mock_cfg, "test_dao_vec_delayed", |builder| builder).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let (key, jobs) = {
+        let mut dio = chain.dio(&session).await;
+        let queue = dio.store(TestQueueDao::default())?;
+        dio.commit().await?;
+        (queue.key().clone(), queue.jobs)
+    };
+    let mut bus = {
+        let mut dio = chain.dio(&session).await;
+        let queue: Dao<TestQueueDao> = dio.load(&key).await?;
+        queue.bus(&chain, jobs)
+    };
+
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut queue: Dao<TestQueueDao> = dio.load(&key).await?;
+        queue.push_store(&mut dio, jobs, TestEnumDao::Blah2(1))?;
+        dio.commit().await?;
+    }
+    assert!(matches!(bus.recv(&session).await?, TestEnumDao::Blah2(1)));
+
+    let deliver_at = {
+        let now = chain.time.current_timestamp()?;
+        ChainTimestamp::from(now.time_since_epoch_ms + 500)
+    };
+    {
+        let mut dio = chain.dio(&session).await;
+        let mut queue: Dao<TestQueueDao> = dio.load(&key).await?;
+        queue.push_delayed(&mut dio, jobs, TestEnumDao::Blah2(2), deliver_at)?;
+
+        // The delayed child is hidden even before it is committed
+        assert_eq!(queue.iter(&mut dio, jobs).await?.count(), 1);
+        dio.commit().await?;
+    }
+    {
+        let mut dio = chain.dio(&session).await;
+        let queue: Dao<TestQueueDao> = dio.load(&key).await?;
+        assert_eq!(queue.iter(&mut dio, jobs).await?.count(), 1);
+    }
+
+    // The chain delivers the delayed child by itself once its time has passed
+    assert!(matches!(bus.recv(&session).await?, TestEnumDao::Blah2(2)));
+    assert!(chain.time.current_timestamp()? >= deliver_at);
+    {
+        let mut dio = chain.dio(&session).await;
+        let queue: Dao<TestQueueDao> = dio.load(&key).await?;
+        let items = queue.iter(&mut dio, jobs).await?.collect::<Vec<_>>();
+        assert_eq!(items.len(), 2);
+        assert!(items.iter().any(|a| matches!(**a, TestEnumDao::Blah2(2))));
+    }
+
+    Ok(())
+}
@@ -5,6 +5,9 @@ use serde::de::*;
 use crate::dio::*;
 use crate::dio::dao::*;
 use crate::error::*;
+use crate::header::*;
+use crate::meta::*;
+use crate::time::ChainTimestamp;
 use std::collections::VecDeque;
 
 /// Rerepresents a vector of children attached to a parent DAO
@@ -19,6 +22,13 @@ use std::collections::VecDeque;
 /// Storing this vector within other DAO's allows complex models
 /// to be represented.
 ///
+/// Children are kept in order, by default they are appended to the end
+/// of the vector however they can also be inserted at (or moved to) a
+/// particular position which makes it possible to model lists such as
+/// chat messages or tasks. Positions are stored as fractional keys in
+/// the metadata of each child thus concurrent inserts from different
+/// clients will converge on the same order.
+///
 /// Alternatively you can store your vectors, maps and other
 /// relationships as collections of `PrimaryKey`'s however you
 /// will need to manage this yourselve and can not benefit from
@@ -85,6 +95,19 @@ where D: Serialize + DeserializeOwned + Clone + Send + Sync,
         )
     }
 
+    /// Returns a page of the children in the vector without loading all of them,
+    /// only children that have been committed to the chain are included
+    pub async fn iter_range<'a, C>(&self, dio: &mut Dio<'a>, vec: DaoVec<C>, offset: usize, limit: usize) -> Result<Iter<C>, LoadError>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync
+    {
+        let keys = vec.keys(dio, self.key().clone()).await;
+        Ok(
+            Iter::new(
+                dio.load_many(keys.into_iter().skip(offset).take(limit)).await?
+            )
+        )
+    }
+
     #[deprecated(
         since = "0.5.1",
         note = "This method has been replaced by push_store and push_make - use one of these instead."
@@ -116,6 +139,113 @@ where D: Serialize + DeserializeOwned + Clone + Send + Sync,
         ret.attach(self, vec);
         Ok(ret)
     }
+
+    /// Adds a child to the vector that is stored straight away but remains hidden from
+    /// anyone iterating or listening on the vector until the chain reaches `deliver_at`
+    pub fn push_delayed<C>(&mut self, dio: &mut Dio, vec: DaoVec<C>, data: C, deliver_at: ChainTimestamp) -> Result<Dao<C>, SerializationError>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync
+    {
+        let mut ret = self.push_make(dio, vec, data)?;
+        ret.row.extra_meta.push(CoreMetadata::DeliverAt(deliver_at));
+        Ok(ret.commit(dio)?)
+    }
+
+    /// Adds a child to the front of the vector
+    pub async fn push_front<'a, C>(&mut self, dio: &mut Dio<'a>, vec: DaoVec<C>, data: C) -> Result<Dao<C>, LoadError>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync
+    {
+        let keys = vec.keys(dio, self.key().clone()).await;
+        let after = match keys.first() {
+            Some(a) => vec.order_of(dio, a).await,
+            None => None,
+        };
+
+        let order = MetaOrder::between(None, after.as_ref());
+        Ok(self.push_at(dio, vec, data, order)?)
+    }
+
+    /// Adds a child to the vector straight after another child
+    pub async fn insert_after<'a, C>(&mut self, dio: &mut Dio<'a>, vec: DaoVec<C>, after: &PrimaryKey, data: C) -> Result<Dao<C>, LoadError>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync
+    {
+        let keys = vec.keys(dio, self.key().clone()).await;
+        let index = match keys.iter().position(|a| *a == *after) {
+            Some(a) => a,
+            None => { return Err(LoadError::NotFound(after.clone())); }
+        };
+
+        let before = vec.order_of(dio, after).await;
+        let next = match keys.get(index + 1) {
+            Some(a) => vec.order_of(dio, a).await,
+            None => None,
+        };
+
+        let order = MetaOrder::between(before.as_ref(), next.as_ref());
+        Ok(self.push_at(dio, vec, data, order)?)
+    }
+
+    /// Moves this child so that it sits directly in front of another child in the
+    /// same vector, like other changes the data object must then be committed
+    pub async fn move_before<'a>(&mut self, dio: &mut Dio<'a>, before: &PrimaryKey) -> Result<(), LoadError>
+    {
+        let parent = match &self.ethereal.row.parent {
+            Some(a) => a.vec.clone(),
+            None => { return Err(LoadError::CollectionDetached); }
+        };
+        let vec = DaoVec::<D>::from_id(parent.collection_id);
+
+        let keys = vec.keys(dio, parent.parent_id).await
+            .into_iter()
+            .filter(|a| *a != *self.key())
+            .collect::<Vec<_>>();
+        let index = match keys.iter().position(|a| *a == *before) {
+            Some(a) => a,
+            None => { return Err(LoadError::NotFound(before.clone())); }
+        };
+
+        let previous = match index {
+            0 => None,
+            n => vec.order_of(dio, &keys[n - 1]).await,
+        };
+        let next = vec.order_of(dio, before).await;
+
+        self.ethereal.state.dirty = true;
+        self.ethereal.row.order = Some(MetaOrder::between(previous.as_ref(), next.as_ref()));
+        Ok(())
+    }
+
+    fn push_at<C>(&mut self, dio: &mut Dio, vec: DaoVec<C>, data: C, order: MetaOrder) -> Result<Dao<C>, SerializationError>
+    where C: Serialize + DeserializeOwned + Clone + Send + Sync
+    {
+        let mut ret = self.push_make(dio, vec, data)?;
+        ret.row.order = Some(order);
+        Ok(ret.commit(dio)?)
+    }
+}
+
+impl<D> DaoVec<D>
+where D: Serialize + DeserializeOwned + Clone + Send + Sync,
+{
+    fn from_id(vec_id: u64) -> DaoVec<D> {
+        DaoVec {
+            vec_id,
+            _phantom1: PhantomData,
+        }
+    }
+
+    /// Returns the keys of the committed children in order
+    async fn keys<'a>(&self, dio: &mut Dio<'a>, parent_id: PrimaryKey) -> Vec<PrimaryKey> {
+        let collection = MetaCollection {
+            parent_id,
+            collection_id: self.vec_id,
+        };
+        dio.lookup_secondary_raw(&collection).await.unwrap_or_default()
+    }
+
+    /// Returns the position of one of the children
+    async fn order_of<'a>(&self, dio: &mut Dio<'a>, key: &PrimaryKey) -> Option<MetaOrder> {
+        dio.lookup_order(key).await
+    }
 }
 
 pub struct Iter<D>
@@ -0,0 +1,109 @@
+#[allow(unused_imports)]
+use log::{info, error, debug};
+use std::error::Error;
+
+use crate::crypto::AteHash;
+
+use super::*;
+
+#[derive(Debug)]
+pub enum ArchiveError {
+    IO(tokio::io::Error),
+    SerializationError(SerializationError),
+    LoadError(LoadError),
+    CommitError(CommitError),
+    ChainCreationError(ChainCreationError),
+    InvalidMagic,
+    UnsupportedVersion(u32),
+    HashMismatch { expected: AteHash, actual: AteHash },
+    CountMismatch { expected: u64, actual: u64 },
+    ChainNotEmpty(String),
+}
+
+impl From<tokio::io::Error>
+for ArchiveError {
+    fn from(err: tokio::io::Error) -> ArchiveError {
+        ArchiveError::IO(err)
+    }
+}
+
+impl From<SerializationError>
+for ArchiveError {
+    fn from(err: SerializationError) -> ArchiveError {
+        ArchiveError::SerializationError(err)
+    }
+}
+
+impl From<LoadError>
+for ArchiveError {
+    fn from(err: LoadError) -> ArchiveError {
+        ArchiveError::LoadError(err)
+    }
+}
+
+impl From<CommitError>
+for ArchiveError {
+    fn from(err: CommitError) -> ArchiveError {
+        ArchiveError::CommitError(err)
+    }
+}
+
+impl From<ChainCreationError>
+for ArchiveError {
+    fn from(err: ChainCreationError) -> ArchiveError {
+        ArchiveError::ChainCreationError(err)
+    }
+}
+
+impl From<bincode::Error>
+for ArchiveError {
+    fn from(err: bincode::Error) -> ArchiveError {
+        ArchiveError::SerializationError(SerializationError::from(err))
+    }
+}
+
+impl std::fmt::Display
+for ArchiveError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            ArchiveError::IO(err) => {
+                write!(f, "Failed to process the chain archive due to an IO error - {}", err)
+            },
+            ArchiveError::SerializationError(err) => {
+                write!(f, "Failed to process the chain archive due to a serialization error - {}", err)
+            },
+            ArchiveError::LoadError(err) => {
+                write!(f, "Failed to export the chain as an event could not be loaded - {}", err)
+            },
+            ArchiveError::CommitError(err) => {
+                write!(f, "Failed to import the chain as the events were rejected - {}", err)
+            },
+            ArchiveError::ChainCreationError(err) => {
+                write!(f, "Failed to import the chain as it could not be created - {}", err)
+            },
+            ArchiveError::InvalidMagic => {
+                write!(f, "The stream is not a chain archive")
+            },
+            ArchiveError::UnsupportedVersion(version) => {
+                write!(f, "The chain archive version ({}) is not supported", version)
+            },
+            ArchiveError::HashMismatch { expected, actual } => {
+                write!(f, "The chain archive is corrupt as an event hash does not match (expected={}, actual={})", expected, actual)
+            },
+            ArchiveError::CountMismatch { expected, actual } => {
+                write!(f, "The chain archive is incomplete (expected {} events but found {})", expected, actual)
+            },
+            ArchiveError::ChainNotEmpty(key) => {
+                write!(f, "The chain ({}) already has events and thus can not be imported into", key)
+            },
+        }
+    }
+}
+
+impl std::error::Error
+for ArchiveError
+{
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        None
+    }
+}
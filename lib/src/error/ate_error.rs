@@ -24,6 +24,7 @@ pub enum AteError
     SinkError(SinkError),
     CompactError(CompactError),
     LoadError(LoadError),
+    ArchiveError(ArchiveError),
     IO(tokio::io::Error),
     CryptoError(CryptoError),
     TransformError(TransformError),
@@ -153,6 +154,14 @@ for AteError
     }   
 }
 
+impl From<ArchiveError>
+for AteError
+{
+    fn from(err: ArchiveError) -> AteError {
+        AteError::ArchiveError(err)
+    }   
+}
+
 impl From<tokio::io::Error>
 for AteError
 {
@@ -232,6 +241,9 @@ for AteError {
             AteError::LoadError(err) => {
                 write!(f, "{}", err)
             },
+            AteError::ArchiveError(err) => {
+                write!(f, "{}", err)
+            },
             AteError::LockError(err) => {
                 write!(f, "{}", err)
             },
@@ -7,6 +7,8 @@ extern crate rmp_serde as rmps;
 use std::sync::mpsc as smpsc;
 use tokio::sync::mpsc as mpsc;
 
+use crate::header::PrimaryKey;
+
 use super::*;
 
 #[derive(Debug)]
@@ -19,6 +21,9 @@ pub enum BusError
     SerializationError(SerializationError),
     LockError(LockError),
     TransformError(TransformError),
+    CommitError(CommitError),
+    TimeError(TimeError),
+    LeaseLost(PrimaryKey),
 }
 
 impl From<LoadError>
@@ -37,6 +42,22 @@ for BusError
     }   
 }
 
+impl From<CommitError>
+for BusError
+{
+    fn from(err: CommitError) -> BusError {
+        BusError::CommitError(err)
+    }   
+}
+
+impl From<TimeError>
+for BusError
+{
+    fn from(err: TimeError) -> BusError {
+        BusError::TimeError(err)
+    }   
+}
+
 impl From<SerializationError>
 for BusError
 {
@@ -91,6 +112,15 @@ for BusError {
             BusError::LockError(err) => {
                 write!(f, "Failed to receive event from BUS due to an error locking the data object - {}", err)
             },
+            BusError::CommitError(err) => {
+                write!(f, "Failed to update the BUS due to an error committing the changes - {}", err)
+            },
+            BusError::TimeError(err) => {
+                write!(f, "Failed to receive event from BUS due to an error reading the time - {}", err)
+            },
+            BusError::LeaseLost(key) => {
+                write!(f, "The lease on the BUS message ({}) has expired or was taken by another consumer", key)
+            },
         }
     }
 }
@@ -8,6 +8,9 @@ use std::sync::mpsc as smpsc;
 use tokio::sync::mpsc as mpsc;
 use tokio::sync::broadcast as broadcast;
 
+use crate::header::PrimaryKey;
+use crate::crypto::AteHash;
+
 use super::*;
 
 #[derive(Debug)]
@@ -16,6 +19,7 @@ pub enum CommitError
     #[allow(dead_code)]
     Aborted,
     NewRootsAreDisabled,
+    ReadOnly,
     TransformError(TransformError),
     LintError(LintError),
     SinkError(SinkError),
@@ -26,6 +30,11 @@ pub enum CommitError
     RootError(String),
     CommsError(CommsError),
     TimeError(TimeError),
+    Conflict {
+        key: PrimaryKey,
+        expected: AteHash,
+        actual: Option<AteHash>,
+    },
 }
 
 impl From<TransformError>
@@ -144,6 +153,9 @@ for CommitError {
             CommitError::NewRootsAreDisabled => {
                 write!(f, "New root objects are currently not allowed for this chain")
             },
+            CommitError::ReadOnly => {
+                write!(f, "The data can not be committed as this view of the chain is read-only")
+            },
             CommitError::TransformError(err) => {
                 write!(f, "Failed to commit the data due to an error transforming the data object into events - {}", err.to_string())
             },
@@ -178,6 +190,12 @@ for CommitError {
             CommitError::RootError(err) => {
                 write!(f, "Failed to commit the data due to an error at the root server while processing the events - {}", err.to_string())
             },
+            CommitError::Conflict { key, expected, actual } => {
+                match actual {
+                    Some(actual) => write!(f, "Failed to commit the data as the data object ({}) was modified concurrently (expected version {} but found {})", key, expected, actual),
+                    None => write!(f, "Failed to commit the data as the data object ({}) was deleted concurrently (expected version {})", key, expected),
+                }
+            },
         }
     }
 }
@@ -27,6 +27,12 @@ pub enum LoadError {
     IO(tokio::io::Error),
     #[allow(dead_code)]
     CollectionDetached,
+    MissingIndex(String),
+    IndexTypeMismatch {
+        index: String,
+        expected: String,
+        actual: String,
+    },
 }
 
 impl From<tokio::io::Error>
@@ -118,6 +124,12 @@ for LoadError {
             LoadError::CollectionDetached => {
                 write!(f, "Collection is detached from its parent, it must be attached before it can be used")
             },
+            LoadError::MissingIndex(name) => {
+                write!(f, "Chain has no secondary index named ({})", name)
+            },
+            LoadError::IndexTypeMismatch { index, expected, actual } => {
+                write!(f, "Secondary index ({}) is for objects of type ({}) not ({})", index, expected, actual)
+            },
         }
     }
 }
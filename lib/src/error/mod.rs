@@ -1,3 +1,4 @@
+pub mod archive_error;
 pub mod ate_error;
 pub mod bus_error;
 pub mod chain_creation_error;
@@ -21,6 +22,7 @@ pub mod validation_error;
 
 pub use ate_error::*;
 pub use ate_error::*;
+pub use archive_error::*;
 pub use bus_error::*;
 pub use chain_creation_error::*;
 pub use commit_error::*;
@@ -8,6 +8,8 @@ use rmp_serde::encode::Error as RmpEncodeError;
 use rmp_serde::decode::Error as RmpDecodeError;
 use serde_json::Error as JsonError;
 
+use crate::spec::SerializationFormat;
+
 #[derive(Debug)]
 pub enum SerializationError
 {
@@ -22,6 +24,13 @@ pub enum SerializationError
     #[allow(dead_code)]
     CollectionDetached,
     SerdeError(String),
+    ChecksumMismatch { offset: u64, expected: u32, actual: u32 },
+    CorruptLog { path: String, offset: u64, reason: String },
+    MetaTooLarge { size: u64, limit: u64 },
+    DataTooLarge { size: u64, limit: u64 },
+    NotSelfDescribing(SerializationFormat),
+    EventTooLarge { path: String, offset: u64, size: u64, limit: u64 },
+    FrameTooLarge { size: u64, limit: u64 },
 }
 
 impl From<RmpEncodeError>
@@ -95,6 +104,27 @@ for SerializationError {
             SerializationError::SerdeError(err) => {
                 write!(f, "Serde error during serialization - {}", err)
             },
+            SerializationError::ChecksumMismatch { offset, expected, actual } => {
+                write!(f, "Event checksum mismatch at 0x{:x} (expected={:08x}, actual={:08x})", offset, expected, actual)
+            },
+            SerializationError::CorruptLog { path, offset, reason } => {
+                write!(f, "Redo log ({}) is corrupt at offset 0x{:x} - {}", path, offset, reason)
+            },
+            SerializationError::MetaTooLarge { size, limit } => {
+                write!(f, "Event metadata is too large ({} bytes) as it exceeds the limit of {} bytes", size, limit)
+            },
+            SerializationError::DataTooLarge { size, limit } => {
+                write!(f, "Event data is too large ({} bytes) as it exceeds the limit of {} bytes", size, limit)
+            },
+            SerializationError::NotSelfDescribing(format) => {
+                write!(f, "Data stored in the {} format can not be decoded without knowing its type", format)
+            },
+            SerializationError::EventTooLarge { path, offset, size, limit } => {
+                write!(f, "Redo log ({}) has an event at offset 0x{:x} that is too large ({} bytes) as it exceeds the limit of {} bytes", path, offset, size, limit)
+            },
+            SerializationError::FrameTooLarge { size, limit } => {
+                write!(f, "Frame is too large ({} bytes) as it exceeds the limit of {} bytes", size, limit)
+            },
         }
     }
 }
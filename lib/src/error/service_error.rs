@@ -26,6 +26,8 @@ pub enum ServiceError<E>
     LockError(LockError),
     PipeError(String),
     ServiceError(String),
+    Denied(String),
+    RateLimited,
     Timeout,
     Aborted
 }
@@ -152,6 +154,8 @@ impl<E> ServiceError<E>
             ServiceError::IO(a) => ServiceError::IO(a),
             ServiceError::Reply(_) => ServiceError::Reply(()),
             ServiceError::ServiceError(a) => ServiceError::ServiceError(a),
+            ServiceError::Denied(a) => ServiceError::Denied(a),
+            ServiceError::RateLimited => ServiceError::RateLimited,
             ServiceError::Timeout => ServiceError::Timeout,
             ServiceError::Aborted => ServiceError::Aborted,
         }
@@ -191,6 +195,12 @@ where E: std::fmt::Debug
             ServiceError::Reply(err) => {
                 write!(f, "Command failed - {:?}", err)
             },
+            ServiceError::Denied(err) => {
+                write!(f, "Command denied - {}", err)
+            },
+            ServiceError::RateLimited => {
+                write!(f, "Command failed - Rate limited")
+            },
             ServiceError::Timeout => {
                 write!(f, "Command failed - Timeout")
             },
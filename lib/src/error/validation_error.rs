@@ -4,6 +4,8 @@ use std::error::Error;
 
 extern crate rmp_serde as rmps;
 
+use crate::header::PrimaryKey;
+
 use super::*;
 
 #[derive(Debug)]
@@ -13,6 +15,10 @@ pub enum ValidationError {
     Detached,
     NoSignatures,
     Trust(TrustError),
+    UniqueViolation {
+        constraint: String,
+        existing: PrimaryKey,
+    },
 }
 
 impl From<TrustError>
@@ -50,6 +56,9 @@ for ValidationError {
             ValidationError::Trust(err) => {
                 write!(f, "The data object event has an issue with trust - {}", err)
             },
+            ValidationError::UniqueViolation { constraint, existing } => {
+                write!(f, "The data object violates the unique constraint ({}) as the value is already used by {}", constraint, existing)
+            },
         }
     }
 }
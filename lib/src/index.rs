@@ -1,12 +1,23 @@
-use fxhash::FxHashMap;
+use fxhash::{FxHashMap, FxHashSet};
 use multimap::MultiMap;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::sync::Arc;
+use parking_lot::Mutex as StdMutex;
+#[allow(unused_imports)]
+use log::{info, warn, debug};
 
 use super::event::*;
 use super::header::*;
 use super::meta::*;
 use super::sink::*;
 use super::error::*;
-use super::time::*;
+use super::transaction::ConversationSession;
+use super::time::ChainTimestamp;
+use super::crypto::AteHash;
 
 pub trait EventIndexer
 where Self: EventSink + Send + Sync + std::fmt::Debug,
@@ -16,6 +27,17 @@ where Self: EventSink + Send + Sync + std::fmt::Debug,
     }
 
     fn clone_indexer(&self) -> Box<dyn EventIndexer>;
+
+    /// Serializes the state of the indexer so that it can be stored in a checkpoint,
+    /// indexers that return nothing will be rebuilt from the events instead
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores the state of the indexer from a snapshot stored in a checkpoint
+    fn restore(&mut self, _snapshot: &[u8]) -> Result<(), SerializationError> {
+        Err(SerializationError::NoData)
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -26,13 +48,118 @@ pub struct EventLeaf
     pub updated: u64,
 }
 
+/// State of a data object at a particular point in time
+#[derive(Debug, Clone)]
+struct EventVersion
+{
+    /// Hash of the event that produced this version
+    record: super::crypto::AteHash,
+    /// Where the data is stored (None means the object was deleted)
+    leaf: Option<EventLeaf>,
+    parent: Option<MetaParent>,
+    order: MetaOrder,
+    /// Time before which the object is hidden from its collection
+    deliver_at: Option<ChainTimestamp>,
+    /// Type the object was stored as (if it was recorded)
+    type_name: Option<String>,
+}
+
+/// Width of each slot in the timer wheel (milliseconds)
+const WHEEL_TICK_MS: u64 = 100;
+
+/// Number of slots in the timer wheel, deadlines further out than one turn of
+/// the wheel are kept in their slot until the wheel comes around to them again
+const WHEEL_SLOTS: u64 = 512;
+
+/// Hashed timer wheel that holds the delivery times of delayed children so
+/// that releasing the ones that are due only visits the slots that elapsed
+#[derive(Debug)]
+struct TimerWheel
+{
+    slots: Vec<Vec<(ChainTimestamp, PrimaryKey)>>,
+    tick: u64,
+    len: usize,
+}
+
+impl Default
+for TimerWheel
+{
+    fn default() -> TimerWheel {
+        TimerWheel {
+            slots: (0..WHEEL_SLOTS).map(|_| Vec::new()).collect::<Vec<_>>(),
+            tick: 0,
+            len: 0,
+        }
+    }
+}
+
+impl TimerWheel
+{
+    fn insert(&mut self, deadline: ChainTimestamp, key: PrimaryKey) {
+        let slot = (deadline.time_since_epoch_ms / WHEEL_TICK_MS) % WHEEL_SLOTS;
+        self.slots[slot as usize].push((deadline, key));
+        self.len = self.len + 1;
+    }
+
+    /// Removes and returns every entry whose deadline has been reached
+    fn advance(&mut self, now: ChainTimestamp) -> Vec<(ChainTimestamp, PrimaryKey)> {
+        let target = now.time_since_epoch_ms / WHEEL_TICK_MS;
+        let ticks = match target.saturating_sub(self.tick) {
+            a if a >= WHEEL_SLOTS => WHEEL_SLOTS,
+            a => a + 1,
+        };
+
+        let mut ret = Vec::new();
+        for n in 0..ticks {
+            let slot = &mut self.slots[((target - n) % WHEEL_SLOTS) as usize];
+            let mut i = 0usize;
+            while i < slot.len() {
+                if slot[i].0 <= now {
+                    ret.push(slot.swap_remove(i));
+                } else {
+                    i = i + 1;
+                }
+            }
+        }
+        self.tick = self.tick.max(target);
+        self.len = self.len - ret.len();
+        ret
+    }
+}
+
+/// Child that is attached to a collection but is not delivered until later
+#[derive(Debug, Clone)]
+struct DelayedChild
+{
+    vec: MetaCollection,
+    order: MetaOrder,
+    deliver_at: ChainTimestamp,
+}
+
 #[derive(Default, Debug)]
 pub(crate) struct BinaryTreeIndexer
 {
     primary: FxHashMap<PrimaryKey, EventLeaf>,
-    secondary: MultiMap<MetaCollection, PrimaryKey>,
-    parents: FxHashMap<PrimaryKey, MetaParent>,
-    uploads: FxHashMap<ChainTimestamp, MetaDelayedUpload>,
+    /// Children of each collection sorted by their position
+    secondary: BTreeMap<MetaCollection, BTreeSet<(MetaOrder, PrimaryKey)>>,
+    parents: FxHashMap<PrimaryKey, (MetaParent, MetaOrder)>,
+    /// Versions of each data object keyed by their timestamp and then the order they
+    /// were fed in (so that versions written in the same millisecond are all kept)
+    versions: FxHashMap<PrimaryKey, BTreeMap<(ChainTimestamp, u64), EventVersion>>,
+    seq: u64,
+    members: FxHashMap<MetaCollection, FxHashSet<PrimaryKey>>,
+    /// Data objects of each type that currently exist
+    types: FxHashMap<String, BTreeSet<PrimaryKey>>,
+    /// Every data object that was written with each type (including those that were later
+    /// deleted) so that the chain can be viewed as it was in the past
+    type_history: FxHashMap<String, BTreeSet<PrimaryKey>>,
+    /// Public keys (by their hash) that signed each event
+    signatures: FxHashMap<AteHash, Vec<AteHash>>,
+    /// Children that are hidden from their collections until they are delivered
+    delayed: FxHashMap<PrimaryKey, DelayedChild>,
+    wheel: TimerWheel,
+    /// Time up to which all the delayed children have been delivered
+    watermark: ChainTimestamp,
 }
 
 impl BinaryTreeIndexer
@@ -48,16 +175,27 @@ impl BinaryTreeIndexer
     }
 
     #[allow(dead_code)]
-    pub(crate) fn feed(&mut self, entry: &EventHeader) {
+    pub(crate) fn feed(&mut self, entry: &EventHeader, timestamp: ChainTimestamp) {
+        self.seq = self.seq + 1;
+        let version = (timestamp, self.seq);
+
         for core in entry.meta.core.iter() {
             match core {
                 CoreMetadata::Tombstone(key) => {
                     self.primary.remove(&key);
-                    if let Some(tree) = self.parents.remove(&key) {
-                        if let Some(vec) = self.secondary.get_vec_mut(&tree.vec) {
-                            vec.retain(|x| *x != *key);
-                        }
+                    self.remove_child(&key);
+                    let type_name = self.last_type_name(&key, entry);
+                    if let Some(type_name) = type_name.as_ref() {
+                        self.remove_type(type_name, &key);
                     }
+                    self.versions.entry(key.clone()).or_default().insert(version, EventVersion {
+                        record: entry.raw.event_hash.clone(),
+                        leaf: None,
+                        parent: None,
+                        order: MetaOrder::default(),
+                        deliver_at: None,
+                        type_name,
+                    });
                     return;
                 },
                 _ => { },
@@ -78,32 +216,245 @@ impl BinaryTreeIndexer
                     });
                     v.record = entry.raw.event_hash.clone();
                     v.updated = match when { Some(t) => t.time_since_epoch_ms, None => 0 };
+
+                    if let Some(t) = entry.meta.get_type_name() {
+                        self.types.entry(t.type_name.clone()).or_default().insert(key.clone());
+                        self.type_history.entry(t.type_name.clone()).or_default().insert(key.clone());
+                    }
+                },
+                CoreMetadata::Signature(sig) => {
+                    for hash in sig.hashes.iter() {
+                        let signed_by = self.signatures.entry(hash.clone()).or_default();
+                        if signed_by.contains(&sig.public_key_hash) == false {
+                            signed_by.push(sig.public_key_hash.clone());
+                        }
+                    }
                 },
                 CoreMetadata::Parent(parent) => {
                     if let Some(key) = entry.meta.get_data_key() {
-                        if let Some(parent) = self.parents.remove(&key) {
-                            if let Some(vec) = self.secondary.get_vec_mut(&parent.vec) {
-                                vec.retain(|x| *x != key);
-                            }
-                        }
-                        self.parents.insert(key.clone(), parent.clone());
+                        // Children written before positions existed are ordered by when they were created
+                        let order = match (entry.meta.get_order(), self.primary.get(&key)) {
+                            (Some(a), _) => a.clone(),
+                            (None, Some(leaf)) => MetaOrder::from_timestamp(leaf.created),
+                            (None, None) => MetaOrder::default(),
+                        };
 
-                        let vec = parent.vec.clone();
-                        let exists = match self.secondary.get_vec(&vec)  {
-                            Some(a) => a.contains(&key),
-                            None => false,
+                        // Children that are not yet due are held back until the chain releases them
+                        self.remove_child(&key);
+                        match entry.meta.get_deliver_at() {
+                            Some(deliver_at) if deliver_at > self.watermark.max(timestamp) => {
+                                self.wheel.insert(deliver_at, key.clone());
+                                self.delayed.insert(key.clone(), DelayedChild {
+                                    vec: parent.vec.clone(),
+                                    order: order.clone(),
+                                    deliver_at,
+                                });
+                            },
+                            _ => {
+                                self.secondary.entry(parent.vec.clone()).or_default().insert((order.clone(), key.clone()));
+                            }
                         };
-                        if exists == false {
-                            self.secondary.insert(vec, key);
-                        }
+                        self.parents.insert(key.clone(), (parent.clone(), order));
                     }
                 },
-                CoreMetadata::DelayedUpload(upload) => {
-                    self.uploads.insert(upload.from, upload.clone());
-                }
                 _ => { },
             }
         }
+
+        // Record the version so that the tree can also be viewed as it was in the past
+        if let Some(key) = entry.meta.get_data_key() {
+            if let Some(leaf) = self.primary.get(&key) {
+                let parent = self.parents.get(&key).map(|a| a.clone());
+                if let Some((parent, _)) = &parent {
+                    self.members.entry(parent.vec.clone()).or_default().insert(key.clone());
+                }
+                let (parent, order) = match parent {
+                    Some((a, b)) => (Some(a), b),
+                    None => (None, MetaOrder::default()),
+                };
+                let type_name = self.last_type_name(&key, entry);
+                self.versions.entry(key).or_default().insert(version, EventVersion {
+                    record: leaf.record.clone(),
+                    leaf: Some(leaf.clone()),
+                    parent,
+                    order,
+                    deliver_at: entry.meta.get_deliver_at(),
+                    type_name,
+                });
+            }
+        }
+    }
+
+    /// Type of a data object as recorded by an event or otherwise its previous version
+    fn last_type_name(&self, key: &PrimaryKey, entry: &EventHeader) -> Option<String> {
+        match entry.meta.get_type_name() {
+            Some(a) => Some(a.type_name.clone()),
+            None => self.versions.get(key)
+                .and_then(|a| a.values().next_back())
+                .and_then(|a| a.type_name.clone()),
+        }
+    }
+
+    fn remove_type(&mut self, type_name: &str, key: &PrimaryKey) {
+        if let Some(set) = self.types.get_mut(type_name) {
+            set.remove(key);
+            if set.is_empty() {
+                self.types.remove(type_name);
+            }
+        }
+    }
+
+    /// Drops the versions that are no longer needed once the history before the
+    /// cut-off has been compacted, only the last version before the cut-off is
+    /// kept (unless it deleted the object) so the chain can still be viewed as it
+    /// was at any point from then on
+    pub(crate) fn prune(&mut self, cut_off: ChainTimestamp) {
+        self.versions.retain(|_, versions| {
+            let older = versions.range(..=(cut_off, u64::MAX))
+                .map(|a| a.0.clone())
+                .rev()
+                .collect::<Vec<_>>();
+            for (n, version) in older.into_iter().enumerate() {
+                let deleted = versions.get(&version).map_or(true, |a| a.leaf.is_none());
+                if n > 0 || deleted {
+                    versions.remove(&version);
+                }
+            }
+            versions.len() > 0
+        });
+
+        let versions = &self.versions;
+        self.members.retain(|vec, keys| {
+            keys.retain(|key| match versions.get(key) {
+                Some(a) => a.values().any(|a| a.parent.as_ref().map(|p| &p.vec) == Some(vec)),
+                None => false,
+            });
+            keys.len() > 0
+        });
+        self.type_history.retain(|_, keys| {
+            keys.retain(|key| versions.contains_key(key));
+            keys.len() > 0
+        });
+    }
+
+    fn remove_child(&mut self, key: &PrimaryKey) {
+        self.delayed.remove(key);
+        if let Some((parent, order)) = self.parents.remove(key) {
+            if let Some(set) = self.secondary.get_mut(&parent.vec) {
+                set.remove(&(order, key.clone()));
+            }
+        }
+    }
+
+    /// Delivers all the delayed children that are due at this time by adding them to
+    /// their collections, the keys of the children that were delivered are returned
+    pub(crate) fn release(&mut self, now: ChainTimestamp) -> Vec<PrimaryKey> {
+        if now <= self.watermark {
+            return Vec::new();
+        }
+        self.watermark = now;
+
+        let mut ret = Vec::new();
+        for (deadline, key) in self.wheel.advance(now) {
+            // (entries for children that were since moved, rescheduled or deleted are stale)
+            match self.delayed.get(&key) {
+                Some(a) if a.deliver_at == deadline => { },
+                _ => { continue; }
+            }
+            if let Some(child) = self.delayed.remove(&key) {
+                self.secondary.entry(child.vec).or_default().insert((child.order, key.clone()));
+                ret.push(key);
+            }
+        }
+        ret
+    }
+
+    /// Returns true if there are children waiting to be delivered
+    pub(crate) fn has_delayed(&self) -> bool {
+        self.wheel.len > 0
+    }
+
+    pub(crate) fn is_delayed(&self, key: &PrimaryKey) -> bool {
+        self.delayed.contains_key(key)
+    }
+
+    pub(crate) fn watermark(&self) -> ChainTimestamp {
+        self.watermark
+    }
+
+    fn lookup_version(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<&EventVersion> {
+        self.versions
+            .get(key)?
+            .range(..=(at, u64::MAX))
+            .next_back()
+            .map(|a| a.1)
+            .filter(|a| a.leaf.is_some())
+    }
+
+    pub(crate) fn lookup_primary_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<EventLeaf> {
+        self.lookup_version(key, at).and_then(|a| a.leaf.clone())
+    }
+
+    /// Returns every version of a data object in the order they were written, the
+    /// leaf is missing for versions that deleted the object
+    pub(crate) fn lookup_versions(&self, key: &PrimaryKey) -> Vec<(ChainTimestamp, super::crypto::AteHash, Option<EventLeaf>)> {
+        match self.versions.get(key) {
+            Some(a) => a.iter()
+                .map(|(t, v)| (t.0.clone(), v.record.clone(), v.leaf.clone()))
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the hashes of the public keys that signed an event
+    pub(crate) fn lookup_signatures(&self, event_hash: &AteHash) -> &[AteHash] {
+        match self.signatures.get(event_hash) {
+            Some(a) => &a[..],
+            None => &[],
+        }
+    }
+
+    /// Finds the version of a data object that an event produced, returns whether the
+    /// object existed before the event and the type it was stored as (None means the
+    /// version is no longer known, e.g. it was compacted)
+    pub(crate) fn lookup_change(&self, key: &PrimaryKey, event_hash: &AteHash) -> Option<(bool, Option<&str>)> {
+        let mut existed = false;
+        for version in self.versions.get(key)?.values() {
+            if version.record == *event_hash {
+                return Some((existed, version.type_name.as_ref().map(|a| a.as_str())));
+            }
+            existed = version.leaf.is_some();
+        }
+        None
+    }
+
+    pub(crate) fn lookup_parent_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaParent> {
+        self.lookup_version(key, at).and_then(|a| a.parent.clone())
+    }
+
+    pub(crate) fn lookup_order_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaOrder> {
+        self.lookup_version(key, at)
+            .filter(|a| a.parent.is_some())
+            .map(|a| a.order.clone())
+    }
+
+    pub(crate) fn lookup_secondary_raw_at(&self, key: &MetaCollection, at: ChainTimestamp) -> Option<Vec<PrimaryKey>> {
+        match self.members.get(key) {
+            Some(set) => {
+                let mut ret = set.iter()
+                    .filter_map(|a| {
+                        match self.lookup_version(a, at) {
+                            Some(v) if v.deliver_at.map(|d| d > at).unwrap_or(false) => None,
+                            Some(v) if v.parent.as_ref().map(|p| &p.vec) == Some(key) => Some((v.order.clone(), a.clone())),
+                            _ => None,
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                ret.sort();
+                Some(ret.into_iter().map(|a| a.1).collect::<Vec<_>>())
+            },
+            None => None,
+        }
     }
 
     pub(crate) fn lookup_primary(&self, key: &PrimaryKey) -> Option<EventLeaf> {
@@ -116,27 +467,30 @@ impl BinaryTreeIndexer
     pub(crate) fn lookup_parent(&self, key: &PrimaryKey) -> Option<MetaParent> {
         match self.parents.get(key) {
             None => None,
-            Some(a) => Some(a.clone())
+            Some(a) => Some(a.0.clone())
         }
     }
 
-    pub(crate) fn lookup_secondary(&self, key: &MetaCollection) -> Option<Vec<EventLeaf>> {
-        match self.secondary.get_vec(key) {
-            Some(vec) => {
-                Some(vec.iter()
-                    .map(|a| a.clone())
-                    .filter_map(|a| self.primary.get(&a))
-                    .map(|a| a.clone())
-                    .collect::<Vec<_>>())
-            },
+    /// Returns the type that a data object was last stored as (if it was recorded)
+    pub(crate) fn lookup_type_name(&self, key: &PrimaryKey) -> Option<String> {
+        self.versions.get(key)
+            .and_then(|a| a.values().next_back())
+            .and_then(|a| a.type_name.clone())
+    }
+
+    /// Returns the position of a child within its collection
+    pub(crate) fn lookup_order(&self, key: &PrimaryKey) -> Option<MetaOrder> {
+        match self.parents.get(key) {
             None => None,
+            Some(a) => Some(a.1.clone())
         }
     }
 
-    pub(crate) fn lookup_secondary_raw(&self, key: &MetaCollection) -> Option<Vec<PrimaryKey>> {
-        match self.secondary.get_vec(key) {
-            Some(vec) => {
-                Some(vec.iter()
+    pub(crate) fn lookup_secondary(&self, key: &MetaCollection) -> Option<Vec<EventLeaf>> {
+        match self.secondary.get(key) {
+            Some(set) => {
+                Some(set.iter()
+                    .filter_map(|a| self.primary.get(&a.1))
                     .map(|a| a.clone())
                     .collect::<Vec<_>>())
             },
@@ -144,14 +498,59 @@ impl BinaryTreeIndexer
         }
     }
 
-    pub(crate) fn get_delayed_upload(&self, from: ChainTimestamp) -> Option<MetaDelayedUpload>
-    {
-        self.uploads.get(&from).map(|e| e.clone())
+    /// Returns the children attached to any of the collections of a parent (including
+    /// those that have not yet been delivered)
+    pub(crate) fn lookup_children(&self, parent_id: &PrimaryKey) -> Vec<PrimaryKey> {
+        let start = MetaCollection { parent_id: parent_id.clone(), collection_id: 0 };
+        let end = MetaCollection { parent_id: parent_id.clone(), collection_id: u64::MAX };
+        let delayed = self.delayed.iter()
+            .filter(|a| a.1.vec.parent_id == *parent_id)
+            .map(|a| a.0.clone());
+        self.secondary.range(start..=end)
+            .flat_map(|(_, set)| set.iter().map(|a| a.1.clone()))
+            .chain(delayed)
+            .collect::<Vec<_>>()
     }
 
-    pub(crate) fn get_pending_uploads(&self) -> Vec<MetaDelayedUpload>
-    {
-        self.uploads.values().filter(|d| d.complete == false).map(|d| d.clone()).collect::<Vec<_>>()
+    /// Returns all the data objects of a particular type that currently exist
+    pub(crate) fn lookup_type(&self, type_name: &str) -> Vec<PrimaryKey> {
+        match self.types.get(type_name) {
+            Some(set) => set.iter()
+                .filter(|a| self.primary.contains_key(a))
+                .map(|a| a.clone())
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        }
+    }
+
+    pub(crate) fn lookup_type_at(&self, type_name: &str, at: ChainTimestamp) -> Vec<PrimaryKey> {
+        match self.type_history.get(type_name) {
+            Some(set) => set.iter()
+                .filter(|a| self.lookup_version(a, at).is_some())
+                .map(|a| a.clone())
+                .collect::<Vec<_>>(),
+            None => Vec::new(),
+        }
+    }
+
+    /// Returns the names of all the types of data objects that currently exist
+    pub(crate) fn type_names(&self) -> Vec<String> {
+        let mut ret = self.types.keys()
+            .map(|a| a.clone())
+            .collect::<Vec<_>>();
+        ret.sort();
+        ret
+    }
+
+    pub(crate) fn lookup_secondary_raw(&self, key: &MetaCollection) -> Option<Vec<PrimaryKey>> {
+        match self.secondary.get(key) {
+            Some(set) => {
+                Some(set.iter()
+                    .map(|a| a.1.clone())
+                    .collect::<Vec<_>>())
+            },
+            None => None,
+        }
     }
 }
 
@@ -176,4 +575,243 @@ for UselessIndexer
     {
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Determines if more than one row may have the same value in a secondary index
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IndexMode
+{
+    /// Only one row may have each value, if another row takes the same value
+    /// then it replaces the previous row in the index
+    Unique,
+    /// Many rows can have the same value
+    NonUnique,
+}
+
+type IndexExtractor = Arc<dyn Fn(&Value) -> Option<Vec<u8>> + Send + Sync>;
+
+#[derive(Default, Debug)]
+struct SecondaryIndexState
+{
+    values: MultiMap<AteHash, PrimaryKey>,
+    keys: FxHashMap<PrimaryKey, AteHash>,
+    dirty: FxHashSet<PrimaryKey>,
+}
+
+impl SecondaryIndexState
+{
+    fn remove(&mut self, key: &PrimaryKey) {
+        if let Some(value) = self.keys.remove(key) {
+            if let Some(vec) = self.values.get_vec_mut(&value) {
+                vec.retain(|x| *x != *key);
+                if vec.len() <= 0 {
+                    self.values.remove(&value);
+                }
+            }
+        }
+    }
+
+    fn update(&mut self, index: &SecondaryIndex, key: PrimaryKey, value: AteHash) {
+        self.remove(&key);
+        self.dirty.remove(&key);
+        if index.mode == IndexMode::Unique {
+            if let Some(existing) = self.values.get_vec(&value).map(|a| a.clone()) {
+                for other in existing.into_iter().filter(|k| *k != key) {
+                    warn!("secondary-index({}): {} replaced {} as the value is unique", index.name, key, other);
+                    self.remove(&other);
+                }
+            }
+        }
+        self.keys.insert(key.clone(), value.clone());
+        self.values.insert(value, key);
+    }
+}
+
+/// User defined index over the data of a particular type of object that allows
+/// them to be found by value rather than by their primary key.
+///
+/// Writers attach a hash of the indexed value to the metadata of each event
+/// (see `MetaIndexed`) so the indexer can maintain the index from the metadata
+/// alone. Rows written without it (e.g. before the index was declared) are
+/// recorded as dirty and their values are extracted when the index is next
+/// queried from a `Dio` that can read them.
+pub struct SecondaryIndex
+{
+    pub(crate) name: String,
+    pub(crate) type_name: String,
+    pub(crate) mode: IndexMode,
+    extractor: IndexExtractor,
+    state: StdMutex<SecondaryIndexState>,
+}
+
+impl std::fmt::Debug
+for SecondaryIndex
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "secondary-index(name={}, type={}, mode={:?})", self.name, self.type_name, self.mode)
+    }
+}
+
+impl SecondaryIndex
+{
+    pub fn new<D, K, F>(name: &str, mode: IndexMode, extractor: F) -> SecondaryIndex
+    where D: DeserializeOwned + 'static,
+          K: Serialize,
+          F: Fn(&D) -> K + Send + Sync + 'static,
+    {
+        let extractor: IndexExtractor = Arc::new(move |data: &Value| {
+            serde_json::from_value::<D>(data.clone())
+                .ok()
+                .map(|data| SecondaryIndex::encode(&extractor(&data)))
+        });
+        SecondaryIndex {
+            name: name.to_string(),
+            type_name: std::any::type_name::<D>().to_string(),
+            mode,
+            extractor,
+            state: StdMutex::new(SecondaryIndexState::default()),
+        }
+    }
+
+    /// Creates an empty copy of this index for another chain
+    pub(crate) fn fresh(&self) -> SecondaryIndex {
+        SecondaryIndex {
+            name: self.name.clone(),
+            type_name: self.type_name.clone(),
+            mode: self.mode,
+            extractor: self.extractor.clone(),
+            state: StdMutex::new(SecondaryIndexState::default()),
+        }
+    }
+
+    pub(crate) fn encode<K: Serialize + ?Sized>(value: &K) -> Vec<u8> {
+        bincode::serialize(value).unwrap_or_default()
+    }
+
+    /// Hash of an encoded value as it is stored in the metadata and the index
+    pub(crate) fn hash(&self, encoded: &[u8]) -> AteHash {
+        AteHash::from_bytes_twice(self.name.as_bytes(), encoded)
+    }
+
+    /// Computes the value that a data object (in its JSON form) has in this index
+    pub(crate) fn claim(&self, data: &Value) -> Option<MetaIndexed> {
+        (self.extractor)(data)
+            .map(|a| MetaIndexed {
+                index: self.name.clone(),
+                hash: self.hash(&a[..]),
+            })
+    }
+
+    /// Takes the rows that changed since the index was last brought up to date
+    pub(crate) fn take_dirty(&self) -> Vec<PrimaryKey> {
+        let mut state = self.state.lock();
+        state.dirty.drain().collect()
+    }
+
+    /// Marks a row as still needing to be indexed (e.g. the session could not read it)
+    pub(crate) fn mark_dirty(&self, key: PrimaryKey) {
+        self.state.lock().dirty.insert(key);
+    }
+
+    /// Updates the value of a row in the index using the decoded object (in its JSON form)
+    pub(crate) fn update(&self, key: PrimaryKey, data: &Value) {
+        let value = match self.claim(data) {
+            Some(a) => a.hash,
+            None => { return; }
+        };
+        self.state.lock().update(self, key, value);
+    }
+
+    pub(crate) fn remove(&self, key: &PrimaryKey) {
+        self.state.lock().remove(key);
+    }
+
+    pub(crate) fn lookup(&self, encoded: &[u8]) -> Vec<PrimaryKey> {
+        let value = self.hash(encoded);
+        let state = self.state.lock();
+        match state.values.get_vec(&value) {
+            Some(a) => a.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// Indexer that keeps a secondary index informed of the rows that are
+/// created, updated and deleted on the chain
+#[derive(Debug)]
+pub(crate) struct SecondaryIndexer
+{
+    index: Arc<SecondaryIndex>,
+}
+
+impl SecondaryIndexer
+{
+    pub(crate) fn new(index: &Arc<SecondaryIndex>) -> SecondaryIndexer {
+        SecondaryIndexer {
+            index: Arc::clone(index),
+        }
+    }
+}
+
+impl EventSink
+for SecondaryIndexer
+{
+    fn feed(&mut self, header: &EventHeader, _conversation: Option<&Arc<ConversationSession>>) -> Result<(), SinkError>
+    {
+        let index = &self.index;
+        let mut state = index.state.lock();
+        for core in header.meta.core.iter() {
+            if let CoreMetadata::Tombstone(key) = core {
+                state.remove(key);
+                state.dirty.remove(key);
+                return Ok(());
+            }
+        }
+
+        if header.raw.data_hash.is_none() {
+            return Ok(());
+        }
+        let key = match header.meta.get_data_key() {
+            Some(a) => a,
+            None => { return Ok(()); }
+        };
+        if let Some(indexed) = header.meta.get_indexed(index.name.as_str()) {
+            state.update(index, key, indexed.hash.clone());
+            return Ok(());
+        }
+        match header.meta.get_type_name() {
+            Some(t) if t.type_name == index.type_name => {
+                state.remove(&key);
+                state.dirty.insert(key);
+            },
+            _ => { }
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self)
+    {
+        let mut state = self.index.state.lock();
+        state.values.clear();
+        state.keys.clear();
+        state.dirty.clear();
+    }
+}
+
+impl EventIndexer
+for SecondaryIndexer
+{
+    fn clone_indexer(&self) -> Box<dyn EventIndexer> {
+        Box::new(SecondaryIndexer {
+            index: self.index.clone(),
+        })
+    }
+
+    fn rebuild(&mut self, headers: &Vec<EventHeader>) -> Result<(), SinkError>
+    {
+        self.reset();
+        for header in headers.iter() {
+            self.feed(header, None)?;
+        }
+        Ok(())
+    }
+}
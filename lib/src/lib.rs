@@ -16,7 +16,7 @@ pub const HASH_ROUTINE: crypto::HashRoutine = if cfg!(feature = "use_blake3") {
     crypto::HashRoutine::Blake3
 };
 
-pub const LOG_VERSION: spec::EventVersion = spec::EventVersion::V2;
+pub const LOG_VERSION: spec::EventVersion = spec::EventVersion::V3;
 
 pub mod utils;
 pub mod error;
@@ -51,5 +51,6 @@ pub mod service;
 pub mod pipe;
 pub mod prelude;
 pub mod anti_replay;
+pub mod unique;
 pub mod flow;
 pub mod repository;
\ No newline at end of file
@@ -73,9 +73,10 @@ impl ActiveSessionPipe
         // Convert the event data into message events
         let evts = MessageEvent::convert_to(&trans.events);
         
-        // If the scope requires synchronization with the remote server then allocate a commit ID
+        // Allocate a commit ID so that the server confirms when it has the events (for
+        // scopes that do not wait on this confirmation the events are staged locally)
         let (commit, receiver) = match &trans.scope {
-            TransactionScope::Full =>
+            TransactionScope::Full | TransactionScope::Local =>
             {
                 // Generate a sender/receiver pair
                 let (sender, receiver) = mpsc::channel(1);
@@ -102,7 +103,10 @@ impl ActiveSessionPipe
 
 impl ActiveSessionPipe
 {
-    pub(super) async fn feed(&self, trans: &mut Transaction) -> Result<(), CommitError>
+    /// Transmits the transaction to the server, if the events are not confirmed by the
+    /// server before this returns then the transaction is marked as unconfirmed and a
+    /// receiver for the late confirmation (if there will be one) is returned
+    pub(super) async fn feed(&self, trans: &mut Transaction) -> Result<Option<mpsc::Receiver<Result<(), CommitError>>>, CommitError>
     {
         // Only transmit the packet if we are meant to
         if trans.transmit == true
@@ -112,7 +116,8 @@ impl ActiveSessionPipe
                 if self.mode.should_error_out() {
                     return Err(CommitError::CommsError(CommsError::Disconnected));
                 } else {
-                    return Ok(())
+                    trans.unconfirmed = true;
+                    return Ok(None)
                 }
             }
 
@@ -120,15 +125,21 @@ impl ActiveSessionPipe
             let receiver = self.feed_internal(trans).await?;
 
             // If we need to wait for the transaction to commit then do so
-            if let Some(mut receiver) = receiver {
-                match receiver.recv().await {
-                    Some(result) => result?,
-                    None => { return Err(CommitError::Aborted); }
-                };
+            match (trans.scope, receiver) {
+                (TransactionScope::Full, Some(mut receiver)) => {
+                    match receiver.recv().await {
+                        Some(result) => result?,
+                        None => { return Err(CommitError::Aborted); }
+                    };
+                },
+                (_, receiver) => {
+                    trans.unconfirmed = true;
+                    return Ok(receiver);
+                }
             }
         }
 
-        Ok(())
+        Ok(None)
     }
 
     pub(super) async fn try_lock(&self, key: PrimaryKey) -> Result<bool, CommitError>
@@ -65,6 +65,17 @@ impl MessageEvent
         feed_me
     }
 
+    /// Checks that the event does not exceed the size limits that are
+    /// enforced on the redo log before it is accepted from a remote peer
+    pub(crate) fn check_limits(&self, limits: &EventLimits) -> Result<(), SerializationError> {
+        if let Some(data) = self.data.as_ref() {
+            limits.check_data(data.len())?;
+        }
+        let meta_bytes = self.format.meta.serialize(&self.meta)?;
+        limits.check_meta(meta_bytes.len())?;
+        Ok(())
+    }
+
     pub(crate) fn data_hash(&self) -> Option<AteHash> {
         match self.data.as_ref() {
             Some(d) => Some(AteHash::from_bytes(&d[..])),
@@ -124,6 +135,21 @@ pub(super) enum Message {
     },
 
     SecuredWith(AteSession),
+
+    /// The commit was rejected because a data object it depends on was modified concurrently
+    CommitConflict {
+        id: u64,
+        key: PrimaryKey,
+        expected: AteHash,
+        actual: Option<AteHash>,
+    },
+
+    /// The commit was rejected because it claims a value that must be unique and is already used
+    CommitUniqueViolation {
+        id: u64,
+        constraint: String,
+        existing: PrimaryKey,
+    },
 }
 
 impl Default
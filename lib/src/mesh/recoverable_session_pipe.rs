@@ -33,6 +33,30 @@ use crate::meta::*;
 use crate::session::*;
 use crate::time::*;
 
+/// Number of staged events that are sent to the server in one transaction when reconnecting
+static STAGED_UPLOAD_BATCH_SIZE: usize = 1000;
+
+/// Returns true if the events were definitively rejected (i.e. sending them again will
+/// never succeed) rather than failing to reach the server
+fn is_rejection(err: &CommitError) -> bool
+{
+    match err {
+        CommitError::RootError(_) => true,
+        CommitError::Conflict { .. } => true,
+        CommitError::ValidationError(_) => true,
+        CommitError::NewRootsAreDisabled => true,
+        CommitError::TransformError(_) => true,
+        CommitError::LintError(_) => true,
+        CommitError::SerializationError(_) => true,
+        _ => false,
+    }
+}
+
+fn staged_hashes(evts: &[EventData]) -> Result<Vec<AteHash>, SerializationError>
+{
+    evts.iter().map(|e| e.as_header_raw().map(|h| h.event_hash)).collect()
+}
+
 pub(super) struct RecoverableSessionPipe
 {
     // Passes onto the next pipe
@@ -63,7 +87,8 @@ impl RecoverableSessionPipe
             .wire_encryption(self.builder.cfg.wire_encryption)
             .connect_to(self.addr.ip, self.addr.port)
             .on_connect(Message::Connected)
-            .buffer_size(self.builder.cfg.buffer_size_client);
+            .buffer_size(self.builder.cfg.buffer_size_client)
+            .max_frame_size(self.builder.cfg.max_frame_size);
         let (node_tx, node_rx)
             = crate::comms::connect::<Message, ()>
             (
@@ -83,6 +108,7 @@ impl RecoverableSessionPipe
             lock_requests: Arc::clone(&lock_requests),
             inbound_conversation: Arc::clone(&inbound_conversation),
             outbound_conversation: Arc::clone(&outbound_conversation),
+            limits: self.builder.cfg.event_limits(),
         });
         
         // Set the pipe and drop the lock so that events can be fed correctly
@@ -102,6 +128,69 @@ impl RecoverableSessionPipe
         ))
     }
 
+    /// Sends all the events that are staged (i.e. were never confirmed) to the server
+    /// and moves them into the main redo log once the server confirms them, events
+    /// that the server rejects are dropped from the staging log
+    async fn upload_staged(&self, chain: &Arc<Chain>) -> Result<(), ChainCreationError>
+    {
+        let staged = chain.staged_events().await;
+        for evts in staged.chunks(STAGED_UPLOAD_BATCH_SIZE) {
+            debug!("sending {} staged events", evts.len());
+            match self.upload(evts).await {
+                Ok(()) => { chain.confirm_staged(&staged_hashes(evts)?[..]).await?; },
+                Err(err) if is_rejection(&err) => {
+                    // Something in the batch was rejected so the events are sent again one
+                    // at a time to find the ones that the server will never accept
+                    for evt in evts.iter() {
+                        let evt = std::slice::from_ref(evt);
+                        let hashes = staged_hashes(evt)?;
+                        match self.upload(evt).await {
+                            Ok(()) => { chain.confirm_staged(&hashes[..]).await?; },
+                            Err(err) if is_rejection(&err) => {
+                                warn!("server rejected staged event {} - {}", hashes[0], err);
+                                chain.discard_staged(&hashes[..]).await?;
+                            },
+                            Err(err) => {
+                                debug!("failed sending staged events - {}", err);
+                                return Ok(());
+                            }
+                        }
+                    }
+                },
+                Err(err) => {
+                    // (the connection is gone so the rest are sent when it reconnects)
+                    debug!("failed sending staged events - {}", err);
+                    return Ok(());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Sends a batch of staged events to the server and waits for it to accept them
+    async fn upload(&self, evts: &[EventData]) -> Result<(), CommitError>
+    {
+        let mut trans = Transaction {
+            scope: TransactionScope::Full,
+            transmit: true,
+            events: evts.to_vec(),
+            conversation: None,
+            unconfirmed: false,
+        };
+
+        let receiver = {
+            let lock = self.active.read().await;
+            match lock.as_ref() {
+                Some(pipe) => pipe.feed_internal(&mut trans).await?,
+                None => { return Err(CommitError::CommsError(CommsError::Disconnected)); }
+            }
+        };
+        match receiver {
+            Some(mut receiver) => receiver.recv().await.unwrap_or(Err(CommitError::Aborted)),
+            None => Err(CommitError::Aborted),
+        }
+    }
+
     pub(super) async fn auto_reconnect(chain: Weak<Chain>, mut status_change: mpsc::Receiver<ConnectionStatusChange>) -> Result<(), ChainCreationError>
     {
         // Enter a loop
@@ -192,10 +281,6 @@ for RecoverableSessionPipe
         let (pipe, node_rx, session)
             = self.create_active_pipe().await?;
 
-        // Clone some parameters out of the pipe that we use later
-        let pipe_tx = pipe.tx.get_unicast_sender();
-        let wire_format = pipe.tx.wire_format;
-
         // We build a anti replay loader and fill it with the events we already have
         // This is because the sync design has a tolerance in what it replays back
         // to the consumer meaning duplicate events will be received from the remote
@@ -275,40 +360,10 @@ for RecoverableSessionPipe
         }
         debug!("loaded {}", self.key.to_string());
 
-        // Now we need to send all the events over that have been delayed
+        // Now we need to send all the events that were staged while we were not connected
         let chain = self.chain.lock().as_ref().map(|a| a.upgrade());
         if let Some(Some(chain)) = chain {
-            for delayed_upload in chain.get_pending_uploads().await {
-                
-                if let Some(reply_at) = &pipe_tx {
-                    debug!("sending pending upload [{}..{}]", delayed_upload.from, delayed_upload.to);
-
-                    // We send all the events for this delayed upload to the server by streaming
-                    // it in a controlled and throttled way
-                    stream_history_range(
-                        Arc::clone(&chain), 
-                        delayed_upload.from..delayed_upload.to, 
-                        reply_at.clone(),
-                        wire_format,
-                    ).await?;
-
-                    // We complete a dummy transaction to confirm that all the data has been
-                    // successfully received by the server and processed before we clear our flag
-                    match chain.multi().await.sync().await {
-                        Ok(()) =>
-                        {
-                            // Finally we clear the pending upload by writing a record for it
-                            MeshSession::complete_delayed_upload(&chain, delayed_upload.from, delayed_upload.to).await?;
-                        },
-                        Err(err) =>
-                        {
-                            debug!("failed sending pending upload - {}", err);
-                        }
-                    };
-                } else {
-                    debug!("failed sending pending upload - no pipe sender");
-                }
-            }
+            self.upload_staged(&chain).await?;
         }
 
         // Mark the pipe as connected
@@ -324,16 +379,52 @@ for RecoverableSessionPipe
 
     async fn feed(&self, mut trans: Transaction) -> Result<(), CommitError>
     {
-        {
+        let receiver = {
             let lock = self.active.read().await;
             if let Some(pipe) = lock.as_ref() {
-                pipe.feed(&mut trans).await?;
+                pipe.feed(&mut trans).await?
             } else if self.mode.should_error_out() {
                 return Err(CommitError::CommsError(CommsError::Disconnected));
+            } else {
+                trans.unconfirmed = trans.transmit;
+                None
             }
+        };
+
+        // Events that the server has not yet confirmed are staged locally
+        let hashes = match receiver {
+            Some(_) => staged_hashes(&trans.events[..])?,
+            None => Vec::new(),
+        };
+        self.next.feed(trans).await?;
+
+        // Once the server confirms the events they are moved into the main redo log
+        if let Some(mut receiver) = receiver {
+            let chain = self.chain.lock().clone();
+            tokio::spawn(async move {
+                let result = receiver.recv().await;
+                let chain = match chain.map(|c| c.upgrade()).flatten() {
+                    Some(a) => a,
+                    None => { return; }
+                };
+                match result {
+                    Some(Ok(())) => {
+                        if let Err(err) = chain.confirm_staged(&hashes[..]).await {
+                            warn!("failed to confirm staged events - {}", err);
+                        }
+                    },
+                    // Events the server rejects will never be confirmed so they are not kept
+                    Some(Err(err)) if is_rejection(&err) => {
+                        warn!("server rejected {} staged events - {}", hashes.len(), err);
+                        if let Err(err) = chain.discard_staged(&hashes[..]).await {
+                            warn!("failed to discard staged events - {}", err);
+                        }
+                    },
+                    _ => { }
+                }
+            });
         }
-
-        self.next.feed(trans).await
+        Ok(())
     }
 
     async fn try_lock(&self, key: PrimaryKey) -> Result<bool, CommitError>
@@ -27,6 +27,7 @@ use super::Registry;
 use crate::flow::OpenFlow;
 use crate::flow::OpenAction;
 use crate::spec::SerializationFormat;
+use crate::spec::EventLimits;
 use crate::repository::ChainRepository;
 use crate::comms::TxDirection;
 use crate::crypto::AteHash;
@@ -102,7 +103,8 @@ where F: OpenFlow + 'static
         let mut node_cfg = NodeConfig::new(cfg_ate.wire_format)
             .wire_encryption(cfg_ate.wire_encryption)
             .timeout(cfg_ate.connect_timeout)
-            .buffer_size(cfg_ate.buffer_size_server);
+            .buffer_size(cfg_ate.buffer_size_server)
+            .max_frame_size(cfg_ate.max_frame_size);
         let mut listen_ports = listen_addrs
             .iter()
             .map(|a| a.port)
@@ -335,6 +337,7 @@ async fn inbox_event(
     evts: Vec<MessageEvent>,
     tx: &NodeTx<SessionContext>,
     pck_data: PacketData,
+    limits: EventLimits,
 )
 -> Result<(), CommsError>
 {
@@ -351,6 +354,19 @@ async fn inbox_event(
         None => { return Ok(()); }
     };
     let commit = commit.clone();
+
+    // Reject any events that are too big to be stored in the redo log
+    for evt in evts.iter() {
+        if let Err(err) = evt.check_limits(&limits) {
+            if let Some(id) = commit {
+                PacketData::reply_at(reply_at, pck_data.wire_format, Message::CommitError {
+                    id,
+                    err: err.to_string(),
+                }).await?;
+            }
+            return Err(CommsError::SerializationError(err));
+        }
+    }
     
     // Feed the events into the chain of trust
     let evts = MessageEvent::convert_from(evts.into_iter());
@@ -359,6 +375,7 @@ async fn inbox_event(
         transmit: false,
         events: evts,
         conversation: Some(Arc::clone(&context.conversation)),
+        unconfirmed: false,
 
     }).await;
 
@@ -377,9 +394,29 @@ async fn inbox_event(
 
     // If the operation has a commit to transmit the response
     if let Some(id) = commit {
-        match &ret {
-            Ok(_) => PacketData::reply_at(reply_at, wire_format, Message::Confirmed(id.clone())).await?,
-            Err(err) => PacketData::reply_at(reply_at, wire_format, Message::CommitError{
+        // (unique constraint violations are passed back so the writer can act on them)
+        let unique = match &ret {
+            Err(CommitError::ValidationError(errs)) => errs.iter()
+                .find_map(|a| match a {
+                    ValidationError::UniqueViolation { constraint, existing } => Some((constraint.clone(), existing.clone())),
+                    _ => None,
+                }),
+            _ => None,
+        };
+        match (&ret, unique) {
+            (Ok(_), _) => PacketData::reply_at(reply_at, wire_format, Message::Confirmed(id.clone())).await?,
+            (Err(CommitError::Conflict { key, expected, actual }), _) => PacketData::reply_at(reply_at, wire_format, Message::CommitConflict {
+                id: id.clone(),
+                key: key.clone(),
+                expected: expected.clone(),
+                actual: actual.clone(),
+            }).await?,
+            (Err(_), Some((constraint, existing))) => PacketData::reply_at(reply_at, wire_format, Message::CommitUniqueViolation {
+                id: id.clone(),
+                constraint,
+                existing,
+            }).await?,
+            (Err(err), None) => PacketData::reply_at(reply_at, wire_format, Message::CommitError{
                 id: id.clone(),
                 err: err.to_string(),
             }).await?
@@ -540,7 +577,7 @@ where F: OpenFlow + 'static
         Message::Subscribe { chain_key, from }
             => inbox_subscribe(root, chain_key, from, reply_at, context, wire_format, tx).await,
         Message::Events { commit, evts }
-            => inbox_event(reply_at, context, commit, evts, tx, pck_data).await,
+            => inbox_event(reply_at, context, commit, evts, tx, pck_data, root.cfg_ate.event_limits()).await,
         Message::Lock { key }
             => inbox_lock(reply_at, context, key, wire_format).await,
         Message::Unlock { key }
@@ -42,6 +42,7 @@ pub struct MeshSession
     pub(super) lock_requests: Arc<StdMutex<FxHashMap<PrimaryKey, LockRequest>>>,
     pub(super) inbound_conversation: Arc<ConversationSession>,
     pub(super) outbound_conversation: Arc<ConversationSession>,
+    pub(super) limits: EventLimits,
 }
 
 impl MeshSession
@@ -166,6 +167,11 @@ impl MeshSession
     pub(super) async fn inbox_events(self: &Arc<MeshSession>, evts: Vec<MessageEvent>, loader: &mut Option<Box<impl Loader>>) -> Result<(), CommsError> {
         debug!("inbox: events cnt={}", evts.len());
 
+        // Reject any events that are too big to be stored in the redo log
+        for evt in evts.iter() {
+            evt.check_limits(&self.limits)?;
+        }
+
         if let Some(chain) = self.chain.upgrade()
         {
             // Convert the events but we do this differently depending on on if we are
@@ -196,6 +202,7 @@ impl MeshSession
                 transmit: false,
                 events: feed_me,
                 conversation: Some(Arc::clone(&self.inbound_conversation)),
+                unconfirmed: false,
             }).await?;
         }
 
@@ -228,6 +235,32 @@ impl MeshSession
         Ok(())
     }
 
+    pub(super) async fn inbox_commit_conflict(self: &Arc<MeshSession>, id: u64, key: PrimaryKey, expected: AteHash, actual: Option<AteHash>) -> Result<(), CommsError> {
+        debug!("inbox: commit_conflict id={}, key={}", id, key);
+
+        let r= {
+            let mut lock = self.commit.lock();
+            lock.remove(&id)
+        };
+        if let Some(result) = r {
+            result.send(Err(CommitError::Conflict { key, expected, actual })).await?;
+        }
+        Ok(())
+    }
+
+    pub(super) async fn inbox_commit_unique_violation(self: &Arc<MeshSession>, id: u64, constraint: String, existing: PrimaryKey) -> Result<(), CommsError> {
+        debug!("inbox: commit_unique_violation id={}, constraint={}", id, constraint);
+
+        let r= {
+            let mut lock = self.commit.lock();
+            lock.remove(&id)
+        };
+        if let Some(result) = r {
+            result.send(Err(CommitError::ValidationError(vec![ValidationError::UniqueViolation { constraint, existing }]))).await?;
+        }
+        Ok(())
+    }
+
     pub(super) fn inbox_lock_result(self: &Arc<MeshSession>, key: PrimaryKey, is_locked: bool) -> Result<(), CommsError> {
         debug!("inbox: lock_result key={} is_locked={}", key.to_string(), is_locked);
 
@@ -242,52 +275,7 @@ impl MeshSession
         Ok(())
     }
 
-    pub(super) async fn record_delayed_upload(chain: &Arc<Chain>, pivot: ChainTimestamp) -> Result<(), CommsError>
-    {
-        let mut guard = chain.inside_async.write().await;
-        let from = guard.range_keys(pivot..).next();
-        if let Some(from) = from
-        {
-            if let Some(a) = guard.chain.timeline.pointers.get_delayed_upload(from) {
-                debug!("delayed_upload exists: {}..{}", a.from, a.to);
-                return Ok(());
-            }
-
-            let to = guard.range_keys(from..).next_back();
-            if let Some(to) = to {
-                debug!("delayed_upload new: {}..{}", from, to);
-                guard.feed_meta_data(&chain.inside_sync, Metadata {
-                    core: vec![CoreMetadata::DelayedUpload(MetaDelayedUpload {
-                        complete: false,
-                        from: from.clone(),
-                        to: to.clone()
-                    })]
-                }).await?;
-            } else {
-                debug!("delayed_upload: {}..error", from);
-            }
-        } else {
-            debug!("delayed_upload: error..error");
-        }
-
-        Ok(())
-    }
-
-    pub(super) async fn complete_delayed_upload(chain: &Arc<Chain>, from: ChainTimestamp, to: ChainTimestamp) -> Result<(), CommsError>
-    {
-        debug!("delayed_upload complete: {}..{}", from, to);
-        let mut guard = chain.inside_async.write().await;
-        let _ = guard.feed_meta_data(&chain.inside_sync, Metadata {
-            core: vec![CoreMetadata::DelayedUpload(MetaDelayedUpload {
-                complete: true,
-                from,
-                to
-            })]
-        }).await?;
-        Ok(())
-    }
-
-    pub(super) async fn inbox_start_of_history(self: &Arc<MeshSession>, size: usize, _from: Option<ChainTimestamp>, to: Option<ChainTimestamp>, loader: &mut Option<Box<impl Loader>>, root_keys: Vec<PublicSignKey>, integrity: IntegrityMode) -> Result<(), CommsError>
+    pub(super) async fn inbox_start_of_history(self: &Arc<MeshSession>, size: usize, _from: Option<ChainTimestamp>, _to: Option<ChainTimestamp>, loader: &mut Option<Box<impl Loader>>, root_keys: Vec<PublicSignKey>, integrity: IntegrityMode) -> Result<(), CommsError>
     {
         // Declare variables
         let size = size;
@@ -305,22 +293,6 @@ impl MeshSession
                     plugin.set_root_keys(&root_keys);
                 }
             }
-
-            // If we are synchronizing from an earlier point in the tree then
-            // add all the events into a redo log that will be shippped
-            if let Some(to) = to {
-                let next = {
-                    let multi = chain.multi().await;
-                    let guard = multi.inside_async.read().await;
-                    let mut iter = guard
-                        .range_keys(to..);
-                    iter.next();
-                    iter.next()
-                };
-                if let Some(next) = next {
-                    MeshSession::record_delayed_upload(&chain, next).await?;
-                }
-            }
         }
         
         // Tell the loader that we will be starting the load process of the history
@@ -371,6 +343,10 @@ impl MeshSession
                 => Self::inbox_confirmed(self, id).await,
             Message::CommitError { id, err }
                 => Self::inbox_commit_error(self, id, err).await,
+            Message::CommitConflict { id, key, expected, actual }
+                => Self::inbox_commit_conflict(self, id, key, expected, actual).await,
+            Message::CommitUniqueViolation { id, constraint, existing }
+                => Self::inbox_commit_unique_violation(self, id, constraint, existing).await,
             Message::LockResult { key, is_locked }
                 => Self::inbox_lock_result(self, key, is_locked),
             Message::EndOfHistory
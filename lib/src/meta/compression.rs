@@ -0,0 +1,22 @@
+use serde::{Serialize, Deserialize};
+
+/// Codec that was used to compress the data of an event
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompressionCodec
+{
+    Snap,
+    Zstd,
+    Lz4,
+}
+
+impl std::fmt::Display
+for CompressionCodec
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CompressionCodec::Snap => write!(f, "snap"),
+            CompressionCodec::Zstd => write!(f, "zstd"),
+            CompressionCodec::Lz4 => write!(f, "lz4"),
+        }
+    }
+}
@@ -28,7 +28,17 @@ pub enum CoreMetadata
     Author(String),
     Type(MetaType),
     Reply(PrimaryKey),
+    /// Retired marker from before unconfirmed writes were staged, it is kept in its
+    /// original slot so that older logs and peers still decode and new variants
+    /// must only ever be added after it
     DelayedUpload(MetaDelayedUpload),
+    Compression(CompressionCodec),
+    Precondition(AteHash),
+    Order(MetaOrder),
+    Unique(MetaUnique),
+    DeliverAt(ChainTimestamp),
+    IdempotencyKey(String),
+    Indexed(MetaIndexed),
 }
 
 impl Default for CoreMetadata {
@@ -59,6 +69,13 @@ for CoreMetadata
             CoreMetadata::Type(a) => write!(f, "type-{}", a),
             CoreMetadata::Reply(a) => write!(f, "reply-{}", a),
             CoreMetadata::DelayedUpload(a) => write!(f, "delayed_upload-{}", a),
+            CoreMetadata::Compression(a) => write!(f, "compression-{}", a),
+            CoreMetadata::Precondition(a) => write!(f, "precondition-{}", a),
+            CoreMetadata::Order(a) => write!(f, "order-{}", a),
+            CoreMetadata::Unique(a) => write!(f, "unique-{}", a),
+            CoreMetadata::DeliverAt(a) => write!(f, "deliver-at-{}", a),
+            CoreMetadata::IdempotencyKey(a) => write!(f, "idempotency-{}", a),
+            CoreMetadata::Indexed(a) => write!(f, "indexed-{}", a),
         }
     }
 }
@@ -125,6 +142,89 @@ impl Metadata
         ret
     }
 
+    pub fn get_compression(&self) -> Option<CompressionCodec>
+    {
+        for core in &self.core {
+            if let CoreMetadata::Compression(a) = core {
+                return Some(*a);
+            }
+        }
+        None
+    }
+
+    pub fn get_author(&self) -> Option<&String>
+    {
+        for core in &self.core {
+            if let CoreMetadata::Author(a) = core {
+                return Some(a);
+            }
+        }
+        None
+    }
+
+    pub fn get_precondition(&self) -> Option<&AteHash>
+    {
+        for core in &self.core {
+            if let CoreMetadata::Precondition(a) = core {
+                return Some(a);
+            }
+        }
+        None
+    }
+
+    pub fn get_order(&self) -> Option<&MetaOrder>
+    {
+        for core in &self.core {
+            if let CoreMetadata::Order(a) = core {
+                return Some(a);
+            }
+        }
+        None
+    }
+
+    pub fn get_unique_values(&self) -> Vec<&MetaUnique>
+    {
+        let mut ret = Vec::new();
+        for core in &self.core {
+            if let CoreMetadata::Unique(a) = core {
+                ret.push(a);
+            }
+        }
+        ret
+    }
+
+    pub fn get_indexed(&self, index: &str) -> Option<&MetaIndexed>
+    {
+        for core in &self.core {
+            if let CoreMetadata::Indexed(a) = core {
+                if a.index == index {
+                    return Some(a);
+                }
+            }
+        }
+        None
+    }
+
+    pub fn get_deliver_at(&self) -> Option<ChainTimestamp>
+    {
+        for core in &self.core {
+            if let CoreMetadata::DeliverAt(a) = core {
+                return Some(*a);
+            }
+        }
+        None
+    }
+
+    pub fn get_idempotency_key(&self) -> Option<&String>
+    {
+        for core in &self.core {
+            if let CoreMetadata::IdempotencyKey(a) = core {
+                return Some(a);
+            }
+        }
+        None
+    }
+
     pub fn get_confidentiality(&self) -> Option<&MetaConfidentiality>
     {
         for core in &self.core {
@@ -259,23 +359,16 @@ impl Metadata
             .next()
     }
 
-    pub fn get_delayed_upload(&self) -> Option<MetaDelayedUpload> {
-        self.core.iter().filter_map(
-            |m| {
-                match m
-                {
-                    CoreMetadata::DelayedUpload(k) => Some(k.clone()),
-                     _ => None
-                }
-            }
-        )
-        .next()
-    } 
-
+    /// Delayed upload markers written by older versions are not part of the history
     pub fn include_in_history(&self) -> bool {
-        if self.get_delayed_upload().is_some() {
-            return false;
-        }
-        true
+        self.core.iter().any(|m| matches!(m, CoreMetadata::DelayedUpload(_))) == false
     }
-}
\ No newline at end of file
+}
+
+#[test]
+fn test_core_metadata_discriminants()
+{
+    // Variants are encoded by their position so retired variants must keep their slot
+    let bytes = bincode::serialize(&CoreMetadata::Compression(CompressionCodec::Snap)).unwrap();
+    assert_eq!(&bytes[..4], &17u32.to_le_bytes()[..]);
+}
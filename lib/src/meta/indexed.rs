@@ -0,0 +1,21 @@
+use serde::{Serialize, Deserialize};
+
+use crate::crypto::AteHash;
+
+/// Value of a data object in a secondary index, like unique constraints only
+/// the hash of the value is stored so the index can be maintained by anyone
+/// who can read the metadata without needing to decrypt the data
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MetaIndexed
+{
+    pub index: String,
+    pub hash: AteHash,
+}
+
+impl std::fmt::Display
+for MetaIndexed
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.index, self.hash)
+    }
+}
@@ -1,19 +1,27 @@
 mod authorization;
 mod collection;
+mod compression;
 mod confidentiality;
 mod core;
 mod delayed_upload;
+mod indexed;
 mod meta_type;
+mod order;
 mod parent;
 mod read_option;
+mod unique;
 mod write_option;
 
 pub use authorization::*;
 pub use confidentiality::*;
 pub use collection::*;
+pub use compression::*;
 pub use self::core::*;
 pub use delayed_upload::*;
+pub use indexed::*;
 pub use meta_type::*;
+pub use order::*;
 pub use parent::*;
 pub use read_option::*;
+pub use unique::*;
 pub use write_option::*;
\ No newline at end of file
@@ -0,0 +1,87 @@
+use serde::{Serialize, Deserialize};
+
+/// Position of a child within the collection it is attached to, the children
+/// are sorted by comparing these keys byte-by-byte and ties (e.g. two clients
+/// that concurrently inserted at the same spot) are broken by the primary key
+/// so that every replica converges on the same order
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct MetaOrder
+{
+    pub key: Vec<u8>,
+}
+
+impl MetaOrder
+{
+    /// Order key for children that are appended to the end of a collection at
+    /// a particular time (milliseconds since the epoch)
+    pub fn from_timestamp(time_since_epoch_ms: u64) -> MetaOrder {
+        MetaOrder {
+            key: time_since_epoch_ms.to_be_bytes().to_vec(),
+        }
+    }
+
+    /// Order key for children that are appended in a batch at the same time, the
+    /// sequence keeps them in the order they were added to the batch
+    pub fn from_sequence(time_since_epoch_ms: u64, sequence: u32) -> MetaOrder {
+        let mut ret = MetaOrder::from_timestamp(time_since_epoch_ms);
+        ret.key.extend_from_slice(&sequence.to_be_bytes());
+        ret
+    }
+
+    /// Generates an order key that sorts after `before` and before `after`, if
+    /// either side is missing then the collection is open ended in that direction
+    pub fn between(before: Option<&MetaOrder>, after: Option<&MetaOrder>) -> MetaOrder
+    {
+        let lo = before.map(|a| &a.key[..]).unwrap_or(&[]);
+        let hi = match after {
+            Some(a) if a.key[..] > *lo => Some(&a.key[..]),
+            _ => None,
+        };
+
+        // When there is nothing after we stay as close as possible to the key
+        // before so that children appended later by the clock still sort after
+        let hi = match hi {
+            Some(a) => a,
+            None => {
+                let mut key = lo.to_vec();
+                key.push(0x80);
+                return MetaOrder { key };
+            }
+        };
+
+        // Treat the keys as base-256 fractions and take the first digit where
+        // there is room between them
+        let mut key = Vec::new();
+        let mut bounded = true;
+        let mut n = 0usize;
+        loop {
+            // (no key exists between `x` and `x` followed by zeros so we just go after it)
+            if bounded && n >= lo.len() && n >= hi.len() {
+                key.push(0x80);
+                return MetaOrder { key };
+            }
+            let l = lo.get(n).map(|a| *a as u16).unwrap_or(0);
+            let h = match bounded {
+                true => hi.get(n).map(|a| *a as u16).unwrap_or(0),
+                false => 0x100,
+            };
+            if h > l + 1 {
+                key.push(((l + h) / 2) as u8);
+                return MetaOrder { key };
+            }
+            key.push(l as u8);
+            if h > l {
+                bounded = false;
+            }
+            n = n + 1;
+        }
+    }
+}
+
+impl std::fmt::Display
+for MetaOrder
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", hex::encode(&self.key))
+    }
+}
@@ -0,0 +1,21 @@
+use serde::{Serialize, Deserialize};
+
+use crate::crypto::AteHash;
+
+/// Value that a data object claims for a unique constraint, only the hash of
+/// the value is stored so that the chain can enforce the constraint without
+/// being able to read the data itself
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MetaUnique
+{
+    pub constraint: String,
+    pub hash: AteHash,
+}
+
+impl std::fmt::Display
+for MetaUnique
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.constraint, self.hash)
+    }
+}
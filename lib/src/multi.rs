@@ -18,6 +18,11 @@ use super::index::*;
 use super::transaction::*;
 use super::repository::*;
 use super::spec::MessageFormat;
+use super::time::ChainTimestamp;
+use super::crypto::AteHash;
+use super::unique::UniqueConstraint;
+use fxhash::FxHashSet;
+use multimap::MultiMap;
 
 use bytes::Bytes;
 
@@ -93,10 +98,88 @@ impl ChainMultiUser
         self.inside_async.read().await.chain.lookup_secondary_raw(key)
     }
 
+    pub async fn lookup_children(&self, parent_id: &PrimaryKey) -> Vec<PrimaryKey> {
+        self.inside_async.read().await.chain.lookup_children(parent_id)
+    }
+
     pub async fn lookup_parent(&self, key: &PrimaryKey) -> Option<MetaParent> {
         self.inside_async.read().await.chain.lookup_parent(key)
     }
 
+    pub(crate) async fn lookup_order(&self, key: &PrimaryKey) -> Option<MetaOrder> {
+        self.inside_async.read().await.chain.lookup_order(key)
+    }
+
+    pub(crate) async fn lookup_type_name(&self, key: &PrimaryKey) -> Option<String> {
+        self.inside_async.read().await.chain.lookup_type_name(key)
+    }
+
+    pub async fn lookup_primary_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<EventLeaf> {
+        self.inside_async.read().await.chain.lookup_primary_at(key, at)
+    }
+
+    pub async fn lookup_secondary_raw_at(&self, key: &MetaCollection, at: ChainTimestamp) -> Option<Vec<PrimaryKey>> {
+        self.inside_async.read().await.chain.lookup_secondary_raw_at(key, at)
+    }
+
+    pub async fn lookup_parent_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaParent> {
+        self.inside_async.read().await.chain.lookup_parent_at(key, at)
+    }
+
+    pub(crate) async fn lookup_order_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaOrder> {
+        self.inside_async.read().await.chain.lookup_order_at(key, at)
+    }
+
+    pub async fn lookup_type(&self, type_name: &str) -> Vec<PrimaryKey> {
+        self.inside_async.read().await.chain.lookup_type(type_name)
+    }
+
+    pub async fn lookup_type_at(&self, type_name: &str, at: ChainTimestamp) -> Vec<PrimaryKey> {
+        self.inside_async.read().await.chain.lookup_type_at(type_name, at)
+    }
+
+    pub async fn type_names(&self) -> Vec<String> {
+        self.inside_async.read().await.chain.type_names()
+    }
+
+    pub(crate) async fn lookup_versions(&self, key: &PrimaryKey) -> Vec<(ChainTimestamp, AteHash, Option<EventLeaf>)> {
+        self.inside_async.read().await.chain.lookup_versions(key)
+    }
+
+    pub(crate) async fn lookup_signatures(&self, hashes: &FxHashSet<AteHash>) -> MultiMap<AteHash, AteHash> {
+        self.inside_async.read().await.chain.lookup_signatures(hashes)
+    }
+
+    /// Returns the hashes of the public keys that signed an event
+    pub(crate) async fn lookup_signed_by(&self, hash: &AteHash) -> Vec<AteHash> {
+        self.inside_async.read().await.chain.lookup_signed_by(hash)
+    }
+
+    /// Events older than this timestamp may have been removed by compaction
+    pub(crate) async fn cut_off(&self) -> Result<ChainTimestamp, SerializationError> {
+        Ok(self.inside_async.read().await.chain.redo.read_chain_header()?.cut_off)
+    }
+
+    pub(crate) fn secondary_index(&self, name: &str) -> Option<Arc<SecondaryIndex>> {
+        self.inside_sync.read().secondary_indexes.get(name).map(|a| Arc::clone(a))
+    }
+
+    pub(crate) fn secondary_indexes_for(&self, type_name: &str) -> Vec<Arc<SecondaryIndex>> {
+        self.inside_sync.read().secondary_indexes
+            .values()
+            .filter(|a| a.type_name == type_name)
+            .map(|a| Arc::clone(a))
+            .collect::<Vec<_>>()
+    }
+
+    pub(crate) fn unique_constraints(&self, type_name: &str) -> Vec<UniqueConstraint> {
+        self.inside_sync.read().unique_constraints
+            .iter()
+            .filter(|a| a.type_name == type_name)
+            .map(|a| a.clone())
+            .collect::<Vec<_>>()
+    }
+
     #[allow(dead_code)]
     pub(crate) fn metadata_lint_many<'a>(&self, lints: &Vec<LintData<'a>>, session: &AteSession, conversation: Option<&Arc<ConversationSession>>) -> Result<Vec<CoreMetadata>, LintError> {
         let guard = self.inside_sync.read();
@@ -144,6 +227,7 @@ impl ChainMultiUser
             transmit: true,
             events: Vec::new(),
             conversation: None,
+            unconfirmed: false,
         };
 
         // Process the transaction in the chain using its pipe
@@ -37,4 +37,15 @@ where Self: EventValidator + EventSink + EventMetadataLinter + EventDataTransfor
     }
 
     fn set_root_keys(&mut self, _root_keys: &Vec<PublicSignKey>) { }
+
+    /// Serializes the state of the plugin so that it can be stored in a checkpoint,
+    /// plugins that return nothing will be rebuilt from the events instead
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        None
+    }
+
+    /// Restores the state of the plugin from a snapshot stored in a checkpoint
+    fn restore(&mut self, _snapshot: &[u8]) -> Result<(), SerializationError> {
+        Err(SerializationError::NoData)
+    }
 }
\ No newline at end of file
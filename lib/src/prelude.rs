@@ -3,6 +3,9 @@ pub use crate::conf::ConfAte;
 pub use crate::conf::ConfMesh;
 pub use crate::conf::ConfiguredFor;
 pub use crate::compact::CompactMode;
+pub use crate::redo::DurabilityPolicy;
+#[cfg(feature = "local_fs")]
+pub use crate::redo::{ColdStore, ColdTier, LocalColdStore};
 pub use crate::header::PrimaryKey;
 pub use crate::error::AteError;
 
@@ -29,11 +32,18 @@ pub use crate::flow::all_persistent_and_centralized_with_root_key;
 pub use crate::flow::all_persistent_and_distributed_with_root_key;
 
 pub use crate::chain::Chain;
+pub use crate::chain::ChainChange;
+pub use crate::chain::ChangeCursor;
+pub use crate::chain::ChangeKind;
+pub use crate::chain::ChangeStream;
 pub use crate::trust::ChainKey;
 pub use crate::conf::ChainBuilder;
+pub use crate::index::IndexMode;
 
 pub use crate::dio::DaoForeign;
 pub use crate::dio::DaoVec;
+pub use crate::dio::DaoBlob;
+pub use crate::dio::DeadLetter;
 pub use crate::dio::DaoRef;
 pub use crate::dio::DaoRefForeign;
 pub use crate::dio::DaoObjReal;
@@ -41,6 +51,10 @@ pub use crate::dio::DaoObjEthereal;
 pub use crate::dio::Dao;
 pub use crate::dio::DaoEthereal;
 pub use crate::dio::Dio;
+pub use crate::dio::DioQuery;
+pub use crate::dio::DaoHistory;
+pub use crate::dio::DaoVersion;
+pub use crate::dio::DynamicDao;
 
 pub use crate::spec::SerializationFormat;
 pub use crate::repository::ChainRepository;
@@ -52,10 +66,17 @@ pub use crate::session::AteGroup;
 pub use crate::session::AteGroupRole;
 pub use crate::session::AteRolePurpose;
 pub use crate::transaction::TransactionScope;
+pub use crate::time::ChainTimestamp;
 
 pub use crate::service::InvocationContext;
 pub use crate::service::ServiceHandler;
 pub use crate::service::ServiceInstance;
+pub use crate::service::StreamingServiceHandler;
+pub use crate::service::StreamingServiceInstance;
+pub use crate::service::ServiceMiddleware;
+pub use crate::service::ServiceMiddlewareInstance;
+pub use crate::service::RequireRoleMiddleware;
+pub use crate::service::RateLimitMiddleware;
 pub use crate::error::ServiceError;
 pub use crate::error::InvokeError;
 
@@ -5,6 +5,7 @@ use crate::event::*;
 use crate::error::*;
 
 use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
 use tokio::io::Result;
 
 #[async_trait]
@@ -12,9 +13,11 @@ pub trait LogWritable {
     /// Writes data to the redo log and returns the new offset in bytes
     async fn write(&mut self, evt: &EventData) -> std::result::Result<LogLookup, SerializationError>;
     async fn flush(&mut self) -> Result<()>;
+    /// Flushes the redo log and waits for the bytes to reach durable storage
+    async fn sync(&mut self) -> Result<()>;
 }
 
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct LogLookup
 {
     pub(crate) index: u32,
@@ -13,6 +13,7 @@ use super::archive::*;
 use super::magic::*;
 
 use crate::spec::LogApi;
+use crate::spec::EventLimits;
 use crate::event::*;
 use crate::error::*;
 
@@ -26,18 +27,20 @@ pub(crate) struct LogAppender
     pub(super) offset: u64,
     header: Vec<u8>,
     pub(crate) index: u32,
+    limits: EventLimits,
 }
 
 impl LogAppender
 {
-    pub async fn new(path_log: String, truncate: bool, index: u32, header_bytes: &[u8]) -> Result<(LogAppender, LogArchive)>
+    pub async fn new(path_log: String, truncate: bool, index: u32, header_bytes: &[u8], limits: EventLimits) -> Result<(LogAppender, LogArchive)>
     {
         // Compute the log file name
         let log_back_path = format!("{}.{}", path_log.clone(), index);
-        let log_back = match truncate {
-            true => OpenOptions::new().read(true).write(true).truncate(true).create(true).open(log_back_path.clone()).await?,
-               _ => OpenOptions::new().read(true).write(true).create(true).open(log_back_path.clone()).await?,
-        };
+        let log_back = OpenOptions::new().read(true).write(true).create(true).open(log_back_path.clone()).await?;
+        if truncate {
+            // The old archive may still be memory mapped by another redo log
+            LogArchive::lock_exclusive(log_back_path.as_str())?.set_len(0)?;
+        }
 
         // Build the appender
         let mut appender = LogAppender {
@@ -48,6 +51,7 @@ impl LogAppender
             offset: 0,
             index,
             header: Vec::new(),
+            limits,
         };
         
         // If it does not have a magic then add one - otherwise read it and check the value
@@ -82,12 +86,19 @@ impl LogAppender
                 offset: self.offset,
                 index: self.index,
                 header: self.header.clone(),
+                limits: self.limits,
             }
         )
     }
 
     pub(super) async fn write(&mut self, evt: &EventData, header: &EventHeaderRaw) -> std::result::Result<LogLookup, SerializationError>
     {
+        // Refuse to write events that could never be read back again
+        self.limits.check_meta(header.meta_bytes.len())?;
+        if let Some(data) = &evt.data_bytes {
+            self.limits.check_data(data.len())?;
+        }
+
         let log_header = crate::LOG_VERSION.write(
             self, 
             &header.meta_bytes[..], 
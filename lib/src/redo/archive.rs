@@ -9,6 +9,12 @@ use std::io::SeekFrom;
 use tokio::fs::File;
 use tokio::sync::Mutex as Mutex;
 use tokio::sync::MutexGuard;
+#[cfg(feature = "mmap")]
+use std::sync::Arc;
+#[cfg(feature = "mmap")]
+use memmap2::Mmap;
+#[cfg(feature = "mmap")]
+use fs2::FileExt;
 
 use crate::spec::*;
 use super::magic::*;
@@ -20,6 +26,14 @@ pub(crate) struct LogArchive
     pub(crate) path: String,
     file: Mutex<File>,
     header: Vec<u8>,
+    /// Read-only memory map of the archive which is only created once the
+    /// archive is sealed (i.e. it is no longer being appended to)
+    #[cfg(feature = "mmap")]
+    map: Option<Arc<Mmap>>,
+    /// File that holds a shared advisory lock on the archive for as long as it
+    /// is mapped so that nothing truncates it from under the memory map
+    #[cfg(feature = "mmap")]
+    map_lock: Option<Arc<std::fs::File>>,
 }
 
 impl LogArchive
@@ -34,6 +48,10 @@ impl LogArchive
             path,
             header: Vec::new(),
             file: Mutex::new(log_random_access),
+            #[cfg(feature = "mmap")]
+            map: None,
+            #[cfg(feature = "mmap")]
+            map_lock: None,
         };
 
         ret.header = {
@@ -59,18 +77,72 @@ impl LogArchive
                 index: self.index,
                 path: self.path.clone(),
                 header: self.header.clone(),
-                file: Mutex::new(log_back),                
+                file: Mutex::new(log_back),
+                #[cfg(feature = "mmap")]
+                map: self.map.clone(),
+                #[cfg(feature = "mmap")]
+                map_lock: self.map_lock.clone(),
             }
         )
     }
 
+    /// Marks the archive as sealed so that all future reads are served from a
+    /// read-only memory map which allows for concurrent lookups without locks.
+    /// A shared advisory lock is held while the archive is mapped, anything that
+    /// truncates archives must take the exclusive lock first (see `lock_exclusive`)
+    #[cfg(feature = "mmap")]
+    pub(crate) fn seal(&mut self) -> Result<()>
+    {
+        let file = std::fs::OpenOptions::new().read(true).open(self.path.clone())?;
+        if file.metadata()?.len() <= 0 {
+            return Ok(());
+        }
+        if let Err(err) = FileExt::try_lock_shared(&file) {
+            return Err(tokio::io::Error::new(err.kind(), format!("The archive ({}) is locked by another process - {}", self.path, err)));
+        }
+        let map = unsafe { Mmap::map(&file)? };
+        self.map = Some(Arc::new(map));
+        self.map_lock = Some(Arc::new(file));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub(crate) fn seal(&mut self) -> Result<()> {
+        Ok(())
+    }
+
+    /// Opens an archive for writing with an exclusive advisory lock, this fails if
+    /// the archive is memory mapped by any open redo log as truncating it would
+    /// crash the process that mapped it
+    #[cfg(feature = "mmap")]
+    pub(crate) fn lock_exclusive(path: &str) -> Result<std::fs::File>
+    {
+        let file = std::fs::OpenOptions::new().write(true).open(path)?;
+        if let Err(err) = FileExt::try_lock_exclusive(&file) {
+            return Err(tokio::io::Error::new(err.kind(), format!("The archive ({}) is mapped by an open redo log - {}", path, err)));
+        }
+        Ok(file)
+    }
+
+    #[cfg(not(feature = "mmap"))]
+    pub(crate) fn lock_exclusive(path: &str) -> Result<std::fs::File> {
+        Ok(std::fs::OpenOptions::new().write(true).open(path)?)
+    }
+
+    /// Returns a reader over the memory map of a sealed archive at a particular
+    /// offset, if the archive is not sealed then `lock_at` must be used instead
+    #[cfg(feature = "mmap")]
+    pub fn map_at(&self, off: u64) -> Option<LogArchiveMap<'_>>
+    {
+        self.map.as_ref().map(|map| LogArchiveMap::new(&map[..], off))
+    }
+
     pub async fn lock_at(&self, off: u64) -> Result<LogArchiveGuard<'_>>
     {
         let mut file = self.file.lock().await;
         file.seek(SeekFrom::Start(off)).await?;
         Ok(
             LogArchiveGuard {
-                index: self.index,
                 offset: off,
                 file,
             }
@@ -78,6 +150,10 @@ impl LogArchive
     }
 
     pub async fn len(&self) -> Result<u64> {
+        #[cfg(feature = "mmap")]
+        if let Some(map) = self.map.as_ref() {
+            return Ok(map.len() as u64);
+        }
         Ok(self.file.lock().await.metadata().await?.len())
     }
 
@@ -89,18 +165,10 @@ impl LogArchive
 #[derive(Debug)]
 pub(crate) struct LogArchiveGuard<'a>
 {
-    index: u32,
     offset: u64,
     file: MutexGuard<'a, File>,
 }
 
-impl<'a> LogArchiveGuard<'a>
-{
-    pub(super) fn index(&'a self) -> u32 {
-        self.index
-    }
-}
-
 #[async_trait]
 impl<'a> LogApi
 for LogArchiveGuard<'a>
@@ -183,4 +251,111 @@ for LogArchiveGuard<'a>
         self.file.sync_all().await?;
         Ok(())
     }
-}
\ No newline at end of file
+}
+/// Reader over the memory map of a sealed archive, many of these can read
+/// from the same archive at the same time
+#[cfg(feature = "mmap")]
+#[derive(Debug)]
+pub(crate) struct LogArchiveMap<'a>
+{
+    offset: u64,
+    data: &'a [u8],
+}
+
+#[cfg(feature = "mmap")]
+impl<'a> LogArchiveMap<'a>
+{
+    pub(crate) fn new(data: &'a [u8], offset: u64) -> LogArchiveMap<'a>
+    {
+        LogArchiveMap {
+            offset,
+            data,
+        }
+    }
+
+    fn take(&mut self, len: usize) -> Result<&'a [u8]>
+    {
+        let start = self.offset as usize;
+        let end = match start.checked_add(len) {
+            Some(a) if a <= self.data.len() => a,
+            _ => { return Err(tokio::io::Error::new(tokio::io::ErrorKind::UnexpectedEof, "Read past the end of the archive")); }
+        };
+        self.offset = end as u64;
+        Ok(&self.data[start..end])
+    }
+}
+
+#[cfg(feature = "mmap")]
+#[async_trait]
+impl<'a> LogApi
+for LogArchiveMap<'a>
+{
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    async fn len(&self) -> Result<u64> {
+        Ok(self.data.len() as u64)
+    }
+
+    async fn seek(&mut self, off: u64) -> Result<()> {
+        self.offset = off;
+        Ok(())
+    }
+    
+    async fn read_u8(&mut self) -> Result<u8> {
+        Ok(self.take(size_of::<u8>())?[0])
+    }
+
+    async fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0u8; size_of::<u16>()];
+        buf.copy_from_slice(self.take(size_of::<u16>())?);
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    async fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0u8; size_of::<u32>()];
+        buf.copy_from_slice(self.take(size_of::<u32>())?);
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    async fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0u8; size_of::<u64>()];
+        buf.copy_from_slice(self.take(size_of::<u64>())?);
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        buf.copy_from_slice(self.take(buf.len())?);
+        Ok(())
+    }
+
+    async fn write_u8(&mut self, _val: u8) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u16(&mut self, _val: u16) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u32(&mut self, _val: u32) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u64(&mut self, _val: u64) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_exact(&mut self, _buf: &[u8]) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(feature = "mmap")]
+fn read_only_error() -> tokio::io::Error {
+    tokio::io::Error::new(tokio::io::ErrorKind::PermissionDenied, "Sealed archives are read-only")
+}
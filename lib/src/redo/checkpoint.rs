@@ -0,0 +1,225 @@
+#[allow(unused_imports)]
+use log::{error, info, warn, debug};
+use serde::{Serialize, Deserialize};
+use tokio::io::{AsyncWriteExt, BufWriter};
+use tokio::io::Result;
+use bytes::Bytes;
+
+use crate::crypto::AteHash;
+use crate::event::EventHeaderRaw;
+use crate::spec::MessageFormat;
+
+use super::api::LogLookup;
+
+/// Version of the checkpoint file format, changing this number will cause
+/// existing checkpoints to be ignored and the redo log replayed in full
+pub(crate) static CHECKPOINT_VERSION: u32 = 2;
+
+/// State of the plugins and indexers attached to a chain at the moment
+/// that a checkpoint was taken
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub struct ChainCheckpoint
+{
+    /// Number of events (in log order) that are covered by the checkpoint
+    pub events: usize,
+    /// Name of each plugin and its serialized state (if it supports it)
+    pub plugins: Vec<(String, Option<Vec<u8>>)>,
+    /// Serialized state of each indexer (if it supports it)
+    pub indexers: Vec<Option<Vec<u8>>>,
+    /// Headers of the events that are covered by the checkpoint (these are handed
+    /// straight to the chain rather than being streamed through the loader)
+    #[serde(skip)]
+    pub(crate) headers: Vec<EventHeaderRaw>,
+}
+
+/// Archive that was covered by a checkpoint and up to which offset
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CheckpointArchive
+{
+    pub(crate) index: u32,
+    pub(crate) header: Vec<u8>,
+    pub(crate) length: u64,
+}
+
+/// Header of a single event that is covered by a checkpoint
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct CheckpointEntry
+{
+    pub(crate) lookup: LogLookup,
+    pub(crate) meta_bytes: Vec<u8>,
+    pub(crate) data_hash: Option<AteHash>,
+    pub(crate) data_size: usize,
+    pub(crate) format: MessageFormat,
+}
+
+impl CheckpointEntry
+{
+    pub(crate) fn new(lookup: LogLookup, header: &EventHeaderRaw) -> CheckpointEntry {
+        CheckpointEntry {
+            lookup,
+            meta_bytes: header.meta_bytes.to_vec(),
+            data_hash: header.data_hash,
+            data_size: header.data_size,
+            format: header.format,
+        }
+    }
+
+    pub(crate) fn into_header(self) -> EventHeaderRaw {
+        EventHeaderRaw::new(
+            AteHash::from_bytes(&self.meta_bytes[..]),
+            Bytes::from(self.meta_bytes),
+            self.data_hash,
+            self.data_size,
+            self.format,
+        )
+    }
+}
+
+/// Sidecar file that is stored next to the redo log archives so that
+/// the chain can be opened without replaying every single event, the
+/// headers themselves are kept in the journal and only the part of it
+/// that the checkpoint covers is trusted
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct LogCheckpoint
+{
+    pub(crate) version: u32,
+    pub(crate) archives: Vec<CheckpointArchive>,
+    pub(crate) entries: u64,
+    pub(crate) entries_len: u64,
+    pub(crate) entries_hash: AteHash,
+    pub(crate) state: ChainCheckpoint,
+}
+
+pub(crate) fn journal_path(path_log: &String) -> String {
+    format!("{}.checkpoint.entries", path_log)
+}
+
+/// Append-only journal of the headers of every event in the redo log, each
+/// checkpoint only needs to sync the entries that were added since the last
+/// one rather than writing out the whole history again
+pub(crate) struct CheckpointJournal
+{
+    path: String,
+    file: BufWriter<tokio::fs::File>,
+    pub(crate) count: u64,
+    pub(crate) len: u64,
+    /// Running hash over all the entries that is used to validate the journal
+    pub(crate) hash: AteHash,
+}
+
+impl CheckpointJournal
+{
+    /// Starts a new (empty) journal
+    pub(crate) async fn create(path: String) -> Result<CheckpointJournal>
+    {
+        let file = tokio::fs::File::create(path.as_str()).await?;
+        Ok(
+            CheckpointJournal {
+                path,
+                file: BufWriter::new(file),
+                count: 0,
+                len: 0,
+                hash: AteHash { val: [0u8; 16] },
+            }
+        )
+    }
+
+    /// Reads the entries that are covered by a checkpoint, if the journal does not match
+    /// the hash that the checkpoint recorded then nothing is returned
+    pub(crate) async fn read(path: &String, checkpoint: &LogCheckpoint) -> Option<Vec<CheckpointEntry>>
+    {
+        let bytes = tokio::fs::read(path.as_str()).await.ok()?;
+        if (bytes.len() as u64) < checkpoint.entries_len {
+            return None;
+        }
+
+        let mut ret = Vec::new();
+        let mut hash = AteHash { val: [0u8; 16] };
+        let mut pos = 0usize;
+        let end = checkpoint.entries_len as usize;
+        while pos < end {
+            if pos + 4 > end {
+                return None;
+            }
+            let len = u32::from_be_bytes([bytes[pos], bytes[pos + 1], bytes[pos + 2], bytes[pos + 3]]) as usize;
+            let entry = bytes.get(pos + 4..pos + 4 + len).filter(|_| pos + 4 + len <= end)?;
+            hash = AteHash::from_bytes_twice(&hash.val[..], entry);
+            ret.push(bincode::deserialize(entry).ok()?);
+            pos = pos + 4 + len;
+        }
+
+        match ret.len() as u64 == checkpoint.entries && hash == checkpoint.entries_hash {
+            true => Some(ret),
+            false => None,
+        }
+    }
+
+    /// Continues a journal from the point that a checkpoint covered (anything written
+    /// after the checkpoint is discarded as those events will be replayed again)
+    pub(crate) async fn resume(path: String, checkpoint: &LogCheckpoint) -> Result<CheckpointJournal>
+    {
+        let mut file = tokio::fs::OpenOptions::new()
+            .write(true)
+            .open(path.as_str())
+            .await?;
+        file.set_len(checkpoint.entries_len).await?;
+        tokio::io::AsyncSeekExt::seek(&mut file, std::io::SeekFrom::End(0)).await?;
+        let file = BufWriter::new(file);
+        Ok(
+            CheckpointJournal {
+                path,
+                file,
+                count: checkpoint.entries,
+                len: checkpoint.entries_len,
+                hash: checkpoint.entries_hash,
+            }
+        )
+    }
+
+    pub(crate) async fn append(&mut self, lookup: LogLookup, header: &EventHeaderRaw) -> Result<()>
+    {
+        let entry = bincode::serialize(&CheckpointEntry::new(lookup, header))
+            .map_err(|err| tokio::io::Error::new(tokio::io::ErrorKind::Other, err.to_string()))?;
+        self.file.write_all(&(entry.len() as u32).to_be_bytes()).await?;
+        self.file.write_all(&entry[..]).await?;
+
+        self.hash = AteHash::from_bytes_twice(&self.hash.val[..], &entry[..]);
+        self.count = self.count + 1;
+        self.len = self.len + 4 + entry.len() as u64;
+        Ok(())
+    }
+
+    /// Makes sure everything in the journal is on the disk before a checkpoint refers to it
+    pub(crate) async fn sync(&mut self) -> Result<()>
+    {
+        self.file.flush().await?;
+        self.file.get_ref().sync_data().await
+    }
+
+    /// Moves the journal so that it sits next to another redo log
+    pub(crate) fn rename(&mut self, path: String) -> Result<()>
+    {
+        std::fs::rename(self.path.as_str(), path.as_str())?;
+        self.path = path;
+        Ok(())
+    }
+
+    /// Opens another handle on the same journal (the buffered entries are flushed first)
+    pub(crate) async fn reopen(&mut self) -> Result<CheckpointJournal>
+    {
+        self.file.flush().await?;
+        let file = tokio::fs::OpenOptions::new()
+            .append(true)
+            .open(self.path.as_str())
+            .await?;
+        Ok(
+            CheckpointJournal {
+                path: self.path.clone(),
+                file: BufWriter::new(file),
+                count: self.count,
+                len: self.len,
+                hash: self.hash,
+            }
+        )
+    }
+}
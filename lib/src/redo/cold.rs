@@ -0,0 +1,356 @@
+#[allow(unused_imports)]
+use log::{error, info, warn, debug};
+use async_trait::async_trait;
+use serde::{Serialize, Deserialize};
+use std::sync::Arc;
+use std::mem::size_of;
+use parking_lot::Mutex as MutexSync;
+use hashlink::LruCache;
+use tokio::io::Result;
+use tokio::io::ErrorKind;
+use snap::read::FrameDecoder;
+#[cfg(feature = "rotate")]
+use snap::read::FrameEncoder;
+
+use crate::spec::*;
+
+/// Storage backend for the cold tier which holds compressed log archives that
+/// are rarely read (e.g. a slower disk, a network mount or an object store)
+#[async_trait]
+pub trait ColdStore: std::fmt::Debug + Send + Sync
+{
+    /// Stores an object in the cold tier replacing any existing one of the same name
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<()>;
+
+    /// Reads an object from the cold tier or returns None if it does not exist
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>>;
+
+    /// Removes an object from the cold tier (if it exists)
+    async fn remove(&self, name: &str) -> Result<()>;
+}
+
+/// Cold tier that stores the compressed archives in a directory
+#[derive(Debug, Clone)]
+pub struct LocalColdStore
+{
+    path: String,
+}
+
+impl LocalColdStore
+{
+    pub fn new(path: &str) -> LocalColdStore {
+        LocalColdStore {
+            path: path.to_string(),
+        }
+    }
+
+    fn path(&self, name: &str) -> String {
+        match self.path.ends_with("/") {
+            true => format!("{}{}", self.path, name),
+            false => format!("{}/{}", self.path, name),
+        }
+    }
+}
+
+#[async_trait]
+impl ColdStore
+for LocalColdStore
+{
+    async fn put(&self, name: &str, data: Vec<u8>) -> Result<()>
+    {
+        tokio::fs::create_dir_all(&self.path).await?;
+
+        // Write to a temporary file first so that partial objects are never read
+        let path = self.path(name);
+        let path_tmp = format!("{}.tmp", path);
+        tokio::fs::write(&path_tmp, &data[..]).await?;
+        tokio::fs::rename(&path_tmp, &path).await?;
+        Ok(())
+    }
+
+    async fn get(&self, name: &str) -> Result<Option<Vec<u8>>>
+    {
+        match tokio::fs::read(self.path(name)).await {
+            Ok(a) => Ok(Some(a)),
+            Err(err) if err.kind() == ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err),
+        }
+    }
+
+    async fn remove(&self, name: &str) -> Result<()>
+    {
+        match tokio::fs::remove_file(self.path(name)).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Tiering policy that moves old log archives (created by rotating the log)
+/// into compressed cold storage
+#[derive(Debug, Clone)]
+pub struct ColdTier
+{
+    /// Archives that are more than this many rotations old are moved to the cold tier
+    pub after_rotations: u32,
+    /// Archives are split into segments of this many bytes which are compressed and
+    /// fetched separately so that reading one event does not fetch the whole archive
+    pub segment_size: u64,
+    /// Number of decompressed segments that are kept in memory for reads
+    pub cache_size: usize,
+    /// Storage backend that the compressed archives are written to
+    pub store: Arc<dyn ColdStore>,
+}
+
+impl ColdTier
+{
+    pub fn new(store: impl ColdStore + 'static, after_rotations: u32) -> ColdTier {
+        ColdTier {
+            after_rotations,
+            segment_size: 4 * 1024 * 1024,
+            cache_size: 8,
+            store: Arc::new(store),
+        }
+    }
+}
+
+/// Describes an archive that has been moved to the cold tier
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ColdArchive
+{
+    pub(crate) index: u32,
+    pub(crate) name: String,
+    pub(crate) header: Vec<u8>,
+    pub(crate) len: u64,
+    pub(crate) segment_size: u64,
+    pub(crate) segments: u32,
+}
+
+impl ColdArchive
+{
+    pub(crate) fn segment_name(&self, segment: u32) -> String {
+        format!("{}.{}.sz", self.name, segment)
+    }
+
+    pub(crate) fn segment_names(&self) -> Vec<String> {
+        (0..self.segments)
+            .map(|n| self.segment_name(n))
+            .collect()
+    }
+}
+
+/// List of the archives of a redo log that are in the cold tier, this is
+/// stored next to the log on the hot disk
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct ColdManifest
+{
+    pub(crate) archives: Vec<ColdArchive>,
+}
+
+pub(super) fn manifest_path(path_log: &String) -> String {
+    format!("{}.cold", path_log)
+}
+
+impl ColdManifest
+{
+    pub(crate) fn load(path_log: &String) -> Result<ColdManifest>
+    {
+        let path = manifest_path(path_log);
+        let data = match std::fs::read(&path) {
+            Ok(a) => a,
+            Err(err) if err.kind() == ErrorKind::NotFound => { return Ok(ColdManifest::default()); }
+            Err(err) => { return Err(err); }
+        };
+        SerializationFormat::Json.deserialize(&data[..])
+            .map_err(|err| tokio::io::Error::new(ErrorKind::InvalidData, format!("Failed to read the cold manifest ({}) - {}", path, err)))
+    }
+
+    pub(crate) fn save(&self, path_log: &String) -> Result<()>
+    {
+        let path = manifest_path(path_log);
+        if self.archives.len() <= 0 {
+            if std::path::Path::new(path.as_str()).exists() {
+                std::fs::remove_file(path)?;
+            }
+            return Ok(());
+        }
+
+        let data = SerializationFormat::Json.serialize(self)
+            .map_err(|err| tokio::io::Error::new(ErrorKind::InvalidData, err.to_string()))?;
+        let path_tmp = format!("{}.tmp", path);
+        std::fs::write(&path_tmp, &data[..])?;
+        std::fs::rename(&path_tmp, &path)?;
+        Ok(())
+    }
+
+    /// Index of the first archive that is still on the local disk
+    pub(crate) fn first_hot_index(&self) -> u32 {
+        self.archives
+            .iter()
+            .map(|a| a.index + 1)
+            .max()
+            .unwrap_or(0)
+    }
+
+    pub(crate) fn get(&self, index: u32) -> Option<&ColdArchive> {
+        self.archives.iter().filter(|a| a.index == index).next()
+    }
+}
+
+#[cfg(feature = "rotate")]
+pub(crate) fn compress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = FrameEncoder::new(data);
+    let mut compressed = Vec::new();
+    std::io::copy(&mut reader, &mut compressed)?;
+    Ok(compressed)
+}
+
+pub(crate) fn decompress(data: &[u8]) -> Result<Vec<u8>> {
+    let mut reader = FrameDecoder::new(data);
+    let mut decompressed = Vec::new();
+    std::io::copy(&mut reader, &mut decompressed)?;
+    Ok(decompressed)
+}
+
+/// Decompressed segments of the archives in the cold tier that were read recently
+pub(crate) type ColdCache = MutexSync<LruCache<(u32, u32), Arc<Vec<u8>>>>;
+
+/// Reader over an archive in the cold tier, each segment is only fetched and
+/// decompressed the first time a read touches it
+pub(crate) struct ColdArchiveReader<'a>
+{
+    tier: &'a ColdTier,
+    archive: &'a ColdArchive,
+    cache: &'a ColdCache,
+    offset: u64,
+}
+
+impl<'a> ColdArchiveReader<'a>
+{
+    pub(crate) fn new(tier: &'a ColdTier, archive: &'a ColdArchive, cache: &'a ColdCache, offset: u64) -> ColdArchiveReader<'a>
+    {
+        ColdArchiveReader {
+            tier,
+            archive,
+            cache,
+            offset,
+        }
+    }
+
+    async fn segment(&self, segment: u32) -> Result<Arc<Vec<u8>>>
+    {
+        if let Some(ret) = self.cache.lock().get(&(self.archive.index, segment)) {
+            return Ok(ret.clone());
+        }
+
+        let name = self.archive.segment_name(segment);
+        let data = match self.tier.store.get(&name).await? {
+            Some(a) => a,
+            None => { return Err(tokio::io::Error::new(ErrorKind::NotFound, format!("The segment {} is missing from the cold tier", name))); }
+        };
+        let ret = Arc::new(decompress(&data[..])?);
+
+        debug!("cold-tier-fetch: {} ({} bytes)", name, ret.len());
+        self.cache.lock().insert((self.archive.index, segment), ret.clone());
+        Ok(ret)
+    }
+
+    async fn take(&mut self, buf: &mut [u8]) -> Result<()>
+    {
+        match self.offset.checked_add(buf.len() as u64) {
+            Some(a) if a <= self.archive.len => { },
+            _ => { return Err(tokio::io::Error::new(ErrorKind::UnexpectedEof, "Read past the end of the archive")); }
+        }
+
+        // Reads may span the boundary between segments
+        let segment_size = self.archive.segment_size.max(1);
+        let mut done = 0usize;
+        while done < buf.len() {
+            let pos = self.offset + done as u64;
+            let data = self.segment((pos / segment_size) as u32).await?;
+            let start = (pos % segment_size) as usize;
+            if start >= data.len() {
+                return Err(tokio::io::Error::new(ErrorKind::InvalidData, format!("The segment of {} at 0x{:x} is shorter than expected", self.archive.name, pos)));
+            }
+            let amt = (data.len() - start).min(buf.len() - done);
+            buf[done..done+amt].copy_from_slice(&data[start..start+amt]);
+            done = done + amt;
+        }
+        self.offset = self.offset + buf.len() as u64;
+        Ok(())
+    }
+}
+
+fn read_only_error() -> tokio::io::Error {
+    tokio::io::Error::new(ErrorKind::PermissionDenied, "Archives in the cold tier are read-only")
+}
+
+#[async_trait]
+impl<'a> LogApi
+for ColdArchiveReader<'a>
+{
+    fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    async fn len(&self) -> Result<u64> {
+        Ok(self.archive.len)
+    }
+
+    async fn seek(&mut self, off: u64) -> Result<()> {
+        self.offset = off;
+        Ok(())
+    }
+
+    async fn read_u8(&mut self) -> Result<u8> {
+        let mut buf = [0u8; size_of::<u8>()];
+        self.take(&mut buf[..]).await?;
+        Ok(buf[0])
+    }
+
+    async fn read_u16(&mut self) -> Result<u16> {
+        let mut buf = [0u8; size_of::<u16>()];
+        self.take(&mut buf[..]).await?;
+        Ok(u16::from_be_bytes(buf))
+    }
+
+    async fn read_u32(&mut self) -> Result<u32> {
+        let mut buf = [0u8; size_of::<u32>()];
+        self.take(&mut buf[..]).await?;
+        Ok(u32::from_be_bytes(buf))
+    }
+
+    async fn read_u64(&mut self) -> Result<u64> {
+        let mut buf = [0u8; size_of::<u64>()];
+        self.take(&mut buf[..]).await?;
+        Ok(u64::from_be_bytes(buf))
+    }
+
+    async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        self.take(buf).await
+    }
+
+    async fn write_u8(&mut self, _val: u8) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u16(&mut self, _val: u16) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u32(&mut self, _val: u32) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_u64(&mut self, _val: u64) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn write_exact(&mut self, _buf: &[u8]) -> Result<()> {
+        Err(read_only_error())
+    }
+
+    async fn sync(&mut self) -> Result<()> {
+        Ok(())
+    }
+}
@@ -14,6 +14,7 @@ use crate::conf::*;
 use crate::chain::*;
 use crate::event::*;
 use crate::error::*;
+use crate::spec::EventLimits;
 use crate::loader::*;
 use crate::redo::LogLookup;
 
@@ -28,6 +29,8 @@ use super::api::LogWritable;
 #[cfg(feature = "local_fs")]
 use super::file_localfs::LogFileLocalFs;
 use super::file_memdb::LogFileMemDb;
+use super::checkpoint::ChainCheckpoint;
+use super::staging::StagingLog;
 
 pub struct RedoLog
 {
@@ -35,13 +38,28 @@ pub struct RedoLog
     log_path: Option<String>,
     flip: Option<RedoLogFlip>,
     pub(super) log_file: Box<dyn LogFile>,
+    staging: StagingLog,
+    #[cfg(test)]
+    syncs: u64,
 }
 
 impl RedoLog
 {
     #[cfg(feature = "local_fs")]
-    async fn new(path_log: Option<String>, flags: OpenFlags, cache_size: usize, cache_ttl: u64, loader: Box<impl Loader>, header_bytes: Vec<u8>) -> std::result::Result<RedoLog, SerializationError>
+    async fn new(path_log: Option<String>, flags: OpenFlags, cache_size: usize, cache_ttl: u64, limits: EventLimits, cold_tier: Option<ColdTier>, checkpoints: bool, mut loader: Box<impl Loader>, header_bytes: Vec<u8>) -> std::result::Result<RedoLog, SerializationError>
     {
+        // Unconfirmed events are kept in a staging file next to the log
+        let path_staging = match (flags.truncate, path_log.as_ref()) {
+            (false, Some(a)) => Some(format!("{}.staging", a)),
+            (true, Some(a)) => {
+                let path_staging = format!("{}.staging", a);
+                let _ = std::fs::remove_file(&path_staging);
+                Some(path_staging)
+            },
+            (_, None) => None,
+        };
+        let staging = StagingLog::open(path_staging, limits).await?;
+
         // Now load the real thing
         let ret = RedoLog {
             log_path: path_log.clone(),
@@ -54,15 +72,34 @@ impl RedoLog
                         cache_size,
                         cache_ttl,
                         header_bytes,
+                        limits,
+                        cold_tier,
+                        checkpoints,
                     ).await?;
 
-                    let cnt = log_file.read_all(loader).await?;
+                    let cnt = log_file.read_all(&mut loader).await?;
                     info!("redo-log: loaded {} events from {} files", cnt, log_file.archives.len());
+
+                    // The staged events are replayed after the confirmed history
+                    // (migrated events are already in the main log)
+                    for evt in staging.events() {
+                        let hash = evt.as_header_raw()?.event_hash;
+                        if staging.is_logged(&hash) {
+                            continue;
+                        }
+                        if let Some(data) = staging.load(&hash)? {
+                            loader.feed_load_data(data).await;
+                        }
+                    }
+                    loader.end_of_history().await;
                     log_file
                 },
                 None => LogFileMemDb::new(header_bytes).await?
             },
             flip: None,
+            staging,
+            #[cfg(test)]
+            syncs: 0,
         };
         Ok(ret)
     }
@@ -74,6 +111,9 @@ impl RedoLog
         let ret = RedoLog {
             log_file: LogFileMemDb::new(header_bytes).await?,
             flip: None,
+            staging: StagingLog::open(None, crate::conf::ConfAte::default().event_limits()).await?,
+            #[cfg(test)]
+            syncs: 0,
         };
         Ok(ret)
     }
@@ -129,7 +169,7 @@ impl RedoLog
                 
                 #[cfg(feature = "local_fs")]
                 if let Some(a) = self.log_path.as_ref() {
-                    new_log_file.move_log_file(a)?;
+                    new_log_file.move_log_file(a).await?;
                 }
 
                 self.log_file = new_log_file;
@@ -145,9 +185,76 @@ impl RedoLog
     }
 
     pub async fn load(&self, hash: AteHash) -> std::result::Result<LoadData, LoadError> {
+        if let Some(data) = self.staging.load(&hash)? {
+            return Ok(data);
+        }
         Ok(self.log_file.load(hash).await?)
     }
 
+    /// Writes an event that has not yet been confirmed by the server into the
+    /// staging log rather than the main redo log
+    pub(crate) async fn stage(&mut self, evt: &EventData) -> std::result::Result<(), SerializationError> {
+        if self.staging.stage(evt).await? == false {
+            self.write(evt).await?;
+        }
+        Ok(())
+    }
+
+    /// Moves events that have been confirmed by the server from the staging log
+    /// into the main redo log and returns how many were moved
+    pub(crate) async fn confirm(&mut self, hashes: &[AteHash]) -> std::result::Result<usize, SerializationError> {
+        let mut moved = Vec::new();
+        for hash in hashes.iter() {
+            match self.staging.get(hash).map(|e| e.clone()) {
+                Some(_) if self.staging.is_logged(hash) => {
+                    moved.push(hash.clone());
+                },
+                Some(evt) => {
+                    self.write_internal(&evt).await?;
+                    moved.push(hash.clone());
+                },
+                None => self.staging.confirm_early(hash.clone()),
+            }
+        }
+        if moved.len() <= 0 {
+            return Ok(0);
+        }
+
+        // The events must be in the main log before they leave the staging log
+        self.log_file.flush().await?;
+        Ok(self.staging.remove(&moved[..]).await?)
+    }
+
+    /// Removes events that the server rejected from the staging log (they will never be
+    /// confirmed so they are dropped rather than uploaded again)
+    pub(crate) async fn discard(&mut self, hashes: &[AteHash]) -> std::result::Result<usize, SerializationError> {
+        Ok(self.staging.remove(hashes).await?)
+    }
+
+    /// Moves an event that is in the main log but was never confirmed into the staging log
+    pub(crate) async fn migrate(&mut self, evt: &EventData) -> std::result::Result<(), SerializationError> {
+        self.staging.migrate(evt).await
+    }
+
+    /// Returns all the events that have not yet been confirmed by the server
+    pub(crate) fn staged(&self) -> Vec<EventData> {
+        self.staging.events()
+    }
+
+    /// Returns true if the event is staged and not yet in the main log
+    pub(crate) fn is_staged(&self, hash: &AteHash) -> bool {
+        self.staging.contains(hash) && self.staging.is_logged(hash) == false
+    }
+
+    async fn write_internal(&mut self, evt: &EventData) -> std::result::Result<LogLookup, SerializationError> {
+        if let Some(flip) = &mut self.flip {
+            flip.deferred.push(evt.clone());
+        }
+        let pointer = self.log_file.write(evt).await?;
+
+        Ok(pointer)
+    }
+
     pub fn count(&self) -> usize {
         self.log_file.count()
     }
@@ -160,6 +267,12 @@ impl RedoLog
         self.log_file.offset()
     }
 
+    /// Number of times the redo log has waited for its bytes to reach durable storage
+    #[cfg(test)]
+    pub(crate) fn sync_count(&self) -> u64 {
+        self.syncs
+    }
+
     pub fn end(&self) -> LogLookup {
         LogLookup {
             index: self.log_file.index(),
@@ -223,6 +336,9 @@ impl RedoLog
                 flags,
                 cfg.load_cache_size,
                 cfg.load_cache_ttl,
+                cfg.event_limits(),
+                cfg.cold_tier.clone(),
+                cfg.checkpoint_interval.is_some(),
                 loader,
                 header_bytes,
             ).await?
@@ -242,13 +358,29 @@ impl RedoLog
         Ok(log)
     }
 
-    pub fn destroy(&mut self) -> Result<()> {
-        self.log_file.destroy()
+    pub async fn destroy(&mut self) -> Result<()> {
+        self.staging.destroy()?;
+        self.log_file.destroy().await
     }
 
     pub fn header(&self, index: u32) -> Vec<u8> {
         self.log_file.header(index)
     }
+
+    /// Writes a checkpoint next to the redo log so that the next time it is opened
+    /// only the events written after this point need to be replayed
+    pub async fn checkpoint(&mut self, state: ChainCheckpoint) -> Result<()> {
+        // While a flip is underway the log file is about to be replaced
+        if self.flip.is_some() {
+            return Ok(());
+        }
+        self.log_file.checkpoint(state).await
+    }
+
+    /// Returns the state that was restored from a checkpoint when the log was opened
+    pub(crate) fn take_checkpoint(&mut self) -> Option<ChainCheckpoint> {
+        self.log_file.take_checkpoint()
+    }
 }
 
 #[async_trait]
@@ -256,10 +388,16 @@ impl LogWritable
 for RedoLog
 {
     async fn write(&mut self, evt: &EventData) -> std::result::Result<LogLookup, SerializationError> {
-        if let Some(flip) = &mut self.flip {
-            flip.deferred.push(evt.clone());
+        let pointer = self.write_internal(evt).await?;
+
+        // If a confirmed copy of a staged event arrives then its no longer unconfirmed
+        if self.staging.len() > 0 {
+            let hash = evt.as_header_raw()?.event_hash;
+            if self.staging.contains(&hash) {
+                self.log_file.flush().await?;
+                self.staging.remove(&[hash]).await?;
+            }
         }
-        let pointer = self.log_file.write(evt).await?;
 
         Ok(pointer)
     }
@@ -268,4 +406,16 @@ for RedoLog
         self.log_file.flush().await?;
         Ok(())
     }
+
+    async fn sync(&mut self) -> Result<()> {
+        // The main log goes first so that a removal from the staging log is never
+        // durable before the event it was moved into
+        self.log_file.sync().await?;
+        self.staging.sync().await?;
+        #[cfg(test)]
+        {
+            self.syncs += 1;
+        }
+        Ok(())
+    }
 }
\ No newline at end of file
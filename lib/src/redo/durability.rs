@@ -0,0 +1,29 @@
+use std::time::Duration;
+
+/// Determines when the bytes written to the redo log are considered durable for
+/// transactions that require their data to be stored on the local disk
+/// (i.e. `TransactionScope::Local` and `TransactionScope::Full`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DurabilityPolicy
+{
+    /// Every transaction is synchronized to disk (fsync) before it is confirmed
+    Always,
+    /// Transactions that arrive close together share a single fsync, a transaction
+    /// is only confirmed once its bytes are on disk which happens when either the
+    /// batch is full or the oldest transaction in the batch has waited `max_delay`
+    GroupCommit {
+        max_delay: Duration,
+        max_batch: usize,
+    },
+    /// Transactions are confirmed once the bytes are handed to the operating system
+    /// which will write them to disk in its own time (data may be lost on power failure)
+    OsBuffered,
+}
+
+impl Default
+for DurabilityPolicy
+{
+    fn default() -> DurabilityPolicy {
+        DurabilityPolicy::OsBuffered
+    }
+}
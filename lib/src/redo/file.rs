@@ -9,6 +9,8 @@ use crate::event::*;
 use crate::error::*;
 use crate::loader::*;
 
+use super::checkpoint::ChainCheckpoint;
+
 #[async_trait]
 pub trait LogFile
 where Self: Sync + Send
@@ -24,12 +26,14 @@ where Self: Sync + Send
 
     async fn load(&self, hash: AteHash) -> std::result::Result<LoadData, LoadError>;
 
-    fn move_log_file(&mut self, new_path: &String) -> Result<()>;
+    async fn move_log_file(&mut self, new_path: &String) -> Result<()>;
 
     async fn begin_flip(&self, header_bytes: Vec<u8>) -> Result<Box<dyn LogFile>>;
 
     async fn flush(&mut self) -> Result<()>;
 
+    async fn sync(&mut self) -> Result<()>;
+
     fn count(&self) -> usize;
 
     fn size(&self) -> u64;
@@ -40,5 +44,9 @@ where Self: Sync + Send
 
     fn header(&self, index: u32) -> Vec<u8>;
 
-    fn destroy(&mut self) -> Result<()>;
+    async fn destroy(&mut self) -> Result<()>;
+
+    async fn checkpoint(&mut self, state: ChainCheckpoint) -> Result<()>;
+
+    fn take_checkpoint(&mut self) -> Option<ChainCheckpoint>;
 }
\ No newline at end of file
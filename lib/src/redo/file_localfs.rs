@@ -5,13 +5,14 @@ use async_trait::async_trait;
 #[cfg(feature = "caching")]
 use cached::Cached;
 use tokio::io::{Result};
+use tokio::io::AsyncReadExt;
 use tokio::io::ErrorKind;
 use bytes::Bytes;
 #[cfg(feature = "caching")]
 use cached::*;
 use fxhash::{FxHashMap};
-#[cfg(feature = "caching")]
 use parking_lot::Mutex as MutexSync;
+use hashlink::LruCache;
 
 use crate::{crypto::*, redo::LogLookup};
 use crate::event::*;
@@ -23,6 +24,8 @@ use super::*;
 use super::magic::*;
 use super::archive::*;
 use super::appender::*;
+use super::checkpoint::*;
+use super::cold::*;
 
 #[cfg(feature = "caching")]
 pub(crate) struct LogFileCache
@@ -39,19 +42,45 @@ pub(super) struct LogFileLocalFs
     pub(crate) lookup: FxHashMap<AteHash, LogLookup>,
     pub(crate) appender: LogAppender,
     pub(crate) archives: FxHashMap<u32, LogArchive>,
+    pub(crate) checkpoints: bool,
+    pub(crate) journal: Option<CheckpointJournal>,
+    pub(crate) restored: Option<ChainCheckpoint>,
+    pub(crate) limits: EventLimits,
     #[cfg(feature = "caching")]
     pub(crate) cache: MutexSync<LogFileCache>,
+    pub(crate) cold: Option<ColdTier>,
+    pub(crate) cold_manifest: ColdManifest,
+    pub(crate) cold_cache: ColdCache,
+}
+
+pub(super) fn checkpoint_path(path_log: &String) -> String {
+    format!("{}.checkpoint", path_log)
 }
 
 impl LogFileLocalFs
 {
-    pub(super) async fn new(temp_file: bool, path_log: String, truncate: bool, _cache_size: usize, _cache_ttl: u64, header_bytes: Vec<u8>) -> Result<Box<LogFileLocalFs>>
+    pub(super) async fn new(temp_file: bool, path_log: String, truncate: bool, _cache_size: usize, _cache_ttl: u64, header_bytes: Vec<u8>, limits: EventLimits, cold: Option<ColdTier>, checkpoints: bool) -> Result<Box<LogFileLocalFs>>
     {
         info!("open at {}", path_log);
+
+        // Archives that were moved to the cold tier are listed in a manifest
+        let cold_manifest = ColdManifest::load(&path_log)?;
+        if cold_manifest.archives.len() > 0 && cold.is_none() {
+            return Err(tokio::io::Error::new(ErrorKind::NotFound, format!("The redo log ({}) has archives in the cold tier but no cold tier is configured.", path_log)));
+        }
+
+        // If the process stopped while it was moving an archive to the cold tier then the
+        // local copy may still exist, given its already in the manifest it is removed
+        for a in cold_manifest.archives.iter() {
+            let path = format!("{}.{}", path_log, a.index);
+            if std::path::Path::new(path.as_str()).exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
         
         // Load all the archives
         let mut archives = FxHashMap::default();
-        let mut n = 0 as u32;
+        let mut n = cold_manifest.first_hot_index();
         
         loop
         {
@@ -66,8 +95,10 @@ impl LogFileLocalFs
                 return Err(tokio::io::Error::new(ErrorKind::AlreadyExists, "Can not start a temporary redo log when there are existing archives."));
             }
             
-            // Add the file as pure archive with no appender
-            archives.insert(n , LogArchive::new(path_log.clone(), n).await?);
+            // Add the file as pure archive with no appender (which is thus sealed)
+            let mut archive = LogArchive::new(path_log.clone(), n).await?;
+            archive.seal()?;
+            archives.insert(n , archive);
             n = n + 1;
         }
 
@@ -76,7 +107,8 @@ impl LogFileLocalFs
             path_log.clone(),
             truncate,
             n,
-            &header_bytes[..]
+            &header_bytes[..],
+            limits,
         ).await?;
         archives.insert(n, archive);
 
@@ -84,6 +116,12 @@ impl LogFileLocalFs
         if temp_file {
             let _ = std::fs::remove_file(appender.path());
         }
+
+        // A fresh log starts a fresh journal (existing logs decide when they are read)
+        let journal = match (checkpoints, temp_file, truncate) {
+            (true, false, true) => Some(CheckpointJournal::create(journal_path(&path_log)).await?),
+            _ => None,
+        };
         
         // Log file
         let ret = LogFileLocalFs {
@@ -98,74 +136,400 @@ impl LogFileLocalFs
                 write: TimedSizedCache::with_size_and_lifespan(_cache_size, _cache_ttl),
             }),
             archives,
+            checkpoints,
+            journal,
+            restored: None,
+            limits,
+            cold_cache: MutexSync::new(LruCache::new(cold.as_ref().map(|a| a.cache_size.max(1)).unwrap_or(1))),
+            cold,
+            cold_manifest,
         };
 
         Ok(Box::new(ret))
     }
 
+    /// Loads the checkpoint that sits next to the archives, if it no longer matches
+    /// the archives on disk then it is ignored and the full log is replayed instead
+    async fn load_checkpoint(&self) -> Option<(LogCheckpoint, Vec<CheckpointEntry>)>
+    {
+        let path = checkpoint_path(&self.path);
+        let bytes = match tokio::fs::read(path.as_str()).await {
+            Ok(a) => a,
+            Err(_) => { return None; }
+        };
+
+        let checkpoint: LogCheckpoint = match bincode::deserialize(&bytes[..]) {
+            Ok(a) => a,
+            Err(err) => {
+                warn!("checkpoint-ignored: {} - {}", path, err);
+                return None;
+            }
+        };
+        if checkpoint.version != CHECKPOINT_VERSION {
+            warn!("checkpoint-ignored: {} - version mismatch", path);
+            return None;
+        }
+
+        for a in checkpoint.archives.iter() {
+            let (header, len) = match (self.archives.get(&a.index), self.cold_manifest.get(a.index)) {
+                (Some(b), _) => (b.header(), b.len().await),
+                (None, Some(b)) => (&b.header[..], Ok(b.len)),
+                (None, None) => {
+                    warn!("checkpoint-ignored: {} - archive {} is missing", path, a.index);
+                    return None;
+                }
+            };
+            if header != &a.header[..] {
+                warn!("checkpoint-ignored: {} - archive {} has a different header", path, a.index);
+                return None;
+            }
+            match len {
+                Ok(len) if len >= a.length => { },
+                _ => {
+                    warn!("checkpoint-ignored: {} - archive {} is shorter than the checkpoint", path, a.index);
+                    return None;
+                }
+            }
+        }
+
+        // The journal must contain exactly the entries the checkpoint was taken over
+        let entries = match CheckpointJournal::read(&journal_path(&self.path), &checkpoint).await {
+            Some(a) => a,
+            None => {
+                warn!("checkpoint-ignored: {} - journal does not match", path);
+                return None;
+            }
+        };
+
+        // The last event that the checkpoint covers in each archive must still be exactly
+        // where the journal says it is (and end where the checkpoint ends) otherwise the
+        // archive was changed underneath it and the checkpoint can not be trusted
+        for a in checkpoint.archives.iter() {
+            let last = match entries.iter().filter(|e| e.lookup.index == a.index).max_by_key(|e| e.lookup.offset) {
+                Some(e) => e,
+                None => { continue; }
+            };
+            if self.covers(a, last).await == false {
+                warn!("checkpoint-ignored: {} - archive {} no longer matches the journal", path, a.index);
+                return None;
+            }
+        }
+
+        Some((checkpoint, entries))
+    }
+
+    /// Checks that the last event a checkpoint covers in an archive is the same event
+    /// that was journaled and that it ends where the covered part of the archive ends
+    async fn covers(&self, archive: &CheckpointArchive, last: &CheckpointEntry) -> bool
+    {
+        // (archives in the cold tier are sealed and never rewritten so they are not fetched just to check them)
+        let archive_local = match self.archives.get(&archive.index) {
+            Some(a) => a,
+            None => { return self.cold_manifest.get(archive.index).is_some(); }
+        };
+        let (evt, end) = match archive_local.lock_at(last.lookup.offset).await {
+            Ok(mut lock) => match EventVersion::read(&mut lock, &self.limits).await {
+                Ok(Some(evt)) => (evt, lock.offset()),
+                _ => { return false; }
+            },
+            Err(_) => { return false; }
+        };
+
+        let data_hash = evt.data.as_ref().map(|a| AteHash::from_bytes(&a[..]));
+        evt.meta == last.meta_bytes &&
+        data_hash == last.data_hash &&
+        end == archive.length
+    }
+
     /// Read all the log files from all the archives including the current one representing the appender
-    pub(super) async fn read_all(&mut self, mut loader: Box<impl Loader>) -> std::result::Result<usize, SerializationError> {
+    /// (the caller is responsible for signalling the end of the history to the loader)
+    pub(super) async fn read_all(&mut self, loader: &mut Box<impl Loader>) -> std::result::Result<usize, SerializationError> {
         let mut lookup = FxHashMap::default();
 
-        let archives = self.archives.values_mut().collect::<Vec<_>>();
+        // Attempt to load a checkpoint so that we only need to replay the tail of the log
+        let checkpoint = match self.checkpoints && self.temp == false {
+            true => self.load_checkpoint().await,
+            false => None,
+        };
+
+        let mut archives = self.archives.values().collect::<Vec<_>>();
+        archives.sort_by_key(|a| a.index);
 
         let mut total: usize = 0;
+        for archive in self.cold_manifest.archives.iter() {
+            total = total + archive.len as usize;
+        }
         for archive in archives.iter() {
             total = total + archive.len().await? as usize;
         }
         loader.start_of_history(total).await;
 
+        // The events covered by the checkpoint are handed to the chain directly and the
+        // journal carries on from where the checkpoint left off
         let mut cnt: usize = 0;
-        for archive in archives
-        {
+        let mut skip = FxHashMap::default();
+        let mut journal = None;
+        if let Some((checkpoint, entries)) = checkpoint {
+            for a in checkpoint.archives.iter() {
+                skip.insert(a.index, a.length);
+            }
+            journal = Some(CheckpointJournal::resume(journal_path(&self.path), &checkpoint).await?);
+
+            let mut state = checkpoint.state;
+            for entry in entries {
+                let pointer = entry.lookup;
+                let header = entry.into_header();
+                lookup.insert(header.event_hash, pointer);
+                state.headers.push(header);
+            }
+            state.events = state.headers.len();
+            cnt = state.events;
+            info!("checkpoint-loaded: {} events", cnt);
+            self.restored = Some(state);
+        } else if self.checkpoints && self.temp == false {
+            journal = Some(CheckpointJournal::create(journal_path(&self.path)).await?);
+        }
+
+        // Archives in the cold tier are older than any of the local ones so they are replayed
+        // first, those that are entirely covered by the checkpoint are not fetched at all
+        let limits = self.limits;
+        if let Some(tier) = self.cold.as_ref() {
+            for archive in self.cold_manifest.archives.iter() {
+                let skip = skip.get(&archive.index);
+                if skip.map(|a| *a >= archive.len).unwrap_or(false) {
+                    continue;
+                }
+                let mut reader = ColdArchiveReader::new(tier, archive, &self.cold_cache, 0);
+                let path = format!("{}/{}", self.path, archive.name);
+                cnt = cnt + LogFileLocalFs::read_archive(&mut reader, archive.index, &path, skip, &limits, loader, &mut lookup, &mut journal).await?;
+            }
+        }
+
+        for archive in archives {
             let mut lock = archive.lock_at(0).await?;
+            cnt = cnt + LogFileLocalFs::read_archive(&mut lock, archive.index, &archive.path, skip.get(&archive.index), &limits, loader, &mut lookup, &mut journal).await?;
+        }
 
-            let _version = match RedoHeader::read(&mut lock).await? {
-                Some(a) => a,
-                None => {
-                    warn!("log-read-error: log file is empty");
+        for (v, k) in lookup.into_iter() {
+            self.lookup.insert(v, k);
+        }
+        self.journal = journal;
+
+        Ok(cnt)
+    }
+
+    /// Replays all the events in a single archive (starting after the checkpoint if there is one)
+    async fn read_archive(api: &mut impl LogApi, index: u32, path: &String, skip: Option<&u64>, limits: &EventLimits, loader: &mut Box<impl Loader>, lookup: &mut FxHashMap<AteHash, LogLookup>, journal: &mut Option<CheckpointJournal>) -> std::result::Result<usize, SerializationError>
+    {
+        match skip {
+            Some(offset) => api.seek(*offset).await?,
+            None => {
+                api.seek(0).await?;
+                let _version = match RedoHeader::read(api).await? {
+                    Some(a) => a,
+                    None => {
+                        warn!("log-read-error: log file is empty");
+                        return Ok(0);
+                    }
+                };
+            }
+        };
+
+        let mut cnt: usize = 0;
+        loop {
+            let offset = api.offset();
+            match LogFileLocalFs::read_once_internal(api, index, limits).await {
+                Ok(Some(head)) => {
+                    #[cfg(feature = "super_verbose")]
+                    debug!("log-read: {:?}", head);
+
+                    lookup.insert(head.header.event_hash, head.lookup);
+                    if let Some(journal) = journal.as_mut() {
+                        journal.append(head.lookup, &head.header).await?;
+                    }
+
+                    loader.feed_load_data(head).await;
+                    cnt = cnt + 1;
+                },
+                Ok(None) => break,
+                Err(SerializationError::ChecksumMismatch { offset, expected, actual }) => {
+                    error!("log-read-error: checksum mismatch in {} at 0x{:x}", path, offset);
+                    return Err(SerializationError::CorruptLog {
+                        path: path.clone(),
+                        offset,
+                        reason: format!("checksum mismatch (expected={:08x}, actual={:08x})", expected, actual),
+                    });
+                },
+                Err(SerializationError::MetaTooLarge { size, limit }) |
+                Err(SerializationError::DataTooLarge { size, limit }) => {
+                    error!("log-read-error: oversized event in {} at 0x{:x}", path, offset);
+                    return Err(SerializationError::EventTooLarge {
+                        path: path.clone(),
+                        offset,
+                        size,
+                        limit,
+                    });
+                },
+                Err(err) => {
+                    debug!("log-load-error: {}", err.to_string());
                     continue;
                 }
-            };
+            }
+        }
+        Ok(cnt)
+    }
 
-            loop {
-                match LogFileLocalFs::read_once_internal(&mut lock).await {
-                    Ok(Some(head)) => {
-                        #[cfg(feature = "super_verbose")]
-                        debug!("log-read: {:?}", head);
+    /// Moves all the archives that are older than the tiering policy into the cold tier
+    #[cfg(feature = "rotate")]
+    async fn move_to_cold_tier(&mut self) -> Result<()>
+    {
+        let tier = match self.cold.clone() {
+            Some(a) => a,
+            None => { return Ok(()); }
+        };
 
-                        lookup.insert(head.header.event_hash, head.lookup);
+        let current = self.appender.index;
+        let mut indexes = self.archives
+            .keys()
+            .filter(|i| current - **i > tier.after_rotations)
+            .map(|i| i.clone())
+            .collect::<Vec<_>>();
+        indexes.sort();
+
+        let file_name = std::path::Path::new(self.path.as_str())
+            .file_name()
+            .map(|a| a.to_string_lossy().to_string())
+            .unwrap_or_default();
+        for index in indexes
+        {
+            let (path, header) = match self.archives.get(&index) {
+                Some(a) => (a.path.clone(), Vec::from(a.header())),
+                None => { continue; }
+            };
 
-                        loader.feed_load_data(head).await;
-                        cnt = cnt + 1;
-                    },
-                    Ok(None) => break,
-                    Err(err) => {
-                        debug!("log-load-error: {}", err.to_string());
-                        continue;
+            // The archive must be safely in the cold tier and the manifest before its removed
+            // (it is read one segment at a time as archives can be far larger than memory)
+            let segment_size = tier.segment_size.max(1);
+            let mut archive = ColdArchive {
+                index,
+                name: format!("{}.{}", file_name, index),
+                header,
+                len: 0,
+                segment_size,
+                segments: 0,
+            };
+            let mut file = tokio::fs::File::open(path.as_str()).await?;
+            let mut segment = vec![0u8; segment_size as usize];
+            loop {
+                let mut filled = 0usize;
+                while filled < segment.len() {
+                    let read = file.read(&mut segment[filled..]).await?;
+                    if read <= 0 {
+                        break;
                     }
+                    filled = filled + read;
+                }
+                if filled <= 0 {
+                    break;
+                }
+
+                tier.store.put(&archive.segment_name(archive.segments), compress(&segment[..filled])?).await?;
+                archive.segments = archive.segments + 1;
+                archive.len = archive.len + filled as u64;
+                if filled < segment.len() {
+                    break;
                 }
             }
+            debug!("cold-tier-moved: {} ({} bytes in {} segments)", archive.name, archive.len, archive.segments);
+
+            self.cold_manifest.archives.push(archive);
+            self.cold_manifest.save(&self.path)?;
+
+            self.archives.remove(&index);
+            tokio::fs::remove_file(path.as_str()).await?;
         }
 
-        for (v, k) in lookup.into_iter() {
-            self.lookup.insert(v, k);
+        Ok(())
+    }
+
+    /// Removes all the archives of a redo log from the cold tier
+    async fn destroy_cold_tier(&self, path_log: &String) -> Result<()>
+    {
+        let manifest = ColdManifest::load(path_log)?;
+        ColdManifest::default().save(path_log)?;
+
+        if let Some(tier) = self.cold.as_ref() {
+            for name in manifest.archives.iter().flat_map(|a| a.segment_names()) {
+                if let Err(err) = tier.store.remove(&name).await {
+                    warn!("cold-tier-remove-failed: {} - {}", name, err);
+                }
+            }
         }
+        Ok(())
+    }
+
+    /// Converts an event that was read from an archive into the data that is returned to the caller
+    fn loaded(&self, hash: AteHash, lookup: LogLookup, result: Option<LogEntry>) -> std::result::Result<LoadData, LoadError>
+    {
+        let result = match result {
+            Some(a) => a,
+            None => { return Err(LoadError::NotFoundByHash(hash)); }
+        };
+        
+        // Hash body
+        let data_hash = match &result.data {
+            Some(data) => Some(AteHash::from_bytes(&data[..])),
+            None => None,
+        };
+        let data_size = match &result.data {
+            Some(data) => data.len(),
+            None => 0
+        };
+        let data = match result.data {
+            Some(data) => Some(Bytes::from(data)),
+            None => None,
+        };
+
+        // Convert the result into a deserialized result
+        let meta = result.header.format.meta.deserialize(&result.meta[..])?;
+        let ret = LoadData {
+            header: EventHeaderRaw::new(
+                AteHash::from_bytes(&result.meta[..]),
+                Bytes::from(result.meta),
+                data_hash,
+                data_size,
+                result.header.format,
+            ),
+            data: EventData {
+                meta,
+                data_bytes: data,
+                format: result.header.format,
+            },
+            lookup,
+        };
+        assert_eq!(hash.to_string(), ret.header.event_hash.to_string());
 
-        loader.end_of_history().await;
+        // Store it in the read cache
+        #[cfg(feature = "caching")]
+        {
+            let mut cache = self.cache.lock();
+            cache.read.cache_set(ret.header.event_hash, ret.clone());
+        }
 
-        Ok(cnt)
+        Ok(
+            ret
+        )
     }
 
-    async fn read_once_internal(guard: &mut LogArchiveGuard<'_>) -> std::result::Result<Option<LoadData>, SerializationError>
+    pub(super) async fn read_once_internal(api: &mut impl LogApi, index: u32, limits: &EventLimits) -> std::result::Result<Option<LoadData>, SerializationError>
     {
-        let offset = guard.offset();
+        let offset = api.offset();
         
         #[cfg(feature = "super_verbose")]
         info!("log-read-event: offset={}", offset);
 
         // Read the log event
-        let evt = match EventVersion::read(guard).await? {
+        let evt = match EventVersion::read(api, limits).await? {
             Some(e) => e,
             None => {
                 return Ok(None);
@@ -206,7 +570,7 @@ impl LogFileLocalFs
                         format: evt.header.format,
                     },
                     lookup: LogLookup {
-                        index: guard.index(),
+                        index,
                         offset,
                     },
                 }
@@ -230,19 +594,28 @@ for LogFileLocalFs
         // Flush and close and increment the log index
         self.appender.sync().await?;
         let next_index = self.appender.index  + 1;
+
+        // The archive behind the old appender will never be written to again
+        if let Some(archive) = self.archives.get_mut(&self.appender.index) {
+            archive.seal()?;
+        }
         
         // Create a new appender
         let (new_appender, new_archive) = LogAppender::new(
             self.path.clone(),
             false,
             next_index,
-            &header_bytes[..]
+            &header_bytes[..],
+            self.limits,
         ).await?;
     
         // Set the new appender
         self.archives.insert(next_index , new_archive);
         self.appender = new_appender;
 
+        // Old archives are moved to the cold tier (if there is one)
+        self.move_to_cold_tier().await?;
+
         // Success
         Ok(())
     }
@@ -265,15 +638,27 @@ for LogFileLocalFs
             })
         };
 
+        let journal = match self.journal.as_mut() {
+            Some(a) => Some(a.reopen().await?),
+            None => None,
+        };
+
         Ok(
             Box::new(LogFileLocalFs {
                 path: self.path.clone(),
                 temp: self.temp,
                 lookup: self.lookup.clone(),
+                checkpoints: self.checkpoints,
+                journal,
+                restored: None,
+                limits: self.limits,
                 appender: self.appender.clone().await?,
                 #[cfg(feature = "caching")]
                 cache,
                 archives: log_archives,
+                cold: self.cold.clone(),
+                cold_manifest: self.cold_manifest.clone(),
+                cold_cache: MutexSync::new(LruCache::new(self.cold_cache.lock().capacity())),
             })
         )
     }
@@ -287,6 +672,9 @@ for LogFileLocalFs
         
         // Record the lookup map
         self.lookup.insert(header.event_hash, lookup);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(lookup, &header).await?;
+        }
 
         #[cfg(feature = "verbose")]
         debug!("log-write: {} - {:?}", header.event_hash, lookup);
@@ -325,6 +713,9 @@ for LogFileLocalFs
 
         // Record the lookup map
         self.lookup.insert(hash.clone(), lookup);
+        if let Some(journal) = self.journal.as_mut() {
+            journal.append(lookup, &result.header).await?;
+        }
 
         // Cache the data
         #[cfg(feature = "caching")]
@@ -366,74 +757,62 @@ for LogFileLocalFs
         };
         let _offset = lookup.offset;
 
-        // Load the archive
+        // Load the archive (if its not on the local disk then it might be in the cold tier)
         let archive = match self.archives.get(&lookup.index) {
             Some(a) => a,
-            None => {
-                return Err(LoadError::NotFoundByHash(hash));
+            None => match (self.cold.as_ref(), self.cold_manifest.get(lookup.index)) {
+                (Some(tier), Some(cold)) => {
+                    let mut loader = ColdArchiveReader::new(tier, cold, &self.cold_cache, _offset);
+                    let result = EventVersion::read(&mut loader, &self.limits).await?;
+                    return self.loaded(hash, lookup, result);
+                },
+                _ => {
+                    return Err(LoadError::NotFoundByHash(hash));
+                }
             }
         };
 
-        // First read all the data into a buffer
-        let result = {
-            let mut loader = archive.lock_at(_offset).await?;
-            match EventVersion::read(&mut loader).await? {
-                Some(a) => a,
-                None => { return Err(LoadError::NotFoundByHash(hash)); }
-            }
-        };
-        
-        // Hash body
-        let data_hash = match &result.data {
-            Some(data) => Some(AteHash::from_bytes(&data[..])),
+        // First read all the data into a buffer (sealed archives are read straight
+        // from their memory map otherwise the file is locked while its read)
+        #[cfg(feature = "mmap")]
+        let mapped = match archive.map_at(_offset) {
+            Some(mut loader) => Some(EventVersion::read(&mut loader, &self.limits).await?),
             None => None,
         };
-        let data_size = match &result.data {
-            Some(data) => data.len(),
-            None => 0
-        };
-        let data = match result.data {
-            Some(data) => Some(Bytes::from(data)),
-            None => None,
-        };
-
-        // Convert the result into a deserialized result
-        let meta = result.header.format.meta.deserialize(&result.meta[..])?;
-        let ret = LoadData {
-            header: EventHeaderRaw::new(
-                AteHash::from_bytes(&result.meta[..]),
-                Bytes::from(result.meta),
-                data_hash,
-                data_size,
-                result.header.format,
-            ),
-            data: EventData {
-                meta,
-                data_bytes: data,
-                format: result.header.format,
-            },
-            lookup,
+        #[cfg(not(feature = "mmap"))]
+        let mapped = None;
+        let result = match mapped {
+            Some(a) => a,
+            None => {
+                let mut loader = archive.lock_at(_offset).await?;
+                EventVersion::read(&mut loader, &self.limits).await?
+            }
         };
-        assert_eq!(hash.to_string(), ret.header.event_hash.to_string());
-
-        // Store it in the read cache
-        #[cfg(feature = "caching")]
-        {
-            let mut cache = self.cache.lock();
-            cache.read.cache_set(ret.header.event_hash, ret.clone());
-        }
-
-        Ok(
-            ret
-        )
+        self.loaded(hash, lookup, result)
     }
 
-    fn move_log_file(&mut self, new_path: &String) -> Result<()>
+    async fn move_log_file(&mut self, new_path: &String) -> Result<()>
     {
         if self.temp == false
         {
+            // The checkpoint no longer describes the logs that are about to be replaced
+            let path_checkpoint = checkpoint_path(new_path);
+            if std::path::Path::new(path_checkpoint.as_str()).exists() == true {
+                std::fs::remove_file(path_checkpoint)?;
+            }
+            let path_journal = journal_path(new_path);
+            match self.journal.as_mut() {
+                Some(journal) => journal.rename(path_journal)?,
+                None if std::path::Path::new(path_journal.as_str()).exists() => std::fs::remove_file(path_journal)?,
+                None => { }
+            }
+
+            // Nor are the archives of the original logs that are in the cold tier
+            let first_hot = ColdManifest::load(new_path)?.first_hot_index();
+            self.destroy_cold_tier(new_path).await?;
+
             // First rename the orginal logs as a backup
-            let mut n = 0;
+            let mut n = first_hot;
             loop {
                 let path_from = format!("{}.{}", new_path, n);
                 let path_to = format!("{}.backup.{}", new_path, n);
@@ -461,7 +840,7 @@ for LogFileLocalFs
             }
 
             // Now delete all the backups
-            let mut n = 0;
+            let mut n = first_hot;
             loop {
                 let path_old = format!("{}.backup.{}", new_path, n);
                 if std::path::Path::new(path_old.as_str()).exists() == true {
@@ -508,6 +887,14 @@ for LogFileLocalFs
         Ok(())
     }
 
+    async fn sync(&mut self) -> Result<()>
+    {
+        // Flush the buffers and then wait for the operating system to write them to disk
+        self.flush().await?;
+        self.appender.sync().await?;
+        Ok(())
+    }
+
     fn count(&self) -> usize {
         self.lookup.values().len()
     }
@@ -531,15 +918,35 @@ for LogFileLocalFs
 
         if let Some(a) = self.archives.get(&index) {
             Vec::from(a.header())
+        } else if let Some(a) = self.cold_manifest.get(index) {
+            a.header.clone()
         } else {
             Vec::new()
         }
     }
 
-    fn destroy(&mut self) -> Result<()>
+    async fn destroy(&mut self) -> Result<()>
     {
+        // Delete the checkpoint as it would no longer be valid
+        let path_checkpoint = checkpoint_path(&self.path);
+        if std::path::Path::new(path_checkpoint.as_str()).exists() == true {
+            std::fs::remove_file(path_checkpoint)?;
+        }
+        self.journal = None;
+        let path_journal = journal_path(&self.path);
+        if std::path::Path::new(path_journal.as_str()).exists() == true {
+            std::fs::remove_file(path_journal)?;
+        }
+
+        // Remove the archives that are in the cold tier
+        let first_hot = self.cold_manifest.first_hot_index();
+        let path = self.path.clone();
+        self.destroy_cold_tier(&path).await?;
+        self.cold_manifest = ColdManifest::default();
+        self.cold_cache.lock().clear();
+
         // Now delete all the log files
-        let mut n = 0;
+        let mut n = first_hot;
         loop {
             let path_old = format!("{}.{}", self.path, n);
             if std::path::Path::new(path_old.as_str()).exists() == true {
@@ -552,6 +959,65 @@ for LogFileLocalFs
         Ok(())
     }
 
+    async fn checkpoint(&mut self, state: ChainCheckpoint) -> Result<()>
+    {
+        // Temporary logs (and logs without checkpoints) keep no journal
+        let journal = match self.journal.as_mut() {
+            Some(a) => a,
+            None => { return Ok(()); }
+        };
+
+        // Everything covered by the checkpoint must be in the log files first and
+        // the journal only needs the entries that were added since the last one
+        self.appender.flush().await?;
+        journal.sync().await?;
+
+        let mut archives = Vec::new();
+        for archive in self.cold_manifest.archives.iter() {
+            archives.push(CheckpointArchive {
+                index: archive.index,
+                header: archive.header.clone(),
+                length: archive.len,
+            });
+        }
+        for archive in self.archives.values() {
+            let length = match archive.index == self.appender.index {
+                true => self.appender.offset(),
+                false => archive.len().await?,
+            };
+            archives.push(CheckpointArchive {
+                index: archive.index,
+                header: Vec::from(archive.header()),
+                length,
+            });
+        }
+
+        let checkpoint = LogCheckpoint {
+            version: CHECKPOINT_VERSION,
+            archives,
+            entries: journal.count,
+            entries_len: journal.len,
+            entries_hash: journal.hash,
+            state,
+        };
+        let bytes = bincode::serialize(&checkpoint)
+            .map_err(|err| tokio::io::Error::new(ErrorKind::Other, err.to_string()))?;
+
+        // Write it to a temporary file first so that a crash does not leave a partial checkpoint
+        let path = checkpoint_path(&self.path);
+        let path_temp = format!("{}.tmp", path);
+        tokio::fs::write(path_temp.as_str(), &bytes[..]).await?;
+        tokio::fs::rename(path_temp, path.as_str()).await?;
+
+        debug!("checkpoint-written: {} ({} events)", path, checkpoint.entries);
+        Ok(())
+    }
+
+    fn take_checkpoint(&mut self) -> Option<ChainCheckpoint>
+    {
+        self.restored.take()
+    }
+
     async fn begin_flip(&self, header_bytes: Vec<u8>) -> Result<Box<dyn LogFile>> {
         let ret = {
             let path_flip = format!("{}.flip", self.path);
@@ -575,6 +1041,9 @@ for LogFileLocalFs
                 cache_size, 
                 cache_ttl,
                 header_bytes,
+                self.limits,
+                self.cold.clone(),
+                self.checkpoints,
             )
         };
 
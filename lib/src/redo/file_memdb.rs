@@ -12,6 +12,7 @@ use crate::spec::*;
 use crate::loader::*;
 
 use super::*;
+use super::checkpoint::ChainCheckpoint;
 
 pub(super) struct LogFileMemDb
 {
@@ -180,6 +181,11 @@ for LogFileMemDb
         Ok(())
     }
 
+    async fn sync(&mut self) -> Result<()>
+    {
+        Ok(())
+    }
+
     fn count(&self) -> usize {
         self.lookup.values().len()
     }
@@ -200,12 +206,22 @@ for LogFileMemDb
         self.header.clone()
     }
 
-    fn destroy(&mut self) -> Result<()>
+    async fn destroy(&mut self) -> Result<()>
     {
         Ok(())
     }
 
-    fn move_log_file(&mut self, _new_path: &String) -> Result<()>
+    async fn checkpoint(&mut self, _state: ChainCheckpoint) -> Result<()>
+    {
+        Ok(())
+    }
+
+    fn take_checkpoint(&mut self) -> Option<ChainCheckpoint>
+    {
+        None
+    }
+
+    async fn move_log_file(&mut self, _new_path: &String) -> Result<()>
     {
         Ok(())
     }
@@ -31,6 +31,10 @@ for FlippedLogFile
     async fn flush(&mut self) -> Result<()> {
         self.log_file.flush().await
     }
+
+    async fn sync(&mut self) -> Result<()> {
+        self.log_file.sync().await
+    }
 }
 
 impl FlippedLogFile
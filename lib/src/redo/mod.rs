@@ -15,12 +15,25 @@ mod loader;
 #[cfg(feature = "local_fs")]
 mod archive;
 mod core;
+mod checkpoint;
+mod staging;
+mod durability;
+#[cfg(feature = "local_fs")]
+mod cold;
+#[cfg(feature = "local_fs")]
+mod reader;
 mod test;
 
 pub use flags::OpenFlags;
 pub use loader::RedoLogLoader;
 pub use self::core::RedoLog;
 pub use api::LogWritable;
+pub use checkpoint::ChainCheckpoint;
+pub use durability::DurabilityPolicy;
+#[cfg(feature = "local_fs")]
+pub use reader::LogFileReader;
+#[cfg(feature = "local_fs")]
+pub use cold::{ColdStore, ColdTier, LocalColdStore};
 
 pub(crate) use api::LogLookup;
 
@@ -0,0 +1,113 @@
+#[allow(unused_imports)]
+use log::{error, info, warn, debug};
+use tokio::io::Result;
+use tokio::io::ErrorKind;
+
+use crate::error::*;
+use crate::spec::*;
+use crate::loader::*;
+
+use super::magic::*;
+use super::archive::*;
+use super::file_localfs::LogFileLocalFs;
+use super::file_localfs::checkpoint_path;
+
+/// Reads the events of a single redo log archive without opening the chain
+/// that it belongs to, this is used by the offline tools to inspect and
+/// repair log files that can not (or should not) be loaded normally
+pub struct LogFileReader
+{
+    archive: LogArchive,
+    limits: EventLimits,
+    offset: u64,
+}
+
+impl LogFileReader
+{
+    pub async fn open(path_log: &str, index: u32, limits: EventLimits) -> Result<LogFileReader>
+    {
+        let archive = LogArchive::new(path_log.to_string(), index).await?;
+
+        // Skip over the redo header so that we are positioned at the first event
+        let offset = {
+            let mut lock = archive.lock_at(0).await?;
+            match RedoHeader::read(&mut lock).await? {
+                Some(_) => lock.offset(),
+                None => {
+                    return Err(tokio::io::Error::new(ErrorKind::InvalidData, format!("The redo log ({}) has no header", archive.path)));
+                }
+            }
+        };
+
+        Ok(
+            LogFileReader {
+                archive,
+                limits,
+                offset,
+            }
+        )
+    }
+
+    /// Path of the archive file that is being read
+    pub fn path(&self) -> &String {
+        &self.archive.path
+    }
+
+    /// Header bytes that were stored when the archive was created
+    pub fn header(&self) -> &[u8] {
+        self.archive.header()
+    }
+
+    /// Offset in the archive where the next event will be read from
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+
+    pub async fn len(&self) -> Result<u64> {
+        self.archive.len().await
+    }
+
+    /// Reads the next event in the archive and returns its offset, when the read fails the
+    /// reader stays at the same offset so that the caller can report where the log broke
+    pub async fn next(&mut self) -> std::result::Result<Option<(u64, LoadData)>, SerializationError>
+    {
+        let mut lock = self.archive.lock_at(self.offset).await?;
+        let ret = match LogFileLocalFs::read_once_internal(&mut lock, self.archive.index, &self.limits).await? {
+            Some(a) => a,
+            None => { return Ok(None); }
+        };
+        self.offset = lock.offset();
+
+        Ok(Some((ret.lookup.offset, ret)))
+    }
+
+    /// Truncates the archive at a particular offset which removes the event at this offset
+    /// and everything after it, any checkpoint for the log is also removed as it would no
+    /// longer match the events in the archive
+    pub async fn truncate(path_log: &str, index: u32, offset: u64) -> Result<()>
+    {
+        // The header of the archive must survive the truncate
+        let reader = LogFileReader::open(path_log, index, EventLimits { max_meta_size: 0, max_data_size: 0 }).await?;
+        let path = reader.path().clone();
+        if offset < reader.offset() {
+            return Err(tokio::io::Error::new(ErrorKind::InvalidInput, format!("The offset 0x{:x} is inside the header of the redo log ({}) which ends at 0x{:x}", offset, path, reader.offset())));
+        }
+        if offset > reader.len().await? {
+            return Err(tokio::io::Error::new(ErrorKind::InvalidInput, format!("The offset 0x{:x} is beyond the end of the redo log ({})", offset, path)));
+        }
+        drop(reader);
+
+        // Archives that are mapped by a running chain must not be touched
+        let file = LogArchive::lock_exclusive(path.as_str())?;
+        file.set_len(offset)?;
+        file.sync_all()?;
+
+        let checkpoint = checkpoint_path(&path_log.to_string());
+        if std::path::Path::new(checkpoint.as_str()).exists() {
+            std::fs::remove_file(checkpoint)?;
+        }
+
+        info!("truncated {} at 0x{:x}", path, offset);
+        Ok(())
+    }
+}
@@ -0,0 +1,364 @@
+#[allow(unused_imports)]
+use log::{error, info, warn, debug};
+use serde::{Serialize, Deserialize};
+use std::collections::BTreeMap;
+use fxhash::{FxHashMap, FxHashSet};
+use tokio::io::AsyncWriteExt;
+use tokio::io::ErrorKind;
+use bytes::Bytes;
+
+use crate::crypto::*;
+use crate::event::*;
+use crate::error::*;
+use crate::spec::*;
+use crate::loader::*;
+
+use super::api::LogLookup;
+
+/// Index that is reported in the lookups of events that are still in the staging log
+pub(crate) static STAGING_INDEX: u32 = u32::MAX;
+
+/// Single event as it is stored in the staging file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct StagedEvent
+{
+    format: MessageFormat,
+    meta: Vec<u8>,
+    data: Option<Vec<u8>>,
+}
+
+/// Records are only ever appended to the staging file, events that leave the
+/// staging log are recorded with a removal rather than rewriting the file
+#[derive(Serialize, Deserialize, Debug, Clone)]
+enum StagingRecord
+{
+    Staged(StagedEvent),
+    Removed(Vec<AteHash>),
+    /// Event that older versions already wrote into the main redo log before it was
+    /// confirmed, it only needs to be uploaded and is never written to the main log again
+    Migrated(StagedEvent),
+}
+
+#[derive(Debug, Clone)]
+struct StagedEntry
+{
+    seq: u64,
+    evt: EventData,
+    logged: bool,
+}
+
+/// Holds the events that were written locally but have not yet been confirmed
+/// by the server. These events are kept out of the main redo log until they are
+/// confirmed so that the chain only ever contains confirmed history.
+pub(crate) struct StagingLog
+{
+    path: Option<String>,
+    file: Option<tokio::fs::File>,
+    dirty: bool,
+    limits: EventLimits,
+    seq: u64,
+    order: BTreeMap<u64, AteHash>,
+    events: FxHashMap<AteHash, StagedEntry>,
+    confirmed: FxHashSet<AteHash>,
+}
+
+impl StagingLog
+{
+    pub(crate) async fn open(path: Option<String>, limits: EventLimits) -> Result<StagingLog, SerializationError>
+    {
+        let mut ret = StagingLog {
+            path,
+            file: None,
+            dirty: false,
+            limits,
+            seq: 0,
+            order: BTreeMap::new(),
+            events: FxHashMap::default(),
+            confirmed: FxHashSet::default(),
+        };
+
+        // Read all the events that were staged the last time the chain was open
+        if let Some(path) = ret.path.clone() {
+            let bytes = match tokio::fs::read(&path).await {
+                Ok(a) => a,
+                Err(err) if err.kind() == ErrorKind::NotFound => Vec::new(),
+                Err(err) => { return Err(SerializationError::IO(err)); }
+            };
+
+            let mut garbage = false;
+            let mut remaining = &bytes[..];
+            while remaining.len() > 0 {
+                if remaining.len() < 4 {
+                    warn!("staging-log: {} has a truncated tail", path);
+                    garbage = true;
+                    break;
+                }
+                let len = u32::from_be_bytes([remaining[0], remaining[1], remaining[2], remaining[3]]) as usize;
+                if remaining.len() < 4 + len {
+                    warn!("staging-log: {} has a truncated tail", path);
+                    garbage = true;
+                    break;
+                }
+
+                let record: StagingRecord = bincode::deserialize(&remaining[4..4+len])?;
+                remaining = &remaining[4+len..];
+
+                let (staged, logged) = match record {
+                    StagingRecord::Staged(staged) => (staged, false),
+                    StagingRecord::Migrated(staged) => (staged, true),
+                    StagingRecord::Removed(hashes) => {
+                        ret.remove_internal(&hashes[..]);
+                        garbage = true;
+                        continue;
+                    }
+                };
+
+                limits.check_meta(staged.meta.len())?;
+                if let Some(data) = staged.data.as_ref() {
+                    limits.check_data(data.len())?;
+                }
+                let evt = EventData {
+                    meta: staged.format.meta.deserialize(&staged.meta[..])?,
+                    data_bytes: staged.data.map(|d| Bytes::from(d)),
+                    format: staged.format,
+                };
+                let hash = evt.as_header_raw()?.event_hash;
+                ret.insert(hash, evt, logged);
+            }
+
+            // Removals (and any broken tail) are compacted away once at startup so
+            // that the file does not keep growing between restarts
+            if garbage {
+                if ret.order.len() <= 0 {
+                    tokio::fs::remove_file(&path).await?;
+                } else {
+                    let mut bytes = Vec::new();
+                    for entry in ret.order.values().filter_map(|h| ret.events.get(h)) {
+                        bytes.extend(StagingLog::encode(&StagingLog::record(&entry.evt, entry.logged)?)?);
+                    }
+                    let path_tmp = format!("{}.tmp", path);
+                    let mut file = tokio::fs::File::create(&path_tmp).await?;
+                    file.write_all(&bytes[..]).await?;
+                    file.sync_data().await?;
+                    tokio::fs::rename(&path_tmp, &path).await?;
+                }
+            }
+
+            if ret.order.len() > 0 {
+                info!("staging-log: loaded {} unconfirmed events from {}", ret.order.len(), path);
+            }
+        }
+
+        Ok(ret)
+    }
+
+    fn record(evt: &EventData, logged: bool) -> Result<StagingRecord, SerializationError>
+    {
+        let staged = StagedEvent {
+            format: evt.format,
+            meta: evt.format.meta.serialize(&evt.meta)?,
+            data: evt.data_bytes.as_ref().map(|d| d.to_vec()),
+        };
+        Ok(
+            match logged {
+                true => StagingRecord::Migrated(staged),
+                false => StagingRecord::Staged(staged),
+            }
+        )
+    }
+
+    fn encode(record: &StagingRecord) -> Result<Vec<u8>, SerializationError>
+    {
+        let record = bincode::serialize(record)?;
+
+        let mut ret = Vec::with_capacity(record.len() + 4);
+        ret.extend_from_slice(&(record.len() as u32).to_be_bytes());
+        ret.extend(record);
+        Ok(ret)
+    }
+
+    async fn append(&mut self, record: &StagingRecord) -> Result<(), SerializationError>
+    {
+        let path = match self.path.as_ref() {
+            Some(a) => a,
+            None => { return Ok(()); }
+        };
+        if self.file.is_none() {
+            self.file = Some(tokio::fs::OpenOptions::new().create(true).append(true).open(path).await?);
+        }
+        if let Some(file) = self.file.as_mut() {
+            file.write_all(&StagingLog::encode(record)?[..]).await?;
+            file.flush().await?;
+            self.dirty = true;
+        }
+        Ok(())
+    }
+
+    fn insert(&mut self, hash: AteHash, evt: EventData, logged: bool)
+    {
+        if self.events.contains_key(&hash) {
+            return;
+        }
+        let seq = self.seq;
+        self.seq = self.seq + 1;
+        self.order.insert(seq, hash);
+        self.events.insert(hash, StagedEntry {
+            seq,
+            evt,
+            logged,
+        });
+    }
+
+    fn remove_internal(&mut self, hashes: &[AteHash]) -> usize
+    {
+        let mut cnt = 0usize;
+        for hash in hashes.iter() {
+            if let Some(entry) = self.events.remove(hash) {
+                self.order.remove(&entry.seq);
+                cnt = cnt + 1;
+            }
+        }
+        cnt
+    }
+
+    /// Adds an event to the staging log, if the server already confirmed the event
+    /// then it is not staged and false is returned. The event only reaches durable
+    /// storage when the redo log is synced (see `sync`)
+    pub(crate) async fn stage(&mut self, evt: &EventData) -> Result<bool, SerializationError>
+    {
+        let hash = evt.as_header_raw()?.event_hash;
+        if self.confirmed.remove(&hash) {
+            return Ok(false);
+        }
+        if self.events.contains_key(&hash) {
+            return Ok(true);
+        }
+        let record = StagingLog::record(evt, false)?;
+        if let StagingRecord::Staged(staged) = &record {
+            self.limits.check_meta(staged.meta.len())?;
+        }
+        if let Some(data) = &evt.data_bytes {
+            self.limits.check_data(data.len())?;
+        }
+        self.append(&record).await?;
+
+        self.insert(hash, evt.clone(), false);
+        Ok(true)
+    }
+
+    /// Adds an event that is already in the main redo log but was never confirmed by
+    /// the server (older versions wrote these straight into the main log), it is
+    /// uploaded like any other staged event but confirming it does not write it again
+    pub(crate) async fn migrate(&mut self, evt: &EventData) -> Result<(), SerializationError>
+    {
+        let hash = evt.as_header_raw()?.event_hash;
+        if self.events.contains_key(&hash) {
+            return Ok(());
+        }
+        self.append(&StagingLog::record(evt, true)?).await?;
+
+        self.insert(hash, evt.clone(), true);
+        Ok(())
+    }
+
+    /// Records that the server confirmed an event before it was staged
+    pub(crate) fn confirm_early(&mut self, hash: AteHash) {
+        self.confirmed.insert(hash);
+    }
+
+    /// Removes events from the staging log (normally because they are now in the main log)
+    pub(crate) async fn remove(&mut self, hashes: &[AteHash]) -> Result<usize, SerializationError>
+    {
+        let cnt = self.remove_internal(hashes);
+        if cnt <= 0 {
+            return Ok(0);
+        }
+
+        // Once nothing is left the file is emptied, otherwise the removal is appended
+        if self.order.len() <= 0 {
+            if let Some(file) = self.file.as_mut() {
+                file.set_len(0).await?;
+                self.dirty = true;
+            } else if let Some(path) = self.path.as_ref() {
+                let _ = tokio::fs::remove_file(path).await;
+            }
+        } else {
+            let removed = hashes.iter()
+                .map(|h| h.clone())
+                .collect::<Vec<_>>();
+            self.append(&StagingRecord::Removed(removed)).await?;
+        }
+
+        Ok(cnt)
+    }
+
+    /// Waits for the staged records to reach durable storage, this is called whenever
+    /// the redo log itself is synced so that staging follows the durability policy
+    pub(crate) async fn sync(&mut self) -> tokio::io::Result<()>
+    {
+        if self.dirty {
+            if let Some(file) = self.file.as_mut() {
+                file.sync_data().await?;
+            }
+            self.dirty = false;
+        }
+        Ok(())
+    }
+
+    pub(crate) fn contains(&self, hash: &AteHash) -> bool {
+        self.events.contains_key(hash)
+    }
+
+    /// Returns true if the staged event is also in the main redo log
+    pub(crate) fn is_logged(&self, hash: &AteHash) -> bool {
+        self.events.get(hash).map(|e| e.logged).unwrap_or(false)
+    }
+
+    pub(crate) fn get(&self, hash: &AteHash) -> Option<&EventData> {
+        self.events.get(hash).map(|e| &e.evt)
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    /// Returns all the staged events in the order they were written
+    pub(crate) fn events(&self) -> Vec<EventData> {
+        self.order
+            .values()
+            .filter_map(|h| self.events.get(h))
+            .map(|e| e.evt.clone())
+            .collect::<Vec<_>>()
+    }
+
+    pub(crate) fn load(&self, hash: &AteHash) -> Result<Option<LoadData>, SerializationError>
+    {
+        let entry = match self.events.get(hash) {
+            Some(a) => a,
+            None => { return Ok(None); }
+        };
+        Ok(Some(
+            LoadData {
+                header: entry.evt.as_header_raw()?,
+                data: entry.evt.clone(),
+                lookup: LogLookup {
+                    index: STAGING_INDEX,
+                    offset: entry.seq,
+                },
+            }
+        ))
+    }
+
+    pub(crate) fn destroy(&mut self) -> tokio::io::Result<()>
+    {
+        self.order.clear();
+        self.events.clear();
+        self.file = None;
+        if let Some(path) = self.path.as_ref() {
+            if std::path::Path::new(path).exists() {
+                std::fs::remove_file(path)?;
+            }
+        }
+        Ok(())
+    }
+}
\ No newline at end of file
@@ -13,8 +13,13 @@ use crate::spec::*;
 
 use super::api::LogWritable;
 use super::core::RedoLog;
+use super::checkpoint::ChainCheckpoint;
+#[cfg(feature = "local_fs")]
+use super::reader::LogFileReader;
 #[cfg(feature = "local_fs")]
 use super::flags::OpenFlags;
+#[cfg(all(feature = "local_fs", feature = "rotate"))]
+use super::cold::*;
 
 /* 
 TESTS 
@@ -197,7 +202,483 @@ fn test_redo_log() {
             #[cfg(feature = "local_fs")]
             assert_eq!(5, rl.count());
 
-            rl.destroy().unwrap();
+            rl.destroy().await.unwrap();
+        }
+    });
+}
+#[cfg(feature = "local_fs")]
+#[test]
+fn test_redo_log_checksum() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mock_cfg = crate::conf::tests::mock_test_config();
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_checksum".to_string());
+
+        // Write a single event and flush it to disk
+        let path = {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![1; 10]), true, mock_cfg.log_format).await;
+            format!("{}/{}.log.0", mock_cfg.log_path.as_ref().unwrap(), mock_chain_key.name)
+        };
+
+        // Flip a bit inside the data blob (the last 10 bytes before the checksum)
+        let mut bytes = std::fs::read(&path).unwrap();
+        let idx = bytes.len() - 4 - 5;
+        bytes[idx] = bytes[idx] ^ 0x01;
+        std::fs::write(&path, &bytes[..]).unwrap();
+
+        // Reopening the log must fail with the exact archive and offset
+        match RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await {
+            Err(crate::error::SerializationError::CorruptLog { path: corrupt_path, .. }) => {
+                assert_eq!(corrupt_path, path);
+            },
+            Err(err) => panic!("Unexpected error - {}", err),
+            Ok(_) => panic!("The corrupt redo log should not have loaded"),
+        }
+
+        let _ = std::fs::remove_file(path);
+    });
+}
+
+#[cfg(feature = "local_fs")]
+#[test]
+fn test_redo_log_event_limits() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut mock_cfg = crate::conf::tests::mock_test_config();
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_limits".to_string());
+
+        // Write an event that is within the default limits
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![1; 100]), true, mock_cfg.log_format).await;
+        }
+
+        // Shrink the limits so that the same event is now too big which must stop the
+        // log from opening rather than silently dropping the event
+        mock_cfg.max_event_data_size = 50;
+        match RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await {
+            Err(crate::error::SerializationError::EventTooLarge { offset, size, limit, .. }) => {
+                assert!(offset > 0);
+                assert!(size >= 100);
+                assert_eq!(limit, 50);
+            },
+            Err(err) => panic!("Unexpected error - {}", err),
+            Ok(_) => panic!("The oversized event should have failed the load"),
+        }
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+
+            // Writing an oversized event must fail before it reaches the disk
+            let evt = EventData {
+                meta: Metadata::for_data(PrimaryKey::generate()),
+                data_bytes: Some(Bytes::from(vec![2; 100])),
+                format: mock_cfg.log_format,
+            };
+            match rl.write(&evt).await {
+                Err(crate::error::SerializationError::DataTooLarge { size, limit }) => {
+                    assert_eq!(size, 100);
+                    assert_eq!(limit, 50);
+                },
+                Err(err) => panic!("Unexpected error - {}", err),
+                Ok(_) => panic!("The oversized event should not have been written"),
+            }
+            rl.destroy().await.unwrap();
+        }
+    });
+}
+
+#[cfg(feature = "local_fs")]
+#[test]
+fn test_redo_log_reader_truncate() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mock_cfg = crate::conf::tests::mock_test_config();
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_reader".to_string());
+        let path_log = format!("{}/{}.log", mock_cfg.log_path.as_ref().unwrap(), mock_chain_key.name);
+
+        let mut hashes = Vec::new();
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            for n in 0..3u8 {
+                hashes.push(test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![n; 10]), true, mock_cfg.log_format).await);
+            }
+        }
+
+        // The reader should walk the events in the order they were written
+        let limits = EventLimits { max_meta_size: mock_cfg.max_event_meta_size, max_data_size: mock_cfg.max_event_data_size };
+        let mut reader = LogFileReader::open(path_log.as_str(), 0, limits).await.expect("Failed to open the reader");
+        let header_end = reader.offset();
+        let mut offsets = Vec::new();
+        while let Some((offset, evt)) = reader.next().await.expect("Failed to read the event") {
+            assert_eq!(evt.header.event_hash, hashes[offsets.len()]);
+            offsets.push(offset);
+        }
+        assert_eq!(offsets.len(), 3);
+        assert_eq!(offsets[0], header_end);
+        let len = reader.len().await.unwrap();
+        drop(reader);
+
+        // Offsets that would damage the header or that are past the end must be rejected
+        assert!(LogFileReader::truncate(path_log.as_str(), 0, 0).await.is_err());
+        assert!(LogFileReader::truncate(path_log.as_str(), 0, header_end - 1).await.is_err());
+        assert!(LogFileReader::truncate(path_log.as_str(), 0, len + 1).await.is_err());
+
+        // Truncating at the second event leaves only the first one behind
+        LogFileReader::truncate(path_log.as_str(), 0, offsets[1]).await.expect("Failed to truncate the log");
+        let mut reader = LogFileReader::open(path_log.as_str(), 0, limits).await.expect("Failed to open the reader");
+        assert_eq!(reader.len().await.unwrap(), offsets[1]);
+        let (_, evt) = reader.next().await.unwrap().expect("The first event should have survived");
+        assert_eq!(evt.header.event_hash, hashes[0]);
+        assert!(reader.next().await.unwrap().is_none());
+        drop(reader);
+
+        let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+        assert_eq!(rl.count(), 1);
+        rl.destroy().await.unwrap();
+    });
+}
+
+#[cfg(feature = "local_fs")]
+#[test]
+fn test_redo_log_checkpoint() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut mock_cfg = crate::conf::tests::mock_test_config();
+        mock_cfg.checkpoint_interval = Some(2);
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_checkpoint".to_string());
+
+        // Write some events and take a checkpoint half way through
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![1; 10]), true, mock_cfg.log_format).await;
+            test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![2; 10]), true, mock_cfg.log_format).await;
+            rl.checkpoint(ChainCheckpoint::default()).await.expect("Failed to write the checkpoint");
+            test_write_data(&mut rl, PrimaryKey::generate(), Some(vec![3; 10]), true, mock_cfg.log_format).await;
+        }
+
+        // Reopening the log should restore the checkpoint and replay the tail
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            let checkpoint = rl.take_checkpoint().expect("The checkpoint should have been loaded");
+            assert_eq!(checkpoint.events, 2);
+            assert_eq!(checkpoint.headers.len(), 2);
+            assert_eq!(loader.len(), 1);
+            assert_eq!(rl.count(), 3);
+
+            // Events restored from the checkpoint can still be loaded from the log
+            for hash in checkpoint.headers.iter().map(|a| a.event_hash).chain(loader.iter().map(|a| a.header.event_hash)) {
+                rl.load(hash).await.expect("Failed to load the event");
+            }
+            rl.checkpoint(ChainCheckpoint::default()).await.expect("Failed to write the checkpoint");
+        }
+
+        // The next checkpoint only appends the tail to the journal
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            let checkpoint = rl.take_checkpoint().expect("The checkpoint should have been loaded");
+            assert_eq!(checkpoint.events, 3);
+            assert_eq!(loader.len(), 0);
+        }
+
+        // If the part of the log that the checkpoint covers is changed then its ignored
+        {
+            let path_archive = format!("/tmp/ate/{}.log.0", mock_chain_key.name);
+            let original = std::fs::read(path_archive.as_str()).unwrap();
+            let mut bytes = original.clone();
+            let pos = bytes.windows(10).rposition(|a| a == &[3u8; 10][..]).expect("The event should be in the archive");
+            bytes[pos..pos + 10].copy_from_slice(&[4u8; 10][..]);
+            std::fs::write(path_archive.as_str(), bytes).unwrap();
+
+            // (the whole log is then replayed which finds the corrupt event)
+            assert!(RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.is_err());
+
+            std::fs::write(path_archive.as_str(), original).unwrap();
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            rl.destroy().await.unwrap();
+        }
+    });
+}
+
+#[cfg(all(feature = "local_fs", feature = "rotate"))]
+#[test]
+fn test_redo_log_sealed_archive() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mock_cfg = crate::conf::tests::mock_test_config();
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_sealed".to_string());
+
+        // Write some events into the first archive then rotate it so that its sealed
+        let key1 = PrimaryKey::generate();
+        let key2 = PrimaryKey::generate();
+        let key3 = PrimaryKey::generate();
+        let (hash1, hash2, hash3) = {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            let hash1 = test_write_data(&mut rl, key1, Some(vec![1; 10]), true, mock_cfg.log_format).await;
+            let hash2 = test_write_data(&mut rl, key2, None, true, mock_cfg.log_format).await;
+            rl.rotate(Vec::new()).await.expect("Failed to rotate the redo log");
+            let hash3 = test_write_data(&mut rl, key3, Some(vec![3; 10]), true, mock_cfg.log_format).await;
+            (hash1, hash2, hash3)
+        };
+
+        // Reopen the log so that the reads are not served from the cache, the events
+        // in the sealed archive are read concurrently from its memory map
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(loader.len(), 3);
+
+            let loads = vec![hash1, hash2, hash1, hash2]
+                .into_iter()
+                .map(|h| rl.load(h))
+                .collect::<Vec<_>>();
+            for ret in futures::future::join_all(loads).await {
+                ret.expect("Failed to load the event from the sealed archive");
+            }
+
+            test_read_data(&mut rl, hash1, key1, Some(vec![1; 10]), mock_cfg.log_format).await;
+            test_read_data(&mut rl, hash2, key2, None, mock_cfg.log_format).await;
+            test_read_data(&mut rl, hash3, key3, Some(vec![3; 10]), mock_cfg.log_format).await;
+
+            // The mapped archive must not be truncated while the log is open
+            #[cfg(feature = "mmap")]
+            {
+                let path_log = format!("/tmp/ate/{}.log", mock_chain_key.name);
+                let len = std::fs::metadata(format!("{}.0", path_log)).unwrap().len();
+                assert!(LogFileReader::truncate(path_log.as_str(), 0, len).await.is_err());
+            }
+            rl.destroy().await.unwrap();
         }
     });
-}
\ No newline at end of file
+}
+
+/// Cold store that counts how many objects are fetched from it
+#[cfg(all(feature = "local_fs", feature = "rotate"))]
+#[derive(Debug)]
+struct CountingColdStore
+{
+    inner: LocalColdStore,
+    gets: std::sync::Arc<std::sync::atomic::AtomicUsize>,
+}
+
+#[cfg(all(feature = "local_fs", feature = "rotate"))]
+#[async_trait::async_trait]
+impl ColdStore
+for CountingColdStore
+{
+    async fn put(&self, name: &str, data: Vec<u8>) -> tokio::io::Result<()> {
+        self.inner.put(name, data).await
+    }
+
+    async fn get(&self, name: &str) -> tokio::io::Result<Option<Vec<u8>>> {
+        self.gets.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        self.inner.get(name).await
+    }
+
+    async fn remove(&self, name: &str) -> tokio::io::Result<()> {
+        self.inner.remove(name).await
+    }
+}
+
+#[cfg(all(feature = "local_fs", feature = "rotate"))]
+#[test]
+fn test_redo_log_cold_tier() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mut mock_cfg = crate::conf::tests::mock_test_config();
+        mock_cfg.checkpoint_interval = Some(1);
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_cold".to_string());
+        let path_log = format!("/tmp/ate/{}.log", mock_chain_key.name);
+        let path_cold = format!("/tmp/ate/{}.cold", mock_chain_key.name);
+        let gets = std::sync::Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut cold_tier = ColdTier::new(CountingColdStore { inner: LocalColdStore::new(path_cold.as_str()), gets: gets.clone() }, 1);
+        cold_tier.segment_size = 64;
+        cold_tier.cache_size = 1;
+        mock_cfg.cold_tier = Some(cold_tier);
+
+        // Write an event into four archives, the oldest two are moved to the cold tier
+        // in segments that are smaller than the events
+        let keys = (0..4).map(|_| PrimaryKey::generate()).collect::<Vec<_>>();
+        let mut hashes = Vec::new();
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            for (n, key) in keys.iter().enumerate() {
+                if n > 0 {
+                    rl.rotate(Vec::new()).await.expect("Failed to rotate the redo log");
+                }
+                hashes.push(test_write_data(&mut rl, key.clone(), Some(vec![n as u8; 100]), true, mock_cfg.log_format).await);
+            }
+
+            // Events in the cold tier can still be read
+            test_read_data(&mut rl, hashes[0], keys[0], Some(vec![0; 100]), mock_cfg.log_format).await;
+            rl.checkpoint(ChainCheckpoint::default()).await.expect("Failed to write the checkpoint");
+        }
+        for n in 0..2 {
+            assert!(std::path::Path::new(format!("{}.{}", path_log, n).as_str()).exists() == false);
+            assert!(std::path::Path::new(format!("{}/{}.log.{}.0.sz", path_cold, mock_chain_key.name, n).as_str()).exists());
+            assert!(std::path::Path::new(format!("{}/{}.log.{}.1.sz", path_cold, mock_chain_key.name, n).as_str()).exists());
+        }
+        for n in 2..4 {
+            assert!(std::path::Path::new(format!("{}.{}", path_log, n).as_str()).exists());
+        }
+
+        // The log can not be opened without the cold tier that holds its archives
+        {
+            let mut cfg = mock_cfg.clone();
+            cfg.cold_tier = None;
+            assert!(RedoLog::open(&cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.is_err());
+        }
+
+        // When the checkpoint covers the cold archives nothing is fetched until an
+        // event in them is read and then only the segments that hold that event
+        {
+            gets.store(0, std::sync::atomic::Ordering::SeqCst);
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(gets.load(std::sync::atomic::Ordering::SeqCst), 0);
+            test_read_data(&mut rl, hashes[1], keys[1], Some(vec![1; 100]), mock_cfg.log_format).await;
+            let fetched = gets.load(std::sync::atomic::Ordering::SeqCst);
+            assert!(fetched > 0);
+            assert!(std::fs::read_dir(path_cold.as_str()).unwrap().count() > fetched);
+        }
+
+        // Without the checkpoint the cold archives are replayed segment by segment
+        {
+            let mut cfg = mock_cfg.clone();
+            cfg.checkpoint_interval = None;
+            let (mut rl, loader) = RedoLog::open(&cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(loader.len(), 4);
+            for (n, (key, hash)) in keys.iter().zip(hashes.iter()).enumerate() {
+                test_read_data(&mut rl, hash.clone(), key.clone(), Some(vec![n as u8; 100]), mock_cfg.log_format).await;
+            }
+            rl.destroy().await.unwrap();
+        }
+        assert!(std::path::Path::new(format!("{}.cold", path_log).as_str()).exists() == false);
+        assert_eq!(std::fs::read_dir(path_cold.as_str()).unwrap().count(), 0);
+    });
+}
+
+#[cfg(feature = "local_fs")]
+#[test]
+fn test_redo_log_staging() {
+    crate::utils::bootstrap_env();
+
+    let rt = Runtime::new().unwrap();
+    rt.block_on(async {
+        let mock_cfg = crate::conf::tests::mock_test_config();
+        let mock_chain_key = ChainKey::default()
+            .with_temp_name("test_redo_staging".to_string());
+        let path_staging = format!("/tmp/ate/{}.log.staging", mock_chain_key.name);
+
+        let key1 = PrimaryKey::generate();
+        let key2 = PrimaryKey::generate();
+        let key3 = PrimaryKey::generate();
+        let evt = |key: PrimaryKey, body: Vec<u8>| {
+            let mut meta = Metadata::for_data(key);
+            meta.core.push(CoreMetadata::Author("test@nowhere.com".to_string()));
+            EventData {
+                meta,
+                data_bytes: Some(Bytes::from(body)),
+                format: mock_cfg.log_format,
+            }
+        };
+        let evt2 = evt(key2, vec![2; 10]);
+        let evt3 = evt(key3, vec![3; 10]);
+        let hash2 = evt2.as_header_raw().unwrap().event_hash;
+        let hash3 = evt3.as_header_raw().unwrap().event_hash;
+
+        // Write one confirmed event and stage two unconfirmed ones
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::create_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            test_write_data(&mut rl, key1, Some(vec![1; 10]), true, mock_cfg.log_format).await;
+            rl.stage(&evt2).await.expect("Failed to stage the event");
+            rl.stage(&evt3).await.expect("Failed to stage the event");
+            assert_eq!(rl.count(), 1);
+            assert_eq!(rl.staged().len(), 2);
+            test_read_data(&mut rl, hash2, key2, Some(vec![2; 10]), mock_cfg.log_format).await;
+        }
+        let staged_size = std::fs::metadata(path_staging.as_str()).unwrap().len();
+
+        // The staged events are replayed after the main log when its reopened, confirming
+        // one of them moves it into the main log
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(loader.len(), 3);
+            assert_eq!(loader.back().unwrap().header.event_hash, hash3);
+            assert_eq!(rl.count(), 1);
+            assert_eq!(rl.staged().len(), 2);
+
+            // Confirming appends a removal rather than rewriting the staging file
+            let before = std::fs::metadata(path_staging.as_str()).unwrap().len();
+            assert_eq!(rl.confirm(&[hash2]).await.expect("Failed to confirm the event"), 1);
+            assert_eq!(rl.count(), 2);
+            assert_eq!(rl.staged().len(), 1);
+            assert!(rl.is_staged(&hash3));
+            assert!(std::fs::metadata(path_staging.as_str()).unwrap().len() > before);
+        }
+
+        // Only the unconfirmed event remains staged after the next reopen
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(loader.len(), 3);
+            assert_eq!(rl.count(), 2);
+            assert_eq!(rl.staged().len(), 1);
+            assert!(std::fs::metadata(path_staging.as_str()).unwrap().len() < staged_size);
+            test_read_data(&mut rl, hash2, key2, Some(vec![2; 10]), mock_cfg.log_format).await;
+            test_read_data(&mut rl, hash3, key3, Some(vec![3; 10]), mock_cfg.log_format).await;
+
+            // A confirmation that arrives before the event is staged sends it straight to the main log
+            let key4 = PrimaryKey::generate();
+            let evt4 = evt(key4, vec![4; 10]);
+            let hash4 = evt4.as_header_raw().unwrap().event_hash;
+            assert_eq!(rl.confirm(&[hash4]).await.expect("Failed to confirm the event"), 0);
+            rl.stage(&evt4).await.expect("Failed to stage the event");
+            assert_eq!(rl.count(), 3);
+            assert!(rl.is_staged(&hash4) == false);
+
+            // Events that the server rejects are dropped without reaching the main log
+            assert_eq!(rl.discard(&[hash3]).await.expect("Failed to discard the event"), 1);
+            assert_eq!(rl.staged().len(), 0);
+            assert_eq!(rl.count(), 3);
+        }
+
+        // Events that older versions wrote straight into the main log without them being
+        // confirmed are uploaded from the staging log but never written a second time
+        let key5 = PrimaryKey::generate();
+        let evt5 = evt(key5, vec![5; 10]);
+        let hash5 = evt5.as_header_raw().unwrap().event_hash;
+        {
+            let (mut rl, _) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            rl.write(&evt5).await.expect("Failed to write the event");
+            rl.migrate(&evt5).await.expect("Failed to migrate the event");
+            assert_eq!(rl.staged().len(), 1);
+            assert!(rl.is_staged(&hash5) == false);
+        }
+        {
+            let (mut rl, loader) = RedoLog::open(&mock_cfg, &mock_chain_key, OpenFlags::open_centralized(), Vec::new()).await.expect("Failed to load the redo log");
+            assert_eq!(loader.len(), 4);
+            assert_eq!(rl.staged().len(), 1);
+            assert_eq!(rl.confirm(&[hash5]).await.expect("Failed to confirm the event"), 1);
+            assert_eq!(rl.count(), 4);
+            assert_eq!(rl.staged().len(), 0);
+
+            rl.destroy().await.unwrap();
+        }
+    });
+}
@@ -24,6 +24,26 @@ impl Chain
     }
 
     pub async fn invoke_ext<REQ, RES, ERR>(self: Arc<Self>, session: Option<&AteSession>, request: REQ, timeout: Duration) -> Result<RES, InvokeError<ERR>>
+    where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+          RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+          ERR: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+    {
+        self.invoke_internal(session, None, request, timeout).await
+    }
+
+    /// Invokes a command that carries an idempotency key, if the call times out then it
+    /// is safe to retry it with the same key as the service will only process the first
+    /// command it sees (within its idempotency window) and answer the others with the
+    /// same response
+    pub async fn invoke_idempotent<REQ, RES, ERR>(self: Arc<Self>, session: Option<&AteSession>, idempotency_key: &str, request: REQ, timeout: Duration) -> Result<RES, InvokeError<ERR>>
+    where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+          RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+          ERR: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
+    {
+        self.invoke_internal(session, Some(idempotency_key), request, timeout).await
+    }
+
+    async fn invoke_internal<REQ, RES, ERR>(self: Arc<Self>, session: Option<&AteSession>, idempotency_key: Option<&str>, request: REQ, timeout: Duration) -> Result<RES, InvokeError<ERR>>
     where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
           RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
           ERR: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
@@ -52,6 +72,9 @@ impl Chain
         cmd.add_extra_metadata(CoreMetadata::Type(MetaType {
             type_name: std::any::type_name::<REQ>().to_string()
         }));
+        if let Some(idempotency_key) = idempotency_key {
+            cmd.add_extra_metadata(CoreMetadata::IdempotencyKey(idempotency_key.to_string()));
+        }
 
         // Sniff out the response object
         let cmd = cmd.commit(&mut dio)?;
@@ -60,26 +83,9 @@ impl Chain
         let response_type_name = std::any::type_name::<RES>().to_string();
         let error_type_name = std::any::type_name::<ServiceErrorReply<ERR>>().to_string();
 
-        let join_res = sniff_for_command(Arc::downgrade(&self), Box::new(move |h| {
-            if let Some(reply) = h.meta.is_reply_to_what() {
-                if reply == cmd_id {
-                    if let Some(t) = h.meta.get_type_name() {
-                        return t.type_name == response_type_name;
-                    }
-                }
-            }
-            false
-        }));
-        let join_err = sniff_for_command(Arc::downgrade(&self), Box::new(move |h| {
-            if let Some(reply) = h.meta.is_reply_to_what() {
-                if reply == cmd_id {
-                    if let Some(t) = h.meta.get_type_name() {
-                        return t.type_name == error_type_name;
-                    }
-                }
-            }
-            false
-        }));
+        // (the sniffers are attached before the command is sent so the reply can not be missed)
+        let (_sniff_res, mut join_res) = sniff_for_replies(&self, cmd_id, response_type_name, 1);
+        let (_sniff_err, mut join_err) = sniff_for_replies(&self, cmd_id, error_type_name, 1);
 
         // Send our command
         dio.commit().await?;
@@ -88,14 +94,14 @@ impl Chain
         let mut timeout = tokio::time::interval(timeout);
         timeout.tick().await;
         select! {
-            key = join_res => {
+            key = join_res.recv() => {
                 let key = match key {
                     Some(a) => a,
                     None => { return Err(InvokeError::Aborted); }
                 };
                 Ok(dio.load::<RES>(&key).await?.take())
             },
-            key = join_err => {
+            key = join_err.recv() => {
                 let key = match key {
                     Some(a) => a,
                     None => { return Err(InvokeError::Aborted); }
@@ -113,6 +119,17 @@ impl Chain
 
     #[allow(dead_code)]
     pub fn add_service<REQ, RES, ERR>(self: &Arc<Self>, session: AteSession, handler: ServiceInstance<REQ, RES, ERR>)
+    where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
+          RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
+          ERR: std::fmt::Debug + Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
+    {
+        self.add_service_with(session, handler, Vec::new())
+    }
+
+    /// Adds a service whose requests first pass through a list of middleware (in
+    /// order) which can reject or transform them before they reach the handler
+    #[allow(dead_code)]
+    pub fn add_service_with<REQ, RES, ERR>(self: &Arc<Self>, session: AteSession, handler: ServiceInstance<REQ, RES, ERR>, middleware: Vec<ServiceMiddlewareInstance<REQ, RES, ERR>>)
     where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
           RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
           ERR: std::fmt::Debug + Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized + 'static,
@@ -123,6 +140,7 @@ impl Chain
                 self,
                 session,
                 Arc::clone(&handler),
+                middleware,
             ))
         );
     }
@@ -0,0 +1,293 @@
+#[allow(unused_imports)]
+use log::{info, error, warn, debug};
+use serde::{Serialize, de::DeserializeOwned};
+use futures::stream::{self, BoxStream, Stream, StreamExt};
+use futures::FutureExt;
+use std::collections::BTreeMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Weak};
+use std::task::{Context, Poll};
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+use crate::{error::*, meta::{CoreMetadata}};
+use crate::dio::*;
+use crate::chain::*;
+use crate::header::*;
+use crate::session::*;
+use crate::meta::*;
+
+use super::*;
+
+impl Chain
+{
+    /// Sends a request to a streaming service and returns the responses as they
+    /// arrive, the timeout is the longest that the stream will wait between items.
+    ///
+    /// Dropping the stream before it ends tells the service to stop.
+    pub async fn invoke_stream<REQ, RES, ERR>(self: Arc<Self>, session: Option<&AteSession>, request: REQ, timeout: Duration) -> Result<InvokeStream<RES, ERR>, InvokeError<ERR>>
+    where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+          RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+          ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+    {
+        // If no session was provided then use the empty one
+        let session = match session {
+            Some(a) => a.clone(),
+            None => self.inside_sync.read().default_session.clone(),
+        };
+
+        // Build the command object
+        let mut dio = self.dio(&session).await;
+        dio.auto_cancel();
+        let mut cmd = dio.make_ext(request, session.log_format, None)?;
+        
+        // Add an encryption key on the command (if the session has one)
+        if let Some(key) = session.read_keys().into_iter().next() {
+            cmd.auth_mut().read = ReadOption::from_key(key)?;
+        }
+
+        // Add the extra metadata about the type so the other side can find it
+        cmd.add_extra_metadata(CoreMetadata::Type(MetaType {
+            type_name: std::any::type_name::<REQ>().to_string()
+        }));
+        let cmd = cmd.commit(&mut dio)?;
+        let cmd_id = cmd.key().clone();
+
+        // Sniff out all the frames that are sent in reply before we send the command
+        let frame_type_name = std::any::type_name::<ServiceStreamFrame<RES, ERR>>().to_string();
+        let (sniffer, replies) = sniff_for_replies(&self, cmd_id, frame_type_name, 32);
+
+        // Send our command
+        dio.commit().await?;
+        drop(dio);
+
+        let finished = Arc::new(AtomicBool::new(false));
+        let cancel = InvokeStreamCancel {
+            chain: Arc::downgrade(&self),
+            session: session.clone(),
+            cmd: cmd_id,
+            finished: Arc::clone(&finished),
+        };
+        let state = InvokeStreamState {
+            chain: self,
+            session,
+            replies,
+            _sniffer: sniffer,
+            pending: BTreeMap::new(),
+            next: 0,
+            timeout,
+            done: false,
+            finished,
+            received: Vec::new(),
+        };
+
+        Ok(
+            InvokeStream {
+                inner: stream::unfold(state, InvokeStreamState::next_item).boxed(),
+                _cancel: cancel,
+            }
+        )
+    }
+
+    #[allow(dead_code)]
+    pub fn add_streaming_service<REQ, RES, ERR>(self: &Arc<Self>, session: AteSession, handler: StreamingServiceInstance<REQ, RES, ERR>)
+    where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+          RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+          ERR: std::fmt::Debug + Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+    {
+        // (the hook registers its own sniffer so it is created before the lock is taken)
+        let hook = Arc::new(StreamingServiceHook::new(
+            self,
+            session,
+            Arc::clone(&handler),
+        ));
+        let mut guard = self.inside_sync.write();
+        guard.services.push(hook);
+    }
+}
+
+/// Responses of a streaming service that are returned by `Chain::invoke_stream`
+pub struct InvokeStream<RES, ERR>
+{
+    inner: BoxStream<'static, Result<RES, InvokeError<ERR>>>,
+    _cancel: InvokeStreamCancel,
+}
+
+impl<RES, ERR> Stream
+for InvokeStream<RES, ERR>
+{
+    type Item = Result<RES, InvokeError<ERR>>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.inner.poll_next_unpin(cx)
+    }
+}
+
+/// Tells the service to stop if the caller drops the stream before it ends
+struct InvokeStreamCancel
+{
+    chain: Weak<Chain>,
+    session: AteSession,
+    cmd: PrimaryKey,
+    finished: Arc<AtomicBool>,
+}
+
+impl Drop
+for InvokeStreamCancel
+{
+    fn drop(&mut self)
+    {
+        if self.finished.load(Ordering::Acquire) {
+            return;
+        }
+        let chain = match self.chain.upgrade() {
+            Some(a) => a,
+            None => { return; }
+        };
+        let session = self.session.clone();
+        let cmd = self.cmd.clone();
+        let cancel_type_name = std::any::type_name::<ServiceStreamCancel>().to_string();
+        tokio::spawn(async move {
+            if let Err(err) = send_reply(&chain, &session, cmd, ServiceStreamCancel::default(), cancel_type_name).await {
+                debug!("stream-cancel-err - {}", err);
+            }
+        });
+    }
+}
+
+struct InvokeStreamState<RES, ERR>
+where RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+{
+    chain: Arc<Chain>,
+    session: AteSession,
+    replies: mpsc::Receiver<PrimaryKey>,
+    _sniffer: ChainSnifferGuard,
+    /// Frames that arrived ahead of the ones before them
+    pending: BTreeMap<u64, ServiceStreamBody<RES, ERR>>,
+    next: u64,
+    timeout: Duration,
+    done: bool,
+    /// Set when the service has sent its last frame
+    finished: Arc<AtomicBool>,
+    /// Frames that were received, they are deleted once the stream is over
+    received: Vec<PrimaryKey>,
+}
+
+impl<RES, ERR> InvokeStreamState<RES, ERR>
+where RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+{
+    /// Deletes the frames that were received now that the stream is over
+    async fn finish(&mut self)
+    {
+        let frames = std::mem::take(&mut self.received);
+        if let Err(err) = delete_frames::<RES, ERR>(&self.chain, &self.session, frames).await {
+            debug!("stream-cleanup-err - {}", err);
+        }
+    }
+
+    async fn next_item(mut self) -> Option<(Result<RES, InvokeError<ERR>>, Self)>
+    {
+        if self.done {
+            return None;
+        }
+        loop {
+            // Return the frames in the order they were sent
+            if let Some(body) = self.pending.remove(&self.next) {
+                self.next = self.next + 1;
+                let ret = match body {
+                    ServiceStreamBody::Item(res) => Ok(res),
+                    ServiceStreamBody::Error(ServiceErrorReply::Reply(e)) => Err(InvokeError::Reply(e)),
+                    ServiceStreamBody::Error(ServiceErrorReply::ServiceError(err)) => Err(InvokeError::ServiceError(err)),
+                    ServiceStreamBody::End => {
+                        self.finished.store(true, Ordering::Release);
+                        self.finish().await;
+                        return None;
+                    }
+                };
+                if ret.is_err() {
+                    self.finished.store(true, Ordering::Release);
+                    self.done = true;
+                    self.finish().await;
+                }
+                return Some((ret, self));
+            }
+
+            // Wait for the next frame to arrive
+            let key = match tokio::time::timeout(self.timeout, self.replies.recv()).await {
+                Ok(Some(a)) => a,
+                Ok(None) => {
+                    self.done = true;
+                    self.finish().await;
+                    return Some((Err(InvokeError::Aborted), self));
+                },
+                Err(_) => {
+                    self.done = true;
+                    self.finish().await;
+                    return Some((Err(InvokeError::Timeout), self));
+                }
+            };
+            let frame = {
+                let mut dio = self.chain.dio(&self.session).await;
+                dio.load::<ServiceStreamFrame<RES, ERR>>(&key).await.map(|a| a.take())
+            };
+            match frame {
+                Ok(frame) => {
+                    self.received.push(key);
+                    self.pending.insert(frame.seq, frame.body);
+                },
+                Err(err) => {
+                    self.done = true;
+                    self.finish().await;
+                    return Some((Err(InvokeError::LoadError(err)), self));
+                }
+            }
+        }
+    }
+}
+
+impl<RES, ERR> Drop
+for InvokeStreamState<RES, ERR>
+where RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+{
+    fn drop(&mut self)
+    {
+        // The frames of a stream that was dropped before it ended are deleted in the background
+        // (including those that arrived but were not read yet)
+        let mut frames = std::mem::take(&mut self.received);
+        while let Some(Some(key)) = self.replies.recv().now_or_never() {
+            frames.push(key);
+        }
+        if frames.is_empty() {
+            return;
+        }
+        let chain = Arc::clone(&self.chain);
+        let session = self.session.clone();
+        tokio::spawn(async move {
+            if let Err(err) = delete_frames::<RES, ERR>(&chain, &session, frames).await {
+                debug!("stream-cleanup-err - {}", err);
+            }
+        });
+    }
+}
+
+pub(super) async fn delete_frames<RES, ERR>(chain: &Arc<Chain>, session: &AteSession, frames: Vec<PrimaryKey>) -> Result<(), AteError>
+where RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send,
+{
+    if frames.is_empty() {
+        return Ok(());
+    }
+    let mut dio = chain.dio(session).await;
+    for key in frames {
+        // (the caller and the service both clean up after a cancel so some may be gone)
+        if dio.exists(&key).await {
+            dio.delete::<ServiceStreamFrame<RES, ERR>>(&key).await?;
+        }
+    }
+    dio.commit().await?;
+    Ok(())
+}
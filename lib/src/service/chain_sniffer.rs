@@ -10,7 +10,8 @@ use super::*;
 pub(crate) struct ChainSniffer
 {
     pub(crate) id: u64,
-    pub(crate) filter: Box<dyn Fn(&EventData) -> bool + Send + Sync>,
+    /// Selects the events that are sniffed and the key that is sent for each of them
+    pub(crate) filter: Box<dyn Fn(&EventData) -> Option<PrimaryKey> + Send + Sync>,
     pub(crate) notify: mpsc::Sender<PrimaryKey>,
 }
 
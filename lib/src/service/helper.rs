@@ -1,5 +1,6 @@
 #[allow(unused_imports)]
 use log::{info, error, warn, debug};
+use serde::{Serialize, de::DeserializeOwned};
 use std::{sync::Weak};
 use tokio::sync::mpsc;
 use std::sync::Arc;
@@ -7,7 +8,11 @@ use parking_lot::RwLockReadGuard as StdRwLockReadGuard;
 
 use crate::{error::*, event::*};
 use crate::chain::*;
+use crate::dio::*;
 use crate::header::*;
+use crate::meta::*;
+use crate::session::*;
+use crate::crypto::AteHash;
 
 use super::*;
 
@@ -16,10 +21,7 @@ pub(crate) fn callback_events_prepare(guard: &StdRwLockReadGuard<ChainProtectedS
     let mut ret = Vec::new();
     
     for sniffer in guard.sniffers.iter() {
-        if let Some(key) = events.iter().filter_map(|e| match (*sniffer.filter)(e) {
-            true => e.meta.get_data_key(),
-            false => None,
-        }).next() {
+        if let Some(key) = events.iter().filter_map(|e| (*sniffer.filter)(e)).next() {
             ret.push(sniffer.convert(key));
         }
     }
@@ -44,34 +46,117 @@ pub(crate) async fn callback_events_notify(mut notifies: Vec<Notify>) -> Result<
     Ok(())
 }
 
-pub(super) async fn sniff_for_command(chain: Weak<Chain>, what: Box<dyn Fn(&EventData) -> bool + Send + Sync>) -> Option<PrimaryKey>
+/// Sniffer that keeps receiving the replies to a command until it is dropped
+pub(super) struct ChainSnifferGuard
+{
+    chain: Weak<Chain>,
+    id: u64,
+}
+
+impl Drop
+for ChainSnifferGuard
+{
+    fn drop(&mut self)
+    {
+        if let Some(chain) = self.chain.upgrade() {
+            let mut guard = chain.inside_sync.write();
+            guard.sniffers.retain(|s| s.id != self.id);
+        }
+    }
+}
+
+pub(super) fn sniff_for_replies(chain: &Arc<Chain>, cmd: PrimaryKey, type_name: String, buffer: usize) -> (ChainSnifferGuard, mpsc::Receiver<PrimaryKey>)
+{
+    sniff(chain, buffer, Box::new(move |h| {
+        if h.meta.is_reply_to_what() != Some(cmd) {
+            return None;
+        }
+        match h.meta.get_type_name() {
+            Some(t) if t.type_name == type_name => h.meta.get_data_key(),
+            _ => None,
+        }
+    }))
+}
+
+/// Sniffs the replies of a particular type to any command, the key of the command
+/// that was replied to is sent rather than the key of the reply
+pub(super) fn sniff_for_any_reply(chain: &Arc<Chain>, type_name: String, buffer: usize) -> (ChainSnifferGuard, mpsc::Receiver<PrimaryKey>)
+{
+    sniff(chain, buffer, Box::new(move |h| {
+        match h.meta.get_type_name() {
+            Some(t) if t.type_name == type_name => h.meta.is_reply_to_what(),
+            _ => None,
+        }
+    }))
+}
+
+fn sniff(chain: &Arc<Chain>, buffer: usize, filter: Box<dyn Fn(&EventData) -> Option<PrimaryKey> + Send + Sync>) -> (ChainSnifferGuard, mpsc::Receiver<PrimaryKey>)
 {
-    // Create a sniffer
     let id = fastrand::u64(..);
-    let (tx, mut rx) = mpsc::channel(1);
+    let (tx, rx) = mpsc::channel(buffer);
     let sniffer = ChainSniffer {
         id,
-        filter: what,
+        filter,
         notify: tx,
     };
 
-    // Insert a sniffer under a lock
-    if let Some(chain) = chain.upgrade() {
-        let mut guard = chain.inside_sync.write();
-        guard.sniffers.push(sniffer);
-    } else {
-        return None;
-    }
+    let mut guard = chain.inside_sync.write();
+    guard.sniffers.push(sniffer);
+
+    (
+        ChainSnifferGuard {
+            chain: Arc::downgrade(chain),
+            id,
+        },
+        rx
+    )
+}
+
+pub(super) async fn send_reply<T>(chain: &Arc<Chain>, session: &AteSession, req: PrimaryKey, res: T, res_type: String) -> Result<(), ServiceError<()>>
+where T: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized
+{
+    send_reply_ext(chain, session, req, res, res_type).await?;
+    Ok(())
+}
 
-    // Now wait for the response
-    let ret = rx.recv().await;
+/// Sends a reply and returns the key of the data object that holds it
+pub(super) async fn send_reply_ext<T>(chain: &Arc<Chain>, session: &AteSession, req: PrimaryKey, res: T, res_type: String) -> Result<PrimaryKey, ServiceError<()>>
+where T: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized
+{
+    // Turn it into a data object to be stored on commit
+    let mut dio = chain.dio(session).await;
+    dio.auto_cancel();
+    let mut res = dio.make_ext(res, session.log_format.clone(), None)?;
 
-    // Remove the sniffer
-    if let Some(chain) = chain.upgrade() {
-        let mut guard = chain.inside_sync.write();
-        guard.sniffers.retain(|s| s.id != id);
+    // If the session has an encryption key then use it
+    if let Some(key) = session.read_keys().into_iter().map(|a| a.clone()).next() {
+        res.auth_mut().read = ReadOption::from_key(&key)?;
     }
 
-    // Return the result
-    ret
-}
\ No newline at end of file
+    // Add the metadata
+    res.add_extra_metadata(CoreMetadata::Type(MetaType {
+        type_name: res_type
+    }));
+    res.add_extra_metadata(CoreMetadata::Reply(req));
+    
+    // Commit the transaction
+    let res = res.commit(&mut dio)?;
+    dio.commit().await?;
+    Ok(res.key().clone())
+}
+
+/// Loads the metadata that was attached to a request and the keys that signed it (the
+/// signatures are looked up by the hash of the request event in the index)
+pub(super) async fn request_metadata(chain: &Arc<Chain>, key: &PrimaryKey) -> Result<(Metadata, Vec<AteHash>), ServiceError<()>>
+{
+    let multi = chain.multi().await;
+    let leaf = match multi.lookup_primary(key).await {
+        Some(a) => a,
+        None => { return Err(ServiceError::LoadError(LoadError::NotFound(key.clone()))); }
+    };
+    let evt = multi.load(leaf).await?;
+    let header = evt.header.as_header()?;
+    let signed_by = multi.lookup_signed_by(&leaf.record).await;
+
+    Ok((header.meta, signed_by))
+}
@@ -0,0 +1,240 @@
+#[allow(unused_imports)]
+use log::{info, error, warn, debug};
+use fxhash::FxHashMap;
+use parking_lot::Mutex as StdMutex;
+use serde::{Serialize, Deserialize};
+use tokio::sync::watch;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::crypto::AteHash;
+use crate::chain::*;
+use crate::dio::*;
+use crate::error::*;
+use crate::header::*;
+use crate::meta::*;
+use crate::session::*;
+
+/// Most replies that are kept in memory, older ones are read back from the chain
+const MAX_CACHED_REPLIES: usize = 1024;
+
+/// Reply that was sent back for a command, it is kept in its serialized form so that
+/// it can be sent again to duplicates of the command
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum IdempotentReply
+{
+    Response(Vec<u8>),
+    Error(Vec<u8>),
+}
+
+/// Reply that is remembered in the chain so that duplicates are still recognised after
+/// the service restarts (or when another node picks up the duplicate)
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct IdempotentRecord
+{
+    reply: IdempotentReply,
+    /// When the reply was sent (milliseconds since the epoch)
+    completed: u64,
+}
+
+/// Idempotency keys are scoped to whoever signed the command so that callers can
+/// not collect the responses that were meant for someone else
+type IdempotentKey = (Option<AteHash>, String);
+
+struct IdempotentEntry
+{
+    at: Instant,
+    reply: watch::Receiver<Option<IdempotentReply>>,
+}
+
+pub(crate) enum IdempotentClaim
+{
+    /// The key has not been seen before so the command must be processed and its
+    /// reply completed on the guard
+    Fresh(IdempotentGuard),
+    /// The key was already claimed by another command whose reply will (or already
+    /// has) been published on this channel
+    Duplicate(watch::Receiver<Option<IdempotentReply>>),
+}
+
+/// Remembers the replies to the commands that carried an idempotency key
+#[derive(Default)]
+pub(crate) struct IdempotencyCache
+{
+    entries: Arc<StdMutex<FxHashMap<IdempotentKey, IdempotentEntry>>>,
+    /// When the expired replies were last deleted from the chain
+    swept: StdMutex<Option<Instant>>,
+}
+
+impl IdempotencyCache
+{
+    /// Returns true (at most once per window) when the replies that are remembered in
+    /// the chain should be checked for any that have expired
+    pub(crate) fn sweep_due(&self, window: Duration) -> bool
+    {
+        let now = Instant::now();
+        let mut swept = self.swept.lock();
+        match *swept {
+            Some(a) if now.duration_since(a) < window => false,
+            _ => {
+                *swept = Some(now);
+                true
+            }
+        }
+    }
+
+    pub(crate) fn claim(&self, signer: Option<AteHash>, key: String, window: Duration) -> IdempotentClaim
+    {
+        let now = Instant::now();
+        let mut guard = self.entries.lock();
+
+        // Forget the replies that are older than the window and then the oldest ones
+        // once there are too many (commands that are still being processed are never
+        // forgotten)
+        guard.retain(|_, a| a.reply.borrow().is_none() || now.duration_since(a.at) < window);
+        if guard.len() >= MAX_CACHED_REPLIES {
+            let mut completed = guard.iter()
+                .filter(|(_, a)| a.reply.borrow().is_some())
+                .map(|(k, a)| (a.at, k.clone()))
+                .collect::<Vec<_>>();
+            completed.sort_by_key(|a| a.0);
+            let excess = (guard.len() + 1).saturating_sub(MAX_CACHED_REPLIES);
+            for (_, key) in completed.into_iter().take(excess) {
+                guard.remove(&key);
+            }
+        }
+
+        let key = (signer, key);
+        if let Some(entry) = guard.get(&key) {
+            return IdempotentClaim::Duplicate(entry.reply.clone());
+        }
+
+        let (tx, rx) = watch::channel(None);
+        guard.insert(key.clone(), IdempotentEntry {
+            at: now,
+            reply: rx,
+        });
+        IdempotentClaim::Fresh(IdempotentGuard {
+            entries: Arc::clone(&self.entries),
+            key,
+            tx: Some(tx),
+        })
+    }
+}
+
+/// Claim on an idempotency key, if it is dropped before a reply is completed then
+/// the key is released so that a later retry will be processed again
+pub(crate) struct IdempotentGuard
+{
+    entries: Arc<StdMutex<FxHashMap<IdempotentKey, IdempotentEntry>>>,
+    key: IdempotentKey,
+    tx: Option<watch::Sender<Option<IdempotentReply>>>,
+}
+
+impl IdempotentGuard
+{
+    pub(crate) fn complete(mut self, reply: IdempotentReply)
+    {
+        if let Some(entry) = self.entries.lock().get_mut(&self.key) {
+            entry.at = Instant::now();
+        }
+        if let Some(tx) = self.tx.take() {
+            let _ = tx.send(Some(reply));
+        }
+    }
+}
+
+impl Drop
+for IdempotentGuard
+{
+    fn drop(&mut self)
+    {
+        if self.tx.is_some() {
+            self.entries.lock().remove(&self.key);
+        }
+    }
+}
+
+/// Key of the data object that remembers the reply for an idempotency key
+pub(crate) fn idempotent_record_key(request_type_name: &str, signer: Option<&AteHash>, key: &str) -> PrimaryKey
+{
+    let mut scope = request_type_name.as_bytes().to_vec();
+    if let Some(signer) = signer {
+        scope.extend_from_slice(&signer.val[..]);
+    }
+    PrimaryKey::from(AteHash::from_bytes_twice(&scope[..], key.as_bytes()))
+}
+
+/// Reads back a reply that was remembered in the chain (if it is still within the window)
+pub(crate) async fn load_idempotent_reply(chain: &Arc<Chain>, session: &AteSession, record: &PrimaryKey, window: Duration) -> Result<Option<IdempotentReply>, ServiceError<()>>
+{
+    let mut dio = chain.dio(session).await;
+    let record = match dio.load::<IdempotentRecord>(record).await {
+        Ok(a) => a.take(),
+        Err(LoadError::NotFound(_)) => { return Ok(None); },
+        Err(err) => { return Err(err.into()); }
+    };
+    let now = chain.time.current_timestamp().map_err(CommitError::from)?.time_since_epoch_ms;
+    if now.saturating_sub(record.completed) >= window.as_millis() as u64 {
+        return Ok(None);
+    }
+    Ok(Some(record.reply))
+}
+
+/// Remembers a reply in the chain (replacing anything that was remembered for the same key)
+pub(crate) async fn store_idempotent_reply(chain: &Arc<Chain>, session: &AteSession, record: PrimaryKey, reply: IdempotentReply) -> Result<(), ServiceError<()>>
+{
+    let mut dio = chain.dio(session).await;
+    dio.auto_cancel();
+    let completed = chain.time.current_timestamp().map_err(CommitError::from)?.time_since_epoch_ms;
+    let mut record = dio.make_ext(IdempotentRecord { reply, completed }, session.log_format, Some(record))?;
+    record.add_extra_metadata(CoreMetadata::Type(MetaType {
+        type_name: std::any::type_name::<IdempotentRecord>().to_string()
+    }));
+
+    // The replies are only readable by the service (if the session has an encryption key)
+    if let Some(key) = session.read_keys().into_iter().map(|a| a.clone()).next() {
+        record.auth_mut().read = ReadOption::from_key(&key)?;
+    }
+    record.commit(&mut dio)?;
+    dio.commit().await?;
+    Ok(())
+}
+
+/// Deletes the replies that are remembered in the chain once they are older than the
+/// window (duplicates that arrive later are processed again anyway)
+pub(crate) async fn sweep_idempotent_replies(chain: &Arc<Chain>, session: &AteSession, window: Duration) -> Result<usize, ServiceError<()>>
+{
+    let keys = chain.multi().await.lookup_type(std::any::type_name::<IdempotentRecord>()).await;
+    let now = chain.time.current_timestamp().map_err(CommitError::from)?.time_since_epoch_ms;
+
+    let mut dio = chain.dio(session).await;
+    let mut expired = 0usize;
+    for key in keys {
+        let record = match dio.load::<IdempotentRecord>(&key).await {
+            Ok(a) => a,
+            Err(LoadError::NotFound(_)) => { continue; },
+            Err(err) => { return Err(err.into()); }
+        };
+        if now.saturating_sub(record.completed) >= window.as_millis() as u64 {
+            dio.delete::<IdempotentRecord>(&key).await?;
+            expired = expired + 1;
+        }
+    }
+    dio.commit().await?;
+    Ok(expired)
+}
+
+/// Waits for the reply of a command that claimed the same idempotency key, if that
+/// command was abandoned without a reply then nothing is returned
+pub(crate) async fn wait_for_idempotent_reply(mut reply: watch::Receiver<Option<IdempotentReply>>) -> Option<IdempotentReply>
+{
+    loop {
+        if let Some(a) = reply.borrow().clone() {
+            return Some(a);
+        }
+        if reply.changed().await.is_err() {
+            return reply.borrow().clone();
+        }
+    }
+}
@@ -4,9 +4,19 @@ use std::sync::Arc;
 
 use crate::session::*;
 use crate::repository::*;
+use crate::meta::*;
+use crate::header::*;
+use crate::crypto::AteHash;
 
+#[derive(Clone)]
 pub struct InvocationContext<'a>
 {
     pub session: &'a AteSession,
-    pub repository: Arc<dyn ChainRepository>
-}
\ No newline at end of file
+    pub repository: Arc<dyn ChainRepository>,
+    /// Primary key of the request that is being processed
+    pub request: PrimaryKey,
+    /// Metadata that was attached to the request when it was written
+    pub meta: Metadata,
+    /// Hashes of the public keys that signed the request (if any)
+    pub signed_by: Vec<AteHash>,
+}
@@ -0,0 +1,130 @@
+#[allow(unused_imports)]
+use log::{info, error, warn, debug};
+use async_trait::async_trait;
+use serde::{Serialize, de::DeserializeOwned};
+use fxhash::FxHashMap;
+use parking_lot::Mutex as StdMutex;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::error::*;
+use crate::crypto::AteHash;
+use crate::session::*;
+
+use super::*;
+
+pub type ServiceMiddlewareInstance<REQ, RES, ERR> = Arc<dyn ServiceMiddleware<REQ, RES, ERR> + Send + Sync>;
+
+/// Interceptor that runs around the handler of a service (see `Chain::add_service_with`).
+///
+/// The middleware runs in the order it was added before the handler is invoked,
+/// any of them can reject the request by returning an error (which is sent back
+/// to the caller instead of invoking the handler) or replace the request with
+/// another one. Once a response is ready it is shown to the middleware that
+/// accepted the request in the reverse order.
+#[async_trait]
+pub trait ServiceMiddleware<REQ, RES, ERR>
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send
+{
+    async fn before<'a>(&self, request: REQ, _context: &InvocationContext<'a>) -> Result<REQ, ServiceError<ERR>>
+    where REQ: 'async_trait
+    {
+        Ok(request)
+    }
+
+    async fn after<'a>(&self, _response: &Result<RES, ServiceError<ERR>>, _context: &InvocationContext<'a>) {
+    }
+}
+
+/// Only accepts requests that were signed with one of the write keys of a particular
+/// role within a group, the keys are taken from the session that the service runs under
+pub struct RequireRoleMiddleware
+{
+    group: String,
+    purpose: AteRolePurpose,
+}
+
+impl RequireRoleMiddleware
+{
+    pub fn new(group: &str, purpose: AteRolePurpose) -> RequireRoleMiddleware {
+        RequireRoleMiddleware {
+            group: group.to_string(),
+            purpose,
+        }
+    }
+}
+
+#[async_trait]
+impl<REQ, RES, ERR> ServiceMiddleware<REQ, RES, ERR>
+for RequireRoleMiddleware
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static
+{
+    async fn before<'a>(&self, request: REQ, context: &InvocationContext<'a>) -> Result<REQ, ServiceError<ERR>>
+    where REQ: 'async_trait
+    {
+        let role = match context.session.get_group_role(&self.group, &self.purpose) {
+            Some(a) => a,
+            None => { return Err(ServiceError::Denied(format!("the service does not know the role ({}) in group ({})", self.purpose, self.group))); }
+        };
+        let allowed = role.write_keys()
+            .map(|a| a.hash())
+            .any(|a| context.signed_by.contains(&a));
+        if allowed == false {
+            return Err(ServiceError::Denied(format!("the request was not signed by the role ({}) in group ({})", self.purpose, self.group)));
+        }
+        Ok(request)
+    }
+}
+
+/// Limits how many requests each caller may make within a window of time, callers
+/// are identified by the key that signed their request.
+///
+/// Requests that are not signed can not be told apart (nothing else in a request
+/// proves who sent it) hence all the unsigned callers share a single allowance and
+/// one busy caller will use it up for all of them. Services that must limit each
+/// caller separately should require signed requests (e.g. with `RequireRoleMiddleware`)
+pub struct RateLimitMiddleware
+{
+    max_requests: u32,
+    window: Duration,
+    callers: StdMutex<FxHashMap<Option<AteHash>, (Instant, u32)>>,
+}
+
+impl RateLimitMiddleware
+{
+    pub fn new(max_requests: u32, window: Duration) -> RateLimitMiddleware {
+        RateLimitMiddleware {
+            max_requests,
+            window,
+            callers: StdMutex::new(FxHashMap::default()),
+        }
+    }
+}
+
+#[async_trait]
+impl<REQ, RES, ERR> ServiceMiddleware<REQ, RES, ERR>
+for RateLimitMiddleware
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send + 'static,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + 'static
+{
+    async fn before<'a>(&self, request: REQ, context: &InvocationContext<'a>) -> Result<REQ, ServiceError<ERR>>
+    where REQ: 'async_trait
+    {
+        let caller = context.signed_by.first().map(|a| a.clone());
+        let now = Instant::now();
+
+        let mut lock = self.callers.lock();
+        lock.retain(|_, (start, _)| now.duration_since(*start) < self.window);
+        let (_, count) = lock.entry(caller).or_insert((now, 0));
+        if *count >= self.max_requests {
+            return Err(ServiceError::RateLimited);
+        }
+        *count = *count + 1;
+        Ok(request)
+    }
+}
@@ -1,19 +1,29 @@
 pub mod chain_invoke;
+pub mod chain_invoke_stream;
 pub mod chain_sniffer;
 pub mod helper;
+pub mod idempotency;
 pub mod invocation_context;
+pub mod middleware;
 pub mod notify;
 pub mod service_handler;
 pub mod service_hook;
+pub mod streaming_service_handler;
+pub mod streaming_service_hook;
 pub mod service;
 pub mod tests;
 
 pub(crate) use chain_sniffer::*;
 pub(crate) use notify::*;
 pub(crate) use service_hook::*;
+pub(crate) use streaming_service_hook::*;
 pub(crate) use helper::*;
+pub(crate) use idempotency::*;
 
 pub use chain_invoke::*;
+pub use chain_invoke_stream::*;
 pub use invocation_context::*;
+pub use middleware::*;
 pub use service_handler::*;
+pub use streaming_service_handler::*;
 pub use service::*;
\ No newline at end of file
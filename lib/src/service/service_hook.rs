@@ -5,12 +5,11 @@ use serde::{Serialize, de::DeserializeOwned};
 use std::{sync::Weak};
 use std::sync::Arc;
 
-use crate::{error::*, event::*, meta::{CoreMetadata}};
-use crate::dio::*;
+use crate::{error::*, event::*};
 use crate::chain::*;
 use crate::session::*;
-use crate::meta::*;
 use crate::header::*;
+use crate::spec::SerializationFormat;
 
 use super::*;
 
@@ -22,6 +21,8 @@ where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
     chain: Weak<Chain>,
     session: AteSession,
     handler: ServiceInstance<REQ, RES, ERR>,
+    middleware: Vec<ServiceMiddlewareInstance<REQ, RES, ERR>>,
+    completed: IdempotencyCache,
     request_type_name: String,
     response_type_name: String,
     error_type_name: String,
@@ -32,11 +33,13 @@ where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
       RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
       ERR: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized
 {
-    pub(crate) fn new(chain: &Arc<Chain>, session: AteSession, handler: ServiceInstance<REQ, RES, ERR>) -> ServiceHook<REQ, RES, ERR> {
+    pub(crate) fn new(chain: &Arc<Chain>, session: AteSession, handler: ServiceInstance<REQ, RES, ERR>, middleware: Vec<ServiceMiddlewareInstance<REQ, RES, ERR>>) -> ServiceHook<REQ, RES, ERR> {
         ServiceHook {
             chain: Arc::downgrade(chain),
             session: session.clone(),
             handler: Arc::clone(&handler),
+            middleware,
+            completed: IdempotencyCache::default(),
             request_type_name: std::any::type_name::<REQ>().to_string(),
             response_type_name: std::any::type_name::<RES>().to_string(),
             error_type_name: std::any::type_name::<ServiceErrorReply<ERR>>().to_string(),
@@ -77,11 +80,12 @@ where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
             }
         };
 
-        let ret = {
+        let (ret, claim) = {
             // Load the object
             let mut dio = chain.dio(&self.session).await;
             dio.auto_cancel();
             let mut req = dio.load::<REQ>(&key).await?;
+            let (meta, signed_by) = request_metadata(&chain, &key).await?;
 
             // Attempt to lock (later delete) the request - if that fails then someone else
             // has likely picked this up and will process it instead
@@ -90,29 +94,91 @@ where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
                 return Ok(())
             }
 
+            // If the command was already processed (or is being processed) under the same
+            // idempotency key then the caller gets the same reply again
+            let mut claim = None;
+            if let Some(idempotency_key) = meta.get_idempotency_key() {
+                let window = chain.inside_sync.read().idempotency_window;
+                if self.completed.sweep_due(window) {
+                    let chain = Arc::clone(&chain);
+                    let session = self.session.clone();
+                    let request_type_name = self.request_type_name.clone();
+                    tokio::spawn(async move {
+                        match sweep_idempotent_replies(&chain, &session, window).await {
+                            Ok(a) if a > 0 => debug!("service [{}] forgot {} expired replies", request_type_name, a),
+                            Ok(_) => { },
+                            Err(err) => debug!("service [{}] failed to forget the expired replies: {}", request_type_name, err),
+                        }
+                    });
+                }
+                let signer = signed_by.first().map(|a| a.clone());
+                match self.completed.claim(signer.clone(), idempotency_key.clone(), window) {
+                    IdempotentClaim::Fresh(guard) => {
+                        // (the reply may be remembered in the chain from before this service started)
+                        let record = idempotent_record_key(self.request_type_name.as_str(), signer.as_ref(), idempotency_key.as_str());
+                        if let Some(reply) = load_idempotent_reply(&chain, &self.session, &record, window).await? {
+                            debug!("service [{}] duplicate of remembered idempotency key ({})", self.request_type_name, idempotency_key);
+                            guard.complete(reply.clone());
+                            req.commit(&mut dio)?;
+                            dio.commit().await?;
+                            return self.resend(&chain, key, reply).await;
+                        }
+                        claim = Some((guard, record));
+                    },
+                    IdempotentClaim::Duplicate(reply) => {
+                        // (if the other command was abandoned without a reply then this one is processed instead)
+                        if let Some(reply) = wait_for_idempotent_reply(reply).await {
+                            debug!("service [{}] duplicate of idempotency key ({})", self.request_type_name, idempotency_key);
+                            req.commit(&mut dio)?;
+                            dio.commit().await?;
+                            return self.resend(&chain, key, reply).await;
+                        }
+                    }
+                }
+            }
+
             // Create the context
             let context = InvocationContext
             {
                 session: &self.session,
                 repository: repo,
+                request: key.clone(),
+                meta,
+                signed_by,
             };
 
             // Invoke the callback in the service
             req.commit(&mut dio)?;
-            let ret = self.handler.process(req.take(), context).await;
+            let ret = self.process(req.take(), context).await;
             dio.commit().await?;
-            ret
+            (ret, claim)
         };
 
         let request_type_name = std::any::type_name::<REQ>().to_string();
+        let format = SerializationFormat::Json;
         match ret {
             Ok(res) => {
                 debug!("service [{}] ok", request_type_name);
-                self.send_reply(chain, key, res, self.response_type_name.clone()).await
+                if let Some((claim, record)) = claim {
+                    let reply = IdempotentReply::Response(format.serialize(&res)?);
+                    claim.complete(reply.clone());
+                    if let Err(err) = store_idempotent_reply(&chain, &self.session, record, reply).await {
+                        debug!("service [{}] failed to remember the reply: {}", request_type_name, err);
+                    }
+                }
+                send_reply(&chain, &self.session, key, res, self.response_type_name.clone()).await
             },
             Err(err) => {
                 let (reply, err) = err.as_reply();
-                let _ = self.send_reply(chain, key, reply, self.error_type_name.clone()).await;
+                // (only the errors raised by the handler itself are remembered, others may go away on a retry)
+                if let (Some((claim, record)), ServiceErrorReply::Reply(_)) = (claim, &reply) {
+                    let remembered = IdempotentReply::Error(format.serialize(&reply)?);
+                    claim.complete(remembered.clone());
+                    if let Err(err) = store_idempotent_reply(&chain, &self.session, record, remembered).await {
+                        debug!("service [{}] failed to remember the reply: {}", request_type_name, err);
+                    }
+                }
+                let _ = send_reply(&chain, &self.session, key, reply, self.error_type_name.clone()).await;
                 debug!("service [{}] error: {}", request_type_name, err);
                 return Err(err);
             }
@@ -125,28 +191,46 @@ where REQ: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
       RES: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized,
       ERR: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized
 {
-    async fn send_reply<T>(&self, chain: Arc<Chain>, req: PrimaryKey, res: T, res_type: String) -> Result<(), ServiceError<()>>
-    where T: Serialize + DeserializeOwned + Clone + Sync + Send + ?Sized
+    /// Passes the request through the middleware on its way to the handler and the
+    /// response back through the middleware that accepted it
+    async fn process<'a>(&self, request: REQ, context: InvocationContext<'a>) -> Result<RES, ServiceError<ERR>>
     {
-        // Turn it into a data object to be stored on commit
-        let mut dio = chain.dio(&self.session).await;
-        dio.auto_cancel();
-        let mut res = dio.make_ext(res, self.session.log_format.clone(), None)?;
-
-        // If the session has an encryption key then use it
-        if let Some(key) = self.session.read_keys().into_iter().map(|a| a.clone()).next() {
-            res.auth_mut().read = ReadOption::from_key(&key)?;
+        let mut accepted = 0usize;
+        let mut request = Ok(request);
+        for middleware in self.middleware.iter() {
+            request = match request {
+                Ok(a) => middleware.before(a, &context).await,
+                Err(_) => break,
+            };
+            if request.is_ok() {
+                accepted = accepted + 1;
+            }
+        }
+
+        let ret = match request {
+            Ok(a) => self.handler.process(a, context.clone()).await,
+            Err(err) => Err(err),
+        };
+
+        for middleware in self.middleware[..accepted].iter().rev() {
+            middleware.after(&ret, &context).await;
         }
+        ret
+    }
 
-        // Add the metadata
-        res.add_extra_metadata(CoreMetadata::Type(MetaType {
-            type_name: res_type
-        }));
-        res.add_extra_metadata(CoreMetadata::Reply(req));
-        
-        // Commit the transaction
-        res.commit(&mut dio)?;
-        dio.commit().await?;
-        Ok(())
+    /// Sends a reply that was remembered for an idempotency key to another request
+    async fn resend(&self, chain: &Arc<Chain>, key: PrimaryKey, reply: IdempotentReply) -> Result<(), ServiceError<()>>
+    {
+        let format = SerializationFormat::Json;
+        match reply {
+            IdempotentReply::Response(data) => {
+                let res: RES = format.deserialize(&data[..])?;
+                send_reply(chain, &self.session, key, res, self.response_type_name.clone()).await
+            },
+            IdempotentReply::Error(data) => {
+                let reply: ServiceErrorReply<ERR> = format.deserialize(&data[..])?;
+                send_reply(chain, &self.session, key, reply, self.error_type_name.clone()).await
+            }
+        }
     }
-}
\ No newline at end of file
+}
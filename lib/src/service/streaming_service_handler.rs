@@ -0,0 +1,48 @@
+#[allow(unused_imports)]
+use log::{info, error, warn, debug};
+use futures::stream::BoxStream;
+use serde::{Serialize, Deserialize, de::DeserializeOwned};
+use std::sync::Arc;
+
+use crate::{error::*};
+
+use super::*;
+
+pub type StreamingServiceInstance<REQ, RES, ERR> = Arc<dyn StreamingServiceHandler<REQ, RES, ERR> + Send + Sync>;
+
+/// Service that replies to each request with a stream of responses (e.g. pages of
+/// search results or progress updates) rather than a single response.
+///
+/// The stream ends when the handler returns `None` or after the first error, if
+/// the caller stops listening then the stream is dropped which cancels any work
+/// that it had not yet done.
+pub trait StreamingServiceHandler<REQ, RES, ERR>
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send
+{
+    fn process<'a>(&'a self, request: REQ, context: InvocationContext<'a>) -> BoxStream<'a, Result<RES, ServiceError<ERR>>>;
+}
+
+/// Part of a streamed reply, the frames are numbered as they may be observed by
+/// the caller in a different order than they were sent
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) struct ServiceStreamFrame<RES, ERR>
+{
+    pub(crate) seq: u64,
+    pub(crate) body: ServiceStreamBody<RES, ERR>,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub(crate) enum ServiceStreamBody<RES, ERR>
+{
+    Item(RES),
+    Error(ServiceErrorReply<ERR>),
+    End,
+}
+
+/// Sent by the caller when it stops listening to a stream before it ends
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+pub(crate) struct ServiceStreamCancel
+{
+}
@@ -0,0 +1,234 @@
+#[allow(unused_imports)]
+use log::{info, error, warn, debug};
+use async_trait::async_trait;
+use futures::StreamExt;
+use serde::{Serialize, de::DeserializeOwned};
+use std::{sync::Weak};
+use std::sync::Arc;
+use std::collections::VecDeque;
+use parking_lot::Mutex;
+use tokio::select;
+use tokio::sync::watch;
+
+use crate::{error::*, event::*};
+use crate::chain::*;
+use crate::session::*;
+use crate::header::*;
+
+use super::*;
+
+/// Most cancels that are remembered for requests that are not being processed here
+const MAX_CANCELS: usize = 1024;
+
+/// Requests that the callers have hung up on, the sniffer that fills it is registered
+/// along with the service so a cancel can not be missed while a request is picked up
+struct StreamCancels
+{
+    keys: Mutex<VecDeque<PrimaryKey>>,
+    changed: watch::Sender<()>,
+    watch: watch::Receiver<()>,
+}
+
+impl StreamCancels
+{
+    fn listen(chain: &Arc<Chain>, cancel_type_name: String) -> Arc<StreamCancels>
+    {
+        let (changed, watch) = watch::channel(());
+        let ret = Arc::new(StreamCancels {
+            keys: Mutex::new(VecDeque::new()),
+            changed,
+            watch,
+        });
+
+        let (sniffer, mut cancels) = sniff_for_any_reply(chain, cancel_type_name, 32);
+        let weak = Arc::downgrade(&ret);
+        tokio::spawn(async move {
+            let _sniffer = sniffer;
+            while let Some(key) = cancels.recv().await {
+                let cancels = match weak.upgrade() {
+                    Some(a) => a,
+                    None => { break; }
+                };
+                {
+                    let mut keys = cancels.keys.lock();
+                    keys.push_back(key);
+                    while keys.len() > MAX_CANCELS {
+                        keys.pop_front();
+                    }
+                }
+                let _ = cancels.changed.send(());
+            }
+        });
+        ret
+    }
+
+    /// Waits for the caller of a request to hang up
+    async fn wait(&self, key: &PrimaryKey)
+    {
+        let mut watch = self.watch.clone();
+        loop {
+            let cancelled = self.keys.lock().contains(key);
+            if cancelled {
+                return;
+            }
+            let _ = watch.changed().await;
+        }
+    }
+}
+
+/// Forgets the cancel of a request once it has been processed
+struct ForgetCancel<'a>
+{
+    cancels: &'a StreamCancels,
+    key: PrimaryKey,
+}
+
+impl<'a> Drop
+for ForgetCancel<'a>
+{
+    fn drop(&mut self) {
+        self.cancels.keys.lock().retain(|a| *a != self.key);
+    }
+}
+
+pub(crate) struct StreamingServiceHook<REQ, RES, ERR>
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send
+{
+    chain: Weak<Chain>,
+    session: AteSession,
+    handler: StreamingServiceInstance<REQ, RES, ERR>,
+    request_type_name: String,
+    frame_type_name: String,
+    cancels: Arc<StreamCancels>,
+}
+
+impl<REQ, RES, ERR> StreamingServiceHook<REQ, RES, ERR>
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send
+{
+    pub(crate) fn new(chain: &Arc<Chain>, session: AteSession, handler: StreamingServiceInstance<REQ, RES, ERR>) -> StreamingServiceHook<REQ, RES, ERR> {
+        StreamingServiceHook {
+            chain: Arc::downgrade(chain),
+            session: session.clone(),
+            handler: Arc::clone(&handler),
+            request_type_name: std::any::type_name::<REQ>().to_string(),
+            frame_type_name: std::any::type_name::<ServiceStreamFrame<RES, ERR>>().to_string(),
+            cancels: StreamCancels::listen(chain, std::any::type_name::<ServiceStreamCancel>().to_string()),
+        }
+    }
+}
+
+#[async_trait]
+impl<REQ, RES, ERR> Service
+for StreamingServiceHook<REQ, RES, ERR>
+where REQ: Serialize + DeserializeOwned + Clone + Sync + Send,
+      RES: Serialize + DeserializeOwned + Clone + Sync + Send,
+      ERR: Serialize + DeserializeOwned + Clone + Sync + Send + std::fmt::Debug
+{
+    fn filter(&self, evt: &EventData) -> bool {
+        if let Some(t) = evt.meta.get_type_name() {
+            return t.type_name == self.request_type_name;
+        }
+        false
+    }
+
+    async fn notify(&self, key: PrimaryKey) -> Result<(), ServiceError<()>>
+    {
+        // Get a reference to the chain
+        let chain = match self.chain.upgrade() {
+            Some(a) => a,
+            None => {
+                return Err(ServiceError::Aborted);
+            }
+        };
+
+        // Load the repository
+        let repo = match chain.repository() {
+            Some(a) => a,
+            None => {
+                warn!("service call failed - repository pointer is missing which means the service was added to a chain that is itself detached from any repositories, this is not allowed.");
+                return Ok(());
+            }
+        };
+
+        // Whatever happens the caller hanging up on this request is no longer of interest afterwards
+        let _forget = ForgetCancel {
+            cancels: &self.cancels,
+            key,
+        };
+
+        let (request, meta, signed_by) = {
+            // Load the object
+            let mut dio = chain.dio(&self.session).await;
+            dio.auto_cancel();
+            let mut req = dio.load::<REQ>(&key).await?;
+            let (meta, signed_by) = request_metadata(&chain, &key).await?;
+
+            // Attempt to lock (later delete) the request - if that fails then someone else
+            // has likely picked this up and will process it instead
+            if req.try_lock_then_delete(&mut dio).await? == false {
+                debug!("service call skipped - someone else locked it");
+                return Ok(())
+            }
+            req.commit(&mut dio)?;
+            let request = req.take();
+            dio.commit().await?;
+            (request, meta, signed_by)
+        };
+
+        // Create the context
+        let context = InvocationContext
+        {
+            session: &self.session,
+            repository: repo,
+            request: key.clone(),
+            meta,
+            signed_by,
+        };
+
+        // Each response is sent as its own frame until the stream ends, fails or
+        // the caller hangs up (which drops the stream and hence cancels the handler)
+        let mut stream = self.handler.process(request, context);
+        let mut seq = 0u64;
+        let mut sent = Vec::new();
+        loop {
+            let body: ServiceStreamBody<RES, ERR> = select! {
+                _ = self.cancels.wait(&key) => {
+                    debug!("service [{}] cancelled", self.request_type_name);
+
+                    // The caller is no longer reading so the frames that it did not get to
+                    // (or that were still on their way to it) are deleted here instead
+                    if let Err(err) = delete_frames::<RES, ERR>(&chain, &self.session, sent).await {
+                        debug!("stream-cleanup-err - {}", err);
+                    }
+                    return Ok(());
+                },
+                res = stream.next() => {
+                    match res {
+                        Some(Ok(res)) => ServiceStreamBody::Item(res),
+                        Some(Err(err)) => {
+                            let (reply, err) = err.as_reply();
+                            let frame = ServiceStreamFrame::<RES, ERR> { seq, body: ServiceStreamBody::Error(reply) };
+                            let _ = send_reply(&chain, &self.session, key, frame, self.frame_type_name.clone()).await;
+                            debug!("service [{}] error: {}", self.request_type_name, err);
+                            return Err(err);
+                        },
+                        None => ServiceStreamBody::End,
+                    }
+                }
+            };
+
+            let end = matches!(body, ServiceStreamBody::End);
+            let frame = ServiceStreamFrame { seq, body };
+            sent.push(send_reply_ext(&chain, &self.session, key, frame, self.frame_type_name.clone()).await?);
+            if end {
+                debug!("service [{}] ok ({} items)", self.request_type_name, seq);
+                return Ok(());
+            }
+            seq = seq + 1;
+        }
+    }
+}
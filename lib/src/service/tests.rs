@@ -5,6 +5,7 @@ use log::{info, error, debug};
 use super::*;
 
 use async_trait::async_trait;
+use futures::stream::{self, BoxStream, StreamExt};
 use serde::{Serialize, Deserialize};
 use std::sync::Arc;
 
@@ -65,4 +66,249 @@ async fn test_service() -> Result<(), AteError>
 
     debug!("received pong with msg [{}]", pong?.msg);
     Ok(())
-}
\ No newline at end of file
+}
+#[derive(Serialize, Deserialize, Debug, Clone)]
+struct Count
+{
+    to: u64,
+    fail_at: Option<u64>,
+}
+
+#[derive(Default)]
+struct CountingTable
+{
+    progress: Arc<std::sync::atomic::AtomicU64>,
+}
+
+impl super::StreamingServiceHandler<Count, u64, Noise>
+for CountingTable
+{
+    fn process<'a>(&'a self, count: Count, _context: InvocationContext<'a>) -> BoxStream<'a, Result<u64, ServiceError<Noise>>>
+    {
+        let fail_at = count.fail_at;
+        stream::iter(1..=count.to)
+            .then(move |n| async move {
+                tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+                self.progress.store(n, std::sync::atomic::Ordering::SeqCst);
+                match fail_at {
+                    Some(a) if a == n => Err(ServiceError::Reply(Noise { dummy: n })),
+                    _ => Ok(n),
+                }
+            })
+            .boxed()
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_service_stream() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let (chain, _builder) = crate::trust::create_test_chain(&mut mock_cfg, "test_chain_stream".to_string(), true, true, None).await;
+
+    debug!("start the streaming service on the chain");
+    let session = AteSession::new(&mock_cfg);
+    let table = Arc::new(CountingTable::default());
+    chain.add_streaming_service(session.clone(), Arc::clone(&table) as StreamingServiceInstance<Count, u64, Noise>);
+    let timeout = std::time::Duration::from_secs(10);
+
+    // All the items arrive in order and then the stream ends
+    let stream = Arc::clone(&chain).invoke_stream::<Count, u64, Noise>(None, Count { to: 5, fail_at: None }, timeout).await?;
+    let items = stream.collect::<Vec<_>>().await
+        .into_iter()
+        .collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(items, vec![1, 2, 3, 4, 5]);
+
+    // The frames are deleted once the stream is over
+    let frames = {
+        let mut dio = chain.dio(&session).await;
+        dio.keys_by_type(std::any::type_name::<ServiceStreamFrame<u64, Noise>>()).await
+    };
+    assert_eq!(frames.len(), 0);
+
+    // An error is the last thing that is received
+    let stream = Arc::clone(&chain).invoke_stream::<Count, u64, Noise>(None, Count { to: 5, fail_at: Some(3) }, timeout).await?;
+    let items = stream.collect::<Vec<_>>().await;
+    assert_eq!(items.len(), 3);
+    assert!(matches!(items[2], Err(InvokeError::Reply(Noise { dummy: 3 }))));
+
+    // Dropping the stream stops the service from producing any more items and every
+    // frame that was sent is deleted (even those the caller never read)
+    let mut stream = Arc::clone(&chain).invoke_stream::<Count, u64, Noise>(None, Count { to: 1000, fail_at: None }, timeout).await?;
+    assert!(matches!(stream.next().await, Some(Ok(1))));
+    for _ in 0..200 {
+        if table.progress.load(std::sync::atomic::Ordering::SeqCst) >= 5 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+    }
+    drop(stream);
+    let mut stopped_at = table.progress.load(std::sync::atomic::Ordering::SeqCst);
+    let mut stable = 0;
+    for _ in 0..200 {
+        tokio::time::sleep(std::time::Duration::from_millis(25)).await;
+        let progress = table.progress.load(std::sync::atomic::Ordering::SeqCst);
+        let frames = {
+            let mut dio = chain.dio(&session).await;
+            dio.keys_by_type(std::any::type_name::<ServiceStreamFrame<u64, Noise>>()).await
+        };
+        stable = match progress == stopped_at && frames.len() == 0 {
+            true => stable + 1,
+            false => 0,
+        };
+        stopped_at = progress;
+        if stable >= 4 {
+            break;
+        }
+    }
+    assert!(stable >= 4, "the service should have stopped and cleaned up its frames");
+    assert!(stopped_at < 1000);
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct ShoutMiddleware
+{
+    replies: std::sync::atomic::AtomicU64,
+}
+
+#[async_trait]
+impl super::ServiceMiddleware<Ping, Pong, Noise>
+for ShoutMiddleware
+{
+    async fn before<'a>(&self, mut ping: Ping, _context: &InvocationContext<'a>) -> Result<Ping, ServiceError<Noise>>
+    where Ping: 'async_trait
+    {
+        ping.msg = ping.msg.to_uppercase();
+        Ok(ping)
+    }
+
+    async fn after<'a>(&self, response: &Result<Pong, ServiceError<Noise>>, _context: &InvocationContext<'a>) {
+        if response.is_ok() {
+            self.replies.fetch_add(1, std::sync::atomic::Ordering::SeqCst);
+        }
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_service_middleware() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let (chain, _builder) = crate::trust::create_test_chain(&mut mock_cfg, "test_chain_middleware".to_string(), true, true, None).await;
+    let session = AteSession::new(&mock_cfg);
+
+    // The middleware can change the request and observe the response while the
+    // rate limit rejects the caller once it has used up its allowance
+    let shout = Arc::new(ShoutMiddleware::default());
+    chain.add_service_with(session.clone(), Arc::new(PingPongTable::default()), vec![
+        Arc::new(RateLimitMiddleware::new(2, std::time::Duration::from_secs(60))),
+        Arc::clone(&shout) as ServiceMiddlewareInstance<Ping, Pong, Noise>,
+    ]);
+    for _ in 0..2 {
+        let pong: Pong = Arc::clone(&chain).invoke::<Ping, Pong, Noise>(Ping { msg: "hi".to_string() }).await?;
+        assert_eq!(pong.msg, "HI");
+    }
+    let pong = Arc::clone(&chain).invoke::<Ping, Pong, Noise>(Ping { msg: "hi".to_string() }).await;
+    assert!(matches!(pong, Err(InvokeError::ServiceError(err)) if err.contains("Rate limited")));
+    assert_eq!(shout.replies.load(std::sync::atomic::Ordering::SeqCst), 2);
+
+    // Requests that are not signed by the role are denied before they reach the handler
+    let (chain, _builder) = crate::trust::create_test_chain(&mut mock_cfg, "test_chain_middleware_role".to_string(), true, true, None).await;
+    let mut admin = session.clone();
+    admin.add_group_write_key(&"admins".to_string(), &AteRolePurpose::Owner, &crate::crypto::PrivateSignKey::generate(crate::crypto::KeySize::Bit256));
+    chain.add_service_with(admin, Arc::new(PingPongTable::default()), vec![
+        Arc::new(RequireRoleMiddleware::new("admins", AteRolePurpose::Owner)),
+    ]);
+    let pong = Arc::clone(&chain).invoke::<Ping, Pong, Noise>(Ping { msg: "hi".to_string() }).await;
+    assert!(matches!(pong, Err(InvokeError::ServiceError(err)) if err.contains("denied")));
+
+    // ...while requests that are signed with the write key of the role are accepted
+    let role_key = crate::crypto::PrivateSignKey::generate(crate::crypto::KeySize::Bit256);
+    let (chain, _builder) = crate::trust::create_test_chain(&mut mock_cfg, "test_chain_middleware_role_ok".to_string(), true, false, Some(role_key.as_public_key())).await;
+    let mut admin = session.clone();
+    admin.add_group_write_key(&"admins".to_string(), &AteRolePurpose::Owner, &role_key);
+    chain.add_service_with(admin.clone(), Arc::new(PingPongTable::default()), vec![
+        Arc::new(RequireRoleMiddleware::new("admins", AteRolePurpose::Owner)),
+    ]);
+    let pong: Pong = Arc::clone(&chain).invoke_ext::<Ping, Pong, Noise>(Some(&admin), Ping { msg: "hi".to_string() }, std::time::Duration::from_secs(10)).await?;
+    assert_eq!(pong.msg, "hi");
+
+    Ok(())
+}
+
+#[derive(Default)]
+struct TallyTable
+{
+    calls: std::sync::atomic::AtomicU64,
+}
+
+#[async_trait]
+impl super::ServiceHandler<Ping, Pong, Noise>
+for TallyTable
+{
+    async fn process<'a>(&self, ping: Ping, _context: InvocationContext<'a>) -> Result<Pong, ServiceError<Noise>>
+    {
+        let calls = self.calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+        Ok(Pong { msg: format!("{}-{}", ping.msg, calls) })
+    }
+}
+
+#[tokio::main]
+#[test]
+async fn test_service_idempotency() -> Result<(), AteError>
+{
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let (chain, _builder) = crate::trust::create_test_chain(&mut mock_cfg, "test_chain_idempotency".to_string(), true, true, None).await;
+    let session = AteSession::new(&mock_cfg);
+
+    let table = Arc::new(TallyTable::default());
+    chain.add_service(session.clone(), Arc::clone(&table) as ServiceInstance<Ping, Pong, Noise>);
+
+    // Retrying with the same key returns the first response without processing it again
+    let timeout = std::time::Duration::from_secs(30);
+    let first: Pong = Arc::clone(&chain).invoke_idempotent::<Ping, Pong, Noise>(None, "transfer-1", Ping { msg: "hi".to_string() }, timeout).await?;
+    let retry: Pong = Arc::clone(&chain).invoke_idempotent::<Ping, Pong, Noise>(None, "transfer-1", Ping { msg: "hi".to_string() }, timeout).await?;
+    assert_eq!(first.msg, "hi-1");
+    assert_eq!(retry.msg, "hi-1");
+    assert_eq!(table.calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // Other keys (or no key at all) are processed as normal
+    let other: Pong = Arc::clone(&chain).invoke_idempotent::<Ping, Pong, Noise>(None, "transfer-2", Ping { msg: "hi".to_string() }, timeout).await?;
+    assert_eq!(other.msg, "hi-2");
+    let plain: Pong = Arc::clone(&chain).invoke::<Ping, Pong, Noise>(Ping { msg: "hi".to_string() }).await?;
+    assert_eq!(plain.msg, "hi-3");
+
+    // The replies are remembered in the chain so a restarted service still recognises them
+    chain.inside_sync.write().services.clear();
+    let restarted = Arc::new(TallyTable::default());
+    chain.add_service(session.clone(), Arc::clone(&restarted) as ServiceInstance<Ping, Pong, Noise>);
+    let retry: Pong = Arc::clone(&chain).invoke_idempotent::<Ping, Pong, Noise>(None, "transfer-1", Ping { msg: "hi".to_string() }, timeout).await?;
+    assert_eq!(retry.msg, "hi-1");
+    assert_eq!(restarted.calls.load(std::sync::atomic::Ordering::SeqCst), 0);
+    assert_eq!(table.calls.load(std::sync::atomic::Ordering::SeqCst), 3);
+
+    // Once the window has passed the remembered replies are deleted from the chain
+    chain.inside_sync.write().idempotency_window = std::time::Duration::from_millis(100);
+    tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+    let _: Pong = Arc::clone(&chain).invoke_idempotent::<Ping, Pong, Noise>(None, "transfer-3", Ping { msg: "hi".to_string() }, timeout).await?;
+    let record = idempotent_record_key(std::any::type_name::<Ping>(), None, "transfer-1");
+    let mut forgotten = false;
+    for _ in 0..200 {
+        if chain.dio(&session).await.exists(&record).await == false {
+            forgotten = true;
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    assert!(forgotten, "the expired reply should have been deleted");
+
+    Ok(())
+}
@@ -288,4 +288,15 @@ for SignaturePlugin
     fn clone_plugin(&self) -> Box<dyn EventPlugin> {
         Box::new(self.clone())
     }
+
+    fn snapshot(&self) -> Option<Vec<u8>> {
+        bincode::serialize(&(&self.pk, &self.sigs)).ok()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), SerializationError> {
+        let (pk, sigs) = bincode::deserialize(snapshot)?;
+        self.pk = pk;
+        self.sigs = sigs;
+        Ok(())
+    }
 }
\ No newline at end of file
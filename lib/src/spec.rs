@@ -98,6 +98,32 @@ pub enum BlobSize
 
 static LOG_MAGIC: &'static [u8; 3] = b"Ate";
 
+/// Limits the size of the meta and data blobs that make up an event so
+/// that corrupt or malicious length fields can not exhaust the memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventLimits
+{
+    pub max_meta_size: usize,
+    pub max_data_size: usize,
+}
+
+impl EventLimits
+{
+    pub fn check_meta(&self, size: usize) -> Result<(), SerializationError> {
+        if size > self.max_meta_size {
+            return Err(SerializationError::MetaTooLarge { size: size as u64, limit: self.max_meta_size as u64 });
+        }
+        Ok(())
+    }
+
+    pub fn check_data(&self, size: usize) -> Result<(), SerializationError> {
+        if size > self.max_data_size {
+            return Err(SerializationError::DataTooLarge { size: size as u64, limit: self.max_data_size as u64 });
+        }
+        Ok(())
+    }
+}
+
 #[async_trait]
 pub trait LogApi
 {
@@ -148,6 +174,23 @@ pub enum EventVersion
     V1 = b'!',
     */
     V2 = b'1',
+    V3 = b'2',
+}
+
+/// Computes the checksum that protects a V3 event record, it covers the version,
+/// the serialization formats and the framed meta and data blobs
+fn event_checksum(version: EventVersion, format: MessageFormat, meta: &[u8], data: Option<&[u8]>) -> u32
+{
+    let data = data.unwrap_or(&[]);
+
+    let mut hasher = crc32fast::Hasher::new();
+    hasher.update(&[version.into(), format.meta.into()]);
+    hasher.update(&(meta.len() as u64).to_be_bytes());
+    hasher.update(meta);
+    hasher.update(&[format.data.into()]);
+    hasher.update(&(data.len() as u64).to_be_bytes());
+    hasher.update(data);
+    hasher.finalize()
 }
 
 impl EventVersion
@@ -194,14 +237,15 @@ impl EventVersion
         return Ok(None);
     }
 
-    async fn read_blob_size(&self, api: &mut impl LogApi) -> Result<usize, SerializationError> {
+    async fn read_blob_size(&self, api: &mut impl LogApi) -> Result<u64, SerializationError> {
         match self {
-            EventVersion::V2 => {
+            EventVersion::V2 |
+            EventVersion::V3 => {
                 match BlobSize::try_from(api.read_u8().await?) {
-                    Ok(BlobSize::U8) => Ok(api.read_u8().await? as usize),
-                    Ok(BlobSize::U16) => Ok(api.read_u16().await? as usize),
-                    Ok(BlobSize::U32) => Ok(api.read_u32().await? as usize),
-                    Ok(BlobSize::U64) => Ok(api.read_u64().await? as usize),
+                    Ok(BlobSize::U8) => Ok(api.read_u8().await? as u64),
+                    Ok(BlobSize::U16) => Ok(api.read_u16().await? as u64),
+                    Ok(BlobSize::U32) => Ok(api.read_u32().await? as u64),
+                    Ok(BlobSize::U64) => Ok(api.read_u64().await?),
                     Err(err) => {
                         Err(SerializationError::IO(tokio::io::Error::new(tokio::io::ErrorKind::Other, format!("Failed to read data at 0x{:x} - {}", api.offset(), err))))
                     }
@@ -212,7 +256,8 @@ impl EventVersion
 
     async fn write_blob_size(&self, api: &mut impl LogApi, val: usize) -> Result<(), SerializationError> {
         match self {
-            EventVersion::V2 => {
+            EventVersion::V2 |
+            EventVersion::V3 => {
                 let blob_size = match val {
                     _ if val < u8::MAX as usize => BlobSize::U8,
                     _ if val < u16::MAX as usize => BlobSize::U16,
@@ -242,7 +287,8 @@ impl EventVersion
 
     async fn write_format(&self, api: &mut impl LogApi, format: SerializationFormat) -> Result<(), SerializationError> {
         match self {
-            EventVersion::V2 => {
+            EventVersion::V2 |
+            EventVersion::V3 => {
                 match api.write_u8(format.into()).await {
                     Ok(_) => Ok(()),
                     Err(err) => Err(SerializationError::IO(tokio::io::Error::new(tokio::io::ErrorKind::Other, format!("Failed to write data at 0x{:x} - {}", api.offset(), err))))
@@ -251,34 +297,60 @@ impl EventVersion
         }
     }
 
-    pub async fn read(api: &mut impl LogApi) -> Result<Option<LogEntry>, SerializationError> {
+    pub async fn read(api: &mut impl LogApi, limits: &EventLimits) -> Result<Option<LogEntry>, SerializationError> {
         let offset = api.offset();
 
         let version = match Self::read_version(api).await? {
             Some(a) => a,
             None => { return Ok(None); }
         };
+        let start = api.offset() - (LOG_MAGIC.len() as u64 + 1);
         
         let format_meta = version.read_format(api).await?;
         let meta_size = version.read_blob_size(api).await?;
-        let mut meta = vec![0 as u8; meta_size];
+        if meta_size > limits.max_meta_size as u64 {
+            return Err(SerializationError::MetaTooLarge { size: meta_size, limit: limits.max_meta_size as u64 });
+        }
+        let mut meta = vec![0 as u8; meta_size as usize];
         api.read_exact(&mut meta[..]).await?;
 
         let format_data = version.read_format(api).await?;
         let data_size = version.read_blob_size(api).await?;
+        if data_size > limits.max_data_size as u64 {
+            return Err(SerializationError::DataTooLarge { size: data_size, limit: limits.max_data_size as u64 });
+        }
         let data = if data_size > 0 {
-            let mut data = vec![0 as u8; data_size];
+            let mut data = vec![0 as u8; data_size as usize];
             api.read_exact(&mut data[..]).await?;
             Some(data)
         } else { None };
 
+        let format = MessageFormat {
+            meta: format_meta,
+            data: format_data,
+        };
+
+        // Newer versions carry a checksum at the end of the record which
+        // allows us to detect torn writes and bit flips
+        match version {
+            EventVersion::V2 => { },
+            EventVersion::V3 => {
+                let expected = api.read_u32().await?;
+                let actual = event_checksum(version, format, &meta[..], data.as_ref().map(|a| &a[..]));
+                if expected != actual {
+                    return Err(SerializationError::ChecksumMismatch {
+                        offset: start,
+                        expected,
+                        actual,
+                    });
+                }
+            }
+        }
+
         Ok(Some(LogEntry {
             header: LogHeader {
                 offset,
-                format: MessageFormat {
-                    meta: format_meta,
-                    data: format_data,
-                }
+                format,
             },
             meta,
             data
@@ -306,6 +378,13 @@ impl EventVersion
             }
         };
 
+        match self {
+            EventVersion::V2 => { },
+            EventVersion::V3 => {
+                api.write_u32(event_checksum(*self, format, meta, data)).await?;
+            }
+        }
+
         Ok(LogHeader {
             offset,
             format,
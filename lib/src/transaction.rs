@@ -56,6 +56,9 @@ pub(crate) struct Transaction
     pub(crate) transmit: bool,
     pub(crate) events: Vec<EventData>,
     pub(crate) conversation: Option<Arc<ConversationSession>>,
+    /// Events that were written locally but not yet confirmed by the server
+    /// are kept in the staging log until they are confirmed
+    pub(crate) unconfirmed: bool,
 }
 
 impl Transaction
@@ -68,6 +71,7 @@ impl Transaction
             transmit,
             events,
             conversation: None,
+            unconfirmed: false,
         }
     }
 }
@@ -27,6 +27,66 @@ pub trait EventDataTransformer: Send + Sync
     fn clone_transformer(&self) -> Box<dyn EventDataTransformer>;
 }
 
+/// Data that is smaller than this many bytes is not compressed by the
+/// compressors (unless they are configured otherwise)
+pub const DEFAULT_COMPRESSION_THRESHOLD: usize = 128;
+
+fn compress_with(codec: CompressionCodec, with: &Bytes, level: i32) -> Result<Vec<u8>, TransformError> {
+    Ok(
+        match codec {
+            CompressionCodec::Snap => {
+                let mut reader = FrameEncoder::new(with.clone().reader());
+                let mut compressed = Vec::new();
+                std::io::copy(&mut reader, &mut compressed)?;
+                compressed
+            },
+            CompressionCodec::Zstd => zstd::encode_all(&with[..], level)?,
+            CompressionCodec::Lz4 => lz4_flex::compress_prepend_size(&with[..]),
+        }
+    )
+}
+
+fn decompress_with(codec: CompressionCodec, with: Bytes) -> Result<Bytes, TransformError> {
+    Ok(
+        Bytes::from(match codec {
+            CompressionCodec::Snap => {
+                let mut reader = FrameDecoder::new(with.reader());
+                let mut decompressed = Vec::new();
+                std::io::copy(&mut reader, &mut decompressed)?;
+                decompressed
+            },
+            CompressionCodec::Zstd => zstd::decode_all(&with[..])?,
+            CompressionCodec::Lz4 => lz4_flex::decompress_size_prepended(&with[..])
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err.to_string()))?,
+        })
+    )
+}
+
+/// Compresses the data and tags the event with the codec that was used, if the data
+/// is too small to be worth compressing (or its already compressed) then its left alone
+fn compress_tagged(codec: CompressionCodec, meta: &mut Metadata, with: Bytes, level: i32, threshold: usize) -> Result<Bytes, TransformError> {
+    if with.len() < threshold || meta.get_compression().is_some() {
+        return Ok(with);
+    }
+
+    let compressed = compress_with(codec, &with, level)?;
+    if compressed.len() >= with.len() {
+        return Ok(with);
+    }
+
+    meta.core.push(CoreMetadata::Compression(codec));
+    Ok(Bytes::from(compressed))
+}
+
+/// Decompresses the data using the codec that the event was tagged with, events
+/// without a tag were never compressed and are returned as is
+fn decompress_tagged(meta: &Metadata, with: Bytes) -> Result<Bytes, TransformError> {
+    match meta.get_compression() {
+        Some(codec) => decompress_with(codec, with),
+        None => Ok(with),
+    }
+}
+
 #[derive(Debug, Default, Clone)]
 pub struct CompressorWithSnapTransformer
 {
@@ -41,18 +101,114 @@ for CompressorWithSnapTransformer
 
     #[allow(unused_variables)]
     fn data_as_underlay(&self, meta: &mut Metadata, with: Bytes, _session: &AteSession, _trans_meta: &TransactionMetadata) -> Result<Bytes, TransformError> {
-        let mut reader = FrameEncoder::new(with.reader());
-        let mut compressed = Vec::new();
-        std::io::copy(&mut reader, &mut compressed)?;
+        if meta.get_compression().is_some() {
+            return Ok(with);
+        }
+        let compressed = compress_with(CompressionCodec::Snap, &with, 0)?;
+        meta.core.push(CoreMetadata::Compression(CompressionCodec::Snap));
         Ok(Bytes::from(compressed))
     }
 
     #[allow(unused_variables)]
     fn data_as_overlay(&self, meta: &Metadata, with: Bytes, _session: &AteSession) -> Result<Bytes, TransformError> {
-        let mut reader = FrameDecoder::new(with.reader());
-        let mut decompressed = Vec::new();
-        std::io::copy(&mut reader, &mut decompressed)?;
-        Ok(Bytes::from(decompressed))
+        // Events written before the codec was recorded in the metadata are always snap compressed
+        let codec = meta.get_compression().unwrap_or(CompressionCodec::Snap);
+        decompress_with(codec, with)
+    }
+}
+
+/// Compresses the data of events with zstd, the codec is recorded on each event
+/// so that chains can mix compressed and uncompressed events (or switch codecs)
+#[derive(Debug, Clone)]
+pub struct CompressorWithZstdTransformer
+{
+    level: i32,
+    threshold: usize,
+}
+
+impl Default
+for CompressorWithZstdTransformer
+{
+    fn default() -> CompressorWithZstdTransformer {
+        CompressorWithZstdTransformer {
+            level: zstd::DEFAULT_COMPRESSION_LEVEL,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl CompressorWithZstdTransformer
+{
+    pub fn new(level: i32) -> CompressorWithZstdTransformer {
+        CompressorWithZstdTransformer {
+            level,
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+
+    /// Data smaller than this number of bytes will not be compressed
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+impl EventDataTransformer
+for CompressorWithZstdTransformer
+{
+    fn clone_transformer(&self) -> Box<dyn EventDataTransformer> {
+        Box::new(self.clone())
+    }
+
+    fn data_as_underlay(&self, meta: &mut Metadata, with: Bytes, _session: &AteSession, _trans_meta: &TransactionMetadata) -> Result<Bytes, TransformError> {
+        compress_tagged(CompressionCodec::Zstd, meta, with, self.level, self.threshold)
+    }
+
+    fn data_as_overlay(&self, meta: &Metadata, with: Bytes, _session: &AteSession) -> Result<Bytes, TransformError> {
+        decompress_tagged(meta, with)
+    }
+}
+
+/// Compresses the data of events with lz4 which is faster than zstd but does not
+/// compress as well, the codec is recorded on each event
+#[derive(Debug, Clone)]
+pub struct CompressorWithLz4Transformer
+{
+    threshold: usize,
+}
+
+impl Default
+for CompressorWithLz4Transformer
+{
+    fn default() -> CompressorWithLz4Transformer {
+        CompressorWithLz4Transformer {
+            threshold: DEFAULT_COMPRESSION_THRESHOLD,
+        }
+    }
+}
+
+impl CompressorWithLz4Transformer
+{
+    /// Data smaller than this number of bytes will not be compressed
+    pub fn with_threshold(mut self, threshold: usize) -> Self {
+        self.threshold = threshold;
+        self
+    }
+}
+
+impl EventDataTransformer
+for CompressorWithLz4Transformer
+{
+    fn clone_transformer(&self) -> Box<dyn EventDataTransformer> {
+        Box::new(self.clone())
+    }
+
+    fn data_as_underlay(&self, meta: &mut Metadata, with: Bytes, _session: &AteSession, _trans_meta: &TransactionMetadata) -> Result<Bytes, TransformError> {
+        compress_tagged(CompressionCodec::Lz4, meta, with, 0, self.threshold)
+    }
+
+    fn data_as_overlay(&self, meta: &Metadata, with: Bytes, _session: &AteSession) -> Result<Bytes, TransformError> {
+        decompress_tagged(meta, with)
     }
 }
 
@@ -145,6 +301,42 @@ fn test_compressor()
     assert_eq!(&test_bytes, &decompressed);
 }
 
+#[test]
+fn test_compressor_codecs()
+{
+    crate::utils::bootstrap_env();
+
+    let zstd = CompressorWithZstdTransformer::default();
+    let lz4 = CompressorWithLz4Transformer::default();
+    let cfg = ConfAte::default();
+    let session = AteSession::new(&cfg);
+    let trans_meta = TransactionMetadata::default();
+
+    // Small payloads are not compressed or tagged
+    let small = Bytes::from("test".as_bytes());
+    let mut meta = Metadata::default();
+    let ret = zstd.data_as_underlay(&mut meta, small.clone(), &session, &trans_meta).unwrap();
+    assert_eq!(&small, &ret);
+    assert_eq!(meta.get_compression(), None);
+    assert_eq!(&small, &lz4.data_as_overlay(&meta, ret, &session).unwrap());
+
+    // Larger payloads are tagged with the codec so any compressor can read them
+    let large = Bytes::from(vec![b'x'; 4096]);
+    for (codec, compressor) in vec![
+        (CompressionCodec::Zstd, Box::new(zstd.clone()) as Box<dyn EventDataTransformer>),
+        (CompressionCodec::Lz4, Box::new(lz4.clone()) as Box<dyn EventDataTransformer>),
+        (CompressionCodec::Snap, Box::new(CompressorWithSnapTransformer::default()) as Box<dyn EventDataTransformer>),
+    ] {
+        let mut meta = Metadata::default();
+        let compressed = compressor.data_as_underlay(&mut meta, large.clone(), &session, &trans_meta).unwrap();
+        assert_eq!(meta.get_compression(), Some(codec));
+        assert!(compressed.len() < large.len());
+
+        assert_eq!(&large, &zstd.data_as_overlay(&meta, compressed.clone(), &session).unwrap());
+        assert_eq!(&large, &lz4.data_as_overlay(&meta, compressed, &session).unwrap());
+    }
+}
+
 #[test]
 fn test_crypto()
 {
@@ -68,6 +68,21 @@ for TreeAuthorityPlugin
         Ok(())
     }
 
+    fn snapshot(&self) -> Option<Vec<u8>>
+    {
+        let signatures = self.signature_plugin.snapshot()?;
+        bincode::serialize(&(&self.auth, &self.parents, signatures)).ok()
+    }
+
+    fn restore(&mut self, snapshot: &[u8]) -> Result<(), SerializationError>
+    {
+        let (auth, parents, signatures): (_, _, Vec<u8>) = bincode::deserialize(snapshot)?;
+        self.signature_plugin.restore(&signatures[..])?;
+        self.auth = auth;
+        self.parents = parents;
+        Ok(())
+    }
+
     fn root_keys(&self) -> Vec<PublicSignKey>
     {
         self.root_keys.values().map(|a| a.clone()).collect::<Vec<_>>()
@@ -7,6 +7,10 @@ use crate::header::*;
 use crate::event::*;
 use crate::index::*;
 use crate::redo::*;
+use crate::time::ChainTimestamp;
+use crate::crypto::AteHash;
+use fxhash::FxHashSet;
+use multimap::MultiMap;
 
 use super::*;
 
@@ -65,6 +69,14 @@ impl<'a> ChainOfTrust
         self.timeline.lookup_parent(key)
     }
 
+    pub(crate) fn lookup_order(&self, key: &PrimaryKey) -> Option<MetaOrder> {
+        self.timeline.lookup_order(key)
+    }
+
+    pub(crate) fn lookup_type_name(&self, key: &PrimaryKey) -> Option<String> {
+        self.timeline.lookup_type_name(key)
+    }
+
     pub(crate) fn lookup_secondary(&self, key: &MetaCollection) -> Option<Vec<EventLeaf>>
     {
         self.timeline.lookup_secondary(key)
@@ -75,6 +87,74 @@ impl<'a> ChainOfTrust
         self.timeline.lookup_secondary_raw(key)
     }
 
+    pub(crate) fn lookup_children(&self, parent_id: &PrimaryKey) -> Vec<PrimaryKey>
+    {
+        self.timeline.lookup_children(parent_id)
+    }
+
+    pub(crate) fn lookup_primary_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<EventLeaf>
+    {
+        self.timeline.lookup_primary_at(key, at)
+    }
+
+    pub(crate) fn lookup_parent_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaParent> {
+        self.timeline.lookup_parent_at(key, at)
+    }
+
+    pub(crate) fn lookup_order_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaOrder> {
+        self.timeline.lookup_order_at(key, at)
+    }
+
+    pub(crate) fn lookup_secondary_raw_at(&self, key: &MetaCollection, at: ChainTimestamp) -> Option<Vec<PrimaryKey>>
+    {
+        self.timeline.lookup_secondary_raw_at(key, at)
+    }
+
+    pub(crate) fn lookup_versions(&self, key: &PrimaryKey) -> Vec<(ChainTimestamp, AteHash, Option<EventLeaf>)>
+    {
+        self.timeline.lookup_versions(key)
+    }
+
+    pub(crate) fn lookup_type(&self, type_name: &str) -> Vec<PrimaryKey>
+    {
+        self.timeline.lookup_type(type_name)
+    }
+
+    pub(crate) fn lookup_type_at(&self, type_name: &str, at: ChainTimestamp) -> Vec<PrimaryKey>
+    {
+        self.timeline.lookup_type_at(type_name, at)
+    }
+
+    pub(crate) fn type_names(&self) -> Vec<String>
+    {
+        self.timeline.type_names()
+    }
+
+    pub(crate) fn release_delayed(&mut self, now: ChainTimestamp) -> Vec<PrimaryKey>
+    {
+        self.timeline.release_delayed(now)
+    }
+
+    pub(crate) fn has_delayed(&self) -> bool
+    {
+        self.timeline.has_delayed()
+    }
+
+    pub(crate) fn is_delayed(&self, key: &PrimaryKey) -> bool
+    {
+        self.timeline.is_delayed(key)
+    }
+
+    pub(crate) fn lookup_signatures(&self, hashes: &FxHashSet<AteHash>) -> MultiMap<AteHash, AteHash>
+    {
+        self.timeline.lookup_signatures(hashes)
+    }
+
+    pub(crate) fn lookup_signed_by(&self, hash: &AteHash) -> Vec<AteHash>
+    {
+        self.timeline.lookup_signed_by(hash)
+    }
+
     pub(crate) fn invalidate_caches(&mut self) {
         self.timeline.invalidate_caches();
     }
@@ -83,9 +163,13 @@ impl<'a> ChainOfTrust
         self.redo.flush().await
     }
 
+    pub(crate) async fn sync(&mut self) -> Result<(), tokio::io::Error> {
+        self.redo.sync().await
+    }
+
     pub(crate) async fn destroy(&mut self) -> Result<(), tokio::io::Error> {
         self.invalidate_caches();
-        self.redo.destroy()
+        self.redo.destroy().await
     }
 
     pub(crate) fn name(&self) -> String {
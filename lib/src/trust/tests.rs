@@ -16,6 +16,8 @@ use crate::validator::*;
 use crate::event::*;
 use crate::spec::*;
 use crate::compact::*;
+use crate::meta::*;
+use crate::time::ChainTimestamp;
 
 use super::*;
 
@@ -61,6 +63,21 @@ pub(crate) async fn create_test_chain(mock_cfg: &mut ConfAte, chain_name: String
     )
 }
 
+/// Creates a temporary barebone chain (every event is accepted) for tests that need
+/// to add their own indexes, compactors or constraints to the builder
+pub(crate) async fn create_barebone_chain<F>(mock_cfg: &mut ConfAte, chain_name: &str, with: F) -> Arc<Chain>
+where F: FnOnce(ChainBuilder) -> ChainBuilder
+{
+    mock_cfg.configured_for(ConfiguredFor::Barebone);
+
+    let builder = ChainBuilder::new(&mock_cfg).await;
+    with(builder)
+        .build()
+        .open(&ChainKey::default().with_temp_name(chain_name.to_string()))
+        .await
+        .unwrap()
+}
+
 #[tokio::main]
 #[test]
 async fn test_chain() -> Result<(), AteError> {
@@ -290,4 +307,168 @@ async fn test_chain() -> Result<(), AteError> {
     }
 
     Ok(())
-}
\ No newline at end of file
+}
+
+#[tokio::main]
+#[test]
+async fn test_chain_export_import() -> Result<(), AteError> {
+    crate::utils::bootstrap_env();
+
+    let key1 = PrimaryKey::generate();
+    let key2 = PrimaryKey::generate();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    let (chain, builder) = create_test_chain(&mut mock_cfg, "test_export".to_string(), true, true, None).await;
+
+    // Add some events to the chain that will be exported
+    {
+        let lock = chain.multi().await;
+        let evt1 = EventData::new(key1.clone(), Bytes::from(vec!(1; 1)), mock_cfg.log_format);
+        let evt2 = EventData::new(key2.clone(), Bytes::from(vec!(2; 1)), mock_cfg.log_format);
+        let trans = Transaction::from_events(vec![evt1, evt2], TransactionScope::Local, false);
+        lock.pipe.feed(trans).await.expect("The event failed to be accepted");
+    }
+    assert_eq!(2, chain.count().await);
+
+    // Rotating the log gives the chain a cut-off that must be carried by the archive
+    #[cfg(feature = "rotate")]
+    {
+        let header = ChainHeader { cut_off: ChainTimestamp::from(1234u64) };
+        let header_bytes = SerializationFormat::Json.serialize(&header)?;
+        chain.single().await.inside_async.chain.redo.rotate(header_bytes).await?;
+    }
+    let cut_off = chain.inside_async.read().await.chain.redo.read_chain_header()?.cut_off;
+    #[cfg(feature = "rotate")]
+    assert_eq!(cut_off, ChainTimestamp::from(1234u64));
+
+    // Export the chain into an archive and make sure its complete
+    debug!("exporting the chain");
+    let mut archive = Vec::new();
+    assert_eq!(2, chain.export(&mut archive).await?);
+    let header = ChainArchiveReader::open(&archive[..], mock_cfg.event_limits()).await?
+        .verify()
+        .await?;
+    assert_eq!(2, header.events);
+    assert_eq!(chain.key(), &header.key);
+    assert_eq!(cut_off, header.chain_header.cut_off);
+
+    // Archives that are truncated or corrupt must be rejected
+    let truncated = &archive[..archive.len()-1];
+    assert!(ChainArchiveReader::open(truncated, mock_cfg.event_limits()).await?.verify().await.is_err());
+    let mut corrupt = archive.clone();
+    let idx = corrupt.len() - 64;
+    corrupt[idx] = corrupt[idx] ^ 0xFF;
+    assert!(ChainArchiveReader::open(&corrupt[..], mock_cfg.event_limits()).await?.verify().await.is_err());
+
+    // Importing over a chain that already has events is not allowed
+    #[cfg(feature = "local_fs")]
+    match builder.import(&archive[..]).await {
+        Err(ArchiveError::ChainNotEmpty(_)) => { },
+        _ => panic!("The import should have failed as the chain already exists"),
+    }
+
+    // Destroy the original chain and import it again from the archive
+    debug!("importing the chain");
+    chain.single().await.destroy().await?;
+    drop(chain);
+    let chain = builder.import(&archive[..]).await?;
+    assert_eq!(2, chain.count().await);
+    assert_eq!(cut_off, chain.inside_async.read().await.chain.redo.read_chain_header()?.cut_off);
+    {
+        let lock = chain.multi().await;
+        let test_data = lock.lookup_primary(&key1).await.expect("Failed to find the entry after the import");
+        let test_data = lock.load(test_data).await?;
+        assert_eq!(test_data.data.data_bytes, Some(Bytes::from(vec!(1; 1))));
+
+        let test_data = lock.lookup_primary(&key2).await.expect("Failed to find the entry after the import");
+        let test_data = lock.load(test_data).await?;
+        assert_eq!(test_data.data.data_bytes, Some(Bytes::from(vec!(2; 1))));
+    }
+
+    chain.single().await.destroy().await?;
+    Ok(())
+}
+
+#[tokio::main]
+#[test]
+async fn test_chain_group_commit() -> Result<(), AteError> {
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    mock_cfg.durability = crate::redo::DurabilityPolicy::GroupCommit {
+        max_delay: std::time::Duration::from_millis(20),
+        max_batch: 4,
+    };
+    let (chain, _builder) = create_test_chain(&mut mock_cfg, "test_group_commit".to_string(), true, true, None).await;
+
+    // Concurrent transactions share the fsyncs and must all be confirmed
+    let syncs = chain.inside_async.read().await.chain.redo.sync_count();
+    {
+        let lock = chain.multi().await;
+        let feeds = (0..10u8)
+            .map(|n| {
+                let evt = EventData::new(PrimaryKey::generate(), Bytes::from(vec!(n; 1)), mock_cfg.log_format);
+                lock.pipe.feed(Transaction::from_events(vec![evt], TransactionScope::Local, false))
+            })
+            .collect::<Vec<_>>();
+        for ret in futures::future::join_all(feeds).await {
+            ret.expect("The event failed to be accepted");
+        }
+    }
+    assert_eq!(10, chain.count().await);
+
+    // Batches of up to four transactions mean that ten commits need at most three fsyncs
+    // (one more is allowed in case the deadline splits a batch early)
+    let syncs = chain.inside_async.read().await.chain.redo.sync_count() - syncs;
+    assert!(syncs >= 1, "the group commit never synced the log");
+    assert!(syncs <= 4, "the commits were not batched ({} fsyncs for 10 commits)", syncs);
+
+    chain.single().await.destroy().await?;
+    Ok(())
+}
+
+#[cfg(feature = "local_fs")]
+#[tokio::main]
+#[test]
+async fn test_chain_delayed_upload_migration() -> Result<(), AteError> {
+    crate::utils::bootstrap_env();
+
+    let mut mock_cfg = crate::conf::tests::mock_test_config();
+    mock_cfg.compact_mode = CompactMode::Never;
+    let (chain, _builder) = create_test_chain(&mut mock_cfg, "test_delayed_upload".to_string(), true, true, None).await;
+    let chain_name = chain.name().await;
+
+    // Older versions wrote unconfirmed events straight into the log and marked the range
+    let evt1 = EventData::new(PrimaryKey::generate(), Bytes::from(vec!(1; 1)), mock_cfg.log_format);
+    let evt2 = EventData::new(PrimaryKey::generate(), Bytes::from(vec!(2; 1)), mock_cfg.log_format);
+    chain.pipe.feed(Transaction::from_events(vec![evt1, evt2], TransactionScope::Local, false)).await?;
+    let (from, to) = {
+        let guard = chain.inside_async.read().await;
+        let keys = guard.range_keys(..).collect::<Vec<_>>();
+        (keys.first().unwrap().clone(), keys.last().unwrap().clone())
+    };
+    let mut marker = EventData::barebone(mock_cfg.log_format);
+    marker.meta.core.push(CoreMetadata::DelayedUpload(MetaDelayedUpload { complete: false, from, to }));
+    chain.pipe.feed(Transaction::from_events(vec![marker], TransactionScope::Local, false)).await?;
+    chain.flush().await?;
+    drop(chain);
+
+    // When the chain is opened the events in the range are moved into the staging log
+    let (chain, _builder) = create_test_chain(&mut mock_cfg, chain_name.clone(), false, true, None).await;
+    let staged = chain.staged_events().await;
+    assert_eq!(staged.len(), 2);
+    let count = chain.count().await;
+    drop(chain);
+
+    // This only happens once and confirming them does not write them into the log again
+    let (chain, _builder) = create_test_chain(&mut mock_cfg, chain_name.clone(), false, true, None).await;
+    assert_eq!(chain.staged_events().await.len(), 2);
+    assert_eq!(chain.count().await, count);
+    let hashes = staged.iter().map(|e| e.as_header_raw().map(|h| h.event_hash)).collect::<Result<Vec<_>, _>>()?;
+    assert_eq!(chain.confirm_staged(&hashes[..]).await?, 2);
+    assert_eq!(chain.count().await, count);
+    assert_eq!(chain.staged_events().await.len(), 0);
+
+    chain.single().await.destroy().await?;
+    Ok(())
+}
@@ -1,6 +1,9 @@
 #[allow(unused_imports)]
 use log::{info, error, debug};
 use btreemultimap::BTreeMultiMap;
+use fxhash::{FxHashMap, FxHashSet};
+use std::collections::BTreeMap;
+use multimap::MultiMap;
 
 use crate::compact::*;
 use crate::meta::*;
@@ -8,12 +11,19 @@ use crate::header::*;
 use crate::event::*;
 use crate::index::*;
 use crate::time::*;
+use crate::crypto::AteHash;
 
 pub(crate) struct ChainTimeline
 {
     pub(crate) history: BTreeMultiMap<ChainTimestamp, EventHeaderRaw>,
     pub(crate) pointers: BinaryTreeIndexer,
     pub(crate) compactors: Vec<Box<dyn EventCompactor>>,
+    /// Events in the history in the order they were added to it (the positions
+    /// are reassigned whenever the timeline is rebuilt, see `generation`)
+    pub(crate) arrivals: BTreeMap<u64, (ChainTimestamp, EventHeaderRaw)>,
+    pub(crate) arrival_of: FxHashMap<AteHash, u64>,
+    /// Number of times the timeline was rebuilt (e.g. by compacting the chain)
+    pub(crate) generation: u64,
 }
 
 impl<'a> ChainTimeline
@@ -27,6 +37,14 @@ impl<'a> ChainTimeline
         self.pointers.lookup_parent(key)
     }
 
+    pub(crate) fn lookup_order(&self, key: &PrimaryKey) -> Option<MetaOrder> {
+        self.pointers.lookup_order(key)
+    }
+
+    pub(crate) fn lookup_type_name(&self, key: &PrimaryKey) -> Option<String> {
+        self.pointers.lookup_type_name(key)
+    }
+
     pub(crate) fn lookup_secondary(&self, key: &MetaCollection) -> Option<Vec<EventLeaf>>
     {
         self.pointers.lookup_secondary(key)
@@ -37,12 +55,85 @@ impl<'a> ChainTimeline
         self.pointers.lookup_secondary_raw(key)
     }
 
+    pub(crate) fn lookup_children(&self, parent_id: &PrimaryKey) -> Vec<PrimaryKey>
+    {
+        self.pointers.lookup_children(parent_id)
+    }
+
+    pub(crate) fn lookup_primary_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<EventLeaf>
+    {
+        self.pointers.lookup_primary_at(key, at)
+    }
+
+    pub(crate) fn lookup_parent_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaParent> {
+        self.pointers.lookup_parent_at(key, at)
+    }
+
+    pub(crate) fn lookup_order_at(&self, key: &PrimaryKey, at: ChainTimestamp) -> Option<MetaOrder> {
+        self.pointers.lookup_order_at(key, at)
+    }
+
+    pub(crate) fn lookup_secondary_raw_at(&self, key: &MetaCollection, at: ChainTimestamp) -> Option<Vec<PrimaryKey>>
+    {
+        self.pointers.lookup_secondary_raw_at(key, at)
+    }
+
+    pub(crate) fn lookup_versions(&self, key: &PrimaryKey) -> Vec<(ChainTimestamp, AteHash, Option<EventLeaf>)>
+    {
+        self.pointers.lookup_versions(key)
+    }
+
+    pub(crate) fn lookup_type(&self, type_name: &str) -> Vec<PrimaryKey>
+    {
+        self.pointers.lookup_type(type_name)
+    }
+
+    pub(crate) fn lookup_type_at(&self, type_name: &str, at: ChainTimestamp) -> Vec<PrimaryKey>
+    {
+        self.pointers.lookup_type_at(type_name, at)
+    }
+
+    pub(crate) fn type_names(&self) -> Vec<String>
+    {
+        self.pointers.type_names()
+    }
+
+    pub(crate) fn release_delayed(&mut self, now: ChainTimestamp) -> Vec<PrimaryKey>
+    {
+        self.pointers.release(now)
+    }
+
+    pub(crate) fn has_delayed(&self) -> bool
+    {
+        self.pointers.has_delayed()
+    }
+
+    pub(crate) fn is_delayed(&self, key: &PrimaryKey) -> bool
+    {
+        self.pointers.is_delayed(key)
+    }
+
+    /// Finds the public keys that signed any of the supplied events
+    pub(crate) fn lookup_signatures(&self, hashes: &FxHashSet<AteHash>) -> MultiMap<AteHash, AteHash>
+    {
+        let mut ret = MultiMap::new();
+        for hash in hashes.iter() {
+            for public_key_hash in self.pointers.lookup_signatures(hash) {
+                ret.insert(hash.clone(), public_key_hash.clone());
+            }
+        }
+        ret
+    }
+
+    pub(crate) fn lookup_signed_by(&self, hash: &AteHash) -> Vec<AteHash>
+    {
+        self.pointers.lookup_signatures(hash).to_vec()
+    }
+
     pub(crate) fn invalidate_caches(&mut self) {
     }
 
     pub(crate) fn add_history(&mut self, header: &EventHeader) {
-        self.pointers.feed(&header);
-
         let raw = header.raw.clone();
 
         #[cfg(feature = "super_verbose")]
@@ -56,7 +147,11 @@ impl<'a> ChainTimeline
             }
         };
 
+        self.pointers.feed(&header, timestamp);
         if header.meta.include_in_history() {
+            let position = self.arrivals.keys().next_back().map_or(0, |a| a + 1);
+            self.arrival_of.insert(raw.event_hash.clone(), position);
+            self.arrivals.insert(position, (timestamp, raw.clone()));
             self.history.insert(timestamp, raw);
         }
     }
@@ -0,0 +1,336 @@
+#![allow(unused_imports)]
+use log::{error, info, debug};
+use std::sync::Arc;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use fxhash::FxHashMap;
+use serde_json::Value;
+use crate::crypto::AteHash;
+
+use super::validator::EventValidator;
+use super::lint::EventMetadataLinter;
+use super::transform::EventDataTransformer;
+use super::sink::{EventSink};
+
+use super::event::*;
+use super::error::*;
+use super::header::*;
+use super::meta::*;
+use super::time::ChainTimestamp;
+use super::trust::IntegrityMode;
+use super::transaction::ConversationSession;
+use super::plugin::*;
+use super::validator::ValidationResult;
+
+/// Declares that the value of one or more fields of a particular type of data
+/// object must be unique across the whole chain (see `ChainBuilder::add_unique_constraint`).
+///
+/// Fields are addressed with JSON pointers (e.g. "/email") into the serialized
+/// form of the data object, when there are multiple fields it is the combination
+/// of their values that must be unique.
+///
+/// The values are hashed by the writer and attached to the metadata of the event
+/// so that the constraint can be enforced by nodes that can not read the data,
+/// however be aware that values which are easy to guess (e.g. email addresses)
+/// can be confirmed by anyone who can read the metadata.
+#[derive(Debug, Clone)]
+pub struct UniqueConstraint
+{
+    pub(crate) name: String,
+    pub(crate) type_name: String,
+    pub(crate) fields: Vec<String>,
+}
+
+impl UniqueConstraint
+{
+    pub fn new<D>(name: &str, fields: &[&str]) -> UniqueConstraint {
+        UniqueConstraint {
+            name: name.to_string(),
+            type_name: std::any::type_name::<D>().to_string(),
+            fields: fields.iter().map(|a| a.to_string()).collect::<Vec<_>>(),
+        }
+    }
+
+    /// Computes the value that a data object claims for this constraint
+    pub(crate) fn claim(&self, data: &Value) -> MetaUnique
+    {
+        let values = self.fields.iter()
+            .map(|a| data.pointer(a.as_str()).map(|a| a.clone()).unwrap_or(Value::Null))
+            .collect::<Vec<_>>();
+        let values = Value::Array(values).to_string();
+
+        MetaUnique {
+            constraint: self.name.clone(),
+            hash: AteHash::from_bytes_twice(self.name.as_bytes(), values.as_bytes()),
+        }
+    }
+}
+
+/// Position of a claim on a value, the claims are ordered so that the first writer wins
+type ClaimOrder = (ChainTimestamp, AteHash);
+
+/// Enforces the unique constraints that were declared on the chain by rejecting any
+/// event that claims a value which is already held by another data object.
+///
+/// In centralized mode the server arbitrates and whoever it sees first wins. In
+/// distributed mode the nodes may see the events in different orders so instead
+/// the claim with the earliest `ChainTimestamp` (then the lowest event hash) owns
+/// the value. A later claim that was already accepted by a node before it saw the
+/// earlier one remains until the chain is next compacted (when it is validated
+/// again) but it never owns the value, so every node agrees on who does.
+///
+/// The timestamp is chosen by the writer so an earlier claim can only take a value
+/// from its owner when the two are within the sync tolerance of each other (i.e.
+/// they were written concurrently), otherwise it could simply be backdated.
+#[derive(Debug, Clone)]
+pub struct UniqueConstraintPlugin
+{
+    constraints: Vec<UniqueConstraint>,
+    integrity: IntegrityMode,
+    sync_tolerance: Duration,
+    /// Every data object that claims each value in the order they will win
+    owners: FxHashMap<MetaUnique, BTreeMap<ClaimOrder, PrimaryKey>>,
+    /// Values that are currently claimed by each data object
+    claims: FxHashMap<PrimaryKey, Vec<(MetaUnique, ClaimOrder)>>,
+    /// Types of the data objects that are covered by a constraint (so that later
+    /// versions which do not record their type are still checked)
+    types: FxHashMap<PrimaryKey, String>,
+}
+
+impl UniqueConstraintPlugin
+{
+    pub fn new(constraints: Vec<UniqueConstraint>, sync_tolerance: Duration) -> UniqueConstraintPlugin
+    {
+        UniqueConstraintPlugin {
+            constraints,
+            integrity: IntegrityMode::Distributed,
+            sync_tolerance,
+            owners: FxHashMap::default(),
+            claims: FxHashMap::default(),
+            types: FxHashMap::default(),
+        }
+    }
+
+    fn order(header: &EventHeader) -> ClaimOrder {
+        let timestamp = match header.meta.get_timestamp() {
+            Some(a) => *a,
+            None => ChainTimestamp::from(0u64),
+        };
+        (timestamp, header.raw.event_hash)
+    }
+
+    /// Returns the data object that owns a value, which is always the earliest claim
+    pub(crate) fn owner(&self, value: &MetaUnique) -> Option<(&ClaimOrder, &PrimaryKey)> {
+        self.owners.get(value).and_then(|a| a.iter().next())
+    }
+
+    /// Returns the position of a claim that the data object already holds on a value,
+    /// later versions of the object keep this position so updates do not lose the value
+    fn claimed(&self, key: &PrimaryKey, value: &MetaUnique) -> Option<ClaimOrder> {
+        self.claims.get(key)
+            .and_then(|a| a.iter().find(|a| a.0 == *value))
+            .map(|a| a.1)
+    }
+
+    fn release(&mut self, key: &PrimaryKey)
+    {
+        if let Some(claims) = self.claims.remove(key) {
+            for (value, order) in claims {
+                if let Some(owners) = self.owners.get_mut(&value) {
+                    owners.remove(&order);
+                    if owners.is_empty() {
+                        self.owners.remove(&value);
+                    }
+                }
+            }
+        }
+    }
+}
+
+impl EventSink
+for UniqueConstraintPlugin
+{
+    fn feed(&mut self, header: &EventHeader, _conversation: Option<&Arc<ConversationSession>>) -> Result<(), SinkError>
+    {
+        if let Some(key) = header.meta.get_tombstone() {
+            self.release(&key);
+            self.types.remove(&key);
+            return Ok(());
+        }
+        if header.raw.data_hash.is_none() {
+            return Ok(());
+        }
+        let key = match header.meta.get_data_key() {
+            Some(a) => a,
+            None => { return Ok(()); }
+        };
+        if let Some(type_name) = header.meta.get_type_name() {
+            if self.constraints.iter().any(|a| a.type_name == type_name.type_name) {
+                self.types.insert(key.clone(), type_name.type_name.clone());
+            }
+        }
+
+        // A new version of a data object replaces all the values it claimed before
+        let order = UniqueConstraintPlugin::order(header);
+        let claims = header.meta.get_unique_values()
+            .into_iter()
+            .map(|a| (a.clone(), self.claimed(&key, a).unwrap_or(order)))
+            .collect::<Vec<_>>();
+        self.release(&key);
+
+        for (value, order) in claims.iter() {
+            self.owners.entry(value.clone()).or_default().insert(*order, key.clone());
+        }
+        if claims.len() > 0 {
+            self.claims.insert(key, claims);
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self) {
+        self.owners.clear();
+        self.claims.clear();
+        self.types.clear();
+    }
+}
+
+impl EventValidator
+for UniqueConstraintPlugin
+{
+    fn validate(&self, header: &EventHeader, _conversation: Option<&Arc<ConversationSession>>) -> Result<ValidationResult, ValidationError>
+    {
+        if header.raw.data_hash.is_none() {
+            return Ok(ValidationResult::Abstain);
+        }
+        let key = match header.meta.get_data_key() {
+            Some(a) => a,
+            None => { return Ok(ValidationResult::Abstain); }
+        };
+        let values = header.meta.get_unique_values();
+
+        // Data objects that are covered by a constraint must declare the value they claim
+        // (the type comes from the event or otherwise from an earlier version of the object)
+        let type_name = match header.meta.get_type_name() {
+            Some(a) => Some(&a.type_name),
+            None => self.types.get(&key),
+        };
+        if let Some(type_name) = type_name {
+            for constraint in self.constraints.iter().filter(|a| a.type_name == *type_name) {
+                if values.iter().any(|a| a.constraint == constraint.name) == false {
+                    return Err(ValidationError::Denied(format!("the data object is missing its value for the unique constraint ({})", constraint.name)));
+                }
+            }
+        }
+
+        let order = UniqueConstraintPlugin::order(header);
+        let tolerance = self.sync_tolerance.as_millis() as u64;
+        for value in values {
+            let (existing_order, existing) = match self.owner(value) {
+                Some(a) if *a.1 != key => a,
+                _ => continue,
+            };
+
+            // In distributed mode a concurrent claim that was made earlier takes the value
+            // from whoever held it (but not one that is too far back to be concurrent)
+            let order = self.claimed(&key, value).unwrap_or(order);
+            let gap = existing_order.0.time_since_epoch_ms.saturating_sub(order.0.time_since_epoch_ms);
+            if self.integrity == IntegrityMode::Distributed && order < *existing_order && gap <= tolerance {
+                continue;
+            }
+
+            #[cfg(feature = "verbose")]
+            debug!("rejected event as it violates the unique constraint ({}) held by {}", value.constraint, existing);
+            return Err(ValidationError::UniqueViolation {
+                constraint: value.constraint.clone(),
+                existing: existing.clone(),
+            });
+        }
+
+        Ok(ValidationResult::Abstain)
+    }
+
+    fn set_integrity_mode(&mut self, mode: IntegrityMode) {
+        self.integrity = mode;
+    }
+
+    fn clone_validator(&self) -> Box<dyn EventValidator> {
+        Box::new(self.clone())
+    }
+
+    fn validator_name(&self) -> &str {
+        "unique-constraint-validator"
+    }
+}
+
+impl EventMetadataLinter
+for UniqueConstraintPlugin
+{
+    fn clone_linter(&self) -> Box<dyn EventMetadataLinter> {
+        Box::new(self.clone())
+    }
+}
+
+impl EventDataTransformer
+for UniqueConstraintPlugin
+{
+    fn clone_transformer(&self) -> Box<dyn EventDataTransformer> {
+        Box::new(self.clone())
+    }
+}
+
+impl EventPlugin
+for UniqueConstraintPlugin
+{
+    fn clone_plugin(&self) -> Box<dyn EventPlugin> {
+        Box::new(self.clone())
+    }
+}
+
+#[test]
+fn test_unique_constraint_order()
+{
+    crate::utils::bootstrap_env();
+
+    use crate::spec::*;
+
+    let constraint = UniqueConstraint::new::<Value>("email", &["/email"]);
+    let value = constraint.claim(&serde_json::json!({ "email": "alice@here.com" }));
+    let claim = |key: &PrimaryKey, when: u64| {
+        let mut meta = Metadata::for_data(key.clone());
+        meta.core.push(CoreMetadata::Timestamp(ChainTimestamp::from(when)));
+        meta.core.push(CoreMetadata::Unique(value.clone()));
+        let format = MessageFormat { meta: SerializationFormat::Json, data: SerializationFormat::Json };
+        EventData { meta, data_bytes: Some(bytes::Bytes::from(vec![1u8])), format }.as_header().unwrap()
+    };
+    let key1 = PrimaryKey::generate();
+    let key2 = PrimaryKey::generate();
+    let earlier = claim(&key1, 100_000);
+    let later = claim(&key2, 110_000);
+
+    // Nodes that see the claims in a different order still agree on the owner
+    let mut node1 = UniqueConstraintPlugin::new(vec![constraint.clone()], Duration::from_secs(30));
+    assert!(node1.validate(&later, None).is_ok());
+    node1.feed(&later, None).unwrap();
+    assert!(node1.validate(&earlier, None).is_ok());
+    node1.feed(&earlier, None).unwrap();
+
+    let mut node2 = UniqueConstraintPlugin::new(vec![constraint.clone()], Duration::from_secs(30));
+    assert!(node2.validate(&earlier, None).is_ok());
+    node2.feed(&earlier, None).unwrap();
+    assert!(node2.validate(&later, None).is_err());
+
+    assert_eq!(node1.owner(&value).map(|a| a.1), Some(&key1));
+    assert_eq!(node2.owner(&value).map(|a| a.1), Some(&key1));
+
+    // Only the owner can keep the value in its later versions
+    let update = claim(&key1, 120_000);
+    assert!(node1.validate(&update, None).is_ok());
+    node1.feed(&update, None).unwrap();
+    assert_eq!(node1.owner(&value).map(|a| a.1), Some(&key1));
+    assert!(node1.validate(&claim(&key2, 130_000), None).is_err());
+
+    // Claims that are backdated further than the sync tolerance can not take the value
+    assert!(node1.validate(&claim(&PrimaryKey::generate(), 1_000), None).is_err());
+    assert!(node1.validate(&claim(&PrimaryKey::generate(), 99_000), None).is_ok());
+}